@@ -1,11 +1,38 @@
-use crate::{str, Context};
+use crate::{semantic::VA_BUILTINS, str, Context};
 
 pub struct Translator {
     ctx: Context,
     pub code: String,
+    /// Last named parameter of variadic function being generated, `va_start` begins after it
+    va_last: Option<Name>,
 }
 
-use crate::syntax::ast::*;
+use crate::syntax::{
+    ast::*,
+    interner::Name,
+    lexer::token::{FloatSuffix, IntSuffix},
+};
+
+/// Escape string so it can be used inside C string or char literal
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\\' => escaped.push_str("\\\\"),
+            '\"' => escaped.push_str("\\\""),
+            '\'' => escaped.push_str("\\\'"),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                escaped.push_str(&format!("\\{:03o}", c as u32))
+            }
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
 
 impl Translator {
     pub fn new(ctx: Context) -> Translator {
@@ -15,17 +42,22 @@ pub fn new(ctx: Context) -> Translator {
                 "
 #include <inttypes.h>
 #include <stddef.h>
+#include <stdarg.h>
 
 
 
 "
             ),
+            va_last: None,
         }
     }
 
     pub fn type_to_c(&mut self, ty: &Type) {
         match ty {
-            Type::Vector(_) => unimplemented!(),
+            Type::Vector(v) => {
+                eprintln!("Vector types are not supported by C++ backend ({})", v.pos);
+                std::process::exit(-1);
+            }
             Type::Basic(basic) => {
                 let name: &str = &str(basic.name);
                 let s = match name {
@@ -33,12 +65,15 @@ pub fn type_to_c(&mut self, ty: &Type) {
                     "u16" => "uint16_t",
                     "u32" => "uint32_t",
                     "u64" => "uint64_t",
+                    "u128" => "unsigned __int128",
+                    "i128" => "__int128",
                     "i64" => "int64_t",
                     "i32" => "int32_t",
                     "i16" => "int16_t",
                     "i8" => "int8_t",
                     "char" => "char",
                     "usize" => "size_t",
+                    "isize" => "ptrdiff_t",
                     "uchar" => "unsigned char",
                     "f32" => "float",
                     "f64" => "double",
@@ -56,21 +91,49 @@ pub fn type_to_c(&mut self, ty: &Type) {
             }
             Type::Func(func) => {
                 self.type_to_c(&func.ret);
-                self.code.push_str(&format!(" (*)("));
-                for (i, ty) in func.params.iter().enumerate() {
-                    self.type_to_c(ty);
-                    if i != func.params.len() - 1 {
-                        self.code.push(',');
-                    }
-                }
+                self.code.push_str(" (*)(");
+                self.params_to_c(&func.params);
+                self.code.push(')');
             }
             Type::Struct(s) => self.code.push_str(&str(s.name).to_string()),
             Type::Void(_) => self.code.push_str("void"),
         }
     }
 
+    fn params_to_c(&mut self, params: &[Box<Type>]) {
+        for (i, ty) in params.iter().enumerate() {
+            self.type_to_c(ty);
+            if i != params.len() - 1 {
+                self.code.push(',');
+            }
+        }
+    }
+
+    /// Declare variable,field or global named `name` with type `ty`. Function pointers and
+    /// fixed size arrays need name in the middle of declarator
+    fn declare(&mut self, ty: &Type, name: Name) {
+        match ty {
+            Type::Func(func) => {
+                self.type_to_c(&func.ret);
+                self.code.push_str(&format!(" (*{})(", str(name)));
+                self.params_to_c(&func.params);
+                self.code.push(')');
+            }
+            Type::Array(array) if array.len.is_some() => {
+                self.type_to_c(&array.subtype);
+                self.code
+                    .push_str(&format!(" {}[{}]", str(name), array.len.unwrap()));
+            }
+            _ => {
+                self.type_to_c(ty);
+                self.code.push_str(&format!(" {}", str(name)));
+            }
+        }
+    }
+
     pub fn gen_stmt(&mut self, stmt: &Stmt) {
         match &stmt.kind {
+            StmtKind::CompTime(stmt) => self.gen_stmt(stmt),
             StmtKind::Expr(expr) => {
                 self.gen_expr(expr);
                 self.code.push(';');
@@ -82,7 +145,7 @@ pub fn gen_stmt(&mut self, stmt: &Stmt) {
                     self.code.push_str("\t");
                     self.gen_stmt(stmt);
                 }
-                self.code.push_str("\n}");
+                self.code.push_str("\n}\n");
             }
             StmtKind::Return(expr) => {
                 self.code.push_str("return ");
@@ -91,8 +154,15 @@ pub fn gen_stmt(&mut self, stmt: &Stmt) {
                 }
                 self.code.push_str(";\n");
             }
-            StmtKind::Break => self.code.push_str("break;\n"),
-            StmtKind::Continue => self.code.push_str("continue;\n"),
+            StmtKind::Break(1, None) => self.code.push_str("break;\n"),
+            StmtKind::Continue(1) => self.code.push_str("continue;\n"),
+            StmtKind::Break(..) | StmtKind::Continue(_) => {
+                eprintln!(
+                    "Statement `{}` is not supported by C++ backend ({})",
+                    stmt, stmt.pos
+                );
+                std::process::exit(-1);
+            }
             StmtKind::If(cond, then, or) => {
                 self.code.push_str("if (");
                 self.gen_expr(cond);
@@ -110,6 +180,7 @@ pub fn gen_stmt(&mut self, stmt: &Stmt) {
                         self.gen_stmt(&or);
                     };
                 }
+                self.code.push('\n');
             }
             StmtKind::While(cond, block) => {
                 self.code.push_str("while (");
@@ -118,17 +189,28 @@ pub fn gen_stmt(&mut self, stmt: &Stmt) {
                 self.gen_stmt(block);
                 self.code.push_str("\n");
             }
+            StmtKind::CFor(var, cond, then, body) => {
+                // variable declared in `for` is visible only inside loop
+                self.code.push_str("{\n");
+                self.gen_stmt(var);
+                self.code.push_str("for (;");
+                self.gen_expr(cond);
+                self.code.push(';');
+                self.gen_expr(then);
+                self.code.push_str(")\n");
+                self.gen_stmt(body);
+                self.code.push_str("\n}\n");
+            }
             StmtKind::Var(name, reassignable, ty, expr) => {
                 if !*reassignable {
-                    self.code.push_str("const");
+                    self.code.push_str("const ");
                 }
-                if ty.is_none() {
-                    self.code.push_str("auto ");
-                } else {
-                    self.type_to_c(ty.as_ref().unwrap());
+                // prefer type recorded by semantic pass, it's already inferred
+                let ty = self.ctx.types.get(&stmt.id).cloned().or_else(|| ty.clone());
+                match ty {
+                    Some(ty) => self.declare(&ty, *name),
+                    None => self.code.push_str(&format!("auto {}", str(*name))),
                 }
-                self.code.push(' ');
-                self.code.push_str(&str(*name).to_string());
                 if expr.is_some() {
                     self.code.push_str(" = ");
                     self.gen_expr(expr.as_ref().unwrap());
@@ -140,50 +222,135 @@ pub fn gen_stmt(&mut self, stmt: &Stmt) {
                 self.gen_stmt(block);
                 self.code.push_str("\n");
             }
-            _ => unimplemented!(),
+            StmtKind::Static(var) => {
+                self.code.push_str("static ");
+                self.gen_stmt(var);
+            }
+            StmtKind::Defer(_) => {
+                eprintln!("`defer` is not supported by C++ backend ({})", stmt.pos);
+                std::process::exit(-1);
+            }
+            StmtKind::Delete(ptr) => {
+                self.code.push_str("delete ");
+                self.gen_expr(ptr);
+                self.code.push(';');
+                self.code.push('\n');
+            }
         }
     }
 
     pub fn gen_expr(&mut self, expr: &Expr) {
         match &expr.kind {
-            ExprKind::Int(i, _, _) => self.code.push_str(&i.to_string()),
-            ExprKind::Float(f, _) => self.code.push_str(&f.to_string()),
-            ExprKind::Char(c) => self.code.push_str(&format!("'{}'", c)),
+            ExprKind::CompTime(expr) | ExprKind::Inline(expr) => self.gen_expr(expr),
+            ExprKind::Int(i, _, suffix) => {
+                let (ty, lit) = match suffix {
+                    IntSuffix::Byte => ("int8_t", format!("{}", i)),
+                    IntSuffix::Int => ("int32_t", format!("{}", i)),
+                    IntSuffix::Long => ("int64_t", format!("{}LL", i)),
+                    IntSuffix::UByte => ("uint8_t", format!("{}U", *i as u64)),
+                    IntSuffix::UInt => ("uint32_t", format!("{}U", *i as u64)),
+                    IntSuffix::ULong => ("uint64_t", format!("{}ULL", *i as u64)),
+                };
+                if let IntSuffix::Int = suffix {
+                    self.code.push_str(&lit);
+                } else {
+                    self.code.push_str(&format!("(({}){})", ty, lit));
+                }
+            }
+            ExprKind::Float(f, suffix) => {
+                self.code.push_str(&format!("{:?}", f));
+                if let FloatSuffix::Float = suffix {
+                    self.code.push('f');
+                }
+            }
+            ExprKind::Char(c) => self
+                .code
+                .push_str(&format!("'{}'", escape(&c.to_string()))),
             ExprKind::Str(s) => {
-                self.code.push_str(&format!("{:?}", s));
+                // Havo strings are `*char`, so drop constness of C++ literal
+                self.code.push_str(&format!("((char*)\"{}\")", escape(s)));
+            }
+            ExprKind::Bool(b) => self.code.push_str(&b.to_string()),
+            ExprKind::Null => self.code.push_str("nullptr"),
+            ExprKind::Variant(name, variant) => {
+                let value = self
+                    .ctx
+                    .file
+                    .find_enum(*name)
+                    .and_then(|e| e.variant(*variant))
+                    .unwrap()
+                    .value;
+                self.code.push_str(&format!("(({}){})", str(*name), value));
             }
             ExprKind::Binary(op, lhs, rhs) => {
+                self.code.push('(');
                 self.gen_expr(lhs);
-                self.code.push_str(op);
+                self.code.push_str(&format!(" {} ", op));
                 self.gen_expr(rhs);
+                self.code.push(')');
+            }
+            ExprKind::Block(stmts, tail) => {
+                // GNU statement expression
+                self.code.push_str("({\n");
+                for stmt in stmts.iter() {
+                    self.code.push_str("\t");
+                    self.gen_stmt(stmt);
+                }
+                self.code.push_str("\t");
+                self.gen_expr(tail);
+                self.code.push_str(";\n})");
+            }
+            ExprKind::Ternary(cond, then, otherwise) => {
+                self.code.push('(');
+                self.gen_expr(cond);
+                self.code.push_str(" ? ");
+                self.gen_expr(then);
+                self.code.push_str(" : ");
+                self.gen_expr(otherwise);
+                self.code.push(')');
             }
             ExprKind::Unary(op, val) => {
+                self.code.push('(');
                 self.code.push_str(op);
                 self.gen_expr(val);
+                self.code.push(')');
+            }
+            ExprKind::Field(..) if expr.str_len().is_some() => {
+                self.code
+                    .push_str(&format!("((size_t){})", expr.str_len().unwrap()));
             }
             ExprKind::Field(val, field) => {
                 let ty: Type = self.ctx.types.get(&val.id).unwrap().clone();
 
+                // `pp.field` with `pp: **T` becomes `(*pp)->field`
+                let mut derefs = 0;
+                let mut subtype = &ty;
+                while let Type::Ptr(ptr) = subtype {
+                    subtype = &ptr.subtype;
+                    derefs += 1;
+                }
+                for _ in 1..derefs {
+                    self.code.push_str("(*");
+                }
                 self.gen_expr(val);
+                for _ in 1..derefs {
+                    self.code.push(')');
+                }
                 if ty.is_ptr() {
-                    let ptr = ty.to_ptr().unwrap();
-                    if ptr.subtype.is_struct() {
-                        self.code.push_str(&format!("->{}", str(*field)));
-                    } else {
-                        panic!();
-                    }
+                    self.code.push_str(&format!("->{}", str(*field)));
                 } else {
                     self.code.push_str(&format!(".{}", str(*field)));
                 }
             }
             ExprKind::Conv(val, ty) => {
-                self.code.push('(');
+                self.code.push_str("((");
                 self.type_to_c(ty);
                 self.code.push(')');
                 self.gen_expr(val);
+                self.code.push(')');
             }
-            ExprKind::Struct(_name, args) => {
-                self.code.push_str("{\n");
+            ExprKind::Struct(name, args) => {
+                self.code.push_str(&format!("{} {{\n", str(name.name())));
                 for (i, arg) in args.iter().enumerate() {
                     let arg: &StructArg = arg;
                     self.code
@@ -196,13 +363,14 @@ pub fn gen_expr(&mut self, expr: &Expr) {
                 self.code.push_str("\n}");
             }
             ExprKind::Deref(expr) => {
-                self.code.push('*');
+                self.code.push_str("(*");
                 self.gen_expr(expr);
+                self.code.push(')');
             }
             ExprKind::AddressOf(expr) => {
-                self.code.push('&');
-
+                self.code.push_str("(&");
                 self.gen_expr(expr);
+                self.code.push(')');
             }
             ExprKind::Assign(e1, e2) => {
                 self.gen_expr(e1);
@@ -210,6 +378,27 @@ pub fn gen_expr(&mut self, expr: &Expr) {
                 self.gen_expr(e2);
             }
             ExprKind::Ident(name) => self.code.push_str(&format!("{}", str(*name))),
+            ExprKind::GetFunc(name) => self.code.push_str(&format!("(&{})", str(*name))),
+            ExprKind::New(ty, init) => {
+                self.code.push_str("new ");
+                self.type_to_c(ty);
+                self.code.push_str("(");
+                if let Some(init) = init {
+                    self.gen_expr(init);
+                }
+                self.code.push_str(")");
+            }
+            ExprKind::Call(path, None, _)
+                if VA_BUILTINS.contains(&str(path.name()).as_str()) =>
+            {
+                let name = str(path.name());
+                if name.as_str() == "va_start" {
+                    let last = self.va_last.unwrap();
+                    self.code.push_str(&format!("va_start(_va_list_,{})", str(last)));
+                } else {
+                    self.code.push_str("va_end(_va_list_)");
+                }
+            }
             ExprKind::Call(path, obj, args) => {
                 let name = path.name();
                 self.code.push_str(&str(name));
@@ -217,8 +406,17 @@ pub fn gen_expr(&mut self, expr: &Expr) {
                 if obj.is_some() {
                     let expr_ = obj.as_ref().unwrap();
                     let ty = self.ctx.types.get(&expr_.id).unwrap().clone();
-                    if !ty.is_ptr() {
+                    let this_is_ptr = self
+                        .ctx
+                        .file
+                        .functions()
+                        .iter()
+                        .find(|f| f.name == name && f.this.is_some())
+                        .map_or(true, |f| f.this.as_ref().unwrap().1.is_ptr());
+                    if this_is_ptr && !ty.is_ptr() {
                         self.code.push('&');
+                    } else if !this_is_ptr && ty.is_ptr() {
+                        self.code.push('*');
                     }
                     self.gen_expr(expr_);
                     if args.len() != 0 {
@@ -238,28 +436,83 @@ pub fn gen_expr(&mut self, expr: &Expr) {
                 self.type_to_c(ty);
                 self.code.push_str(")");
             }
+            ExprKind::AlignOf(ty) => {
+                self.code.push_str("alignof(");
+                self.type_to_c(ty);
+                self.code.push_str(")");
+            }
+            ExprKind::OffsetOf(name, field) => {
+                self.code.push_str(&format!("offsetof({}, {})", str(*name), str(*field)));
+            }
+            ExprKind::VaArg(ty) => {
+                self.code.push_str("va_arg(_va_list_,");
+                self.type_to_c(ty);
+                self.code.push_str(")");
+            }
             ExprKind::ArrayIdx(array, index) => {
                 self.gen_expr(array);
                 self.code.push('[');
                 self.gen_expr(index);
                 self.code.push(']');
             }
-            _ => panic!("{:?}", expr),
+            _ => {
+                eprintln!(
+                    "Expression `{}` is not supported by C++ backend ({})",
+                    expr, expr.pos
+                );
+                std::process::exit(-1);
+            }
         }
     }
 
+    /// Function prototype without trailing `;`. `this` goes first,then normal parameters
+    fn gen_prototype(&mut self, f: &Function) {
+        self.type_to_c(&f.ret);
+        self.code.push_str(&format!(" {}(", str(f.name).to_string()));
+        let mut params = vec![];
+        if let Some((name, ty)) = &f.this {
+            params.push((*name, ty.clone()));
+        }
+        params.extend(f.params.iter().cloned());
+        for (i, (name, ty)) in params.iter().enumerate() {
+            self.declare(ty, *name);
+            if i != params.len() - 1 {
+                self.code.push(',');
+            }
+        }
+        if f.variadic {
+            if !params.is_empty() {
+                self.code.push(',');
+            }
+            self.code.push_str("...");
+        }
+        self.code.push(')');
+    }
+
     pub fn gen_toplevel(&mut self, elems: &[Elem]) {
         // predefining all structures
         for elem in elems.iter() {
             match elem {
-                Elem::Struct(struct_) => self
-                    .code
-                    .push_str(&format!("struct {};\n", str(struct_.name).to_string())),
+                Elem::Struct(struct_) => self.code.push_str(&format!(
+                    "{} {};\n",
+                    if struct_.union { "union" } else { "struct" },
+                    str(struct_.name).to_string()
+                )),
                 Elem::ConstExpr { name, expr, .. } => {
                     self.code
-                        .push_str(&format!("#define {} ", str(*name).to_string()));
+                        .push_str(&format!("#define {} (", str(*name).to_string()));
                     self.gen_expr(expr);
-                    self.code.push('\n');
+                    self.code.push_str(")\n");
+                }
+                Elem::Alias(name, ty) => {
+                    self.code.push_str("typedef ");
+                    self.declare(ty, *name);
+                    self.code.push_str(";\n");
+                }
+                Elem::Enum(e) => {
+                    self.code.push_str("typedef ");
+                    self.declare(&e.repr, e.name);
+                    self.code.push_str(";\n");
                 }
                 _ => {}
             }
@@ -268,49 +521,34 @@ pub fn gen_toplevel(&mut self, elems: &[Elem]) {
         for elem in elems.iter() {
             if let Elem::Func(func) = elem {
                 let f: &Function = func;
-                if f.external || f.internal {
-                    self.code.push_str("extern \"C\" {\n");
+                if f.internal {
+                    // builtins are known to C++ compiler already
+                    continue;
                 }
-                self.type_to_c(&f.ret);
-                self.code
-                    .push_str(&format!(" {} (", str(f.name).to_string()));
-                if f.this.is_some() {
-                    let (name, ty) = f.this.as_ref().unwrap();
-
-                    self.type_to_c(ty);
-                    self.code.push_str(&format!(" {}", str(*name).to_owned()));
-                    if f.params.len() != 0 {
-                        self.code.push(',');
-                    }
-                }
-                for (i, (_, ty)) in f.params.iter().enumerate() {
-                    self.type_to_c(ty);
-                    if i != f.params.len() - 1 {
-                        self.code.push(',');
-                    } else {
-                        if f.variadic {
-                            self.code.push_str(",...");
-                        }
-                    }
-                }
-                self.code.push_str(");\n");
-                if f.external || f.internal {
-                    self.code.push_str(" } \n");
+                if f.external {
+                    self.code.push_str("extern \"C\" ");
+                } else if !f.public && str(f.name).as_str() != "main" {
+                    self.code.push_str("static ");
                 }
+                self.gen_prototype(f);
+                self.code.push_str(";\n");
             }
         }
         for elem in elems.iter() {
             match elem {
                 Elem::Struct(s) => {
-                    self.code
-                        .push_str(&format!("struct {} {{\n", str(s.name).to_string()));
+                    self.code.push_str(&format!(
+                        "{} {} {{\n",
+                        if s.union { "union" } else { "struct" },
+                        str(s.name).to_string()
+                    ));
                     let s: &Struct = s;
                     for field in s.fields.iter() {
                         let f: &StructField = field;
 
-                        self.type_to_c(&f.data_type);
-                        self.code
-                            .push_str(&format!(" {};\n", str(f.name).to_string()));
+                        self.code.push('\t');
+                        self.declare(&f.data_type, f.name);
+                        self.code.push_str(";\n");
                     }
                     self.code.push_str("};\n");
                 }
@@ -322,91 +560,71 @@ pub fn gen_toplevel(&mut self, elems: &[Elem]) {
                 Elem::Const(c) => {
                     let c: &Const = c;
                     self.code.push_str(&format!("const "));
-                    self.type_to_c(&c.typ);
-                    self.code
-                        .push_str(&format!(" {} = ", str(c.name).to_string()));
+                    self.declare(&c.typ, c.name);
+                    self.code.push_str(" = ");
                     self.gen_expr(&c.expr);
                     self.code.push_str(";\n");
                 }
-                Elem::Func(f) => {
-                    if f.body.is_some() {
-                        let f: &Function = f;
-                        self.type_to_c(&f.ret);
-                        self.code
-                            .push_str(&format!(" {} (", str(f.name).to_string()));
-                        if f.this.is_some() {
-                            let (name, ty) = f.this.as_ref().unwrap();
-
-                            self.type_to_c(ty);
-                            self.code.push_str(&format!(" {}", str(*name).to_owned()));
-                            if f.params.len() != 0 {
-                                self.code.push(',');
-                            }
-                        }
-                        for (i, (name, ty)) in f.params.iter().enumerate() {
-                            self.type_to_c(ty);
-                            self.code.push_str(&format!(" {} ", str(*name).to_string()));
-                            if i != f.params.len() - 1 {
-                                self.code.push(',');
-                            } else {
-                                if f.variadic {
-                                    self.code.push_str("...");
-                                }
-                            }
-                        }
-
-                        self.code.push(')');
-                        let body = f.body.as_ref().unwrap();
-
-                        self.gen_stmt(body);
-                        self.code.push('\n');
-                    }
-                }
-
                 Elem::Global(global) => {
                     let global: &Global = global;
                     if global.external {
-                        self.code.push_str("extern \"C\" {\n");
+                        self.code.push_str("extern \"C\" ");
+                    } else if !global.public {
+                        self.code.push_str("static ");
                     }
-                    self.code.push_str("static ");
-                    self.type_to_c(&global.typ);
-                    self.code
-                        .push_str(&format!(" {}", str(global.name).to_owned()));
+                    if global.thread_local {
+                        self.code.push_str("thread_local ");
+                    }
+                    self.declare(&global.typ, global.name);
                     if global.expr.is_some() {
                         self.code.push_str(" = ");
                         self.gen_expr(global.expr.as_ref().unwrap());
                     }
-                    self.code.push(';');
-                    if global.external {
-                        self.code.push_str("\n}\n");
-                    }
+                    self.code.push_str(";\n");
                 }
-                Elem::Link(_) => {}
-                Elem::Enum => {}
-                Elem::Import(_) => {}
                 _ => {}
             }
         }
+        for elem in elems.iter() {
+            if let Elem::Func(f) = elem {
+                if f.body.is_some() {
+                    let f: &Function = f;
+                    self.gen_prototype(f);
+                    self.code.push('\n');
+                    let body = f.body.as_ref().unwrap();
+
+                    if f.variadic {
+                        // `va_list` read by intrinsics lives in scope wrapping whole body
+                        self.va_last = f.params.last().or(f.this.as_ref()).map(|(name, _)| *name);
+                        self.code.push_str("{\nva_list _va_list_;\n");
+                        self.gen_stmt(body);
+                        self.code.push_str("\n}");
+                    } else {
+                        self.gen_stmt(body);
+                    }
+                    self.code.push('\n');
+                }
+            }
+        }
     }
 
     pub fn run(&mut self) {
+        if self.ctx.run {
+            eprintln!("`havo run` needs JIT, cpp backend only writes C++ source");
+            std::process::exit(-1);
+        }
         let elems = self.ctx.file.elems.clone();
         self.gen_toplevel(&elems);
 
-        let file = format!("output.cc");
+        let file = if self.ctx.output.is_empty() {
+            "output.cc".to_owned()
+        } else {
+            self.ctx.output.clone()
+        };
 
-        use std::io::Write;
-        let mut f = std::fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(file)
-            .unwrap();
-        f.write_all(self.code.as_bytes()).unwrap();
-
-        std::process::Command::new("c++")
-            .arg("-lc")
-            .arg("output.cc")
-            .spawn()
-            .unwrap();
+        if let Err(e) = std::fs::write(&file, self.code.as_bytes()) {
+            eprintln!("Failed to write {}: {}", file, e);
+            std::process::exit(-1);
+        }
     }
 }
@@ -0,0 +1,653 @@
+//! Cranelift backend
+//!
+//! Only integer subset of the language is supported for now: integer locals and arithmetic,
+//! `if`/`while`/`loop`/`for`, `return` and direct calls. Everything else is reported as
+//! unsupported instead of producing wrong code.
+
+use crate::{
+    str,
+    syntax::{
+        ast::{Elem, Expr, ExprKind, Function, NodeId, Stmt, StmtKind, Type},
+        interner::Name,
+    },
+    Context as CContext,
+};
+use ::cranelift::{codegen::ir::TrapCode, prelude::*};
+use colored::Colorize;
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+use cranelift_simplejit::{SimpleJITBackend, SimpleJITBuilder};
+use std::collections::{HashMap, VecDeque};
+
+macro_rules! unsupported {
+    ($what: expr, $pos: expr) => {
+        error!(
+            format!("{} is unsupported in cranelift backend", $what),
+            $pos
+        )
+    };
+}
+
+/// Convert AST type to Cranelift type
+pub fn ty_to_ctype(ty: &Type) -> types::Type {
+    match ty {
+        Type::Basic(basic) => {
+            let name: &str = &str(basic.name);
+            match name {
+                "i8" | "u8" | "char" | "uchar" | "bool" => types::I8,
+                "i16" | "u16" => types::I16,
+                "i32" | "u32" => types::I32,
+                "i64" | "u64" | "isize" | "usize" => types::I64,
+                _ => unsupported!(format!("type `{}`", ty), basic.pos),
+            }
+        }
+        _ => unsupported!(format!("type `{}`", ty), ty.pos()),
+    }
+}
+
+fn ty_is_bool(ty: &Type) -> bool {
+    match ty {
+        Type::Basic(basic) => str(basic.name).as_str() == "bool",
+        _ => false,
+    }
+}
+
+fn ty_is_signed(ty: &Type) -> bool {
+    match ty {
+        Type::Basic(basic) => {
+            let name: &str = &str(basic.name);
+            match name {
+                "i8" | "i16" | "i32" | "i64" | "isize" | "char" => true,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Function declared in module
+pub struct FunctionUnit {
+    /// AST Function
+    pub f: Function,
+    /// Cranelift function id
+    pub id: FuncId,
+    pub sig: Signature,
+}
+
+pub struct Codegen<'a> {
+    context: &'a mut CContext,
+    module: Module<SimpleJITBackend>,
+    functions: HashMap<Name, Vec<FunctionUnit>>,
+}
+
+impl<'a> Codegen<'a> {
+    pub fn new(context: &'a mut CContext) -> Codegen<'a> {
+        let builder = SimpleJITBuilder::new(default_libcall_names());
+
+        Codegen {
+            context,
+            module: Module::new(builder),
+            functions: HashMap::new(),
+        }
+    }
+
+    fn signature(&self, func: &Function) -> Signature {
+        if func.this.is_some() {
+            unsupported!("method", func.pos);
+        }
+        if func.variadic {
+            unsupported!("variadic function", func.pos);
+        }
+        if func.internal {
+            unsupported!("internal function", func.pos);
+        }
+        let mut sig = self.module.make_signature();
+        for (_, ty) in func.params.iter() {
+            sig.params.push(AbiParam::new(ty_to_ctype(ty)));
+        }
+        if !func.ret.is_void() {
+            sig.returns.push(AbiParam::new(ty_to_ctype(&func.ret)));
+        }
+
+        sig
+    }
+
+    pub fn gen_toplevel(&mut self, elems: &[Elem]) {
+        for elem in elems.iter() {
+            match elem {
+                Elem::Func(func) => {
+                    let sig = self.signature(func);
+                    let name_str = str(func.name).to_string();
+                    let (linkage, name) = if func.external {
+                        (Linkage::Import, name_str)
+                    } else if name_str == "main"
+                        || func.has_attribute("export")
+                        || func.has_attribute("no_mangle")
+                    {
+                        (Linkage::Export, name_str)
+                    } else {
+                        let linkage = if func.public {
+                            Linkage::Export
+                        } else {
+                            Linkage::Local
+                        };
+                        (linkage, crate::mangle::mangle(func))
+                    };
+                    let id = match self.module.declare_function(&name, linkage, &sig) {
+                        Ok(id) => id,
+                        Err(e) => error!(e, func.pos),
+                    };
+
+                    self.functions
+                        .entry(func.name)
+                        .or_insert_with(Vec::new)
+                        .push(FunctionUnit {
+                            f: func.clone(),
+                            id,
+                            sig,
+                        });
+                }
+                Elem::Struct(s) => unsupported!("structure", s.pos),
+                Elem::Global(global) => unsupported!("global variable", global.pos),
+                _ => (),
+            }
+        }
+
+        let mut ctx = self.module.make_context();
+        let mut builder_ctx = FunctionBuilderContext::new();
+        for elem in elems.iter() {
+            if let Elem::Func(func) = elem {
+                if func.external || func.body.is_none() {
+                    continue;
+                }
+                let (id, sig) = self.functions[&func.name]
+                    .iter()
+                    .find(|unit| unit.f.id == func.id)
+                    .map(|unit| (unit.id, unit.sig.clone()))
+                    .unwrap();
+                ctx.func.signature = sig;
+
+                let mut translator = FunctionTranslator {
+                    builder: FunctionBuilder::new(&mut ctx.func, &mut builder_ctx),
+                    module: &mut self.module,
+                    functions: &self.functions,
+                    types: &self.context.types,
+                    variables: HashMap::new(),
+                    var_id: 0,
+                    break_blocks: VecDeque::new(),
+                    continue_blocks: VecDeque::new(),
+                    terminated: false,
+                    ret: *func.ret.clone(),
+                };
+                translator.translate(func);
+
+                if let Err(e) = self.module.define_function(id, &mut ctx) {
+                    error!(e, func.pos);
+                }
+                self.module.clear_context(&mut ctx);
+            }
+        }
+
+        self.module.finalize_definitions();
+    }
+
+    pub fn compile(&mut self) {
+        if !self.context.jit {
+            eprintln!(
+                "{}: cranelift backend supports only JIT compilation for now, use --jit",
+                "ERROR".red()
+            );
+            std::process::exit(-1);
+        }
+        let elems = self.context.file.elems.clone();
+        self.gen_toplevel(&elems);
+
+        let main = match self.functions.get(&crate::intern("main")) {
+            Some(units) => units[0].id,
+            None => {
+                eprintln!("{}: function `main` not found", "ERROR".red());
+                std::process::exit(-1);
+            }
+        };
+        let code = self.module.get_finalized_function(main);
+        let main_fn: fn() -> i32 = unsafe { std::mem::transmute(code) };
+
+        let status = main_fn();
+        if self.context.run {
+            std::process::exit(status);
+        }
+    }
+}
+
+/// Translates body of single function
+struct FunctionTranslator<'a> {
+    builder: FunctionBuilder<'a>,
+    module: &'a mut Module<SimpleJITBackend>,
+    functions: &'a HashMap<Name, Vec<FunctionUnit>>,
+    types: &'a HashMap<NodeId, Type>,
+    variables: HashMap<Name, (Variable, Type)>,
+    var_id: usize,
+    break_blocks: VecDeque<Ebb>,
+    continue_blocks: VecDeque<Ebb>,
+    /// true if current block already ended with `return`,`break` or `continue`
+    terminated: bool,
+    ret: Type,
+}
+
+impl<'a> FunctionTranslator<'a> {
+    fn translate(&mut self, func: &Function) {
+        let entry = self.builder.create_ebb();
+        self.builder.append_ebb_params_for_function_params(entry);
+        self.builder.switch_to_block(entry);
+
+        for (i, (name, ty)) in func.params.iter().enumerate() {
+            let val = self.builder.ebb_params(entry)[i];
+            let var = self.declare_var(*name, *ty.clone());
+            self.builder.def_var(var, val);
+        }
+
+        self.gen_stmt(func.body.as_ref().unwrap());
+
+        if !self.terminated {
+            if self.ret.is_void() {
+                self.builder.ins().return_(&[]);
+            } else {
+                self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+            }
+        }
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+    }
+
+    fn declare_var(&mut self, name: Name, ty: Type) -> Variable {
+        let var = Variable::new(self.var_id);
+        self.var_id += 1;
+        self.builder.declare_var(var, ty_to_ctype(&ty));
+        self.variables.insert(name, (var, ty));
+
+        var
+    }
+
+    fn get_expr_type(&self, expr: &Expr) -> Type {
+        match self.types.get(&expr.id) {
+            Some(ty) => ty.clone(),
+            None => error!(format!("type of expression `{}` is unknown", expr), expr.pos),
+        }
+    }
+
+    fn cast(&mut self, val: Value, from: &Type, to: &Type) -> Value {
+        let (cfrom, cto) = (ty_to_ctype(from), ty_to_ctype(to));
+        if cfrom == cto {
+            val
+        } else if cfrom.bits() > cto.bits() {
+            self.builder.ins().ireduce(cto, val)
+        } else if ty_is_signed(from) {
+            self.builder.ins().sextend(cto, val)
+        } else {
+            self.builder.ins().uextend(cto, val)
+        }
+    }
+
+    /// Continue code generation in `ebb`
+    fn switch_to(&mut self, ebb: Ebb) {
+        self.builder.switch_to_block(ebb);
+        self.terminated = false;
+    }
+
+    fn gen_stmt(&mut self, stmt: &Stmt) {
+        if self.terminated {
+            // code after `return`,`break` or `continue` is unreachable but still must be placed
+            // in some block
+            let dead = self.builder.create_ebb();
+            self.switch_to(dead);
+        }
+        match &stmt.kind {
+            StmtKind::CompTime(s) => self.gen_stmt(s),
+            StmtKind::Block(stmts) => {
+                for stmt in stmts.iter() {
+                    self.gen_stmt(stmt);
+                }
+            }
+            StmtKind::Expr(expr) => {
+                self.gen_expr(expr);
+            }
+            StmtKind::Var(name, _, _, init) => {
+                let ty = match self.types.get(&stmt.id) {
+                    Some(ty) => ty.clone(),
+                    None => error!(
+                        format!("type of variable `{}` is unknown", str(*name)),
+                        stmt.pos
+                    ),
+                };
+                let val = match init {
+                    Some(init) => {
+                        let val = self.gen_value(init);
+                        let init_ty = self.get_expr_type(init);
+                        self.cast(val, &init_ty, &ty)
+                    }
+                    None => self.builder.ins().iconst(ty_to_ctype(&ty), 0),
+                };
+                let var = self.declare_var(*name, ty);
+                self.builder.def_var(var, val);
+            }
+            StmtKind::Return(expr) => {
+                match expr {
+                    Some(expr) => {
+                        let val = self.gen_value(expr);
+                        let ty = self.get_expr_type(expr);
+                        let ret = self.ret.clone();
+                        let val = self.cast(val, &ty, &ret);
+                        self.builder.ins().return_(&[val]);
+                    }
+                    None => {
+                        self.builder.ins().return_(&[]);
+                    }
+                }
+                self.terminated = true;
+            }
+            StmtKind::If(cond, then, or) => {
+                let cond = self.gen_value(cond);
+                let then_ebb = self.builder.create_ebb();
+                let else_ebb = self.builder.create_ebb();
+                let merge_ebb = self.builder.create_ebb();
+                self.builder.ins().brz(cond, else_ebb, &[]);
+                self.builder.ins().jump(then_ebb, &[]);
+
+                self.switch_to(then_ebb);
+                self.gen_stmt(then);
+                if !self.terminated {
+                    self.builder.ins().jump(merge_ebb, &[]);
+                }
+
+                self.switch_to(else_ebb);
+                if let Some(or) = or {
+                    self.gen_stmt(or);
+                }
+                if !self.terminated {
+                    self.builder.ins().jump(merge_ebb, &[]);
+                }
+
+                self.switch_to(merge_ebb);
+            }
+            StmtKind::While(cond, body) => {
+                let header = self.builder.create_ebb();
+                let body_ebb = self.builder.create_ebb();
+                let exit = self.builder.create_ebb();
+                self.builder.ins().jump(header, &[]);
+
+                self.switch_to(header);
+                let cond = self.gen_value(cond);
+                self.builder.ins().brz(cond, exit, &[]);
+                self.builder.ins().jump(body_ebb, &[]);
+
+                self.switch_to(body_ebb);
+                self.gen_loop_body(body, header, exit);
+
+                self.switch_to(exit);
+            }
+            StmtKind::Loop(body) => {
+                let body_ebb = self.builder.create_ebb();
+                let exit = self.builder.create_ebb();
+                self.builder.ins().jump(body_ebb, &[]);
+
+                self.switch_to(body_ebb);
+                self.gen_loop_body(body, body_ebb, exit);
+
+                self.switch_to(exit);
+            }
+            StmtKind::CFor(var, cond, then, body) => {
+                self.gen_stmt(var);
+                let header = self.builder.create_ebb();
+                let body_ebb = self.builder.create_ebb();
+                let update = self.builder.create_ebb();
+                let exit = self.builder.create_ebb();
+                self.builder.ins().jump(header, &[]);
+
+                self.switch_to(header);
+                let cond = self.gen_value(cond);
+                self.builder.ins().brz(cond, exit, &[]);
+                self.builder.ins().jump(body_ebb, &[]);
+
+                self.switch_to(body_ebb);
+                self.break_blocks.push_back(exit);
+                self.continue_blocks.push_back(update);
+                self.gen_stmt(body);
+                self.break_blocks.pop_back();
+                self.continue_blocks.pop_back();
+                if !self.terminated {
+                    self.builder.ins().jump(update, &[]);
+                }
+
+                self.switch_to(update);
+                self.gen_expr(then);
+                self.builder.ins().jump(header, &[]);
+
+                self.switch_to(exit);
+            }
+            StmtKind::Break(_, Some(_)) => unsupported!("`break` with value", stmt.pos),
+            StmtKind::Break(..) | StmtKind::Continue(_) => {
+                let target = match stmt.kind {
+                    StmtKind::Break(depth, _) if depth <= self.break_blocks.len() => {
+                        self.break_blocks.get(self.break_blocks.len() - depth)
+                    }
+                    StmtKind::Continue(depth) if depth <= self.continue_blocks.len() => {
+                        self.continue_blocks.get(self.continue_blocks.len() - depth)
+                    }
+                    _ => None,
+                };
+                match target.cloned() {
+                    Some(ebb) => {
+                        self.builder.ins().jump(ebb, &[]);
+                    }
+                    None => error!(format!("`{}` outside of loop", stmt), stmt.pos),
+                }
+                self.terminated = true;
+            }
+            StmtKind::Defer(_) => unsupported!("`defer`", stmt.pos),
+            StmtKind::Delete(_) => unsupported!("`delete`", stmt.pos),
+            StmtKind::Static(_) => unsupported!("`static` variable", stmt.pos),
+        }
+    }
+
+    /// Generate loop body where `continue` jumps to `next` and `break` jumps to `exit`
+    fn gen_loop_body(&mut self, body: &Stmt, next: Ebb, exit: Ebb) {
+        self.break_blocks.push_back(exit);
+        self.continue_blocks.push_back(next);
+        self.gen_stmt(body);
+        self.break_blocks.pop_back();
+        self.continue_blocks.pop_back();
+        if !self.terminated {
+            self.builder.ins().jump(next, &[]);
+        }
+    }
+
+    /// Generate expression that must produce value
+    fn gen_value(&mut self, expr: &Expr) -> Value {
+        match self.gen_expr(expr) {
+            Some(val) => val,
+            None => error!(format!("expression `{}` has no value", expr), expr.pos),
+        }
+    }
+
+    fn gen_expr(&mut self, expr: &Expr) -> Option<Value> {
+        let val = match &expr.kind {
+            ExprKind::CompTime(e) | ExprKind::Inline(e) => return self.gen_expr(e),
+            ExprKind::Int(i, _, _) => {
+                let ty = ty_to_ctype(&self.get_expr_type(expr));
+                self.builder.ins().iconst(ty, *i)
+            }
+            ExprKind::Bool(b) => self.builder.ins().iconst(types::I8, *b as i64),
+            ExprKind::Char(c) => self.builder.ins().iconst(types::I8, *c as i64),
+            ExprKind::Ident(name) => match self.variables.get(name) {
+                Some((var, _)) => self.builder.use_var(*var),
+                None => unsupported!(format!("global or constant `{}`", name), expr.pos),
+            },
+            ExprKind::Assign(to, from) => {
+                let (var, ty) = match &to.kind {
+                    ExprKind::Ident(name) if self.variables.contains_key(name) => {
+                        self.variables[name].clone()
+                    }
+                    _ => unsupported!(format!("assignment to `{}`", to), to.pos),
+                };
+                let val = self.gen_value(from);
+                let from_ty = self.get_expr_type(from);
+                let val = self.cast(val, &from_ty, &ty);
+                self.builder.def_var(var, val);
+                val
+            }
+            ExprKind::Conv(val, to) => {
+                let from = self.get_expr_type(val);
+                let val = self.gen_value(val);
+                self.cast(val, &from, to)
+            }
+            ExprKind::Unary(op, val) => {
+                let ty = self.get_expr_type(val);
+                let val = self.gen_value(val);
+                let op: &str = op;
+                match op {
+                    "-" => self.builder.ins().ineg(val),
+                    "+" => val,
+                    "!" if ty_is_bool(&ty) => {
+                        let cond = self.builder.ins().icmp_imm(IntCC::Equal, val, 0);
+                        self.builder.ins().bint(types::I8, cond)
+                    }
+                    "!" => self.builder.ins().bnot(val),
+                    _ => unsupported!(format!("unary operator `{}`", op), expr.pos),
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let op: &str = op;
+                if op == "&&" || op == "||" {
+                    return Some(self.gen_logical(op, lhs, rhs));
+                }
+                let ty = self.get_expr_type(lhs);
+                let rhs_ty = self.get_expr_type(rhs);
+                let x = self.gen_value(lhs);
+                let y = self.gen_value(rhs);
+                let y = self.cast(y, &rhs_ty, &ty);
+                let signed = ty_is_signed(&ty);
+
+                let cmp = |signed_cc, unsigned_cc| {
+                    if signed {
+                        signed_cc
+                    } else {
+                        unsigned_cc
+                    }
+                };
+                let cc = match op {
+                    "==" => Some(IntCC::Equal),
+                    "!=" => Some(IntCC::NotEqual),
+                    "<" => Some(cmp(IntCC::SignedLessThan, IntCC::UnsignedLessThan)),
+                    "<=" => Some(cmp(
+                        IntCC::SignedLessThanOrEqual,
+                        IntCC::UnsignedLessThanOrEqual,
+                    )),
+                    ">" => Some(cmp(IntCC::SignedGreaterThan, IntCC::UnsignedGreaterThan)),
+                    ">=" => Some(cmp(
+                        IntCC::SignedGreaterThanOrEqual,
+                        IntCC::UnsignedGreaterThanOrEqual,
+                    )),
+                    _ => None,
+                };
+                if let Some(cc) = cc {
+                    let cond = self.builder.ins().icmp(cc, x, y);
+                    return Some(self.builder.ins().bint(types::I8, cond));
+                }
+
+                match op {
+                    "+" => self.builder.ins().iadd(x, y),
+                    "-" => self.builder.ins().isub(x, y),
+                    "*" => self.builder.ins().imul(x, y),
+                    "/" if signed => self.builder.ins().sdiv(x, y),
+                    "/" => self.builder.ins().udiv(x, y),
+                    "%" if signed => self.builder.ins().srem(x, y),
+                    "%" => self.builder.ins().urem(x, y),
+                    "&" => self.builder.ins().band(x, y),
+                    "|" => self.builder.ins().bor(x, y),
+                    "^" => self.builder.ins().bxor(x, y),
+                    "<<" => self.builder.ins().ishl(x, y),
+                    ">>" if signed => self.builder.ins().sshr(x, y),
+                    ">>" | ">>>" => self.builder.ins().ushr(x, y),
+                    _ => unsupported!(format!("binary operator `{}`", op), expr.pos),
+                }
+            }
+            ExprKind::Call(path, this, args) => {
+                if this.is_some() {
+                    unsupported!("method call", expr.pos);
+                }
+                return self.gen_call(expr, path.name(), args);
+            }
+            _ => unsupported!(format!("expression `{}`", expr), expr.pos),
+        };
+
+        Some(val)
+    }
+
+    /// Short-circuit `&&` and `||`
+    fn gen_logical(&mut self, op: &str, lhs: &Expr, rhs: &Expr) -> Value {
+        let rhs_ebb = self.builder.create_ebb();
+        let merge = self.builder.create_ebb();
+        self.builder.append_ebb_param(merge, types::I8);
+
+        let x = self.gen_value(lhs);
+        if op == "&&" {
+            self.builder.ins().brz(x, merge, &[x]);
+        } else {
+            self.builder.ins().brnz(x, merge, &[x]);
+        }
+        self.builder.ins().jump(rhs_ebb, &[]);
+
+        self.switch_to(rhs_ebb);
+        let y = self.gen_value(rhs);
+        self.builder.ins().jump(merge, &[y]);
+
+        self.switch_to(merge);
+        self.builder.ebb_params(merge)[0]
+    }
+
+    fn gen_call(&mut self, expr: &Expr, name: Name, args: &[Box<Expr>]) -> Option<Value> {
+        let arg_types = args
+            .iter()
+            .map(|arg| self.get_expr_type(arg))
+            .collect::<Vec<_>>();
+        let functions = self.functions;
+        let units = match functions.get(&name) {
+            Some(units) => units,
+            None => error!(format!("function `{}` not found", name), expr.pos),
+        };
+        let unit = units
+            .iter()
+            .find(|unit| {
+                unit.f.params.len() == args.len()
+                    && unit
+                        .f
+                        .params
+                        .iter()
+                        .zip(arg_types.iter())
+                        .all(|((_, param), arg)| **param == *arg)
+            })
+            .or_else(|| units.iter().find(|unit| unit.f.params.len() == args.len()));
+        let unit = match unit {
+            Some(unit) => unit,
+            None => error!(
+                format!("no overload of `{}` takes {} arguments", name, args.len()),
+                expr.pos
+            ),
+        };
+
+        let mut values = vec![];
+        for (arg, ((_, param), arg_ty)) in args
+            .iter()
+            .zip(unit.f.params.iter().zip(arg_types.iter()))
+        {
+            let val = self.gen_value(arg);
+            values.push(self.cast(val, arg_ty, param));
+        }
+
+        let callee = self
+            .module
+            .declare_func_in_func(unit.id, &mut self.builder.func);
+        let call = self.builder.ins().call(callee, &values);
+
+        self.builder.inst_results(call).first().cloned()
+    }
+}
@@ -0,0 +1,137 @@
+//! Lowering of syntax sugar into constructs backends already handle.
+//!
+//! Runs after semantic pass, so types of nodes created here are recorded in `Context::types`
+//! directly instead of checking them again.
+
+use crate::{
+    ast::{visit::*, *},
+    gen_id, intern,
+    syntax::interner::Name,
+    Context, Position,
+};
+use std::collections::HashMap;
+
+/// Replace every `operand?` in program with
+///
+/// ```text
+/// loop {
+///     var _try_N_ = operand;
+///     if !_try_N_.ok {
+///         return Result<T, E> { ok: false, error: _try_N_.error };
+///     }
+///     break _try_N_.value;
+/// }
+/// ```
+///
+/// `loop` expression keeps `?` usable anywhere expression is allowed (conditions of loops too)
+/// and evaluation order of surrounding expression intact.
+pub fn lower_try(ctx: &mut Context) {
+    let mut lowering = TryLowering {
+        types: &mut ctx.types,
+        ret: None,
+        tmp_id: 0,
+    };
+    for elem in ctx.file.elems.iter_mut() {
+        lowering.fold_elem(elem);
+    }
+}
+
+struct TryLowering<'a> {
+    types: &'a mut HashMap<NodeId, Type>,
+    /// return type of function being lowered
+    ret: Option<Type>,
+    tmp_id: usize,
+}
+
+impl TryLowering<'_> {
+    fn expr(&mut self, pos: Position, kind: ExprKind, ty: Type) -> Box<Expr> {
+        let id = gen_id();
+        self.types.insert(id, ty);
+        box Expr { id, pos, kind }
+    }
+
+    fn stmt(&mut self, pos: Position, kind: StmtKind) -> Box<Stmt> {
+        box Stmt {
+            id: gen_id(),
+            pos,
+            kind,
+        }
+    }
+
+    /// `tmp.field` where `tmp` holds value of `result` type
+    fn field(
+        &mut self,
+        pos: Position,
+        tmp: Name,
+        result: &Type,
+        field: &str,
+        ty: Type,
+    ) -> Box<Expr> {
+        let tmp = self.expr(pos, ExprKind::Ident(tmp), result.clone());
+        self.expr(pos, ExprKind::Field(tmp, intern(field)), ty)
+    }
+
+    fn lower(&mut self, operand: Box<Expr>, pos: Position) -> Box<Stmt> {
+        let result = self.types[&operand.id].clone();
+        let (value, error) = {
+            let (value, error) = result.to_struct().unwrap().result_types().unwrap();
+            (value.clone(), error.clone())
+        };
+        let ret = self.ret.clone().unwrap();
+        let ret_name = ret.to_struct().unwrap().name;
+        let bool_ty = Type::create_basic(gen_id(), pos, intern("bool"));
+        let tmp = intern(&format!("_try_{}_", self.tmp_id));
+        self.tmp_id += 1;
+
+        let var = self.stmt(
+            pos,
+            StmtKind::Var(tmp, false, Some(result.clone()), Some(operand)),
+        );
+        self.types.insert(var.id, result.clone());
+
+        let ok = self.field(pos, tmp, &result, "ok", bool_ty.clone());
+        let failed = self.expr(pos, ExprKind::Unary("!".to_owned(), ok), bool_ty.clone());
+        let ok = self.expr(pos, ExprKind::Bool(false), bool_ty);
+        let err = self.field(pos, tmp, &result, "error", error);
+        let args = vec![
+            StructArg {
+                id: gen_id(),
+                name: intern("ok"),
+                pos,
+                expr: ok,
+            },
+            StructArg {
+                id: gen_id(),
+                name: intern("error"),
+                pos,
+                expr: err,
+            },
+        ];
+        let early = self.expr(pos, ExprKind::Struct(Path::new(ret_name), args), ret);
+        let early = self.stmt(pos, StmtKind::Return(Some(early)));
+        let check = self.stmt(pos, StmtKind::If(failed, early, None));
+
+        let value = self.field(pos, tmp, &result, "value", value);
+        let unwrap = self.stmt(pos, StmtKind::Break(1, Some(value)));
+
+        self.stmt(pos, StmtKind::Block(vec![var, check, unwrap]))
+    }
+}
+
+impl Folder for TryLowering<'_> {
+    fn fold_function(&mut self, func: &mut Function) {
+        self.ret = Some((*func.ret).clone());
+        walk_function_mut(self, func);
+    }
+
+    fn fold_expr(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+        if let ExprKind::Try(_) = &expr.kind {
+            let operand = match std::mem::replace(&mut expr.kind, ExprKind::Null) {
+                ExprKind::Try(operand) => operand,
+                _ => unreachable!(),
+            };
+            expr.kind = ExprKind::Loop(self.lower(operand, expr.pos));
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
 
 use self::Msg::*;
-use crate::syntax::position::Position;
+use crate::syntax::position::{source_offset, Position};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Msg {
@@ -65,6 +65,8 @@ pub enum Msg {
     UnclosedChar,
     UnclosedString,
     NumberOverflow(String),
+    InvalidNumberSuffix(String),
+    InvalidNumberSeparator,
     ExpectedClass(String),
     ExpectedFactor(String),
     ExpectedToken(String, String),
@@ -77,7 +79,10 @@ pub enum Msg {
     ExpectedClassElement(String),
     RedundantModifier(String),
     MisplacedModifier(String),
+    MisplacedAttribute(String),
     InvalidEscapeSequence(char),
+    InvalidHexEscape,
+    InvalidUnicodeEscape,
     MissingFctBody,
     FctCallExpected,
     ThisOrSuperExpected(String),
@@ -110,9 +115,17 @@ pub enum Msg {
     MissingAbstractOverride(String, String),
     ModifierNotAllowedForStaticMethod(String),
     GlobalInitializerNotSupported,
+    InlineExpectsCall,
+    InvalidBreakDepth,
+    UnknownLabel(String),
+    UnknownMacro(String),
+    MacroArgsCount(String, usize, usize),
+    ConcatIdentsExpectsIdents,
+    InvalidEnumDiscriminant(String),
     MakeIteratorReturnType(String),
     UnknownStructField(String, String),
     StructFieldNotInitialized(String, String),
+    BlockValueExpected,
 }
 
 impl Msg {
@@ -237,12 +250,15 @@ pub fn message(&self) -> String {
             MisplacedElse => "misplace else.".into(),
             ExpectedToken(ref exp, ref got) => format!("expected {} but got {}.", exp, got),
             NumberOverflow(ref ty) => format!("number does not fit into type {}.", ty),
+            InvalidNumberSuffix(ref suffix) => format!("invalid number suffix `{}`.", suffix),
+            InvalidNumberSeparator => "`_` is only allowed between digits of number.".into(),
             ExpectedClass(ref cls) => format!("expected class name but got {}.", cls),
             ExpectedFactor(ref got) => format!("factor expected but got {}.", got),
             ExpectedTrait(ref trt) => format!("expected trait name but got {}.", trt),
             ExpectedType(ref got) => format!("type expected but got {}.", got),
             ExpectedIdentifier(ref tok) => format!("identifier expected but got {}.", tok),
             MisplacedModifier(ref modifier) => format!("misplaced modifier `{}`.", modifier),
+            MisplacedAttribute(ref attr) => format!("misplaced attribute `@{}`.", attr),
             ExpectedTopLevelElement(ref token) => format!(
                 "expected function,structure,global variable or constexpr but got {}.",
                 token
@@ -254,6 +270,10 @@ pub fn message(&self) -> String {
             UnknownChar(ch) => format!("unknown character {} (codepoint {}).", ch, ch as usize),
             UnclosedComment => "unclosed comment.".into(),
             InvalidEscapeSequence(ch) => format!("unknown escape sequence `\\{}`.", ch),
+            InvalidHexEscape => "`\\x` expects two hex digits not greater than `7F`.".into(),
+            InvalidUnicodeEscape => {
+                "`\\u` expects code point written as `{...}` with 1 to 6 hex digits.".into()
+            }
             UnclosedString => "unclosed string.".into(),
             UnclosedChar => "unclosed char.".into(),
             IoError => "error reading from file.".into(),
@@ -335,6 +355,20 @@ pub fn message(&self) -> String {
             ModifierNotAllowedForStaticMethod(ref modifier) => {
                 format!("modifier `{}` not allowed for static method.", modifier)
             }
+            InlineExpectsCall => "`inline` expects function call.".into(),
+            InvalidBreakDepth => "loop depth must be positive integer.".into(),
+            UnknownLabel(ref name) => format!("no enclosing loop is labeled `{}`.", name),
+            UnknownMacro(ref name) => format!("unknown macro `{}!`.", name),
+            MacroArgsCount(ref name, expected, got) => format!(
+                "macro `{}!` expects {} argument(s) but got {}.",
+                name, expected, got
+            ),
+            ConcatIdentsExpectsIdents => {
+                "`concat_idents!` expects identifiers separated by commas.".into()
+            }
+            InvalidEnumDiscriminant(ref got) => {
+                format!("enum discriminant must be integer literal but got {}.", got)
+            }
             GlobalInitializerNotSupported => {
                 "global variables do no support initial assignment for now.".into()
             }
@@ -348,6 +382,7 @@ pub fn message(&self) -> String {
             StructFieldNotInitialized(ref struc, ref field) => {
                 format!("field `{}` in struct `{}` not initialized.", field, struc)
             }
+            BlockValueExpected => "block expression must end with expression.".into(),
         }
     }
 }
@@ -370,6 +405,12 @@ pub fn new(path: String, src: String, pos: Position, msg: Msg) -> MsgWithPos {
         }
     }
 
+    /// Source from error position to end of its line
+    fn rest_of_line(&self) -> &str {
+        let start = source_offset(&self.src, self.pos);
+        self.src[start..].lines().next().unwrap_or("")
+    }
+
     pub fn message(&self) -> String {
         use colored::*;
         if self.path.is_empty() {
@@ -377,18 +418,16 @@ pub fn message(&self) -> String {
                 "{} at {}: {}\n| {}",
                 "error".red(),
                 self.pos,
-                self.msg.message(),
-                &self.src.lines().nth(self.pos.line as usize - 1).unwrap()
-                    [self.pos.column as usize - 1..]
+                self.msg.message().as_str().bold(),
+                self.rest_of_line()
             )
         } else {
             format!(
                 "{} {}: {}\n|\n| {}\n|",
                 "error".red(),
                 self.pos,
-                self.msg.message(),
-                &self.src.lines().nth(self.pos.line as usize - 1).unwrap()
-                    [self.pos.column as usize - 1..]
+                self.msg.message().as_str().bold(),
+                self.rest_of_line()
             )
         }
     }
@@ -408,3 +447,79 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         write!(f, "{}", self.message())
     }
 }
+
+/// When diagnostics are rendered with ANSI colors
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorChoice {
+    /// Colorize only if stderr is a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<ColorChoice, &'static str> {
+        match &*s.to_lowercase() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err("expected auto, always or never"),
+        }
+    }
+}
+
+extern "C" {
+    fn isatty(fd: std::os::raw::c_int) -> std::os::raw::c_int;
+}
+
+impl ColorChoice {
+    /// Enable or disable colors for every diagnostic printed after this call
+    pub fn apply(self) {
+        let enabled = match self {
+            ColorChoice::Auto => unsafe { isatty(2) != 0 },
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        };
+        colored::control::set_override(enabled);
+    }
+}
+
+thread_local! {
+    /// Set while diagnostics are caught by `catch_errors` instead of exiting the process
+    static CATCHING: std::cell::Cell<bool> = std::cell::Cell::new(false);
+}
+
+/// Diagnostic reported by `error!` while it was caught by `catch_errors`
+#[derive(Clone, Debug)]
+pub struct Caught {
+    pub msg: String,
+    pub pos: Position,
+}
+
+/// Whether `error!` should unwind to `catch_errors` instead of exiting
+pub fn catching() -> bool {
+    CATCHING.with(|catching| catching.get())
+}
+
+/// Unwind to enclosing `catch_errors` with diagnostic `msg` at `pos`
+pub fn unwind(msg: String, pos: impl std::borrow::Borrow<Position>) -> ! {
+    let pos = *pos.borrow();
+    // `resume_unwind` doesn't run panic hook, so nothing is printed
+    std::panic::resume_unwind(Box::new(Caught { msg, pos }))
+}
+
+/// Run `f` returning first error it reports with `error!` instead of exiting, so library API
+/// used by tooling keeps running. Other panics are propagated.
+pub fn catch_errors<T>(f: impl FnOnce() -> T) -> Result<T, Caught> {
+    let outer = CATCHING.with(|catching| catching.replace(true));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(outer));
+    match result {
+        Ok(val) => Ok(val),
+        Err(payload) => match payload.downcast::<Caught>() {
+            Ok(caught) => Err(*caught),
+            Err(payload) => std::panic::resume_unwind(payload),
+        },
+    }
+}
@@ -1,24 +1,35 @@
 use crate::Context as CContext;
 use gccjit_rs::{
     block::{BinaryOp, Block, ComparisonOp, UnaryOp},
-    ctx::{Context, GlobalKind, OutputKind},
+    ctx::{Context, GlobalKind, OptimizationLevel, OutputKind},
     field::Field,
     function::{Function as CFunction, FunctionType},
     lvalue::LValue,
+    parameter::Parameter,
     rvalue::{RValue, ToRValue},
     ty::Type as CType,
 };
 
 use crate::{
+    optimize::const_eval::{
+        eval_env_builtin, eval_file_builtin, included_file, ENV_BUILTINS, FILE_BUILTINS,
+        PTR_SIZE,
+    },
+    mangle::mangle,
+    semantic::{args_match, array_decays_to, VA_BUILTINS, VEC_BUILTINS},
     str,
     syntax::ast::{
-        Elem, Expr, ExprKind, Function, NodeId, Stmt, StmtKind, StructArg, StructField, Type,
+        visit::{walk_expr, walk_stmt, Visitor},
+        AttributeArg, Elem, Expr, ExprKind, Function, NodeId, Stmt, StmtKind, StructArg,
+        StructField, Type,
     },
 };
 
 use crate::syntax::interner::Name;
+use colored::Colorize;
+use linked_hash_map::LinkedHashMap;
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     ffi::CString,
 };
 
@@ -30,6 +41,63 @@ fn gccloc_from_loc(
     ctx.new_location(str(loc.file).to_string(), loc.line as _, loc.column as _)
 }
 
+/// Function attributes set via libgccjit's `gcc_jit_function_add_attribute`, which isn't
+/// wrapped by `gccjit_rs` yet
+#[allow(non_camel_case_types, dead_code)]
+#[repr(C)]
+#[derive(Copy, Clone)]
+enum gcc_jit_fn_attribute {
+    GCC_JIT_FN_ATTRIBUTE_ALIAS,
+    GCC_JIT_FN_ATTRIBUTE_ALWAYS_INLINE,
+    GCC_JIT_FN_ATTRIBUTE_INLINE,
+    GCC_JIT_FN_ATTRIBUTE_NOINLINE,
+    GCC_JIT_FN_ATTRIBUTE_TARGET,
+    GCC_JIT_FN_ATTRIBUTE_USED,
+    GCC_JIT_FN_ATTRIBUTE_VISIBILITY,
+    GCC_JIT_FN_ATTRIBUTE_COLD,
+    GCC_JIT_FN_ATTRIBUTE_RETURNS_TWICE,
+    GCC_JIT_FN_ATTRIBUTE_PURE,
+    GCC_JIT_FN_ATTRIBUTE_CONST,
+    GCC_JIT_FN_ATTRIBUTE_WEAK,
+    GCC_JIT_FN_ATTRIBUTE_NONNULL,
+}
+
+/// Thread-local storage models accepted by `gcc_jit_lvalue_set_tls_model`
+#[allow(non_camel_case_types, dead_code)]
+#[repr(C)]
+#[derive(Copy, Clone)]
+enum gcc_jit_tls_model {
+    GCC_JIT_TLS_MODEL_NONE,
+    GCC_JIT_TLS_MODEL_GLOBAL_DYNAMIC,
+    GCC_JIT_TLS_MODEL_LOCAL_DYNAMIC,
+    GCC_JIT_TLS_MODEL_INITIAL_EXEC,
+    GCC_JIT_TLS_MODEL_LOCAL_EXEC,
+}
+
+extern "C" {
+    fn gcc_jit_lvalue_set_tls_model(
+        lvalue: *mut gccjit_rs::sys::gcc_jit_lvalue,
+        model: gcc_jit_tls_model,
+    );
+    fn gcc_jit_lvalue_set_link_section(
+        lvalue: *mut gccjit_rs::sys::gcc_jit_lvalue,
+        section_name: *const std::os::raw::c_char,
+    );
+    fn gcc_jit_global_set_initializer(
+        global: *mut gccjit_rs::sys::gcc_jit_lvalue,
+        blob: *const std::os::raw::c_void,
+        num_bytes: usize,
+    ) -> *mut gccjit_rs::sys::gcc_jit_lvalue;
+    fn gcc_jit_function_add_attribute(
+        func: *mut gccjit_rs::sys::gcc_jit_function,
+        attribute: gcc_jit_fn_attribute,
+    );
+    fn gcc_jit_rvalue_set_bool_require_tail_call(
+        call: *mut gccjit_rs::sys::gcc_jit_rvalue,
+        require_tail_call: std::os::raw::c_int,
+    );
+}
+
 /// This type stores information about function such as gccjit and ast
 /// representation
 #[derive(Clone)]
@@ -41,6 +109,258 @@ pub struct FunctionUnit {
     pub this_ast: Option<Type>,
     pub this_ir: Option<CType>,
     pub irname: String,
+    /// Always-inline copy of function used by `inline f(...)` calls, created only if function is
+    /// called that way
+    pub inline_c: Option<CFunction>,
+}
+
+/// Collects names of functions called with `inline f(...)`
+#[derive(Default)]
+struct InlineCalls(HashSet<Name>);
+
+impl Visitor for InlineCalls {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let ExprKind::Inline(call) = &expr.kind {
+            if let ExprKind::Call(path, ..) = &call.kind {
+                self.0.insert(path.name());
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// gccjit optimization level for `-O<level>`, `None` if `level` is out of range
+pub fn opt_level(level: u8) -> Option<OptimizationLevel> {
+    match level {
+        0 => Some(OptimizationLevel::None),
+        1 => Some(OptimizationLevel::Limited),
+        2 => Some(OptimizationLevel::Standard),
+        3 => Some(OptimizationLevel::Aggressive),
+        _ => None,
+    }
+}
+
+/// Output of gcc driver that libgccjit invokes for AOT compilation, `None` if it can't be run
+fn gcc_driver(args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new("gcc").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Target triple gccjit generates code for
+pub fn target_triple() -> Option<String> {
+    gcc_driver(&["-dumpmachine"]).map(|triple| triple.trim().to_owned())
+}
+
+/// Size of pointers on target gccjit generates code for, `None` if gcc doesn't report it
+pub fn target_ptr_size() -> Option<usize> {
+    const DEFINE: &str = "#define __SIZEOF_POINTER__ ";
+    let defines = gcc_driver(&["-dM", "-E", "-x", "c", "/dev/null"])?;
+    defines
+        .lines()
+        .find(|line| line.starts_with(DEFINE))
+        .and_then(|line| line[DEFINE.len()..].trim().parse().ok())
+}
+
+/// Target features enabled for host CPU (`-march=native`), named as their `-m` options are
+pub fn native_features() -> Vec<String> {
+    let help = gcc_driver(&["-march=native", "-Q", "--help=target"]).unwrap_or_default();
+    help.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some(option), Some("[enabled]")) if option.starts_with("-m") => {
+                    Some(option[2..].to_owned())
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Smallest multiple of `align` that is at least `size`
+fn round_up(size: usize, align: usize) -> usize {
+    (size + align - 1) / align * align
+}
+
+/// Aggregates are stored and passed by value as is: gccjit rejects casts of structs, arrays and
+/// vectors, and their layout (lane count, alignment) is already fixed by the type
+fn is_aggregate(ty: &Type) -> bool {
+    ty.is_struct() || ty.is_array() || ty.is_vec()
+}
+
+/// Collects names read by global initializer, globals among them must be initialized first
+#[derive(Default)]
+struct Idents(Vec<Name>);
+
+impl Visitor for Idents {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let ExprKind::Ident(name) = &expr.kind {
+            self.0.push(*name);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Whether `a` and `b` are declarations of same function
+fn same_signature(a: &Function, b: &Function) -> bool {
+    a.name == b.name
+        && a.params == b.params
+        && a.ret == b.ret
+        && a.variadic == b.variadic
+        && a.this == b.this
+}
+
+/// Finds `break` that leaves loop whose body is visited, `loops` counts loops nested in body
+#[derive(Default)]
+struct EscapingBreak {
+    loops: usize,
+    found: bool,
+}
+
+impl Visitor for EscapingBreak {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Break(depth, _) if *depth > self.loops => self.found = true,
+            StmtKind::Loop(_) | StmtKind::While(..) | StmtKind::CFor(..) => {
+                self.loops += 1;
+                walk_stmt(self, stmt);
+                self.loops -= 1;
+                return;
+            }
+            _ => (),
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let ExprKind::Loop(_) = &expr.kind {
+            self.loops += 1;
+            walk_expr(self, expr);
+            self.loops -= 1;
+        } else {
+            walk_expr(self, expr);
+        }
+    }
+}
+
+/// Whether control never leaves `stmt` normally: it's `loop` or `while true` without `break`
+/// out of it, or block ending with such loop
+fn never_falls_through(stmt: &Stmt) -> bool {
+    let body = match &stmt.kind {
+        StmtKind::Loop(body) => body,
+        StmtKind::While(cond, body) => match &cond.kind {
+            ExprKind::Bool(true) => body,
+            _ => return false,
+        },
+        StmtKind::Block(stmts) => return stmts.last().map_or(false, |s| never_falls_through(s)),
+        StmtKind::CompTime(stmt) => return never_falls_through(stmt),
+        _ => return false,
+    };
+    let mut breaks = EscapingBreak::default();
+    breaks.visit_stmt(body);
+    !breaks.found
+}
+
+/// Finds what may leave pointer into frame of function for its callee: taken addresses,
+/// aggregates (arrays decay to pointers) and receivers passed by address
+struct FrameEscapes<'a> {
+    types: &'a HashMap<NodeId, Type>,
+    found: bool,
+}
+
+impl Visitor for FrameEscapes<'_> {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if let StmtKind::Var(_, _, ty, init) = &stmt.kind {
+            let ty = ty
+                .as_ref()
+                .or_else(|| init.as_ref().and_then(|init| self.types.get(&init.id)));
+            self.found |= ty.map_or(false, is_aggregate);
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::AddressOf(_)
+            | ExprKind::Array(..)
+            | ExprKind::Slice(..)
+            | ExprKind::Call(_, Some(_), _) => self.found = true,
+            _ => (),
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Whether calls of `func` from itself in tail position can reuse its frame: nothing in its
+/// body may point into the frame and its parameters and result are passed in registers
+fn allows_tail_calls(func: &Function, types: &HashMap<NodeId, Type>) -> bool {
+    let body = match &func.body {
+        Some(body) => body,
+        None => return false,
+    };
+    let mut escapes = FrameEscapes {
+        types,
+        found: false,
+    };
+    escapes.visit_stmt(body);
+    !escapes.found
+        && func.this.is_none()
+        && !func.variadic
+        && !is_aggregate(&func.ret)
+        && func.params.iter().all(|(_, param)| !is_aggregate(param))
+}
+
+/// Value range of signed integer type up to 64 bits, `__int128` can hold results of arithmetic
+/// on them
+fn signed_range(ty: &Type) -> Option<(i64, i64)> {
+    match ty {
+        Type::Basic(basic) => match str(basic.name).as_str() {
+            "i8" => Some((i8::MIN as i64, i8::MAX as i64)),
+            "i16" => Some((i16::MIN as i64, i16::MAX as i64)),
+            "i32" => Some((i32::MIN as i64, i32::MAX as i64)),
+            "i64" | "isize" => Some((i64::MIN, i64::MAX)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Method that only reads or writes single field of its receiver: `return this.field` or
+/// `this.field = param` (optionally followed by bare `return`). Call costs more than such body, so
+/// calls to it go to always-inline copy even at `-O0`.
+fn is_trivial_accessor(func: &Function) -> bool {
+    let this = match &func.this {
+        Some((this, _)) => *this,
+        None => return false,
+    };
+    let is_this_field = |expr: &Expr| match &expr.kind {
+        ExprKind::Field(base, _) => match &base.kind {
+            ExprKind::Ident(name) => *name == this,
+            _ => false,
+        },
+        _ => false,
+    };
+    let is_param = |expr: &Expr| match &expr.kind {
+        ExprKind::Ident(name) => func.params.iter().any(|(param, _)| param == name),
+        _ => false,
+    };
+    let stmts = match func.body.as_ref().map(|body| &body.kind) {
+        Some(StmtKind::Block(stmts)) => stmts.iter().map(|stmt| &stmt.kind).collect::<Vec<_>>(),
+        _ => return false,
+    };
+    match &stmts[..] {
+        [StmtKind::Return(Some(value))] => is_this_field(value),
+        [StmtKind::Expr(assign)] | [StmtKind::Expr(assign), StmtKind::Return(None)] => {
+            match &assign.kind {
+                ExprKind::Assign(to, value) => is_this_field(to) && is_param(value),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
 }
 
 /// Variable info that stores lvalue,type and gccjit type
@@ -49,6 +369,8 @@ pub struct VarInfo {
     pub lval: LValue,
     pub ty: Type,
     pub cty: CType,
+    /// `false` for `let`/`const` bindings and non-`var` globals
+    pub mutable: bool,
 }
 /// GccStruct stores gccjit struct type and information about fields
 #[derive(Clone)]
@@ -56,10 +378,25 @@ pub struct GccStruct {
     pub ty: CType,
     pub fields: HashMap<Name, Field>,
     pub types: Vec<Type>,
+    pub union: bool,
+    /// `@packed(N)`: fields are aligned to at most `N` bytes
+    pub packing: Option<usize>,
+    /// `@align(N)` of each field, parallel to `types`
+    pub aligns: Vec<Option<usize>>,
+    /// `@align(N)` of struct
+    pub align: Option<usize>,
 }
 
 use super::eval::Const;
 use std::{cell::RefCell, rc::Rc};
+/// Struct `{ ptr: *T, len: usize }` that slices `T[]` are lowered to
+#[derive(Clone, Copy)]
+pub struct SliceType {
+    pub ty: CType,
+    pub ptr: Field,
+    pub len: Field,
+}
+
 /// Main unit used in codegeneration.
 ///
 /// This unit performs translating AST into GIMPLE tree,after translation GCC
@@ -68,16 +405,21 @@ pub struct Codegen<'a> {
     pub ctx: Context,
     context: &'a mut CContext,
     continue_blocks: VecDeque<Block>,
-    break_blocks: VecDeque<Block>,
+    /// Loop exits, `loop` expressions also keep local that receives `break` value
+    break_blocks: VecDeque<(Block, Option<(LValue, Type)>)>,
     known_vars: HashMap<Name, Rc<RefCell<Const>>>,
     cur_func: Option<CFunction>,
     cur_block: Option<Block>,
     variables: HashMap<Name, VarInfo>,
-    globals: HashMap<Name, (VarInfo, Option<Box<Expr>>)>,
-    functions: HashMap<Name, Vec<FunctionUnit>>,
-    external_functions: HashMap<Name, FunctionUnit>,
-    const_functions: HashMap<Name, Vec<Function>>,
-    structures: HashMap<Name, GccStruct>,
+    /// Symbols are kept in declaration order, so iterating them (e.g to initialize globals)
+    /// gives same output on every run
+    globals: LinkedHashMap<Name, (VarInfo, Option<Box<Expr>>)>,
+    functions: LinkedHashMap<Name, Vec<FunctionUnit>>,
+    external_functions: LinkedHashMap<Name, FunctionUnit>,
+    const_functions: LinkedHashMap<Name, Vec<Function>>,
+    structures: LinkedHashMap<Name, GccStruct>,
+    /// Slice structs by element type, every slice of same elements must have same gccjit type
+    slices: HashMap<String, SliceType>,
     constants: HashMap<Name, Expr>,
     block_id: usize,
     fun_id: usize,
@@ -85,6 +427,29 @@ pub struct Codegen<'a> {
     tmp_id: usize,
     terminated: Vec<bool>,
     cur_return: Option<Type>,
+    /// Calls of current function from itself in tail position reuse its frame, see
+    /// `allows_tail_calls`
+    tail_calls: bool,
+    /// (symbol, versioned name) pairs collected from `@symver` attributes
+    symvers: Vec<(String, String)>,
+    /// libc functions declared for runtime checks
+    runtime_functions: HashMap<String, CFunction>,
+    /// Set by `inline f(...)`, makes next generated call use always-inline copy of callee
+    force_inline: bool,
+    /// Statements registered by `defer` in each enclosing block of current function
+    defers: Vec<Vec<Stmt>>,
+    /// Number of blocks with defers enclosing each loop, parallel to `break_blocks`
+    loop_defers: Vec<usize>,
+    /// Never written globals, zero values of aggregate types are copied from them
+    zeroes: HashMap<String, LValue>,
+    /// Globals holding contents of string literals emitted so far
+    strings: HashMap<String, LValue>,
+    /// Storage and init flag of each `static` local, shared by every body generated for its
+    /// function (e.g always-inline copy)
+    statics: HashMap<NodeId, (LValue, Option<LValue>)>,
+    /// Slots with variadic arguments of current function and cursor `va_arg` reads next one
+    /// from, see `va_slots`
+    va_list: Option<(RValue, LValue)>,
 }
 
 impl<'a> Codegen<'a> {
@@ -105,20 +470,16 @@ pub fn ty_size(&self, ty: &Type) -> usize {
                     "u32" => 4,
                     "i64" => 8,
                     "u64" => 8,
+                    "i128" => 16,
+                    "u128" => 16,
                     "f32" => 4,
                     "f64" => 8,
                     "bool" => 1,
                     "usize" => 8,
                     s => {
                         let interned = crate::syntax::interner::intern(s);
-                        if self.structures.contains_key(&interned) {
-                            let structure = self.structures.get(&interned).unwrap().clone();
-                            let mut size = 0;
-                            for field in structure.types.iter() {
-                                size += self.ty_size(field)
-                            }
-
-                            size
+                        if let Some(structure) = self.structures.get(&interned) {
+                            self.struct_layout(structure).0
                         } else if let Some(ty) = self
                             .aliases
                             .get(&crate::syntax::interner::intern(s))
@@ -131,26 +492,121 @@ pub fn ty_size(&self, ty: &Type) -> usize {
                     }
                 }
             }
-            Type::Ptr(_ptr) => 8,
-            Type::Func(_tyfunc) => 8,
+            Type::Ptr(_) | Type::Func(_) => PTR_SIZE,
             Type::Struct(structure) => {
                 let structure = self.structures.get(&structure.name).unwrap();
-                let mut size = 0;
-                for field in structure.types.iter() {
-                    size += self.ty_size(field)
-                }
-
-                size
+                self.struct_layout(structure).0
             }
             Type::Array(array) => {
                 if array.len.is_some() {
-                    self.ty_size(&array.subtype) * array.len.unwrap() as usize
+                    let len = array.len.unwrap();
+                    match self.ty_size(&array.subtype).checked_mul(len) {
+                        Some(size) => size,
+                        None => error!(format!("size of type {} overflows", ty), array.pos),
+                    }
+                } else {
+                    // pointer and length
+                    2 * PTR_SIZE
+                }
+            }
+        }
+    }
+
+    /// Alignment of type in bytes, matches layout gcc gives to the type
+    pub fn ty_align(&self, ty: &Type) -> usize {
+        match ty {
+            Type::Vector(_) => self.ty_size(ty),
+            Type::Void(_) => 1,
+            Type::Ptr(_) | Type::Func(_) => PTR_SIZE,
+            Type::Array(array) if array.len.is_some() => self.ty_align(&array.subtype),
+            Type::Array(_) => PTR_SIZE,
+            Type::Struct(structure) => self.struct_layout(&self.structures[&structure.name]).1,
+            Type::Basic(basic) => {
+                if let Some(structure) = self.structures.get(&basic.name) {
+                    self.struct_layout(structure).1
+                } else if let Some(ty) = self.aliases.get(&basic.name) {
+                    self.ty_align(ty)
                 } else {
-                    8
+                    // primitive types are aligned to their size
+                    self.ty_size(ty)
                 }
             }
         }
     }
+
+    /// Size and alignment of struct: fields are placed in order, each at offset that is multiple
+    /// of its alignment (capped by packing, raised by `@align`), and size is rounded up to
+    /// alignment of struct
+    fn struct_layout(&self, structure: &GccStruct) -> (usize, usize) {
+        let (_, size, align) = self.field_offsets(structure);
+        (size, align)
+    }
+
+    /// Offsets of fields of struct in order, then its size and alignment
+    fn field_offsets(&self, structure: &GccStruct) -> (Vec<usize>, usize, usize) {
+        let mut offsets = vec![];
+        let mut size = 0;
+        let mut align = structure.align.unwrap_or(1);
+        for (field, forced) in structure.types.iter().zip(structure.aligns.iter()) {
+            let field_align = match structure.packing {
+                Some(packing) => self.ty_align(field).min(packing),
+                None => self.ty_align(field),
+            };
+            let field_align = field_align.max(forced.unwrap_or(1));
+            let field_size = self.ty_size(field);
+            align = align.max(field_align);
+            let offset = if structure.union {
+                0
+            } else {
+                round_up(size, field_align)
+            };
+            offsets.push(offset);
+            size = size.max(offset + field_size);
+        }
+        (offsets, round_up(size, align), align)
+    }
+
+    /// gccjit type of field of struct packed to `packing`: alignment of type is lowered to
+    /// packing, then raised to `@align(N)` of field
+    fn field_ctype(&mut self, field: &StructField, packing: Option<usize>) -> CType {
+        let cty = self.ty_to_ctype(&field.data_type);
+        let natural = self.ty_align(&field.data_type);
+        let align = packing.map_or(natural, |packing| natural.min(packing));
+        let align = align.max(field.align().unwrap_or(1));
+        if align == natural {
+            cty
+        } else {
+            self.aligned_ctype(cty, align)
+        }
+    }
+
+    /// Variant of `ty` aligned to `align` bytes
+    fn aligned_ctype(&self, ty: CType, align: usize) -> CType {
+        use gccjit_rs::sys::*;
+        unsafe {
+            let ptr = gccjit_rs::ty::get_ptr(&ty);
+            gccjit_rs::ty::from_ptr(gcc_jit_type_get_aligned(ptr, align as _))
+        }
+    }
+
+    /// Type `ty` created for `structure`, with `@align` of struct applied
+    fn struct_ctype(&self, structure: &GccStruct, ty: CType) -> CType {
+        match structure.align {
+            Some(_) => self.aligned_ctype(ty, self.struct_layout(structure).1),
+            None => ty,
+        }
+    }
+
+    /// `__int128` type, it has no Rust counterpart implementing `Typeable` so it's requested by
+    /// size
+    fn int128_type(&self, signed: bool) -> CType {
+        use gccjit_rs::sys::*;
+        unsafe {
+            let ptr = gccjit_rs::ctx::context_get_ptr(&self.ctx);
+            gccjit_rs::ty::from_ptr(gcc_jit_context_get_int_type(ptr, 16, signed as _))
+        }
+    }
+
     /// Convert AST type into GCC type
     pub fn ty_to_ctype(&mut self, ty: &Type) -> CType {
         let ctx = self.ctx;
@@ -168,6 +624,8 @@ pub fn ty_to_ctype(&mut self, ty: &Type) -> CType {
                     "i64" => self.ctx.new_vector_type::<i64>(v.size),
                     "i32" => self.ctx.new_vector_type::<i32>(v.size),
                     "usize" => self.ctx.new_vector_type::<usize>(v.size),
+                    "f32" => self.ctx.new_vector_type::<f32>(v.size),
+                    "f64" => self.ctx.new_vector_type::<f64>(v.size),
                     _ => unimplemented!(),
                 }
             }
@@ -184,6 +642,8 @@ pub fn ty_to_ctype(&mut self, ty: &Type) -> CType {
                     "u32" => ctx.new_type::<u32>(),
                     "i64" => ctx.new_type::<i64>(),
                     "u64" => ctx.new_type::<u64>(),
+                    "i128" => self.int128_type(true),
+                    "u128" => self.int128_type(false),
                     "f32" => ctx.new_type::<f32>(),
                     "f64" => ctx.new_type::<f64>(),
                     "bool" => ctx.new_type::<bool>(),
@@ -224,7 +684,7 @@ pub fn ty_to_ctype(&mut self, ty: &Type) -> CType {
                     let mut types = vec![];
                     for field in struct_.fields.iter() {
                         let field: &StructField = field;
-                        let cty = self.ty_to_ctype(&field.data_type).clone();
+                        let cty = self.field_ctype(field, struct_.packing());
                         types.push(field.data_type.clone());
                         let name: &str = &str(field.name).to_string();
                         let cfield = self.ctx.new_field(
@@ -243,24 +703,39 @@ pub fn ty_to_ctype(&mut self, ty: &Type) -> CType {
                             .new_struct_type(None, &str(struct_.name).to_string(), &fields)
                             .as_type()
                     };
-                    self.structures.insert(
-                        struct_.name,
-                        GccStruct {
-                            ty,
-                            fields: cfields,
-                            types,
-                        },
-                    );
+                    let mut cstruct = GccStruct {
+                        ty,
+                        fields: cfields,
+                        types,
+                        union: struct_.union,
+                        packing: struct_.packing(),
+                        aligns: struct_.fields.iter().map(StructField::align).collect(),
+                        align: struct_.align(),
+                    };
+                    cstruct.ty = self.struct_ctype(&cstruct, ty);
+                    let ty = cstruct.ty;
+                    self.structures.insert(struct_.name, cstruct);
                     ty
                 }
             }
             Type::Array(array) => {
                 if array.len.is_some() {
                     let len = *array.len.as_ref().unwrap();
+                    // gccjit takes length of array as `int`
+                    if len > i32::max_value() as usize {
+                        error!(
+                            format!(
+                                "array length {} is too large, at most {} elements are supported",
+                                len,
+                                i32::max_value()
+                            ),
+                            array.pos
+                        );
+                    }
 
                     ctx.new_array_type(None, self.ty_to_ctype(&array.subtype), len as i32)
                 } else {
-                    self.ty_to_ctype(&array.subtype).make_pointer()
+                    self.slice_type(&array.subtype).ty
                 }
             }
         }
@@ -268,10 +743,16 @@ pub fn ty_to_ctype(&mut self, ty: &Type) -> CType {
     /// assign some value to lvalue
     fn assign(&mut self, pos: crate::syntax::position::Position, to: &Expr, from: &Expr) -> RValue {
         let lval = self.expr_to_lvalue(to).unwrap();
+        let type_ = self.get_expr_type(to);
+        let from_ty = self.get_expr_type(from);
+        if array_decays_to(&from_ty, &type_) {
+            let loc = Some(gccloc_from_loc(&self.ctx, &pos));
+            let addr = self.decay_array(from, &type_, loc);
+            self.cur_block.unwrap().add_assignment(loc, lval, addr);
+            return addr;
+        }
         let rval = self.gen_expr(from);
-        let type_ = self.get_id_type(to.id);
-        let _ = self.get_id_type(from.id);
-        let do_cast = match type_ {
+        let do_cast = match &type_ {
             Type::Basic(basic) => {
                 if self.structures.contains_key(&basic.name) {
                     false
@@ -285,16 +766,59 @@ fn assign(&mut self, pos: crate::syntax::position::Position, to: &Expr, from: &E
             _ => true,
         };
 
+        let loc = Some(gccloc_from_loc(&self.ctx, &pos));
         let val = if do_cast {
-            self.ctx.new_cast(None, rval, lval.to_rvalue().get_type())
+            self.convert(rval, &from_ty, &type_, None)
+        } else if self.may_alias(to, from) {
+            // aggregate is copied whole, source overlapping destination is read into temporary
+            // first so no part of it is overwritten before it's read
+            let tmp = self.cur_func.unwrap().new_local(
+                loc,
+                rval.get_type(),
+                &format!("_copy_{}_", self.tmp_id),
+            );
+            self.tmp_id += 1;
+            self.cur_block.unwrap().add_assignment(loc, tmp, rval);
+            tmp.to_rvalue()
         } else {
             rval
         };
-        self.cur_block
-            .unwrap()
-            .add_assignment(Some(gccloc_from_loc(&self.ctx, &pos)), lval, val);
+        self.cur_block.unwrap().add_assignment(loc, lval, val);
+
+        // value of assignment is value stored, reading it back doesn't evaluate `from` again
+        lval.to_rvalue()
+    }
 
-        rval
+    /// Variable `place` is part of, `None` if it may be reached through pointer or slice
+    fn place_var(&self, place: &Expr) -> Option<Name> {
+        match &place.kind {
+            ExprKind::Ident(name) => Some(*name),
+            ExprKind::Field(base, _) | ExprKind::ArrayIdx(base, _) => {
+                let ty = self.get_expr_type(base);
+                if ty.is_ptr() || ty.is_slice() {
+                    None
+                } else {
+                    self.place_var(base)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether reading `from` may read memory written by assignment to `to`. Only places may
+    /// overlap, other expressions produce new values.
+    fn may_alias(&self, to: &Expr, from: &Expr) -> bool {
+        match &from.kind {
+            ExprKind::Ident(_)
+            | ExprKind::Field(..)
+            | ExprKind::ArrayIdx(..)
+            | ExprKind::Deref(_) => (),
+            _ => return false,
+        }
+        match (self.place_var(to), self.place_var(from)) {
+            (Some(to), Some(from)) => to == from,
+            _ => true,
+        }
     }
     /// Search for func with params and this value
     fn search_for_func(
@@ -340,7 +864,7 @@ fn search_for_func(
                             sig_params.push(*p.clone());
                         }
 
-                        if sig_params == params {
+                        if args_match(params, &sig_params) {
                             return Some((
                                 function.c,
                                 function
@@ -363,7 +887,8 @@ fn search_for_func(
 
             for (index, param) in params.iter().enumerate() {
                 if index < function.f.params.len() {
-                    params_okay = param == &*function.f.params[index].1;
+                    let expected = &*function.f.params[index].1;
+                    params_okay = param == expected || array_decays_to(param, expected);
                 } else {
                     if function.f.variadic && params_okay {
                         not_found = false;
@@ -488,18 +1013,29 @@ pub fn new(context: &'a mut CContext, name: &str) -> Codegen<'a> {
             cur_block: None,
             cur_func: None,
             variables: HashMap::new(),
-            globals: HashMap::new(),
-            functions: HashMap::new(),
-            external_functions: HashMap::new(),
-            structures: HashMap::new(),
+            globals: LinkedHashMap::new(),
+            functions: LinkedHashMap::new(),
+            external_functions: LinkedHashMap::new(),
+            structures: LinkedHashMap::new(),
+            slices: HashMap::new(),
             constants: HashMap::new(),
             block_id: 0,
             fun_id: 0,
             aliases: HashMap::new(),
             tmp_id: 0,
             terminated: vec![],
-            const_functions: HashMap::new(),
+            const_functions: LinkedHashMap::new(),
             cur_return: None,
+            tail_calls: false,
+            symvers: vec![],
+            runtime_functions: HashMap::new(),
+            zeroes: HashMap::new(),
+            strings: HashMap::new(),
+            statics: HashMap::new(),
+            va_list: None,
+            force_inline: false,
+            defers: vec![],
+            loop_defers: vec![],
         }
     }
     /// Find struct type
@@ -544,8 +1080,8 @@ pub fn expr_to_lvalue(&mut self, expr: &Expr) -> Option<LValue> {
             }
             ExprKind::Binary(op, lhs, rhs) => {
                 let _op: &str = op;
-                let t1 = self.get_id_type(lhs.id);
-                let t2 = self.get_id_type(rhs.id);
+                let t1 = self.get_expr_type(lhs);
+                let t2 = self.get_expr_type(rhs);
 
                 if t1.is_ptr() && crate::semantic::ty_is_any_int(&t2) {
                     let array = self.gen_expr(lhs);
@@ -561,19 +1097,113 @@ pub fn expr_to_lvalue(&mut self, expr: &Expr) -> Option<LValue> {
                 }
             }
             ExprKind::ArrayIdx(array, index) => {
-                let array = self.gen_expr(array);
-                let index = self.gen_expr(index);
+                let array_ty = self.get_expr_type(array);
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                let mut slice_len = None;
+                let array = if let Some(vec) = array_ty.to_vec() {
+                    // gccjit can't index vectors, index pointer to their lanes instead
+                    let lane_ptr = self.ty_to_ctype(&vec.subtype).make_pointer();
+                    let vec = match self.expr_to_lvalue(array) {
+                        Some(lval) => lval,
+                        None => {
+                            let cty = self.ty_to_ctype(&array_ty);
+                            let tmp = self.cur_func.unwrap().new_local(
+                                loc,
+                                cty,
+                                &format!("_vec_{}_", self.tmp_id),
+                            );
+                            self.tmp_id += 1;
+                            let val = self.gen_expr(array);
+                            self.cur_block.unwrap().add_assignment(loc, tmp, val);
+                            tmp
+                        }
+                    };
+                    self.ctx.new_cast(loc, vec.get_address(loc), lane_ptr)
+                } else if array_ty.is_slice() {
+                    let (ptr, len) = self.slice_parts(array, loc);
+                    slice_len = Some(len);
+                    ptr
+                } else if array_ty.is_ptr() {
+                    let ptr = self.gen_expr(array);
+                    self.check_null(ptr, expr.pos)
+                } else {
+                    self.gen_expr(array)
+                };
+                let mut index = self.gen_expr(index);
+
+                let u64_ty = self.ctx.new_type::<u64>();
+                let bound = match array_ty.to_array().and_then(|array| array.len) {
+                    Some(len) => Some((
+                        self.ctx.new_rvalue_from_long(u64_ty, len as _),
+                        format!("array of length {}", len),
+                    )),
+                    None => slice_len.map(|len| {
+                        let len = self.ctx.new_cast(loc, len, u64_ty);
+                        (len, "slice of length %lu".to_owned())
+                    }),
+                };
+                if let (Some((len, what)), true) = (bound, self.context.debug_bounds) {
+                    // index is used twice, don't evaluate it twice
+                    let tmp = self.cur_func.unwrap().new_local(
+                        loc,
+                        self.ctx.new_type::<i64>(),
+                        &format!("_idx_{}_", self.tmp_id),
+                    );
+                    self.tmp_id += 1;
+                    let as_i64 = self.ctx.new_cast(loc, index, self.ctx.new_type::<i64>());
+                    self.cur_block.unwrap().add_assignment(loc, tmp, as_i64);
+                    index = tmp.to_rvalue();
+
+                    // negative index becomes huge when unsigned, so one comparison is enough
+                    let out_of_bounds = self.ctx.new_comparison(
+                        loc,
+                        ComparisonOp::GreaterThanEquals,
+                        self.ctx.new_cast(loc, index, u64_ty),
+                        len,
+                    );
+                    let args = if slice_len.is_some() {
+                        vec![index, len]
+                    } else {
+                        vec![index]
+                    };
+                    self.runtime_check(
+                        out_of_bounds,
+                        expr.pos,
+                        &format!("index %ld out of bounds for {}", what),
+                        &args,
+                    );
+                }
 
-                Some(self.ctx.new_array_access(
-                    Some(gccloc_from_loc(&self.ctx, &expr.pos)),
-                    array,
-                    index,
-                ))
+                Some(self.ctx.new_array_access(loc, array, index))
             }
             ExprKind::Field(object, name) => {
-                let ty: Type = self.get_id_type(object.id).clone();
+                let ty: Type = self.get_expr_type(object).clone();
 
+                if let (Some(array), true) = (ty.to_array(), ty.is_slice()) {
+                    let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                    let slice = self.slice_type(&array.subtype);
+                    let field = if str(*name).as_str() == "len" {
+                        slice.len
+                    } else {
+                        slice.ptr
+                    };
+                    let lval = self.slice_lvalue(object, loc);
+                    return Some(lval.access_field(loc, field));
+                }
                 if ty.is_ptr() {
+                    // `pp.field` with `pp: **T` dereferences until single pointer to struct left
+                    let mut lval = self.gen_expr(object);
+                    let mut ty = ty;
+                    while let Some(subtype) = ty.to_ptr().map(|ptr| *ptr.subtype.clone()) {
+                        lval = self.check_null(lval, expr.pos);
+                        if !subtype.is_ptr() {
+                            break;
+                        }
+                        lval = lval
+                            .dereference(Some(gccloc_from_loc(&self.ctx, &expr.pos)))
+                            .to_rvalue();
+                        ty = subtype;
+                    }
                     let ptr = ty.to_ptr().unwrap();
                     if ptr.subtype.is_struct() {
                         let struct_ = self
@@ -583,8 +1213,7 @@ pub fn expr_to_lvalue(&mut self, expr: &Expr) -> Option<LValue> {
                             .clone();
 
                         let cfield = struct_.fields.get(name).expect("Field not found");
-                        let lval = self.gen_expr(object);
-                        let _ast_ty = self.get_id_type(expr.id);
+                        let _ast_ty = self.get_expr_type(expr);
                         //let cty = self.ty_to_ctype(&ast_ty);
 
                         Some(lval.dereference_field(
@@ -595,7 +1224,6 @@ pub fn expr_to_lvalue(&mut self, expr: &Expr) -> Option<LValue> {
                         let struct_ = self.structures.get(&basic.name).unwrap().clone();
 
                         let cfield = struct_.fields.get(name).expect("Field not found");
-                        let lval = self.gen_expr(object);
 
                         //Some(lval.access_field(, *cfield))
                         return Some(lval.dereference_field(
@@ -627,6 +1255,7 @@ pub fn expr_to_lvalue(&mut self, expr: &Expr) -> Option<LValue> {
             }
             ExprKind::Deref(expr_) => {
                 let val = self.gen_expr(expr_);
+                let val = self.check_null(val, expr.pos);
 
                 Some(val.dereference(Some(gccloc_from_loc(&self.ctx, &expr.pos))))
             }
@@ -634,9 +1263,25 @@ pub fn expr_to_lvalue(&mut self, expr: &Expr) -> Option<LValue> {
             _ => None, // unimplemented or impossible to get lval
         }
     }
-    /// Get type by expression Id
-    fn get_id_type(&self, id: NodeId) -> Type {
-        self.context.types.get(&id).unwrap().clone()
+    /// Get type by node Id, reports error at `pos` if semantic pass didn't record type for this node
+    fn get_id_type(
+        &self,
+        id: NodeId,
+        pos: crate::syntax::position::Position,
+        what: impl FnOnce() -> String,
+    ) -> Type {
+        match self.context.types.get(&id) {
+            Some(ty) => ty.clone(),
+            None => error!(format!("type of {} is unknown", what()), pos),
+        }
+    }
+    /// Get type of expression, conversion has its target type even if semantic pass didn't
+    /// record it (e.g when created by optimization pass)
+    fn get_expr_type(&self, expr: &Expr) -> Type {
+        match &expr.kind {
+            ExprKind::Conv(_, to) if !self.context.types.contains_key(&expr.id) => (**to).clone(),
+            _ => self.get_id_type(expr.id, expr.pos, || format!("expression `{}`", expr)),
+        }
     }
     /// Create new name for block
     fn block_name_new(&mut self) -> String {
@@ -650,12 +1295,24 @@ pub fn gen_stmt(&mut self, stmt: &Stmt, init: bool) {
             StmtKind::CompTime(s) => self.gen_stmt(s, init),
 
             StmtKind::Expr(expr) => {
-                let rval = self.gen_expr(expr);
-                self.cur_block
-                    .unwrap()
-                    .add_eval(Some(gccloc_from_loc(&self.ctx, &expr.pos)), rval);
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                match &expr.kind {
+                    // store is already emitted, evaluating value read back from `to` would
+                    // repeat side effects of its index
+                    ExprKind::Assign(to, from) => {
+                        self.assign(expr.pos, to, from);
+                    }
+                    _ => {
+                        let rval = self.gen_expr(expr);
+                        self.cur_block.unwrap().add_eval(loc, rval);
+                    }
+                }
+                if self.calls_noreturn(expr) {
+                    self.end_unreachable(loc);
+                }
             }
             StmtKind::Block(stmts) => {
+                self.defers.push(vec![]);
                 if !init {
                     let old_block = self.cur_block;
                     let block_name = self.block_name_new();
@@ -665,34 +1322,74 @@ pub fn gen_stmt(&mut self, stmt: &Stmt, init: bool) {
                     for stmt in stmts.iter() {
                         self.gen_stmt(stmt, false);
                     }
+                    self.leave_block(stmts);
 
                     self.cur_block = old_block;
                 } else {
                     for stmt in stmts.iter() {
                         self.gen_stmt(stmt, false);
                     }
+                    self.leave_block(stmts);
                 }
             }
-            StmtKind::Break => {
-                let break_bb = if let Some(block) = self.break_blocks.back() {
-                    *block
-                } else {
-                    panic!("");
-                };
-                //let dead_block = self.cur_func.unwrap().new_block(self.block_name_new());
-
+            StmtKind::Static(var) => self.gen_static(var),
+            StmtKind::Defer(deferred) => {
+                self.defers.last_mut().unwrap().push((**deferred).clone());
+            }
+            StmtKind::Delete(ptr) => {
+                let loc = Some(gccloc_from_loc(&self.ctx, &stmt.pos));
+                let void_ptr = self.ctx.new_type::<*mut u8>();
+                let name = if self.context.gc { "GC_free" } else { "free" };
+                let void = self.ctx.new_type::<()>();
+                let free = self.runtime_function(name, void, &[void_ptr], false);
+                let ptr = self.gen_expr(ptr);
+                let ptr = self.ctx.new_cast(loc, ptr, void_ptr);
                 self.cur_block
                     .unwrap()
-                    .end_with_jump(Some(gccloc_from_loc(&self.ctx, &stmt.pos)), break_bb);
-                self.cur_block = Some(break_bb);
-                *self.terminated.last_mut().unwrap() = true;
+                    .add_eval(loc, self.ctx.new_call(loc, free, &[ptr]));
             }
-            StmtKind::Continue => {
-                let continue_bb = if let Some(block) = self.continue_blocks.back() {
-                    *block
-                } else {
-                    panic!("")
-                };
+            StmtKind::Break(depth, value) => {
+                let loc = Some(gccloc_from_loc(&self.ctx, &stmt.pos));
+                if *depth > self.break_blocks.len() {
+                    error!(
+                        format!(
+                            "`break {}` used inside of {} loops",
+                            depth,
+                            self.break_blocks.len()
+                        ),
+                        stmt.pos
+                    );
+                }
+                let (break_bb, target) = self.break_blocks[self.break_blocks.len() - depth].clone();
+                if let (Some(value), Some((local, ty))) = (value, target) {
+                    let val = self.gen_expr(value);
+                    let val = if !is_aggregate(&ty) {
+                        let cty = self.ty_to_ctype(&ty);
+                        self.ctx.new_cast(loc, val, cty)
+                    } else {
+                        val
+                    };
+                    self.cur_block.unwrap().add_assignment(loc, local, val);
+                }
+                self.run_defers(self.loop_defers[self.loop_defers.len() - depth]);
+                let dead_block = self.cur_func.unwrap().new_block(self.block_name_new());
+
+                self.cur_block.unwrap().end_with_jump(loc, break_bb);
+                self.cur_block = Some(dead_block);
+            }
+            StmtKind::Continue(depth) => {
+                if *depth > self.continue_blocks.len() {
+                    error!(
+                        format!(
+                            "`continue {}` used inside of {} loops",
+                            depth,
+                            self.continue_blocks.len()
+                        ),
+                        stmt.pos
+                    );
+                }
+                let continue_bb = self.continue_blocks[self.continue_blocks.len() - depth];
+                self.run_defers(self.loop_defers[self.loop_defers.len() - depth]);
                 let dead_block = self.cur_func.unwrap().new_block(self.block_name_new());
 
                 self.cur_block
@@ -701,15 +1398,48 @@ pub fn gen_stmt(&mut self, stmt: &Stmt, init: bool) {
                 self.cur_block = Some(dead_block);
             }
             StmtKind::Return(expr) => {
+                let has_defers = self.defers.iter().any(|scope| !scope.is_empty());
                 if expr.is_some() {
                     let expr = expr.as_ref().unwrap();
-                    let val = self.gen_expr(expr);
-                    //let ty = self.cur_return.as_ref().unwrap().clone();
+                    let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                    let ret = self.cur_return.clone().unwrap();
+                    let from = self.get_expr_type(expr);
+                    // value is converted to declared return type, e.g `return 5` from `i64`
+                    // function returns full-width value
+                    let mut val = if !is_aggregate(&from) && from != ret {
+                        let val = self.gen_expr(expr);
+                        self.convert(val, &from, &ret, loc)
+                    } else if !has_defers && self.is_self_call(expr) {
+                        // deep tail recursion runs in constant stack, e.g `return f(n - 1)`
+                        let call = self.gen_expr(expr);
+                        unsafe {
+                            gcc_jit_rvalue_set_bool_require_tail_call(
+                                gccjit_rs::rvalue::get_ptr(&call),
+                                1,
+                            );
+                        }
+                        call
+                    } else {
+                        self.gen_expr(expr)
+                    };
+                    if has_defers {
+                        // deferred statements may change what return value is computed from
+                        let tmp = self.cur_func.unwrap().new_local(
+                            loc,
+                            val.get_type(),
+                            &format!("_ret_{}_", self.tmp_id),
+                        );
+                        self.tmp_id += 1;
+                        self.cur_block.unwrap().add_assignment(loc, tmp, val);
+                        val = tmp.to_rvalue();
+                    }
+                    self.run_defers(0);
 
                     self.cur_block
                         .unwrap()
                         .end_with_return(Some(gccloc_from_loc(&self.ctx, &stmt.pos)), val);
                 } else {
+                    self.run_defers(0);
                     self.cur_block.unwrap().end_with_void_return(None);
                 }
 
@@ -720,8 +1450,9 @@ pub fn gen_stmt(&mut self, stmt: &Stmt, init: bool) {
                     *self.terminated.last_mut().unwrap() = true;
                 }
             }
-            StmtKind::Var(name, _, _, init) => {
-                let ty = self.get_id_type(stmt.id).clone();
+            StmtKind::Var(name, reassignable, _, init) => {
+                let ty =
+                    self.get_id_type(stmt.id, stmt.pos, || format!("variable `{}`", str(*name)));
 
                 let cty = self.ty_to_ctype(&ty);
                 let local = self.cur_func.unwrap().new_local(
@@ -731,14 +1462,7 @@ pub fn gen_stmt(&mut self, stmt: &Stmt, init: bool) {
                 );
                 if init.is_some() {
                     let expr = init.as_ref().unwrap();
-                    let rval = self.gen_expr(expr);
-                    let ast_ty = self.get_id_type(expr.id);
-                    let cty = self.ty_to_ctype(&ast_ty);
-                    let rval = if !ty.is_struct() && !ty.is_array() {
-                        self.ctx.new_cast(None, rval, cty)
-                    } else {
-                        rval
-                    };
+                    let rval = self.var_init(expr, &ty);
                     self.cur_block.unwrap().add_assignment(
                         Some(gccloc_from_loc(&self.ctx, &expr.pos)),
                         local,
@@ -752,6 +1476,7 @@ pub fn gen_stmt(&mut self, stmt: &Stmt, init: bool) {
                         cty,
                         lval: local,
                         ty: ty.clone(),
+                        mutable: *reassignable,
                     },
                 );
             }
@@ -813,12 +1538,16 @@ pub fn gen_stmt(&mut self, stmt: &Stmt, init: bool) {
                     func.new_block(&format!("for_cond:{}", self.block_name_new()));
                 let loop_body: Block =
                     func.new_block(&format!("for_loop_body:{}", self.block_name_new()));
+                // `continue` goes here too, so step runs before condition is tested again
+                let loop_step: Block =
+                    func.new_block(&format!("for_step:{}", self.block_name_new()));
                 let after_loop: Block =
                     func.new_block(&format!("after_for:{}", self.block_name_new()));
                 //let for_body: Block =
                 // func.new_block(&format!("for_body:{}",self.block_name_new()));
-                self.break_blocks.push_back(after_loop);
-                self.continue_blocks.push_back(loop_cond);
+                self.break_blocks.push_back((after_loop, None));
+                self.continue_blocks.push_back(loop_step);
+                self.loop_defers.push(self.defers.len());
                 //self.cur_block.unwrap().end_with_jump(None,for_body);
                 //self.cur_block = Some(for_body);
 
@@ -832,11 +1561,18 @@ pub fn gen_stmt(&mut self, stmt: &Stmt, init: bool) {
                 self.cur_block = Some(loop_body);
                 self.terminated.push(false);
                 self.gen_stmt(body, true);
+                // body ending with `return` has no way to fall through to step, which is then
+                // reached only by `continue`
+                if !*self.terminated.last().unwrap() {
+                    self.cur_block.unwrap().end_with_jump(None, loop_step);
+                }
+                self.cur_block = Some(loop_step);
                 self.gen_expr(then);
                 self.cur_block.unwrap().end_with_jump(None, loop_cond);
 
                 self.continue_blocks.pop_back();
                 self.break_blocks.pop_back();
+                self.loop_defers.pop();
                 self.cur_block = Some(after_loop);
                 self.terminated.pop();
             }
@@ -846,8 +1582,9 @@ pub fn gen_stmt(&mut self, stmt: &Stmt, init: bool) {
                 let loop_cond: Block = func.new_block(self.block_name_new());
                 let loop_body: Block = func.new_block(self.block_name_new());
                 let after_loop: Block = func.new_block(self.block_name_new());
-                self.break_blocks.push_back(after_loop);
+                self.break_blocks.push_back((after_loop, None));
                 self.continue_blocks.push_back(loop_cond);
+                self.loop_defers.push(self.defers.len());
 
                 self.cur_block.unwrap().end_with_jump(None, loop_cond);
                 self.cur_block = Some(loop_cond);
@@ -865,39 +1602,383 @@ pub fn gen_stmt(&mut self, stmt: &Stmt, init: bool) {
 
                 self.continue_blocks.pop_back();
                 self.break_blocks.pop_back();
+                self.loop_defers.pop();
                 self.cur_block = Some(after_loop);
             }
-            StmtKind::Loop(body) => {
-                let bb = self.cur_func.unwrap().new_block(self.block_name_new());
-                let after = self.cur_func.unwrap().new_block(self.block_name_new());
-                self.break_blocks.push_back(after);
-                self.continue_blocks.push_back(bb);
+            StmtKind::Loop(body) => self.gen_loop(body, None),
+        }
+    }
 
-                self.cur_block.unwrap().end_with_jump(None, bb);
-                self.cur_block = Some(bb);
+    /// Value of initializer `expr` of variable with type `ty`
+    fn var_init(&mut self, expr: &Expr, ty: &Type) -> RValue {
+        let ast_ty = self.get_expr_type(expr);
+        if array_decays_to(&ast_ty, ty) {
+            self.decay_array(expr, ty, Some(gccloc_from_loc(&self.ctx, &expr.pos)))
+        } else {
+            let rval = self.gen_expr(expr);
+            let cty = self.ty_to_ctype(&ast_ty);
+            if !is_aggregate(ty) {
+                self.ctx.new_cast(None, rval, cty)
+            } else {
+                rval
+            }
+        }
+    }
 
-                self.gen_stmt(body, true);
+    /// `static` local lives in internal global, zeroed until its initializer runs on first entry
+    fn gen_static(&mut self, var: &Stmt) {
+        let (name, reassignable, init) = match &var.kind {
+            StmtKind::Var(name, reassignable, _, init) => (*name, *reassignable, init),
+            _ => unreachable!(),
+        };
+        let loc = Some(gccloc_from_loc(&self.ctx, &var.pos));
+        let ty = self.get_id_type(var.id, var.pos, || format!("variable `{}`", str(name)));
+        let cty = self.ty_to_ctype(&ty);
+        let bool_ty = self.ctx.new_type::<bool>();
+        let (global, done) = match self.statics.get(&var.id) {
+            Some(storage) => *storage,
+            None => {
+                let global = self.ctx.new_global(
+                    loc,
+                    GlobalKind::Internal,
+                    cty,
+                    &format!("_static_{}_{}_", str(name), self.tmp_id),
+                );
+                let done = init.as_ref().map(|_| {
+                    self.ctx.new_global(
+                        loc,
+                        GlobalKind::Internal,
+                        bool_ty,
+                        &format!("_static_init_{}_", self.tmp_id),
+                    )
+                });
+                self.tmp_id += 1;
+                self.statics.insert(var.id, (global, done));
+                (global, done)
+            }
+        };
+        if let (Some(init), Some(done)) = (init, done) {
+            let func = self.cur_func.unwrap();
+            let bb_init = func.new_block(&format!("static_init:{}", self.block_name_new()));
+            let bb_after = func.new_block(&format!("after:{}", self.block_name_new()));
+            self.cur_block
+                .unwrap()
+                .end_with_conditional(loc, done.to_rvalue(), bb_after, bb_init);
+            self.cur_block = Some(bb_init);
+            let rval = self.var_init(init, &ty);
+            let block = self.cur_block.unwrap();
+            block.add_assignment(loc, global, rval);
+            block.add_assignment(loc, done, self.ctx.new_rvalue_from_int(bool_ty, 1));
+            block.end_with_jump(loc, bb_after);
+            self.cur_block = Some(bb_after);
+        }
 
-                self.cur_block.unwrap().end_with_jump(None, bb);
+        self.variables.insert(
+            name,
+            VarInfo {
+                cty,
+                lval: global,
+                ty,
+                mutable: reassignable,
+            },
+        );
+    }
 
-                self.cur_block = Some(after);
+    /// Close block `stmts` that ends by falling through and run statements it deferred, block
+    /// that ends with jump already ran them
+    fn leave_block(&mut self, stmts: &[Box<Stmt>]) {
+        let deferred = self.defers.pop().unwrap();
+        match stmts.last().map(|stmt| &stmt.kind) {
+            Some(StmtKind::Return(_))
+            | Some(StmtKind::Break(..))
+            | Some(StmtKind::Continue(_)) => (),
+            Some(StmtKind::Expr(expr)) if self.calls_noreturn(expr) => (),
+            _ => {
+                for stmt in deferred.iter().rev() {
+                    self.gen_stmt(stmt, true);
+                }
             }
         }
     }
+
+    /// C type variadic argument of type `ty` is stored as in its slot: integers are widened to 64
+    /// bits and floats to `f64` like C promotes them, pointers are kept as they are
+    fn va_slot_type(&mut self, ty: &Type, pos: crate::syntax::position::Position) -> CType {
+        match ty {
+            Type::Ptr(_) | Type::Func(_) => self.ty_to_ctype(ty),
+            Type::Basic(basic) => match str(basic.name).as_str() {
+                "f32" | "f64" => self.ctx.new_type::<f64>(),
+                "u8" | "uchar" | "u16" | "u32" | "u64" | "usize" | "bool" => {
+                    self.ctx.new_type::<u64>()
+                }
+                "i8" | "char" | "i16" | "i32" | "i64" | "isize" => self.ctx.new_type::<i64>(),
+                _ => error!(format!("value of type {} can't be variadic argument", ty), pos),
+            },
+            _ => error!(format!("value of type {} can't be variadic argument", ty), pos),
+        }
+    }
+
+    /// libgccjit can't create `va_list`, so variadic functions defined in program take their
+    /// variadic arguments in array of 8 byte slots passed after other parameters. Stores `args`
+    /// in such array and returns pointer to its first slot.
+    fn va_slots(&mut self, args: &[&Expr], loc: Option<gccjit_rs::location::Location>) -> RValue {
+        let slot_ty = self.ctx.new_type::<u64>();
+        let array_ty = self
+            .ctx
+            .new_array_type(loc, slot_ty, args.len().max(1) as i32);
+        let slots = self.cur_func.unwrap().new_local(
+            loc,
+            array_ty,
+            &format!("_va_args_{}_", self.tmp_id),
+        );
+        self.tmp_id += 1;
+        let index_ty = self.ctx.new_type::<i32>();
+        for (i, arg) in args.iter().enumerate() {
+            let ty = self.get_expr_type(arg);
+            let cty = self.va_slot_type(&ty, arg.pos);
+            let val = self.gen_expr(arg);
+            let val = self.ctx.new_cast(loc, val, cty);
+            let index = self.ctx.new_rvalue_from_int(index_ty, i as i32);
+            let slot = self
+                .ctx
+                .new_array_access(loc, slots.to_rvalue(), index)
+                .get_address(loc);
+            let slot = self.ctx.new_cast(loc, slot, cty.make_pointer());
+            self.cur_block
+                .unwrap()
+                .add_assignment(loc, slot.dereference(loc), val);
+        }
+        let first = self.ctx.new_rvalue_zero(index_ty);
+        self.ctx
+            .new_array_access(loc, slots.to_rvalue(), first)
+            .get_address(loc)
+    }
+
+    /// Whether `expr` calls function that is `@noreturn` in all its overloads
+    fn calls_noreturn(&self, expr: &Expr) -> bool {
+        let name = match &expr.kind {
+            ExprKind::Call(path, ..) => path.name(),
+            _ => return false,
+        };
+        if let Some(unit) = self.external_functions.get(&name) {
+            return unit.f.has_attribute("noreturn");
+        }
+        match self.functions.get(&name) {
+            Some(units) => units.iter().all(|unit| unit.f.has_attribute("noreturn")),
+            None => false,
+        }
+    }
+
+    /// Terminate current block after call that never returns, so function doesn't need
+    /// `return` after it
+    fn end_unreachable(&mut self, loc: Option<gccjit_rs::location::Location>) {
+        let block = self.cur_block.unwrap();
+        let unreachable = self.ctx.get_builtin_function("__builtin_unreachable");
+        block.add_eval(loc, self.ctx.new_call(loc, unreachable, &[]));
+        let ret = self.cur_return.clone().unwrap();
+        if ret.is_void() {
+            block.end_with_void_return(loc);
+        } else {
+            // never read, value is only needed to end block
+            let cty = self.ty_to_ctype(&ret);
+            let tmp = self.cur_func.unwrap().new_local(
+                loc,
+                cty,
+                &format!("_unreachable_{}_", self.tmp_id),
+            );
+            self.tmp_id += 1;
+            block.end_with_return(loc, tmp.to_rvalue());
+        }
+        if let Some(terminated) = self.terminated.last_mut() {
+            *terminated = true;
+        }
+    }
+
+    /// Run deferred statements of blocks nested deeper than `depth`, innermost first, before
+    /// jumping out of them
+    fn run_defers(&mut self, depth: usize) {
+        let scopes = self.defers[depth..].to_vec();
+        for stmt in scopes.iter().rev().flat_map(|scope| scope.iter().rev()) {
+            self.gen_stmt(stmt, true);
+        }
+    }
+
+    /// Generate `loop` body, `value` receives value of `break` that leaves this loop
+    fn gen_loop(&mut self, body: &Stmt, value: Option<(LValue, Type)>) {
+        let bb = self.cur_func.unwrap().new_block(self.block_name_new());
+        let after = self.cur_func.unwrap().new_block(self.block_name_new());
+        self.break_blocks.push_back((after, value));
+        self.continue_blocks.push_back(bb);
+        self.loop_defers.push(self.defers.len());
+
+        self.cur_block.unwrap().end_with_jump(None, bb);
+        self.cur_block = Some(bb);
+
+        self.gen_stmt(body, true);
+
+        self.cur_block.unwrap().end_with_jump(None, bb);
+
+        self.continue_blocks.pop_back();
+        self.break_blocks.pop_back();
+        self.loop_defers.pop();
+        self.cur_block = Some(after);
+    }
     /// Generate GIMPLE expression from AST expression
     pub fn gen_expr(&mut self, expr: &Expr) -> RValue {
         match &expr.kind {
-            ExprKind::ArrayIdx(array, index) => {
-                let array = self.gen_expr(array);
-                let index = self.gen_expr(index);
-
-                self.ctx.new_array_access(None, array, index).to_rvalue()
-            }
-            ExprKind::Ident(name) => {
-                if self.constants.contains_key(name) {
-                    let constexpr = self.constants.get(name).unwrap().clone();
-                    if let Some(lval) = self.expr_to_lvalue(&constexpr) {
-                        return lval.to_rvalue();
+            ExprKind::ArrayIdx(..) => self.expr_to_lvalue(expr).unwrap().to_rvalue(),
+            ExprKind::Array(_, exprs) => {
+                // array literal is built in temporary local element by element
+                let ty = self.get_expr_type(expr);
+                let cty = self.ty_to_ctype(&ty);
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                let tmp = self.cur_func.unwrap().new_local(
+                    loc,
+                    cty,
+                    &format!("_array_{}_", self.tmp_id),
+                );
+                self.tmp_id += 1;
+                let subtype = ty.to_array().unwrap().subtype.clone();
+                let subtype_cty = self.ty_to_ctype(&subtype);
+                for (i, elem) in exprs.iter().enumerate() {
+                    let val = self.gen_expr(elem);
+                    let val = if !is_aggregate(&subtype) {
+                        self.ctx.new_cast(loc, val, subtype_cty)
+                    } else {
+                        val
+                    };
+                    let index = self
+                        .ctx
+                        .new_rvalue_from_int(self.ctx.new_type::<i32>(), i as i32);
+                    let slot = self.ctx.new_array_access(loc, tmp.to_rvalue(), index);
+                    self.cur_block.unwrap().add_assignment(loc, slot, val);
+                }
+                tmp.to_rvalue()
+            }
+            ExprKind::Slice(array, start, end) => {
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                let subtype = *self.get_expr_type(array).to_array().unwrap().subtype.clone();
+                let (ptr, len) = self.slice_parts(array, loc);
+                // bounds are used more than once, evaluate them once
+                let u64_ty = self.ctx.new_type::<u64>();
+                let mut bounds = vec![];
+                for bound in [start, end].iter() {
+                    let val = self.gen_expr(bound);
+                    let tmp = self.cur_func.unwrap().new_local(
+                        loc,
+                        u64_ty,
+                        &format!("_bound_{}_", self.tmp_id),
+                    );
+                    self.tmp_id += 1;
+                    let val = self.ctx.new_cast(loc, val, u64_ty);
+                    self.cur_block.unwrap().add_assignment(loc, tmp, val);
+                    bounds.push(tmp.to_rvalue());
+                }
+                let (start, end) = (bounds[0], bounds[1]);
+                if self.context.debug_bounds {
+                    let len = self.ctx.new_cast(loc, len, u64_ty);
+                    let reversed = self
+                        .ctx
+                        .new_comparison(loc, ComparisonOp::GreaterThan, start, end);
+                    let past_end = self
+                        .ctx
+                        .new_comparison(loc, ComparisonOp::GreaterThan, end, len);
+                    let bool_ty = self.ctx.new_type::<bool>();
+                    let failed = self.ctx.new_binary_op(
+                        loc,
+                        BinaryOp::LogicalOr,
+                        bool_ty,
+                        reversed,
+                        past_end,
+                    );
+                    self.runtime_check(
+                        failed,
+                        expr.pos,
+                        "slice %lu..%lu out of bounds for length %lu",
+                        &[start, end, len],
+                    );
+                }
+                let ptr = self
+                    .ctx
+                    .new_array_access(loc, ptr, start)
+                    .get_address(loc);
+                let len = self
+                    .ctx
+                    .new_binary_op(loc, BinaryOp::Minus, u64_ty, end, start);
+                self.new_slice(&subtype, ptr, len, loc)
+            }
+            ExprKind::Loop(body) => {
+                let ty = self.get_expr_type(expr);
+                let cty = self.ty_to_ctype(&ty);
+                let local = self.cur_func.unwrap().new_local(
+                    Some(gccloc_from_loc(&self.ctx, &expr.pos)),
+                    cty,
+                    &format!("_loop_{}_", self.tmp_id),
+                );
+                self.tmp_id += 1;
+                self.gen_loop(body, Some((local, ty)));
+                local.to_rvalue()
+            }
+            ExprKind::Block(stmts, tail) => {
+                // rvalue of tail may read locals that deferred statements change, so its value
+                // is stored before leaving block
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                let ty = self.get_expr_type(expr);
+                let cty = self.ty_to_ctype(&ty);
+                let local = self.cur_func.unwrap().new_local(
+                    loc,
+                    cty,
+                    &format!("_block_{}_", self.tmp_id),
+                );
+                self.tmp_id += 1;
+                self.defers.push(vec![]);
+                for stmt in stmts.iter() {
+                    self.gen_stmt(stmt, false);
+                }
+                let val = self.gen_expr(tail);
+                self.cur_block.unwrap().add_assignment(loc, local, val);
+                self.leave_block(&[]);
+                local.to_rvalue()
+            }
+            ExprKind::Ternary(cond, then, otherwise) => {
+                // each arm is generated in its own block and stores value into local
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                let ty = self.get_expr_type(expr);
+                let cty = self.ty_to_ctype(&ty);
+                let func = self.cur_func.unwrap();
+                let local = func.new_local(loc, cty, &format!("_ternary_{}_", self.tmp_id));
+                self.tmp_id += 1;
+
+                let cond_ty = self.get_expr_type(cond);
+                let cond = self.gen_expr(cond);
+                let cond = self.to_bool(cond, &cond_ty, loc);
+                let bb_then = func.new_block(&format!("ternary_true:{}", self.block_name_new()));
+                let bb_else = func.new_block(&format!("ternary_false:{}", self.block_name_new()));
+                let bb_merge = func.new_block(&format!("after:{}", self.block_name_new()));
+                self.cur_block
+                    .unwrap()
+                    .end_with_conditional(loc, cond, bb_then, bb_else);
+
+                for (block, arm) in [(bb_then, then), (bb_else, otherwise)].iter() {
+                    self.cur_block = Some(*block);
+                    let val = self.gen_expr(arm);
+                    let scalar = self.find_struct(&ty).is_none() && !is_aggregate(&ty);
+                    let val = if scalar && !ty.is_slice() {
+                        self.ctx.new_cast(loc, val, cty)
+                    } else {
+                        val
+                    };
+                    self.cur_block.unwrap().add_assignment(loc, local, val);
+                    self.cur_block.unwrap().end_with_jump(loc, bb_merge);
+                }
+                self.cur_block = Some(bb_merge);
+                local.to_rvalue()
+            }
+            ExprKind::Ident(name) => {
+                if self.constants.contains_key(name) {
+                    let constexpr = self.constants.get(name).unwrap().clone();
+                    if let Some(lval) = self.expr_to_lvalue(&constexpr) {
+                        return lval.to_rvalue();
                     } else {
                         return self.gen_expr(&constexpr);
                     }
@@ -929,47 +2010,46 @@ macro_rules! new_basic_ty {
 
                 use crate::syntax::lexer::token::IntSuffix;
                 let int: i64 = *i as _;
-                let (val, ty) = match suffix {
+                // Every literal is truncated to its own width first and then materialized
+                // through `long`, so no value goes through lossy `i32` conversion.
+                let (ctype, int, ty) = match suffix {
                     IntSuffix::Int => (
-                        self.ctx
-                            .new_rvalue_from_int(self.ctx.new_type::<i32>(), int as i32),
+                        self.ctx.new_type::<i32>(),
+                        i64::from(int as i32),
                         new_basic_ty!("i32"),
                     ),
                     IntSuffix::UInt => (
-                        self.ctx
-                            .new_rvalue_from_int(self.ctx.new_type::<u32>(), int as i32),
+                        self.ctx.new_type::<u32>(),
+                        i64::from(int as u32),
                         new_basic_ty!("u32"),
                     ),
                     IntSuffix::Byte => (
-                        self.ctx
-                            .new_rvalue_from_int(self.ctx.new_type::<i8>(), int as i32),
+                        self.ctx.new_type::<i8>(),
+                        i64::from(int as i8),
                         new_basic_ty!("i8"),
                     ),
                     IntSuffix::UByte => (
-                        self.ctx
-                            .new_rvalue_from_int(self.ctx.new_type::<u8>(), int as i32),
+                        self.ctx.new_type::<u8>(),
+                        i64::from(int as u8),
                         new_basic_ty!("u8"),
                     ),
-                    IntSuffix::Long => (
-                        self.ctx
-                            .new_rvalue_from_long(self.ctx.new_type::<i64>(), int),
-                        new_basic_ty!("i64"),
-                    ),
-                    IntSuffix::ULong => (
-                        self.ctx
-                            .new_rvalue_from_long(self.ctx.new_type::<u64>(), int),
-                        new_basic_ty!("u64"),
-                    ),
+                    IntSuffix::Long => (self.ctx.new_type::<i64>(), int, new_basic_ty!("i64")),
+                    IntSuffix::ULong => (self.ctx.new_type::<u64>(), int, new_basic_ty!("u64")),
                 };
+                let val = self.ctx.new_rvalue_from_long(ctype, int);
 
                 if !self.context.types.contains_key(&expr.id) {
                     self.context.types.insert(expr.id, ty);
                 }
                 val
             }
-            ExprKind::Str(s) => self.ctx.new_string_literal(s),
-            ExprKind::Deref(expr) => {
-                let rvalue = self.gen_expr(expr);
+            ExprKind::Str(s) => {
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                self.string_literal(s, loc)
+            }
+            ExprKind::Deref(expr_) => {
+                let rvalue = self.gen_expr(expr_);
+                let rvalue = self.check_null(rvalue, expr.pos);
                 rvalue.dereference(None).to_rvalue()
             }
             ExprKind::Unary(op, expr_) => {
@@ -980,22 +2060,36 @@ macro_rules! new_basic_ty {
                 match op {
                     "-" => self.ctx.new_unary_op(None, UnaryOp::Minus, ty, rval),
                     "!" => {
-                        let ast_ty = self.get_id_type(expr.id);
-                        if crate::semantic::ty_is_any_int(&ast_ty) {
+                        let ast_ty = self.get_expr_type(expr_);
+                        if crate::semantic::ty_is_bool(&ast_ty) {
+                            self.ctx
+                                .new_unary_op(None, UnaryOp::LogicalNegate, ty, rval)
+                        } else if crate::semantic::ty_is_any_int(&ast_ty) {
                             self.ctx
                                 .new_unary_op(None, UnaryOp::BitwiseNegate, ty, rval)
                         } else {
-                            self.ctx
-                                .new_unary_op(None, UnaryOp::LogicalNegate, ty, rval)
+                            let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                            let rval = self.to_bool(rval, &ast_ty, loc);
+                            self.ctx.new_unary_op(
+                                loc,
+                                UnaryOp::LogicalNegate,
+                                rval.get_type(),
+                                rval,
+                            )
                         }
                     }
                     "+" => rval,
                     _ => unreachable!(),
                 }
             }
+            ExprKind::Field(..) if expr.str_len().is_some() => {
+                let usize_ty = self.ctx.new_type::<usize>();
+                self.ctx
+                    .new_rvalue_from_long(usize_ty, expr.str_len().unwrap() as _)
+            }
             ExprKind::Field(_expr_, _name) => {
                 self.expr_to_lvalue(expr).unwrap().to_rvalue()
-                /*let ast_ty = self.get_id_type(expr_.id);
+                /*let ast_ty = self.get_expr_type(expr_);
                 let rvalue = self.gen_expr(expr_).clone();
 
                 if ast_ty.is_ptr()
@@ -1054,8 +2148,8 @@ macro_rules! new_basic_ty {
                 /*let lval = self.expr_to_lvalue(lval_).unwrap();
                 let val = self.gen_expr(rval_);
 
-                let ast_ty = self.get_id_type(rval_.id);
-                let lval_ty = self.get_id_type(lval_.id);
+                let ast_ty = self.get_expr_type(rval_);
+                let lval_ty = self.get_expr_type(lval_);
 
                 let val = if !ast_ty.is_struct()
                 {
@@ -1079,7 +2173,7 @@ macro_rules! new_basic_ty {
                 .ctx
                 .new_rvalue_from_int(self.ctx.new_type::<bool>(), *b as i32),
             ExprKind::AddressOf(expr_) => {
-                let ty = self.get_id_type(expr.id);
+                let ty = self.get_expr_type(expr);
                 let _cty = self.ty_to_ctype(&ty);
                 let val = self.expr_to_lvalue(expr_);
                 if val.is_none() {
@@ -1109,22 +2203,160 @@ macro_rules! new_basic_ty {
                 }
             }
             ExprKind::Conv(val, to) => {
-                let cty = self.ty_to_ctype(to);
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                let from = self.get_expr_type(val);
+                if to.is_slice() && array_decays_to(&from, to) {
+                    return self.decay_array(val, to, loc);
+                }
+                if let (Type::Array(array), true) = (&from, to.is_ptr()) {
+                    // `arr as *T` is `&arr[0]`, slices already point to their first element
+                    let addr = if array.len.is_some() {
+                        self.array_address(val, loc)
+                    } else {
+                        self.slice_parts(val, loc).0
+                    };
+                    let cty = self.ty_to_ctype(to);
+                    return self.ctx.new_cast(loc, addr, cty);
+                }
                 let rval = self.gen_expr(val);
-                self.ctx
-                    .new_cast(Some(gccloc_from_loc(&self.ctx, &expr.pos)), rval, cty)
+                self.convert(rval, &from, to, loc)
             }
 
+            ExprKind::Call(name, None, _)
+                if VA_BUILTINS.contains(&str(name.name()).as_str()) =>
+            {
+                let (slots, next) = self.va_list.unwrap();
+                if str(name.name()).as_str() == "va_start" {
+                    let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                    self.cur_block.unwrap().add_assignment(loc, next, slots);
+                }
+                // `va_end` has nothing to release, value of either one isn't used
+                next.to_rvalue()
+            }
+            ExprKind::VaArg(_) => {
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                let (_, next) = self.va_list.unwrap();
+                let ty = self.get_expr_type(expr);
+                let slot_ty = self.va_slot_type(&ty, expr.pos);
+                let slot = self
+                    .ctx
+                    .new_cast(loc, next.to_rvalue(), slot_ty.make_pointer());
+                // read before cursor moves to next slot
+                let val = self.cur_func.unwrap().new_local(
+                    loc,
+                    slot_ty,
+                    &format!("_va_arg_{}_", self.tmp_id),
+                );
+                self.tmp_id += 1;
+                let block = self.cur_block.unwrap();
+                block.add_assignment(loc, val, slot.dereference(loc).to_rvalue());
+                let one = self.ctx.new_rvalue_from_int(self.ctx.new_type::<i32>(), 1);
+                let after = self
+                    .ctx
+                    .new_array_access(loc, next.to_rvalue(), one)
+                    .get_address(loc);
+                block.add_assignment(loc, next, after);
+                let cty = self.ty_to_ctype(&ty);
+                self.ctx.new_cast(loc, val.to_rvalue(), cty)
+            }
+            ExprKind::Call(name, None, args)
+                if ENV_BUILTINS.contains(&str(name.name()).as_str()) =>
+            {
+                // not folded by `ConstEval` (e.g `--consteval` not passed), evaluate here
+                let val = eval_env_builtin(&str(name.name()), args, expr.pos).unwrap();
+                self.gen_expr(&Expr {
+                    id: expr.id,
+                    pos: expr.pos,
+                    kind: val.to_kind(expr.pos).unwrap(),
+                })
+            }
+            ExprKind::Call(name, None, args)
+                if FILE_BUILTINS.contains(&str(name.name()).as_str()) =>
+            {
+                let name = str(name.name());
+                if name.as_str() == "include_str" {
+                    let val = eval_file_builtin(&name, args, expr.pos).unwrap();
+                    return self.gen_expr(&Expr {
+                        id: expr.id,
+                        pos: expr.pos,
+                        kind: val.to_kind(expr.pos).unwrap(),
+                    });
+                }
+                // bytes are kept in read-only global instead of being stored one by one
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                let bytes = included_file(&name, args, expr.pos);
+                let cty = self.ty_to_ctype(&self.get_expr_type(expr));
+                let global = self.ctx.new_global(
+                    loc,
+                    GlobalKind::Internal,
+                    cty.make_const(),
+                    &format!("_bytes_{}_", self.tmp_id),
+                );
+                self.tmp_id += 1;
+                unsafe {
+                    gcc_jit_global_set_initializer(
+                        gccjit_rs::lvalue::get_ptr(&global),
+                        bytes.as_ptr() as *const _,
+                        bytes.len(),
+                    );
+                }
+                global.to_rvalue()
+            }
+            ExprKind::Call(name, None, args)
+                if VEC_BUILTINS.contains(&str(name.name()).as_str()) =>
+            {
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                let ty = self.get_expr_type(&args[0]);
+                let val = self.gen_expr(&args[0]);
+                let lane_cty = self.ty_to_ctype(&ty.to_vec().unwrap().subtype);
+                let bool_cty = self.ctx.new_type::<bool>();
+                let is_sum = str(name.name()).as_str() == "vec_sum";
+                let (op, result_cty) = match str(name.name()).as_str() {
+                    "vec_sum" => (BinaryOp::Plus, lane_cty),
+                    "vec_any" => (BinaryOp::LogicalOr, bool_cty),
+                    _ => (BinaryOp::LogicalAnd, bool_cty),
+                };
+
+                let mut result: Option<RValue> = None;
+                for lane in self.vec_lanes(val, &ty, loc) {
+                    let lane = if is_sum {
+                        lane
+                    } else {
+                        let zero = self.ctx.new_rvalue_zero(lane_cty);
+                        self.ctx
+                            .new_comparison(loc, ComparisonOp::NotEquals, lane, zero)
+                    };
+                    result = Some(match result {
+                        Some(acc) => self.ctx.new_binary_op(loc, op, result_cty, acc, lane),
+                        None => lane,
+                    });
+                }
+                result.unwrap()
+            }
+            ExprKind::Inline(call) => {
+                self.force_inline = true;
+                let val = self.gen_expr(call);
+                // const evaluation may have replaced call with its value
+                self.force_inline = false;
+                val
+            }
             ExprKind::Call(name, this, args) => {
+                // arguments may contain calls too, only this one is forced
+                let force_inline = std::mem::replace(&mut self.force_inline, false);
                 let param_types = args
                     .iter()
-                    .map(|expr| self.get_id_type(expr.id).clone())
+                    .map(|expr| self.get_expr_type(expr).clone())
                     .collect::<Vec<_>>();
 
                 let var = if let Some(functions) = self.functions.get(&name.name()) {
-                    let functions = functions.clone();
+                    let mut functions = functions.clone();
+                    for unit in functions.iter_mut() {
+                        if force_inline || is_trivial_accessor(&unit.f) {
+                            unit.c = unit.inline_c.unwrap_or(unit.c);
+                        }
+                    }
                     let ty = if let Some(this) = this {
-                        Some(self.get_id_type(this.id))
+                        Some(self.get_expr_type(this))
                     } else {
                         None
                     };
@@ -1139,13 +2371,26 @@ macro_rules! new_basic_ty {
                         std::process::exit(-1);
                     }
                     let (val, c_types, ast_types) = val.unwrap();
+                    let variadic = functions.iter().any(|unit| {
+                        unit.f.variadic
+                            && gccjit_rs::function::get_ptr(&unit.c)
+                                == gccjit_rs::function::get_ptr(&val)
+                    });
                     let mut params = vec![];
+                    let mut va_args = vec![];
 
                     for (i, arg) in args.iter().enumerate() {
-                        if i < ast_types.len() {
-                            let val = self.gen_expr(arg);
+                        if variadic && i >= ast_types.len() {
+                            va_args.push(&**arg);
+                        } else if i < ast_types.len() {
                             let ty = &ast_types[i];
-                            let implicit_casted = if !ty.is_struct() && !ty.is_array() {
+                            if array_decays_to(&param_types[i], ty) {
+                                let loc = Some(gccloc_from_loc(&self.ctx, &arg.pos));
+                                params.push(self.decay_array(arg, ty, loc));
+                                continue;
+                            }
+                            let val = self.gen_expr(arg);
+                            let implicit_casted = if !is_aggregate(&ty) {
                                 let cty = c_types[i];
                                 self.ctx.new_cast(None, val, cty)
                             } else {
@@ -1160,14 +2405,26 @@ macro_rules! new_basic_ty {
 
                     if this.is_some() {
                         let expr = this.clone().unwrap().clone();
-                        let ty = self.get_id_type(expr.id);
+                        let ty = self.get_expr_type(expr);
                         let val = if !ty.is_ptr() {
                             let cty = self.ty_to_ctype(&ty).make_pointer();
-                            let val = self.gen_expr(&Expr {
-                                pos: expr.pos,
-                                id: expr.id,
-                                kind: ExprKind::AddressOf(expr),
-                            });
+                            let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                            let val = match self.expr_to_lvalue(&expr) {
+                                Some(lval) => lval.get_address(loc),
+                                None => {
+                                    // receiver is a temporary (e.g `make().method()`), spill it
+                                    // to a local so the method gets a valid address
+                                    let rval = self.gen_expr(&expr);
+                                    let tmp = self.cur_func.unwrap().new_local(
+                                        loc,
+                                        rval.get_type(),
+                                        &format!("_this_{}_", self.tmp_id),
+                                    );
+                                    self.tmp_id += 1;
+                                    self.cur_block.unwrap().add_assignment(loc, tmp, rval);
+                                    tmp.get_address(loc)
+                                }
+                            };
                             let val = self.ctx.new_cast(None, val, cty);
                             val
                         } else {
@@ -1175,6 +2432,10 @@ macro_rules! new_basic_ty {
                         };
                         params.push(val);
                     }
+                    if variadic {
+                        let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                        params.push(self.va_slots(&va_args, loc));
+                    }
 
                     return self.ctx.new_call(
                         Some(self.ctx.new_location(
@@ -1207,11 +2468,15 @@ macro_rules! new_basic_ty {
                     let mut params = vec![];
                     for (i, arg) in args.iter().enumerate() {
                         if i < unit.f.params.len() {
+                            if array_decays_to(&param_types[i], &unit.f.params[i].1) {
+                                let loc = Some(gccloc_from_loc(&self.ctx, &arg.pos));
+                                let param = unit.f.params[i].1.clone();
+                                params.push(self.decay_array(arg, &param, loc));
+                                continue;
+                            }
                             let val = self.gen_expr(arg);
                             let cty = self.ty_to_ctype(&unit.f.params[i].1);
-                            let val = if !unit.f.params[i].1.is_struct()
-                                && !unit.f.params[i].1.is_array()
-                            {
+                            let val = if !is_aggregate(&unit.f.params[i].1) {
                                 self.ctx.new_cast(None, val, cty)
                             } else {
                                 val
@@ -1230,15 +2495,38 @@ macro_rules! new_basic_ty {
                         &params,
                     );
                 } else if let Some(var) = self.variables.get(&name.name()) {
-                    var.lval
+                    var.clone()
                 } else {
                     panic!();
                 };
 
+                // call through function pointer, arguments are cast to parameter types of its
+                // signature just like for direct calls
+                let ty = match &var.ty {
+                    Type::Basic(basic) if self.aliases.contains_key(&basic.name) => {
+                        self.aliases[&basic.name].clone()
+                    }
+                    ty => ty.clone(),
+                };
+                let sig = ty.to_func().unwrap();
                 let mut params = vec![];
-                for arg in args.iter() {
-                    params.push(self.gen_expr(arg));
+                for (i, arg) in args.iter().enumerate() {
+                    let param = &sig.params[i];
+                    let loc = Some(gccloc_from_loc(&self.ctx, &arg.pos));
+                    if array_decays_to(&param_types[i], param) {
+                        params.push(self.decay_array(arg, param, loc));
+                        continue;
+                    }
+                    let val = self.gen_expr(arg);
+                    let val = if !is_aggregate(param) {
+                        let cty = self.ty_to_ctype(param);
+                        self.ctx.new_cast(loc, val, cty)
+                    } else {
+                        val
+                    };
+                    params.push(val);
                 }
+                let var = var.lval;
 
                 self.ctx.new_call_through_ptr(
                     Some(gccloc_from_loc(&self.ctx, &expr.pos)),
@@ -1253,7 +2541,6 @@ macro_rules! new_basic_ty {
                 let struct_: GccStruct = self
                     .find_struct(&Type::create_basic(expr.id, expr.pos, name))
                     .expect("Struct not found");
-                //let rval: RValue = self.ctx.new_rvalue_zero(struct_.ty.as_type());
                 let tmp_ = format!("_{}_", self.tmp_id);
                 self.tmp_id += 1;
                 let tmp: LValue = self.cur_func.unwrap().new_local(
@@ -1261,7 +2548,20 @@ macro_rules! new_basic_ty {
                     struct_.ty,
                     &tmp_,
                 );
-                //self.cur_block.unwrap().add_assignment(None, tmp, rval);
+                // omitted fields are zeroed before listed ones are stored, so in union listed
+                // field keeps its value
+                // structs made by constant evaluation list every field and have no types recorded
+                let fields = if args.len() < struct_.types.len() {
+                    self.get_expr_type(expr).to_struct().unwrap().fields.clone()
+                } else {
+                    vec![]
+                };
+                for field in fields.iter().filter(|f| args.iter().all(|arg| arg.name != f.name)) {
+                    let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                    let lval = tmp.access_field(loc, *struct_.fields.get(&field.name).unwrap());
+                    let zero = self.zero_value(&field.data_type);
+                    self.cur_block.unwrap().add_assignment(loc, lval, zero);
+                }
                 for arg in args.iter() {
                     let arg: &StructArg = arg;
                     let val = self.gen_expr(&arg.expr);
@@ -1276,11 +2576,61 @@ macro_rules! new_basic_ty {
                 }
                 tmp.to_rvalue()
             }
+            ExprKind::New(ty, init) => {
+                let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                let usize_ty = self.ctx.new_type::<usize>();
+                let void_ptr = self.ctx.new_type::<*mut u8>();
+                let size = self.ctx.new_rvalue_from_long(usize_ty, self.ty_size(ty) as _);
+                // both allocators return zeroed memory
+                let mem = if self.context.is_gced(expr.id) {
+                    let gc_malloc = self.runtime_function("GC_malloc", void_ptr, &[usize_ty], false);
+                    self.ctx.new_call(loc, gc_malloc, &[size])
+                } else {
+                    let calloc =
+                        self.runtime_function("calloc", void_ptr, &[usize_ty, usize_ty], false);
+                    let one = self.ctx.new_rvalue_from_long(usize_ty, 1);
+                    self.ctx.new_call(loc, calloc, &[one, size])
+                };
+
+                let cty = self.ty_to_ctype(ty);
+                let ptr = self.cur_func.unwrap().new_local(
+                    loc,
+                    cty.make_pointer(),
+                    &format!("_new_{}_", self.tmp_id),
+                );
+                self.tmp_id += 1;
+                self.cur_block
+                    .unwrap()
+                    .add_assignment(loc, ptr, self.ctx.new_cast(loc, mem, cty.make_pointer()));
+                if let Some(init) = init {
+                    let val = self.gen_expr(init);
+                    let val = if self.find_struct(ty).is_none() && !is_aggregate(ty) {
+                        self.ctx.new_cast(loc, val, cty)
+                    } else {
+                        val
+                    };
+                    let place = ptr.to_rvalue().dereference(loc);
+                    self.cur_block.unwrap().add_assignment(loc, place, val);
+                }
+                ptr.to_rvalue()
+            }
             ExprKind::SizeOf(ty) => {
                 let size = self.ty_size(ty);
                 self.ctx
                     .new_rvalue_from_int(self.ctx.new_type::<usize>(), size as i32)
             }
+            ExprKind::AlignOf(ty) => {
+                let align = self.ty_align(ty);
+                self.ctx
+                    .new_rvalue_from_int(self.ctx.new_type::<usize>(), align as i32)
+            }
+            ExprKind::OffsetOf(name, field) => {
+                let s = self.context.file.find_struct(*name).unwrap();
+                let index = s.fields.iter().position(|f| f.name == *field).unwrap();
+                let (offsets, ..) = self.field_offsets(&self.structures[name]);
+                self.ctx
+                    .new_rvalue_from_int(self.ctx.new_type::<usize>(), offsets[index] as i32)
+            }
             ExprKind::GetFunc(name) => {
                 if self.functions.contains_key(name) {
                     let functions: &Vec<FunctionUnit> = self.functions.get(name).unwrap();
@@ -1308,8 +2658,8 @@ macro_rules! new_basic_ty {
             }
 
             ExprKind::Binary(op, e1, e2) => {
-                let t1 = self.get_id_type(e1.id);
-                let t2 = self.get_id_type(e2.id);
+                let t1 = self.get_expr_type(e1);
+                let t2 = self.get_expr_type(e2);
                 use crate::semantic::{ty_is_any_float, ty_is_any_int};
                 let op: &str = op;
                 if op.contains("==")
@@ -1329,6 +2679,13 @@ macro_rules! new_basic_ty {
                         "!=" => ComparisonOp::NotEquals,
                         _ => unreachable!(),
                     };
+                    if t1.is_vec() && t2.is_vec() {
+                        let loc = Some(gccloc_from_loc(&self.ctx, &expr.pos));
+                        let mask = self.get_expr_type(expr);
+                        let lhs = self.gen_expr(e1);
+                        let rhs = self.gen_expr(e2);
+                        return self.vec_comparison(comparison, lhs, rhs, &t1, &mask, loc);
+                    }
                     let cty = self.ty_to_ctype(&t1);
                     let e1 = self.gen_expr(e1);
                     let r2 = self.gen_expr(e2);
@@ -1371,6 +2728,11 @@ macro_rules! new_basic_ty {
                     let r = self
                         .ctx
                         .new_cast(Some(gccloc_from_loc(&self.ctx, &e2.pos)), r, cty);
+                    if self.context.debug_overflow && (op == "+" || op == "-" || op == "*") {
+                        if let Some(range) = signed_range(&t1) {
+                            return self.checked_arith(op, cty, range, l, r, expr.pos);
+                        }
+                    }
                     self.ctx.new_binary_op(
                         Some(gccloc_from_loc(&self.ctx, &expr.pos)),
                         binary,
@@ -1426,7 +2788,7 @@ macro_rules! new_basic_ty {
                         l,
                         r,
                     )
-                } else if t1.is_vec() && ty_is_any_int(&t2) || ty_is_any_float(&t2) {
+                } else if t1.is_vec() && (ty_is_any_int(&t2) || ty_is_any_float(&t2)) {
                     let cty = self.ty_to_ctype(&t1);
                     let op: &str = op;
                     let binary = match op {
@@ -1485,21 +2847,87 @@ macro_rules! new_basic_ty {
             ExprKind::Null => self
                 .ctx
                 .new_rvalue_from_ptr(self.ctx.new_type::<*mut u8>(), 0 as *mut ()),
+            ExprKind::Variant(name, variant) => {
+                let value = self
+                    .context
+                    .file
+                    .find_enum(*name)
+                    .and_then(|e| e.variant(*variant))
+                    .unwrap()
+                    .value;
+                let ty = self.ty_to_ctype(&self.get_expr_type(expr));
+                self.ctx.new_rvalue_from_long(ty, value)
+            }
             v => panic!("{:?}", v),
         }
     }
 
+    /// `N` of `@align(N)`, alignment must be power of two
+    fn align_arg(&self, align: &crate::syntax::ast::Attribute) -> usize {
+        match align.int_arg() {
+            Some(n) if n > 0 && (n as u64).is_power_of_two() => n as usize,
+            Some(n) if n > 0 => error!(
+                format!("`@align` expects power of two, but got {}", n),
+                align.pos
+            ),
+            _ => error!("`@align` expects positive integer", align.pos),
+        }
+    }
+
+    /// `@align(N)` of struct and its fields must be power of two. `@packed(N)` (`N` is 1 when
+    /// omitted) caps alignment of fields, so field with stricter `@align` than packing can't be
+    /// laid out as requested.
+    fn check_alignment(&self, s: &crate::syntax::ast::Struct) {
+        if let Some(align) = s.attribute("align") {
+            self.align_arg(align);
+        }
+        let packed = s.attribute("packed");
+        let packing = match packed {
+            None => usize::max_value(),
+            Some(packed) if packed.args.is_empty() => 1,
+            Some(packed) => match packed.int_arg() {
+                Some(packing) if packing > 0 => packing as usize,
+                _ => error!("`@packed` expects positive integer", packed.pos),
+            },
+        };
+        for field in s.fields.iter() {
+            let align = match field.attribute("align") {
+                Some(align) => align,
+                None => continue,
+            };
+            match self.align_arg(align) {
+                n if n <= packing => (),
+                n => {
+                    error!(
+                        false,
+                        format!(
+                            "field `{}` requires {}-byte alignment, but struct `{}` is packed to {} byte(s)",
+                            str(field.name),
+                            n,
+                            str(s.name),
+                            packing
+                        ),
+                        align.pos
+                    );
+                    note!("packing is specified here", packed.unwrap().pos);
+                    std::process::exit(-1);
+                }
+            }
+        }
+    }
+
     pub fn gen_toplevel(&mut self, elems: &mut [Elem]) {
         for elem in elems.iter() {
             match elem {
                 Elem::Struct(s) => {
                     let s: &crate::syntax::ast::Struct = s;
+                    self.check_alignment(s);
                     let mut fields = vec![];
                     let mut cfields = HashMap::new();
                     let mut types = vec![];
                     for field in s.fields.iter() {
                         let field: &StructField = field;
-                        let cty = self.ty_to_ctype(&field.data_type).clone();
+                        let cty = self.field_ctype(field, s.packing());
                         types.push(field.data_type.clone());
                         let name: &str = &str(field.name).to_string();
                         let cfield = self.ctx.new_field(
@@ -1527,11 +2955,16 @@ pub fn gen_toplevel(&mut self, elems: &mut [Elem]) {
                             .as_type()
                     };
 
-                    let cstruct = GccStruct {
+                    let mut cstruct = GccStruct {
                         ty: struct_,
                         fields: cfields,
                         types,
+                        union: s.union,
+                        packing: s.packing(),
+                        aligns: s.fields.iter().map(StructField::align).collect(),
+                        align: s.align(),
                     };
+                    cstruct.ty = self.struct_ctype(&cstruct, struct_);
                     if !self.structures.contains_key(&s.name) {
                         self.structures.insert(s.name, cstruct);
                     }
@@ -1545,20 +2978,29 @@ pub fn gen_toplevel(&mut self, elems: &mut [Elem]) {
                 Elem::Alias(name, ty) => {
                     self.aliases.insert(*name, ty.clone());
                 }
+                Elem::Enum(e) => {
+                    self.aliases.insert(e.name, e.repr.clone());
+                }
                 _ => (),
             }
         }
 
+        let mut inline_calls = InlineCalls::default();
+        for elem in elems.iter() {
+            inline_calls.visit_elem(elem);
+        }
+        let inline_called = inline_calls.0;
+
         for elem in elems.iter_mut() {
             match elem {
                 Elem::Func(func) => {
                     let func: &mut Function = func;
+                    // linkage follows visibility only, `inline` adds always_inline attribute in
+                    // `add_fn_hints`
                     let linkage = if func.external {
                         FunctionType::Extern
                     } else if func.static_ || !func.public {
                         FunctionType::Internal
-                    } else if func.inline {
-                        FunctionType::AlwaysInline
                     } else {
                         FunctionType::Exported
                     };
@@ -1589,81 +3031,78 @@ pub fn gen_toplevel(&mut self, elems: &mut [Elem]) {
                             )
                         };
 
+                        if !func.internal {
+                            self.add_fn_hints(func, f);
+                        }
+
                         let unit = FunctionUnit {
                             f: func.clone(),
                             c: f,
                             this_ast: None,
                             this_ir: None,
                             irname: str(func.name).to_string(),
+                            inline_c: None,
                         };
 
                         self.external_functions.insert(func.name, unit);
                     } else {
-                        let mut params = vec![];
+                        let params = self.new_params(func);
+                        let id = self.fun_id;
 
-                        for (name, ty) in func.params.iter() {
-                            let ty = self.ty_to_ctype(ty);
-                            params.push(self.ctx.new_parameter(None, ty, &str(*name).to_string()));
+                        func.ir_temp_id = id;
+                        let name = mangle(func);
+                        // `@export` and `@no_mangle` overloads and `main` keep plain name, so they
+                        // may share symbol with other definition
+                        let clash = self.functions.values().flatten().find(|unit| {
+                            unit.irname == name && !same_signature(&unit.f, func)
+                        });
+                        if let Some(unit) = clash {
+                            error!(
+                                false,
+                                format!("symbol `{}` is already defined", name),
+                                func.pos
+                            );
+                            note!("previous definition of symbol is here", unit.f.pos);
+                            std::process::exit(-1);
                         }
-                        if func.this.is_some() {
-                            let (name, ty) = func.this.as_ref().unwrap();
-                            let ty = self.ty_to_ctype(ty);
-                            params.push(self.ctx.new_parameter(None, ty, &str(*name).to_string()));
+
+                        if func.variadic && func.is_c_abi() {
+                            error!(
+                                "variadic function called from C can't be defined with gccjit \
+                                 backend, it takes variadic arguments in array instead of va_list",
+                                func.pos
+                            );
                         }
-                        let name_str: &str = &str(func.name).to_string();
-                        let id = self.fun_id;
+                        let ret = self.ty_to_ctype(&func.ret);
 
-                        func.ir_temp_id = id;
-                        let name = if name_str == "main" {
-                            "main".to_owned()
+                        // variadic arguments come in array of slots, see `va_slots`
+                        let f = self
+                            .ctx
+                            .new_function(None, linkage, ret, &params, &name, false);
+                        let inline_c = if inline_called.contains(&func.name)
+                            || is_trivial_accessor(func) && !func.has_attribute("cold")
+                        {
+                            // parameters belong to single function, copy gets its own
+                            let params = self.new_params(func);
+                            Some(self.ctx.new_function(
+                                None,
+                                FunctionType::AlwaysInline,
+                                ret,
+                                &params,
+                                &format!("{}__inline", name),
+                                false,
+                            ))
                         } else {
-                            fn ty_to_n(ty: &Type) -> String {
-                                let mut s = String::new();
-
-                                match ty {
-                                    Type::Vector(v) => {
-                                        s.push_str(&format!("vec{}{}", v.subtype, v.size))
-                                    }
-                                    Type::Basic(b) => s.push_str(&str(b.name)),
-                                    Type::Ptr(ptr) => {
-                                        s.push_str("ptr");
-                                        s.push_str(&ty_to_n(&ptr.subtype));
-                                    }
-                                    Type::Func(_) => {
-                                        s.push_str(&format!("{}", ty));
-                                    }
-                                    Type::Struct(st) => s.push_str(&format!("{}", str(st.name))),
-                                    Type::Void(_) => s.push_str("v"),
-                                    Type::Array(array) => {
-                                        s.push_str("ptr");
-                                        s.push_str(&ty_to_n(&array.subtype));
-                                    }
-                                }
-
-                                s
-                            }
-                            let mut name = str(func.name).to_string();
-                            if func.this.is_some() {
-                                name.push_str("this");
-                                let this = *func.this.clone().unwrap().1.clone();
-                                name.push_str(&ty_to_n(&this));
-                            }
-                            for (_, param) in func.params.iter() {
-                                name.push_str(&ty_to_n(param));
-                            }
-                            name
+                            None
                         };
 
-                        let ret = self.ty_to_ctype(&func.ret);
+                        self.add_fn_hints(func, f);
 
-                        let f = self.ctx.new_function(
-                            None,
-                            linkage,
-                            ret,
-                            &params,
-                            &name,
-                            func.variadic,
-                        );
+                        if let Some(attr) = func.attribute("symver") {
+                            if let Some(AttributeArg::Str(version)) = attr.args.first() {
+                                self.symvers.push((name.clone(), version.clone()));
+                            }
+                        }
 
                         let (this_ast, this_ir) = if let Some((_, ty)) = &func.this {
                             let ty = *ty.clone();
@@ -1677,18 +3116,14 @@ fn ty_to_n(ty: &Type) -> String {
                         if let Some(functions) = self.functions.get_mut(&func.name) {
                             let mut found = false;
                             for fun in functions.iter_mut() {
-                                if fun.f.name == func.name
-                                    && fun.f.params == func.params
-                                    && fun.f.ret == func.ret
-                                    && fun.f.variadic == func.variadic
-                                    && fun.f.this == func.this
-                                {
+                                if same_signature(&fun.f, func) {
                                     *fun = FunctionUnit {
                                         f: func.clone(),
                                         c: f,
                                         this_ast: this_ast.clone(),
                                         this_ir,
                                         irname: name.clone(),
+                                        inline_c,
                                     };
                                     self.fun_id += 1;
                                     found = true;
@@ -1703,6 +3138,7 @@ fn ty_to_n(ty: &Type) -> String {
                                     this_ast: this_ast.clone(),
                                     this_ir,
                                     irname: name,
+                                    inline_c,
                                 });
                             }
                         } else {
@@ -1714,6 +3150,7 @@ fn ty_to_n(ty: &Type) -> String {
                                     this_ast,
                                     this_ir,
                                     irname: name,
+                                    inline_c,
                                 }],
                             );
                         }
@@ -1736,11 +3173,32 @@ fn ty_to_n(ty: &Type) -> String {
                     } else {
                         self.ctx.new_global(None, GlobalKind::Internal, cty, name)
                     };
+                    if global.thread_local {
+                        // global-dynamic is the only model valid for every output kind (JIT
+                        // code and shared libraries included)
+                        unsafe {
+                            gcc_jit_lvalue_set_tls_model(
+                                gccjit_rs::lvalue::get_ptr(&lval),
+                                gcc_jit_tls_model::GCC_JIT_TLS_MODEL_GLOBAL_DYNAMIC,
+                            );
+                        }
+                    }
+                    // only globals can have `@section`, libgccjit can't set it for function
+                    if let Some(section) = global.attribute("section") {
+                        let section = CString::new(section.str_arg().unwrap()).unwrap();
+                        unsafe {
+                            gcc_jit_lvalue_set_link_section(
+                                gccjit_rs::lvalue::get_ptr(&lval),
+                                section.as_ptr(),
+                            );
+                        }
+                    }
 
                     let varinfo = VarInfo {
                         lval,
                         cty,
                         ty: *global.typ.clone(),
+                        mutable: global.reassignable,
                     };
 
                     self.globals
@@ -1761,73 +3219,10 @@ fn ty_to_n(ty: &Type) -> String {
 
                         for fun in functions.iter() {
                             if fun.f.ir_temp_id == func.ir_temp_id {
-                                self.cur_func = Some(fun.c);
-                                self.cur_block = Some(fun.c.new_block("entry"));
-                                let block = self.cur_block.unwrap();
-
-                                if &str(func.name).to_string() == "main" {
-                                    for (_, (varinfo, expr)) in self.globals.clone().iter() {
-                                        if expr.is_some() {
-                                            let val = self.gen_expr(expr.as_ref().unwrap());
-                                            block.add_assignment(None, varinfo.lval, val);
-                                        }
-                                    }
+                                self.gen_function_body(func, fun.c);
+                                if let Some(inline_c) = fun.inline_c {
+                                    self.gen_function_body(func, inline_c);
                                 }
-
-                                for (i, (name, param)) in func.params.iter().enumerate() {
-                                    let cty = self.ty_to_ctype(param);
-                                    let loc = fun.c.new_local(None, cty, &str(*name).to_string());
-                                    let param_ = fun.c.get_param(i as _);
-                                    block.add_assignment(None, loc, param_.to_rvalue());
-                                    self.variables.insert(
-                                        *name,
-                                        VarInfo {
-                                            lval: loc,
-                                            cty,
-                                            ty: *param.clone(),
-                                        },
-                                    );
-                                }
-
-                                if let Some((name, ty)) = &func.this {
-                                    let cty = self.ty_to_ctype(ty);
-                                    let loc = fun.c.new_local(None, cty, &str(*name).to_string());
-                                    let param_ = fun.c.get_param(func.params.len() as _);
-                                    block.add_assignment(None, loc, param_);
-                                    self.variables.insert(
-                                        *name,
-                                        VarInfo {
-                                            lval: loc,
-                                            cty,
-                                            ty: *ty.clone(),
-                                        },
-                                    );
-                                }
-                                self.cur_return = Some(*func.ret.clone());
-                                self.gen_stmt(func.body.as_ref().unwrap(), true);
-                                /*if !self.terminated.last().unwrap_or(&false)
-                                {
-                                    let ret = self.cur_return.clone().unwrap().clone();
-                                    if ret.is_void()
-                                    {
-                                        self.cur_block.unwrap().end_with_void_return(None);
-                                    }
-                                    else
-                                    {
-                                        if ret.is_struct()
-                                        {
-                                            panic!("Can't create zero value for struct");
-                                        }
-                                        if !self.terminated.last().unwrap_or(&false)
-                                        {
-                                            let val =
-                                                self.ctx.new_rvalue_zero(self.ty_to_ctype(&ret));
-                                            self.cur_block.unwrap().end_with_return(None, val);
-                                        }
-                                    }
-                                }*/
-                                //let cty = ty_to_ctype(&func.ret, &self.ctx);
-                                //block.end_with_return(None,self.ctx.new_rvalue_zero(cty));
                             }
                         }
                     }
@@ -1838,40 +3233,841 @@ fn ty_to_n(ty: &Type) -> String {
         }
     }
 
+    /// Globals in order their initializers run: every global after globals its initializer
+    /// reads, otherwise in declaration order. Initializers depending on each other are an error
+    fn globals_init_order(&self) -> Vec<Name> {
+        type Globals = LinkedHashMap<Name, (VarInfo, Option<Box<Expr>>)>;
+        /// `visiting` holds globals whose dependencies are being ordered, outermost first
+        fn visit(globals: &Globals, name: Name, visiting: &mut Vec<Name>, order: &mut Vec<Name>) {
+            if order.contains(&name) {
+                return;
+            }
+            let expr = match &globals.get(&name).unwrap().1 {
+                Some(expr) => expr,
+                None => return order.push(name),
+            };
+            if let Some(start) = visiting.iter().position(|global| *global == name) {
+                let cycle = visiting[start..]
+                    .iter()
+                    .chain(std::iter::once(&name))
+                    .map(|global| str(*global).to_string())
+                    .collect::<Vec<_>>();
+                error!(
+                    format!(
+                        "initializers of globals depend on each other: {}",
+                        cycle.join(" -> ")
+                    ),
+                    expr.pos
+                );
+            }
+            visiting.push(name);
+            let mut idents = Idents::default();
+            idents.visit_expr(expr);
+            for dep in idents.0 {
+                if globals.contains_key(&dep) {
+                    visit(globals, dep, visiting, order);
+                }
+            }
+            visiting.pop();
+            order.push(name);
+        }
+
+        let mut order = vec![];
+        for name in self.globals.keys() {
+            visit(&self.globals, *name, &mut vec![], &mut order);
+        }
+        order
+    }
+
+    /// Generate body of `func` into gccjit function `c`
+    fn gen_function_body(&mut self, func: &Function, c: CFunction) {
+        self.cur_func = Some(c);
+        self.cur_block = Some(c.new_block("entry"));
+        let block = self.cur_block.unwrap();
+
+        if &str(func.name).to_string() == "main" {
+            if self.context.gc {
+                // collector must be ready before global initializers allocate
+                let void = self.ctx.new_type::<()>();
+                let gc_init = self.runtime_function("GC_init", void, &[], false);
+                block.add_eval(None, self.ctx.new_call(None, gc_init, &[]));
+            }
+            for name in self.globals_init_order() {
+                let (varinfo, expr) = self.globals.get(&name).unwrap().clone();
+                if let Some(expr) = expr {
+                    let val = self.gen_expr(&expr);
+                    block.add_assignment(None, varinfo.lval, val);
+                }
+            }
+        }
+
+        for (i, (name, param)) in func.params.iter().enumerate() {
+            let cty = self.ty_to_ctype(param);
+            let loc = c.new_local(None, cty, &str(*name).to_string());
+            let param_ = c.get_param(i as _);
+            block.add_assignment(None, loc, param_.to_rvalue());
+            self.variables.insert(
+                *name,
+                VarInfo {
+                    lval: loc,
+                    cty,
+                    ty: *param.clone(),
+                    mutable: true,
+                },
+            );
+        }
+
+        if let Some((name, ty)) = &func.this {
+            let cty = self.ty_to_ctype(ty);
+            let loc = c.new_local(None, cty, &str(*name).to_string());
+            let param_ = c.get_param(func.params.len() as _);
+            block.add_assignment(None, loc, param_);
+            self.variables.insert(
+                *name,
+                VarInfo {
+                    lval: loc,
+                    cty,
+                    ty: *ty.clone(),
+                    mutable: true,
+                },
+            );
+        }
+        self.va_list = if func.variadic {
+            let index = func.params.len() + func.this.is_some() as usize;
+            let slots = c.get_param(index as _).to_rvalue();
+            let next = c.new_local(None, slots.get_type(), "_va_next_");
+            Some((slots, next))
+        } else {
+            None
+        };
+        self.cur_return = Some(*func.ret.clone());
+        self.tail_calls = allows_tail_calls(func, &self.context.types);
+        let body = func.body.as_ref().unwrap();
+        self.gen_stmt(body, true);
+        if never_falls_through(body) {
+            // block after infinite loop has no predecessors, but gccjit still wants it
+            // terminated
+            self.end_unreachable(None);
+        }
+        /*if !self.terminated.last().unwrap_or(&false)
+        {
+            let ret = self.cur_return.clone().unwrap().clone();
+            if ret.is_void()
+            {
+                self.cur_block.unwrap().end_with_void_return(None);
+            }
+            else
+            {
+                if ret.is_struct()
+                {
+                    panic!("Can't create zero value for struct");
+                }
+                if !self.terminated.last().unwrap_or(&false)
+                {
+                    let val =
+                        self.ctx.new_rvalue_zero(self.ty_to_ctype(&ret));
+                    self.cur_block.unwrap().end_with_return(None, val);
+                }
+            }
+        }*/
+        //let cty = ty_to_ctype(&func.ret, &self.ctx);
+        //block.end_with_return(None,self.ctx.new_rvalue_zero(cty));
+    }
+
+    /// Pointer to first byte of string literal `s`. Literals with same contents share one
+    /// read-only global, so they compare equal
+    fn string_literal(&mut self, s: &str, loc: Option<gccjit_rs::location::Location>) -> RValue {
+        let global = match self.strings.get(s) {
+            Some(global) => *global,
+            None => {
+                let mut bytes = s.as_bytes().to_vec();
+                bytes.push(0);
+                let char_ty = self.ctx.new_type::<char>();
+                let cty = self.ctx.new_array_type(None, char_ty, bytes.len() as i32);
+                let global = self.ctx.new_global(
+                    None,
+                    GlobalKind::Internal,
+                    cty.make_const(),
+                    &format!("_str_{}_", self.strings.len()),
+                );
+                unsafe {
+                    gcc_jit_global_set_initializer(
+                        gccjit_rs::lvalue::get_ptr(&global),
+                        bytes.as_ptr() as *const _,
+                        bytes.len(),
+                    );
+                }
+                self.strings.insert(s.to_owned(), global);
+                global
+            }
+        };
+        let zero = self.ctx.new_rvalue_zero(self.ctx.new_type::<i32>());
+        self.ctx
+            .new_array_access(loc, global.to_rvalue(), zero)
+            .get_address(loc)
+    }
+
+    /// Zero value of type `ty`, aggregates get it from zero-initialized global of their type
+    fn zero_value(&mut self, ty: &Type) -> RValue {
+        let cty = self.ty_to_ctype(ty);
+        match ty {
+            Type::Ptr(_) | Type::Func(_) => return self.ctx.new_null(cty),
+            _ if !is_aggregate(ty) && !ty.is_slice() && self.find_struct(ty).is_none() => {
+                return self.ctx.new_rvalue_zero(cty)
+            }
+            _ => (),
+        }
+        let key = ty.to_string();
+        if let Some(zero) = self.zeroes.get(&key) {
+            return zero.to_rvalue();
+        }
+        let zero = self.ctx.new_global(
+            None,
+            GlobalKind::Internal,
+            cty,
+            &format!("_zero_{}_", self.zeroes.len()),
+        );
+        self.zeroes.insert(key, zero);
+        zero.to_rvalue()
+    }
+
+    /// Convert scalar to `bool` that is always 0 or 1: `val != 0` (or `val != null`)
+    fn to_bool(
+        &self,
+        val: RValue,
+        ty: &Type,
+        loc: Option<gccjit_rs::location::Location>,
+    ) -> RValue {
+        if crate::semantic::ty_is_bool(ty) {
+            return val;
+        }
+        let zero = match ty {
+            Type::Ptr(_) | Type::Func(_) => self.ctx.new_null(val.get_type()),
+            _ => self.ctx.new_rvalue_zero(val.get_type()),
+        };
+        self.ctx
+            .new_comparison(loc, ComparisonOp::NotEquals, val, zero)
+    }
+
+    /// Lanes of vector `val` of type `ty`, read through pointer to its first lane
+    fn vec_lanes(
+        &mut self,
+        val: RValue,
+        ty: &Type,
+        loc: Option<gccjit_rs::location::Location>,
+    ) -> Vec<RValue> {
+        let vec = ty.to_vec().unwrap();
+        let cty = self.ty_to_ctype(ty);
+        let tmp = self
+            .cur_func
+            .unwrap()
+            .new_local(loc, cty, &format!("_vec_{}_", self.tmp_id));
+        self.tmp_id += 1;
+        self.cur_block.unwrap().add_assignment(loc, tmp, val);
+
+        let lane_ptr = self.ty_to_ctype(&vec.subtype).make_pointer();
+        let lanes = self.ctx.new_cast(loc, tmp.get_address(loc), lane_ptr);
+        let idx_ty = self.ctx.new_type::<i32>();
+        (0..vec.size)
+            .map(|i| {
+                let idx = self.ctx.new_rvalue_from_int(idx_ty, i as i32);
+                self.ctx.new_array_access(loc, lanes, idx).to_rvalue()
+            })
+            .collect()
+    }
+
+    /// Compare vectors `lhs` and `rhs` of type `ty` lane by lane, producing vector of type
+    /// `mask` with -1 in lanes where comparison holds and 0 elsewhere
+    fn vec_comparison(
+        &mut self,
+        op: ComparisonOp,
+        lhs: RValue,
+        rhs: RValue,
+        ty: &Type,
+        mask: &Type,
+        loc: Option<gccjit_rs::location::Location>,
+    ) -> RValue {
+        let lhs = self.vec_lanes(lhs, ty, loc);
+        let rhs = self.vec_lanes(rhs, ty, loc);
+
+        let mask_cty = self.ty_to_ctype(mask);
+        let result = self
+            .cur_func
+            .unwrap()
+            .new_local(loc, mask_cty, &format!("_mask_{}_", self.tmp_id));
+        self.tmp_id += 1;
+        let lane_cty = self.ty_to_ctype(&mask.to_vec().unwrap().subtype);
+        let lanes = self
+            .ctx
+            .new_cast(loc, result.get_address(loc), lane_cty.make_pointer());
+        let idx_ty = self.ctx.new_type::<i32>();
+        for (i, (l, r)) in lhs.into_iter().zip(rhs).enumerate() {
+            let holds = self.ctx.new_comparison(loc, op, l, r);
+            let one = self.ctx.new_cast(loc, holds, lane_cty);
+            let lane = self.ctx.new_unary_op(loc, UnaryOp::Minus, lane_cty, one);
+            let idx = self.ctx.new_rvalue_from_int(idx_ty, i as i32);
+            self.cur_block
+                .unwrap()
+                .add_assignment(loc, self.ctx.new_array_access(loc, lanes, idx), lane);
+        }
+        result.to_rvalue()
+    }
+
+    /// Convert scalar `val` of type `from` to type `to`.
+    ///
+    /// gccjit casts only between numeric types and between pointer types, so pointer <-> integer
+    /// conversions go through `usize` and reinterpret its bits via memory.
+    fn convert(
+        &mut self,
+        val: RValue,
+        from: &Type,
+        to: &Type,
+        loc: Option<gccjit_rs::location::Location>,
+    ) -> RValue {
+        let is_ptr = |ty: &Type| match ty {
+            Type::Ptr(_) | Type::Func(_) => true,
+            _ => false,
+        };
+        if crate::semantic::ty_is_bool(to) {
+            return self.to_bool(val, from, loc);
+        }
+        let to_cty = self.ty_to_ctype(to);
+        let usize_cty = self.ctx.new_type::<usize>();
+        match (is_ptr(from), is_ptr(to)) {
+            (true, false) => {
+                let from_cty = self.ty_to_ctype(from);
+                let bits = self.reinterpret(val, from_cty, usize_cty, loc);
+                self.ctx.new_cast(loc, bits, to_cty)
+            }
+            (false, true) => {
+                let bits = self.ctx.new_cast(loc, val, usize_cty);
+                self.reinterpret(bits, usize_cty, to_cty, loc)
+            }
+            _ => self.ctx.new_cast(loc, val, to_cty),
+        }
+    }
+
+    /// Address of first element of fixed-size array, i.e what array decays to as pointer
+    fn array_address(
+        &mut self,
+        array: &Expr,
+        loc: Option<gccjit_rs::location::Location>,
+    ) -> RValue {
+        let lval = match self.expr_to_lvalue(array) {
+            Some(lval) => lval,
+            None => {
+                // temporary array (e.g returned from call), spill it so it has an address
+                let rval = self.gen_expr(array);
+                let tmp = self.cur_func.unwrap().new_local(
+                    loc,
+                    rval.get_type(),
+                    &format!("_arr_{}_", self.tmp_id),
+                );
+                self.tmp_id += 1;
+                self.cur_block.unwrap().add_assignment(loc, tmp, rval);
+                tmp
+            }
+        };
+        let zero = self.ctx.new_rvalue_zero(self.ctx.new_type::<i32>());
+        self.ctx
+            .new_array_access(loc, lval.to_rvalue(), zero)
+            .get_address(loc)
+    }
+
+    /// Struct type of slices with `subtype` elements
+    fn slice_type(&mut self, subtype: &Type) -> SliceType {
+        let key = subtype.to_string();
+        if let Some(slice) = self.slices.get(&key) {
+            return *slice;
+        }
+        let ptr_ty = self.ty_to_ctype(subtype).make_pointer();
+        let ptr = self.ctx.new_field(None, ptr_ty, "ptr");
+        let len = self.ctx.new_field(None, self.ctx.new_type::<usize>(), "len");
+        let ty = self
+            .ctx
+            .new_struct_type(None, &format!("slice_{}", key), &[ptr, len])
+            .as_type();
+        let slice = SliceType { ty, ptr, len };
+        self.slices.insert(key, slice);
+        slice
+    }
+
+    /// Slice with `subtype` elements starting at `ptr`
+    fn new_slice(
+        &mut self,
+        subtype: &Type,
+        ptr: RValue,
+        len: RValue,
+        loc: Option<gccjit_rs::location::Location>,
+    ) -> RValue {
+        let slice = self.slice_type(subtype);
+        let tmp = self.cur_func.unwrap().new_local(
+            loc,
+            slice.ty,
+            &format!("_slice_{}_", self.tmp_id),
+        );
+        self.tmp_id += 1;
+        let ptr_ty = self.ty_to_ctype(subtype).make_pointer();
+        let usize_ty = self.ctx.new_type::<usize>();
+        let block = self.cur_block.unwrap();
+        block.add_assignment(
+            loc,
+            tmp.access_field(loc, slice.ptr),
+            self.ctx.new_cast(loc, ptr, ptr_ty),
+        );
+        block.add_assignment(
+            loc,
+            tmp.access_field(loc, slice.len),
+            self.ctx.new_cast(loc, len, usize_ty),
+        );
+        tmp.to_rvalue()
+    }
+
+    /// Slice value of `slice` as lvalue, temporary slices are spilled to local
+    fn slice_lvalue(
+        &mut self,
+        slice: &Expr,
+        loc: Option<gccjit_rs::location::Location>,
+    ) -> LValue {
+        match self.expr_to_lvalue(slice) {
+            Some(lval) => lval,
+            None => {
+                let rval = self.gen_expr(slice);
+                let tmp = self.cur_func.unwrap().new_local(
+                    loc,
+                    rval.get_type(),
+                    &format!("_slice_{}_", self.tmp_id),
+                );
+                self.tmp_id += 1;
+                self.cur_block.unwrap().add_assignment(loc, tmp, rval);
+                tmp
+            }
+        }
+    }
+
+    /// Pointer to first element and length of fixed-size array or slice `array`
+    fn slice_parts(
+        &mut self,
+        array: &Expr,
+        loc: Option<gccjit_rs::location::Location>,
+    ) -> (RValue, RValue) {
+        let ty = self.get_expr_type(array);
+        let array_ty = ty.to_array().unwrap();
+        if let Some(len) = array_ty.len {
+            let usize_ty = self.ctx.new_type::<usize>();
+            let len = self.ctx.new_rvalue_from_long(usize_ty, len as _);
+            return (self.array_address(array, loc), len);
+        }
+        let slice = self.slice_type(&array_ty.subtype);
+        let lval = self.slice_lvalue(array, loc);
+        (
+            lval.access_field(loc, slice.ptr).to_rvalue(),
+            lval.access_field(loc, slice.len).to_rvalue(),
+        )
+    }
+
+    /// Fixed-size `array` passed where `to` expects it decayed: address of its first element
+    /// for pointer, slice over all elements for slice
+    fn decay_array(
+        &mut self,
+        array: &Expr,
+        to: &Type,
+        loc: Option<gccjit_rs::location::Location>,
+    ) -> RValue {
+        match to.to_array() {
+            Some(slice) => {
+                let (ptr, len) = self.slice_parts(array, loc);
+                self.new_slice(&slice.subtype, ptr, len, loc)
+            }
+            None => self.array_address(array, loc),
+        }
+    }
+
+    /// Reinterpret bits of `val` as value of same-sized type `to`: `*(to*)&tmp`
+    fn reinterpret(
+        &mut self,
+        val: RValue,
+        from: CType,
+        to: CType,
+        loc: Option<gccjit_rs::location::Location>,
+    ) -> RValue {
+        let tmp = self
+            .cur_func
+            .unwrap()
+            .new_local(loc, from, &format!("_cast_{}_", self.tmp_id));
+        self.tmp_id += 1;
+        self.cur_block.unwrap().add_assignment(loc, tmp, val);
+        let addr = tmp.get_address(loc);
+        self.ctx
+            .new_cast(loc, addr, to.make_pointer())
+            .dereference(loc)
+            .to_rvalue()
+    }
+
+    /// Parameters of gccjit function: normal parameters first, then `this`
+    fn new_params(&mut self, func: &Function) -> Vec<Parameter> {
+        let mut params = vec![];
+
+        for (name, ty) in func.params.iter() {
+            let ty = self.ty_to_ctype(ty);
+            params.push(self.ctx.new_parameter(None, ty, &str(*name).to_string()));
+        }
+        if let Some((name, ty)) = &func.this {
+            let ty = self.ty_to_ctype(ty);
+            params.push(self.ctx.new_parameter(None, ty, &str(*name).to_string()));
+        }
+        if func.variadic {
+            let slots = self.ctx.new_type::<u64>().make_pointer();
+            params.push(self.ctx.new_parameter(None, slots, "_va_slots_"));
+        }
+        params
+    }
+
+    /// Declare libc function used by generated runtime checks, or reuse program's own extern
+    /// declaration of it
+    fn runtime_function(
+        &mut self,
+        name: &str,
+        ret: CType,
+        params: &[CType],
+        variadic: bool,
+    ) -> CFunction {
+        if let Some(unit) = self.external_functions.get(&crate::intern(name)) {
+            return unit.c;
+        }
+        if let Some(f) = self.runtime_functions.get(name) {
+            return *f;
+        }
+        let params = params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| self.ctx.new_parameter(None, *ty, &format!("arg{}", i)))
+            .collect::<Vec<_>>();
+        let f = self
+            .ctx
+            .new_function(None, FunctionType::Extern, ret, &params, name, variadic);
+        self.runtime_functions.insert(name.to_owned(), f);
+        f
+    }
+
+    /// Function called by failed runtime checks, `abort` unless `--panic-handler` names other
+    /// one. Handler defined in program is called directly, declared one is resolved by linker.
+    fn panic_handler(&mut self) -> CFunction {
+        let handler = self.context.panic_handler.clone();
+        let units = self.functions.get(&crate::intern(&handler));
+        if let Some(unit) = units.and_then(|units| units.first()) {
+            return unit.c;
+        }
+        let void = self.ctx.new_type::<()>();
+        self.runtime_function(&handler, void, &[], false)
+    }
+
+    /// Check that `--panic-handler` names function declared in program that takes no
+    /// arguments, default `abort` comes from libc
+    fn check_panic_handler(&self) {
+        let name = self.context.panic_handler.clone();
+        if name == "abort" {
+            return;
+        }
+        let handler = self.context.file.elems.iter().find_map(|elem| match elem {
+            Elem::Func(func) if str(func.name).as_str() == name => Some(func),
+            _ => None,
+        });
+        match handler {
+            Some(func) if func.params.is_empty() && func.this.is_none() => (),
+            Some(func) => error!(
+                format!("panic handler `{}` must take no arguments", name),
+                func.pos
+            ),
+            None => {
+                eprintln!(
+                    "{}: panic handler `{}` is not declared",
+                    "ERROR".red(),
+                    name
+                );
+                std::process::exit(-1);
+            }
+        }
+    }
+
+    /// Continue in new block if `failed` is false, otherwise print
+    /// `"<pos>: <message>"` (`printf`-formatted with `args`) to stderr and call panic handler
+    fn runtime_check(
+        &mut self,
+        failed: RValue,
+        pos: crate::syntax::position::Position,
+        message: &str,
+        args: &[RValue],
+    ) {
+        let loc = Some(gccloc_from_loc(&self.ctx, &pos));
+        let func = self.cur_func.unwrap();
+        let fail = func.new_block(&self.block_name_new());
+        let ok = func.new_block(&self.block_name_new());
+        self.cur_block
+            .unwrap()
+            .end_with_conditional(loc, failed, fail, ok);
+
+        let format = self
+            .ctx
+            .new_string_literal(&format!("{}: {}\n", pos, message));
+        let int = self.ctx.new_type::<i32>();
+        let dprintf = self.runtime_function("dprintf", int, &[int, format.get_type()], true);
+        let mut dprintf_args = vec![self.ctx.new_rvalue_from_int(int, 2), format];
+        dprintf_args.extend_from_slice(args);
+        fail.add_eval(loc, self.ctx.new_call(loc, dprintf, &dprintf_args));
+        let handler = self.panic_handler();
+        fail.add_eval(loc, self.ctx.new_call(loc, handler, &[]));
+        fail.end_with_jump(loc, ok);
+
+        self.cur_block = Some(ok);
+    }
+
+    /// Compute `l op r` in `__int128`, where it can't overflow, and abort if result doesn't fit
+    /// into `range` of operand type `cty`
+    fn checked_arith(
+        &mut self,
+        op: &str,
+        cty: CType,
+        range: (i64, i64),
+        l: RValue,
+        r: RValue,
+        pos: crate::syntax::position::Position,
+    ) -> RValue {
+        let loc = Some(gccloc_from_loc(&self.ctx, &pos));
+        let binary = match op {
+            "+" => BinaryOp::Plus,
+            "-" => BinaryOp::Minus,
+            _ => BinaryOp::Mult,
+        };
+        let wide = self.int128_type(true);
+        let l = self.ctx.new_cast(loc, l, wide);
+        let r = self.ctx.new_cast(loc, r, wide);
+        let result = self
+            .cur_func
+            .unwrap()
+            .new_local(loc, wide, &format!("_wide_{}_", self.tmp_id));
+        self.tmp_id += 1;
+        self.cur_block
+            .unwrap()
+            .add_assignment(loc, result, self.ctx.new_binary_op(loc, binary, wide, l, r));
+
+        let i64_ty = self.ctx.new_type::<i64>();
+        let (min, max) = range;
+        let min = self
+            .ctx
+            .new_cast(loc, self.ctx.new_rvalue_from_long(i64_ty, min), wide);
+        let max = self
+            .ctx
+            .new_cast(loc, self.ctx.new_rvalue_from_long(i64_ty, max), wide);
+        let below = self
+            .ctx
+            .new_comparison(loc, ComparisonOp::LessThan, result.to_rvalue(), min);
+        let above = self
+            .ctx
+            .new_comparison(loc, ComparisonOp::GreaterThan, result.to_rvalue(), max);
+        let failed = self.ctx.new_binary_op(
+            loc,
+            BinaryOp::LogicalOr,
+            self.ctx.new_type::<bool>(),
+            below,
+            above,
+        );
+        self.runtime_check(
+            failed,
+            pos,
+            &format!("signed integer overflow in `{}`", op),
+            &[],
+        );
+        self.ctx.new_cast(loc, result.to_rvalue(), cty)
+    }
+
+    /// With `--debug-null` abort if pointer `ptr` dereferenced at `pos` is null. Returns value
+    /// to dereference instead of `ptr`, so pointer expression is evaluated once.
+    fn check_null(&mut self, ptr: RValue, pos: crate::syntax::position::Position) -> RValue {
+        if !self.context.debug_null {
+            return ptr;
+        }
+        let loc = Some(gccloc_from_loc(&self.ctx, &pos));
+        let ty = ptr.get_type();
+        let tmp = self
+            .cur_func
+            .unwrap()
+            .new_local(loc, ty, &format!("_ptr_{}_", self.tmp_id));
+        self.tmp_id += 1;
+        self.cur_block.unwrap().add_assignment(loc, tmp, ptr);
+        let is_null = self.ctx.new_comparison(
+            loc,
+            ComparisonOp::Equals,
+            tmp.to_rvalue(),
+            self.ctx.new_null(ty),
+        );
+        self.runtime_check(is_null, pos, "null pointer dereference", &[]);
+        tmp.to_rvalue()
+    }
+
+    /// Whether `expr` calls function being generated, and the call may reuse its frame when it's
+    /// in tail position. Calls from always-inline copy of function call the original, so they're
+    /// never self calls.
+    fn is_self_call(&mut self, expr: &Expr) -> bool {
+        let (name, args) = match &expr.kind {
+            ExprKind::Call(name, None, args) if self.tail_calls => (name, args),
+            _ => return false,
+        };
+        let functions = match self.functions.get(&name.name()) {
+            Some(functions) => functions.clone(),
+            None => return false,
+        };
+        let types = args
+            .iter()
+            .map(|arg| self.get_expr_type(arg))
+            .collect::<Vec<_>>();
+        match self.search_for_func(&types, None, &functions) {
+            Some((callee, _, _)) => {
+                gccjit_rs::function::get_ptr(&callee)
+                    == gccjit_rs::function::get_ptr(&self.cur_func.unwrap())
+            }
+            None => false,
+        }
+    }
+
+    fn add_fn_attribute(&self, f: CFunction, attribute: gcc_jit_fn_attribute) {
+        unsafe {
+            gcc_jit_function_add_attribute(gccjit_rs::function::get_ptr(&f), attribute);
+        }
+    }
+
+    /// Pass `@pure`, `@const`, `@inline` and `@cold` of `func` to its gccjit function `f`.
+    /// `@inline` only asks to inline calls, unlike `inline` modifier it doesn't change linkage.
+    /// `@cold` function is placed apart from hot code and never inlined into its callers.
+    fn add_fn_hints(&self, func: &Function, f: CFunction) {
+        if func.has_attribute("pure") {
+            self.add_fn_attribute(f, gcc_jit_fn_attribute::GCC_JIT_FN_ATTRIBUTE_PURE);
+        }
+        if func.has_attribute("const") {
+            self.add_fn_attribute(f, gcc_jit_fn_attribute::GCC_JIT_FN_ATTRIBUTE_CONST);
+        }
+        if (func.inline || func.has_attribute("inline")) && !func.external {
+            self.add_fn_attribute(f, gcc_jit_fn_attribute::GCC_JIT_FN_ATTRIBUTE_ALWAYS_INLINE);
+        }
+        if func.has_attribute("cold") {
+            self.add_fn_attribute(f, gcc_jit_fn_attribute::GCC_JIT_FN_ATTRIBUTE_COLD);
+            self.add_fn_attribute(f, gcc_jit_fn_attribute::GCC_JIT_FN_ATTRIBUTE_NOINLINE);
+        }
+    }
+
+    /// Emit `.symver` directives for `@symver` functions and, when linking, pass version script
+    /// that defines every used version node. Returns path of written script, it's removed after
+    /// linking.
+    fn emit_symvers(&mut self) -> Option<std::path::PathBuf> {
+        if self.symvers.is_empty() {
+            return None;
+        }
+        let mut nodes = vec![];
+        for (symbol, version) in self.symvers.iter() {
+            let asm = CString::new(format!(".symver {}, {}", symbol, version)).unwrap();
+            use gccjit_rs::sys::*;
+            unsafe {
+                let ptr = gccjit_rs::ctx::context_get_ptr(&self.ctx);
+                gcc_jit_context_add_top_level_asm(ptr, std::ptr::null_mut(), asm.as_ptr());
+            }
+            let node = version.trim_start_matches(|c| c != '@').trim_start_matches('@');
+            if !nodes.contains(&node) {
+                nodes.push(node);
+            }
+        }
+
+        if self.context.jit || self.context.emit_obj || self.context.emit_asm {
+            return None;
+        }
+        let script = nodes
+            .iter()
+            .map(|node| format!("{} {{ }};\n", node))
+            .collect::<String>();
+        let path = std::env::temp_dir().join(format!("havo-{}.map", std::process::id()));
+        std::fs::write(&path, script).unwrap();
+        self.ctx
+            .add_driver_option(&format!("-Wl,--version-script={}", path.display()));
+        Some(path)
+    }
+
     pub fn compile(&mut self) {
         if self.context.emit_asm && self.context.jit {
             self.ctx.set_dump_code(true);
         }
 
-        self.ctx
-            .set_opt_level(unsafe { std::mem::transmute(i32::from(self.context.opt)) });
+        match opt_level(self.context.opt) {
+            Some(level) => self.ctx.set_opt_level(level),
+            None => {
+                eprintln!(
+                    "{}: optimization level {} is out of range, expected 0..3",
+                    "ERROR".red(),
+                    self.context.opt
+                );
+                std::process::exit(-1);
+            }
+        }
 
+        if self.context.gc {
+            self.ctx.add_driver_option("-lgc");
+        }
+        if !self.context.sanitize.is_empty() {
+            if self.context.jit {
+                eprintln!(
+                    "{}: `--sanitize` needs AOT compilation, it can't be used with `--jit`",
+                    "ERROR".red()
+                );
+                std::process::exit(-1);
+            }
+            // compiler instruments code, driver links sanitizer runtime
+            let sanitize = format!("-fsanitize={}", self.context.sanitize.join(","));
+            self.ctx.add_command_line_option(&sanitize);
+            self.ctx.add_driver_option(&sanitize);
+        }
+        self.check_panic_handler();
+        match target_ptr_size() {
+            // layouts and folded `sizeof` use `PTR_SIZE`
+            Some(size) if size != PTR_SIZE => {
+                eprintln!(
+                    "{}: target has {}-byte pointers, only targets with {}-byte pointers are \
+                     supported",
+                    "ERROR".red(),
+                    size,
+                    PTR_SIZE
+                );
+                std::process::exit(-1);
+            }
+            _ => (),
+        }
         let mut elems = self.context.file.elems.clone();
 
         self.gen_toplevel(&mut elems);
+        let version_script = self.emit_symvers();
 
         if self.context.jit {
-            use std::env::args;
-
             let result = self.ctx.compile();
-            let args = args();
-            let argc = args.len() as i32;
-            let argv: Vec<String> = args.collect::<Vec<String>>();
-            let argv_c = argv
+            let argc = self.context.args.len() as i32;
+            let argv = self
+                .context
+                .args
                 .iter()
-                .map(|s| std::ffi::CString::new(s.as_bytes()).unwrap().as_ptr())
+                .map(|arg| CString::new(arg.as_bytes()).unwrap())
                 .collect::<Vec<_>>();
-
-            let env = std::env::vars();
-            let mut envp = vec![];
-            for (key, val) in env {
-                envp.push(CString::new(format!("{} = {}", key, val)).unwrap().as_ptr());
-            }
+            let env = std::env::vars()
+                .map(|(key, val)| CString::new(format!("{}={}", key, val)).unwrap())
+                .collect::<Vec<_>>();
+            // both arrays are null-terminated, strings stay alive until `main` returns
+            let mut argv_c = argv.iter().map(|arg| arg.as_ptr()).collect::<Vec<_>>();
+            argv_c.push(std::ptr::null());
+            let mut envp = env.iter().map(|var| var.as_ptr()).collect::<Vec<_>>();
+            envp.push(std::ptr::null());
 
             let main_fn: fn(i32, *const *const i8, *const *const i8) -> i32 =
                 unsafe { std::mem::transmute(result.get_function("main")) };
 
-            main_fn(argc, argv_c.as_ptr(), envp.as_slice().as_ptr());
+            let status = main_fn(argc, argv_c.as_ptr(), envp.as_ptr());
+            if self.context.run {
+                std::process::exit(status);
+            }
         } else {
             // these two calls needed because by default binary don't linked with libc and
             // libm
@@ -1892,6 +4088,9 @@ pub fn compile(&mut self) {
                 OutputKind::Executable
             };
             self.ctx.compile_to_file(kind, out_path);
+            if let Some(script) = version_script {
+                let _ = std::fs::remove_file(script);
+            }
         }
     }
 }
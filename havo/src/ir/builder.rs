@@ -1,4 +1,5 @@
-use super::{Block, Entity, Function, InstructionData, IrType, Opcode, Value, CFG};
+use super::{Block, Entity, FuncionId, Function, InstructionData, IrType, Opcode, Value, CFG};
+use crate::syntax::interner::intern;
 use linked_hash_map::LinkedHashMap as HashMap;
 
 pub struct FunctionBuilder<'a> {
@@ -44,13 +45,100 @@ pub fn stack_alloc(&mut self, size: usize) -> Value {
     pub fn stack_alloc_ty(&mut self, ty: IrType) -> Value {
         let value = self
             .cfg()
-            .make_inst(InstructionData::Alloca(Opcode::Alloca, box ty));
-        self.func
-            .value_types
-            .insert(value, IrType::Ptr(box IrType::UInt(8)));
+            .make_inst(InstructionData::Alloca(Opcode::Alloca, box ty.clone()));
+        self.func.value_types.insert(value, IrType::Ptr(box ty));
         value
     }
 
+    pub fn current_block(&self) -> Option<Block> {
+        self.func.cfg.current_block()
+    }
+
+    pub fn is_terminated(&self, block: Block) -> bool {
+        self.func.cfg.is_terminated(block)
+    }
+
+    fn typed(&mut self, data: InstructionData, ty: IrType) -> Value {
+        let value = self.cfg().make_inst(data);
+        self.func.value_types.insert(value, ty);
+        value
+    }
+
+    pub fn f32const(&mut self, imm: f32) -> Value {
+        self.typed(
+            InstructionData::UnaryIeee32(Opcode::F32Const, imm.to_bits()),
+            IrType::F32,
+        )
+    }
+
+    pub fn f64const(&mut self, imm: f64) -> Value {
+        self.typed(
+            InstructionData::UnaryIeee64(Opcode::F64Const, imm.to_bits()),
+            IrType::F64,
+        )
+    }
+
+    pub fn strconst(&mut self, s: &str) -> Value {
+        self.typed(
+            InstructionData::UnaryStr(Opcode::StrConst, intern(s)),
+            IrType::Ptr(box IrType::Int(8)),
+        )
+    }
+
+    pub fn zero_init(&mut self, ty: IrType) -> Value {
+        self.typed(
+            InstructionData::ZeroInit(Opcode::ZeroInit, box ty.clone()),
+            ty,
+        )
+    }
+
+    /// Integer or float binary operation producing value of type `ty`
+    pub fn binary(&mut self, op: Opcode, lhs: Value, rhs: Value, ty: IrType) -> Value {
+        let data = match op {
+            Opcode::Fadd
+            | Opcode::Fsub
+            | Opcode::Fmul
+            | Opcode::Fdiv
+            | Opcode::Fmod
+            | Opcode::Fcmp(_) => InstructionData::FloatBinary(op, lhs, rhs),
+            _ => InstructionData::IntBinary(op, lhs, rhs),
+        };
+        self.typed(data, ty)
+    }
+
+    pub fn load_param(&mut self, ty: IrType, idx: u16) -> Value {
+        self.typed(
+            InstructionData::LoadParam(Opcode::LoadParam, box ty.clone(), idx),
+            ty,
+        )
+    }
+
+    /// Load value of type `ty` stored at `ptr`
+    pub fn load(&mut self, ptr: Value, ty: IrType) -> Value {
+        self.typed(InstructionData::Unary(Opcode::Load, ptr), ty)
+    }
+
+    /// Load value of type `ty` stored at `ptr[idx]`
+    pub fn load_index(&mut self, ptr: Value, idx: Value, ty: IrType) -> Value {
+        self.typed(InstructionData::Load(Opcode::Load, ptr, idx), ty)
+    }
+
+    pub fn cast(&mut self, val: Value, ty: IrType) -> Value {
+        self.typed(
+            InstructionData::Cast(Opcode::Cast, val, box ty.clone()),
+            ty,
+        )
+    }
+
+    pub fn call(&mut self, func: FuncionId, args: Vec<Value>, ret: IrType) -> Value {
+        self.typed(InstructionData::Call(Opcode::Call, func, args), ret)
+    }
+
+    pub fn return_void(&mut self) {
+        self.cfg()
+            .make_inst(InstructionData::VoidReturn(Opcode::Return));
+    }
+
     pub fn iconst(&mut self, type_: IrType, imm: impl Into<i64>) -> Value {
         let value = self
             .cfg()
@@ -0,0 +1,557 @@
+//! Lowering of type checked AST into IR.
+//!
+//! Every local variable lives in its own stack slot (`alloca`) and is accessed with
+//! `load`/`store`, so lowering doesn't need to build SSA form.
+
+use super::{
+    builder::FunctionBuilder, Block, CondCode, Entity, FuncionId, Function, IrType, Module,
+    Opcode, Value,
+};
+use crate::{
+    ast::{Elem, Expr, ExprKind, Function as AstFunction, Stmt, StmtKind, Type},
+    str,
+    syntax::{interner::Name, lexer::token::FloatSuffix},
+    Context,
+};
+use colored::Colorize;
+use std::collections::HashMap;
+
+pub fn ty_to_ir(ty: &Type) -> IrType {
+    match ty {
+        Type::Basic(basic) => match str(basic.name).as_str() {
+            "i8" | "char" => IrType::Int(8),
+            "i16" => IrType::Int(16),
+            "i32" => IrType::Int(32),
+            "i64" => IrType::Int(64),
+            "i128" => IrType::Int(128),
+            "u8" | "uchar" => IrType::UInt(8),
+            "u16" => IrType::UInt(16),
+            "u32" => IrType::UInt(32),
+            "u64" => IrType::UInt(64),
+            "u128" => IrType::UInt(128),
+            "usize" => IrType::Usize,
+            "isize" => IrType::Isize,
+            "f32" => IrType::F32,
+            "f64" => IrType::F64,
+            "bool" => IrType::Bool,
+            name => error!(format!("type `{}` can't be lowered to IR", name), basic.pos),
+        },
+        Type::Void(_) => IrType::Void,
+        Type::Ptr(ptr) => IrType::Ptr(box ty_to_ir(&ptr.subtype)),
+        Type::Array(array) => IrType::Ptr(box ty_to_ir(&array.subtype)),
+        Type::Struct(s) => IrType::Struct(
+            str(s.name).to_string(),
+            s.fields.iter().map(|f| ty_to_ir(&f.data_type)).collect(),
+        ),
+        Type::Func(func) => IrType::Func(
+            func.params.iter().map(|p| ty_to_ir(p)).collect(),
+            box ty_to_ir(&func.ret),
+        ),
+        Type::Vector(v) => error!("vector types can't be lowered to IR yet", v.pos),
+    }
+}
+
+/// Lower all functions of the program
+pub fn lower(ctx: &Context) -> Module {
+    let functions = ctx
+        .file
+        .elems
+        .iter()
+        .filter_map(|elem| match elem {
+            Elem::Func(func) => Some(func.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut module = Module { functions: vec![] };
+    for (id, func) in functions.iter().enumerate() {
+        let mut ir = Function::new(FuncionId::new(id), &str(func.name));
+        ir.params = params_of(func).iter().map(|ty| ty_to_ir(ty)).collect();
+        ir.ret = ty_to_ir(&func.ret);
+        ir.external = func.external || func.body.is_none();
+        if !ir.external {
+            let mut lowering = FunctionLowering {
+                ctx,
+                functions: &functions,
+                builder: FunctionBuilder::new(&mut ir),
+                scopes: vec![HashMap::new()],
+                loops: vec![],
+            };
+            lowering.lower_body(func);
+        }
+        module.functions.push(ir);
+    }
+    module
+}
+
+/// Parameter types in calling order, method receiver comes last
+fn params_of(func: &AstFunction) -> Vec<Type> {
+    let mut params = func
+        .params
+        .iter()
+        .map(|(_, ty)| (**ty).clone())
+        .collect::<Vec<_>>();
+    if let Some((_, this)) = &func.this {
+        params.push((**this).clone());
+    }
+    params
+}
+
+struct FunctionLowering<'a, 'b> {
+    ctx: &'a Context,
+    functions: &'a [AstFunction],
+    builder: FunctionBuilder<'b>,
+    /// stack slots of variables visible in each nested block
+    scopes: Vec<HashMap<Name, (Value, IrType)>>,
+    /// (continue, break) targets of enclosing loops
+    loops: Vec<(Block, Block)>,
+}
+
+impl<'a, 'b> FunctionLowering<'a, 'b> {
+    fn lower_body(&mut self, func: &AstFunction) {
+        let entry = self.builder.new_block();
+        self.builder.switch_to_block(entry);
+
+        let mut names = func.params.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        if let Some((this, _)) = &func.this {
+            names.push(*this);
+        }
+        for (i, (name, ty)) in names.iter().zip(params_of(func).iter()).enumerate() {
+            let ty = ty_to_ir(ty);
+            let param = self.builder.load_param(ty.clone(), i as u16);
+            let slot = self.builder.stack_alloc_ty(ty.clone());
+            self.builder.store(slot, param);
+            self.scopes.last_mut().unwrap().insert(*name, (slot, ty));
+        }
+
+        self.lower_stmt(func.body.as_ref().unwrap());
+        if !self.terminated() {
+            if func.ret.is_void() {
+                self.builder.return_void();
+            } else {
+                error!(
+                    format!("function `{}` doesn't return value", str(func.name)),
+                    func.pos
+                );
+            }
+        }
+    }
+
+    fn terminated(&self) -> bool {
+        let block = self.builder.current_block().unwrap();
+        self.builder.is_terminated(block)
+    }
+
+    /// Continue in fresh block if current one already terminated (code after `return`)
+    fn ensure_open_block(&mut self) {
+        if self.terminated() {
+            let block = self.builder.new_block();
+            self.builder.switch_to_block(block);
+        }
+    }
+
+    fn jump_unless_terminated(&mut self, block: Block) {
+        if !self.terminated() {
+            self.builder.jump(block);
+        }
+    }
+
+    fn type_of(&self, expr: &Expr) -> Type {
+        match self.ctx.types.get(&expr.id) {
+            Some(ty) => ty.clone(),
+            None => error!(format!("type of expression `{}` is unknown", expr), expr.pos),
+        }
+    }
+
+    fn var(&self, name: Name, expr: &Expr) -> (Value, IrType) {
+        for scope in self.scopes.iter().rev() {
+            if let Some(var) = scope.get(&name) {
+                return var.clone();
+            }
+        }
+        error!(
+            format!("`{}` can't be lowered to IR, only locals are supported", name),
+            expr.pos
+        )
+    }
+
+    fn lower_stmt(&mut self, stmt: &Stmt) {
+        self.ensure_open_block();
+        match &stmt.kind {
+            StmtKind::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                for stmt in stmts.iter() {
+                    self.lower_stmt(stmt);
+                }
+                self.scopes.pop();
+            }
+            StmtKind::CompTime(stmt) => self.lower_stmt(stmt),
+            StmtKind::Expr(expr) => {
+                self.lower_expr(expr);
+            }
+            StmtKind::Var(name, _, _, init) => {
+                let ty = match self.ctx.types.get(&stmt.id) {
+                    Some(ty) => ty_to_ir(ty),
+                    None => error!(
+                        format!("type of variable `{}` is unknown", name),
+                        stmt.pos
+                    ),
+                };
+                let slot = self.builder.stack_alloc_ty(ty.clone());
+                if let Some(init) = init {
+                    let val = self.lower_expr(init);
+                    self.builder.store(slot, val);
+                }
+                self.scopes.last_mut().unwrap().insert(*name, (slot, ty));
+            }
+            StmtKind::Return(expr) => match expr {
+                Some(expr) => {
+                    let val = self.lower_expr(expr);
+                    self.builder.return_(val);
+                }
+                None => self.builder.return_void(),
+            },
+            StmtKind::If(cond, then, otherwise) => {
+                let then_block = self.builder.new_block();
+                let else_block = self.builder.new_block();
+                let cond = self.lower_expr(cond);
+                self.builder.jump_if(then_block, cond);
+                self.builder.jump(else_block);
+
+                self.builder.switch_to_block(then_block);
+                self.lower_stmt(then);
+                let merge = self.builder.new_block();
+                self.jump_unless_terminated(merge);
+
+                self.builder.switch_to_block(else_block);
+                if let Some(otherwise) = otherwise {
+                    self.lower_stmt(otherwise);
+                }
+                self.jump_unless_terminated(merge);
+                self.builder.switch_to_block(merge);
+            }
+            StmtKind::While(cond, body) => {
+                let header = self.builder.new_block();
+                let body_block = self.builder.new_block();
+                let exit = self.builder.new_block();
+                self.builder.jump(header);
+
+                self.builder.switch_to_block(header);
+                let cond = self.lower_expr(cond);
+                self.builder.jump_ifnot(exit, cond);
+                self.builder.jump(body_block);
+
+                self.builder.switch_to_block(body_block);
+                self.lower_loop_body(body, header, exit);
+                self.builder.switch_to_block(exit);
+            }
+            StmtKind::Loop(body) => {
+                let body_block = self.builder.new_block();
+                let exit = self.builder.new_block();
+                self.builder.jump(body_block);
+
+                self.builder.switch_to_block(body_block);
+                self.lower_loop_body(body, body_block, exit);
+                self.builder.switch_to_block(exit);
+            }
+            StmtKind::CFor(init, cond, step, body) => {
+                self.scopes.push(HashMap::new());
+                self.lower_stmt(init);
+                let header = self.builder.new_block();
+                let body_block = self.builder.new_block();
+                let step_block = self.builder.new_block();
+                let exit = self.builder.new_block();
+                self.builder.jump(header);
+
+                self.builder.switch_to_block(header);
+                let cond = self.lower_expr(cond);
+                self.builder.jump_ifnot(exit, cond);
+                self.builder.jump(body_block);
+
+                self.builder.switch_to_block(body_block);
+                self.lower_loop_body(body, step_block, exit);
+                self.builder.switch_to_block(step_block);
+                self.lower_expr(step);
+                self.builder.jump(header);
+
+                self.builder.switch_to_block(exit);
+                self.scopes.pop();
+            }
+            StmtKind::Continue(depth) => {
+                if *depth > self.loops.len() {
+                    error!("`continue` outside of loop", stmt.pos);
+                }
+                let (continue_, _) = self.loops[self.loops.len() - depth];
+                self.builder.jump(continue_);
+            }
+            StmtKind::Break(depth, value) => {
+                if value.is_some() {
+                    error!("`break` with value can't be lowered to IR", stmt.pos);
+                }
+                if *depth > self.loops.len() {
+                    error!("`break` outside of loop", stmt.pos);
+                }
+                let (_, break_) = self.loops[self.loops.len() - depth];
+                self.builder.jump(break_);
+            }
+            StmtKind::Defer(_) => error!("`defer` can't be lowered to IR yet", stmt.pos),
+            StmtKind::Delete(_) => error!("`delete` can't be lowered to IR yet", stmt.pos),
+            StmtKind::Static(_) => error!("`static` variable can't be lowered to IR yet", stmt.pos),
+        }
+    }
+
+    /// Lower loop body, after it control goes to `continue_`
+    fn lower_loop_body(&mut self, body: &Stmt, continue_: Block, break_: Block) {
+        self.loops.push((continue_, break_));
+        self.lower_stmt(body);
+        self.loops.pop();
+        self.jump_unless_terminated(continue_);
+    }
+
+    /// Address of place expression
+    fn lower_place(&mut self, expr: &Expr) -> Value {
+        match &expr.kind {
+            ExprKind::Ident(name) => self.var(*name, expr).0,
+            ExprKind::Deref(ptr) => self.lower_expr(ptr),
+            _ => error!(
+                format!("`{}` can't be used as place in IR yet", expr),
+                expr.pos
+            ),
+        }
+    }
+
+    fn lower_expr(&mut self, expr: &Expr) -> Value {
+        match &expr.kind {
+            ExprKind::Int(int, _, _) => {
+                let ty = ty_to_ir(&self.type_of(expr));
+                self.builder.iconst(ty, *int)
+            }
+            ExprKind::Char(c) => self.builder.iconst(IrType::Int(8), *c as i64),
+            ExprKind::Bool(b) => self.builder.iconst(IrType::Bool, *b as i64),
+            ExprKind::Float(float, FloatSuffix::Float) => self.builder.f32const(*float as f32),
+            ExprKind::Float(float, FloatSuffix::Double) => self.builder.f64const(*float),
+            ExprKind::Str(s) => self.builder.strconst(s),
+            ExprKind::Variant(name, variant) => {
+                let value = self
+                    .ctx
+                    .file
+                    .find_enum(*name)
+                    .and_then(|e| e.variant(*variant))
+                    .unwrap()
+                    .value;
+                let ty = ty_to_ir(&self.type_of(expr));
+                self.builder.iconst(ty, value)
+            }
+            ExprKind::Null => {
+                let ty = ty_to_ir(&self.type_of(expr));
+                self.builder.zero_init(ty)
+            }
+            ExprKind::Ident(name) => {
+                let (slot, ty) = self.var(*name, expr);
+                self.builder.load(slot, ty)
+            }
+            ExprKind::Assign(to, from) => {
+                let val = self.lower_expr(from);
+                let place = self.lower_place(to);
+                self.builder.store(place, val);
+                val
+            }
+            ExprKind::AddressOf(place) => self.lower_place(place),
+            ExprKind::Deref(ptr) => {
+                let ty = ty_to_ir(&self.type_of(expr));
+                let ptr = self.lower_expr(ptr);
+                self.builder.load(ptr, ty)
+            }
+            ExprKind::ArrayIdx(ptr, idx) => {
+                let ty = ty_to_ir(&self.type_of(expr));
+                let ptr = self.lower_expr(ptr);
+                let idx = self.lower_expr(idx);
+                self.builder.load_index(ptr, idx, ty)
+            }
+            ExprKind::Conv(val, to) => {
+                let val = self.lower_expr(val);
+                self.builder.cast(val, ty_to_ir(to))
+            }
+            ExprKind::Unary(op, val) => {
+                let ty = ty_to_ir(&self.type_of(val));
+                let val = self.lower_expr(val);
+                match op.as_str() {
+                    "+" => val,
+                    "-" if ty == IrType::F32 || ty == IrType::F64 => {
+                        let zero = if ty == IrType::F32 {
+                            self.builder.f32const(0.0)
+                        } else {
+                            self.builder.f64const(0.0)
+                        };
+                        self.builder.binary(Opcode::Fsub, zero, val, ty)
+                    }
+                    "-" => {
+                        let zero = self.builder.iconst(ty.clone(), 0);
+                        self.builder.binary(Opcode::Isub, zero, val, ty)
+                    }
+                    "!" if ty == IrType::Bool => {
+                        let zero = self.builder.iconst(IrType::Bool, 0);
+                        let cmp = Opcode::Icmp(CondCode::Equals);
+                        self.builder.binary(cmp, val, zero, IrType::Bool)
+                    }
+                    "!" => {
+                        let ones = self.builder.iconst(ty.clone(), -1);
+                        self.builder.binary(Opcode::Xor, val, ones, ty)
+                    }
+                    _ => error!(format!("unary `{}` can't be lowered to IR", op), expr.pos),
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) if op == "&&" || op == "||" => {
+                self.lower_logical(op == "&&", lhs, rhs)
+            }
+            ExprKind::Ternary(cond, then, otherwise) => {
+                self.lower_ternary(cond, then, otherwise, ty_to_ir(&self.type_of(expr)))
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let ty = ty_to_ir(&self.type_of(lhs));
+                let float = ty == IrType::F32 || ty == IrType::F64;
+                let lhs = self.lower_expr(lhs);
+                let rhs = self.lower_expr(rhs);
+                let cmp = |cc| {
+                    if float {
+                        Opcode::Fcmp(cc)
+                    } else {
+                        Opcode::Icmp(cc)
+                    }
+                };
+                let (opcode, result) = match op.as_str() {
+                    "+" if float => (Opcode::Fadd, ty),
+                    "-" if float => (Opcode::Fsub, ty),
+                    "*" if float => (Opcode::Fmul, ty),
+                    "/" if float => (Opcode::Fdiv, ty),
+                    "%" if float => (Opcode::Fmod, ty),
+                    "+" => (Opcode::Iadd, ty),
+                    "-" => (Opcode::Isub, ty),
+                    "*" => (Opcode::Imul, ty),
+                    "/" => (Opcode::Idiv, ty),
+                    "%" => (Opcode::Imod, ty),
+                    ">>" => (Opcode::Shr, ty),
+                    "<<" => (Opcode::Shl, ty),
+                    "&" => (Opcode::And, ty),
+                    "|" => (Opcode::Or, ty),
+                    "^" => (Opcode::Xor, ty),
+                    "==" => (cmp(CondCode::Equals), IrType::Bool),
+                    "!=" => (cmp(CondCode::NotEquals), IrType::Bool),
+                    ">" => (cmp(CondCode::GreaterThan), IrType::Bool),
+                    ">=" => (cmp(CondCode::GreaterThanEquals), IrType::Bool),
+                    "<" => (cmp(CondCode::LessThan), IrType::Bool),
+                    "<=" => (cmp(CondCode::LessThanEquals), IrType::Bool),
+                    _ => error!(format!("binary `{}` can't be lowered to IR", op), expr.pos),
+                };
+                self.builder.binary(opcode, lhs, rhs, result)
+            }
+            ExprKind::Call(path, this, args) => {
+                let arg_types = args.iter().map(|arg| self.type_of(arg)).collect::<Vec<_>>();
+                let this_ty = this.as_ref().map(|this| self.type_of(this));
+                let (id, func) =
+                    self.find_function(path.name(), &arg_types, this_ty.as_ref(), expr);
+
+                let mut values = args.iter().map(|arg| self.lower_expr(arg)).collect::<Vec<_>>();
+                if let (Some(this), Some((_, this_param))) = (this, &func.this) {
+                    let receiver = if this_param.is_ptr() && !self.type_of(this).is_ptr() {
+                        self.lower_place(this)
+                    } else {
+                        self.lower_expr(this)
+                    };
+                    values.push(receiver);
+                }
+                let ret = ty_to_ir(&func.ret);
+                self.builder.call(id, values, ret)
+            }
+            _ => error!(
+                format!("expression `{}` can't be lowered to IR yet", expr),
+                expr.pos
+            ),
+        }
+    }
+
+    /// Short-circuit `&&`/`||` through stack slot holding result
+    fn lower_logical(&mut self, and: bool, lhs: &Expr, rhs: &Expr) -> Value {
+        let slot = self.builder.stack_alloc_ty(IrType::Bool);
+        let rhs_block = self.builder.new_block();
+        let merge = self.builder.new_block();
+
+        let lhs = self.lower_expr(lhs);
+        self.builder.store(slot, lhs);
+        if and {
+            self.builder.jump_if(rhs_block, lhs);
+        } else {
+            self.builder.jump_ifnot(rhs_block, lhs);
+        }
+        self.builder.jump(merge);
+
+        self.builder.switch_to_block(rhs_block);
+        let rhs = self.lower_expr(rhs);
+        self.builder.store(slot, rhs);
+        self.builder.jump(merge);
+
+        self.builder.switch_to_block(merge);
+        self.builder.load(slot, IrType::Bool)
+    }
+
+    /// `cond ? then : otherwise` through stack slot holding value of arm that ran
+    fn lower_ternary(&mut self, cond: &Expr, then: &Expr, otherwise: &Expr, ty: IrType) -> Value {
+        let slot = self.builder.stack_alloc_ty(ty.clone());
+        let then_block = self.builder.new_block();
+        let merge = self.builder.new_block();
+
+        let cond = self.lower_expr(cond);
+        self.builder.jump_if(then_block, cond);
+        let val = self.lower_expr(otherwise);
+        let val = self.builder.cast(val, ty.clone());
+        self.builder.store(slot, val);
+        self.builder.jump(merge);
+
+        self.builder.switch_to_block(then_block);
+        let val = self.lower_expr(then);
+        let val = self.builder.cast(val, ty.clone());
+        self.builder.store(slot, val);
+        self.builder.jump(merge);
+
+        self.builder.switch_to_block(merge);
+        self.builder.load(slot, ty)
+    }
+
+    fn find_function(
+        &self,
+        name: Name,
+        args: &[Type],
+        this: Option<&Type>,
+        expr: &Expr,
+    ) -> (FuncionId, &'a AstFunction) {
+        let functions = self.functions;
+        for (id, func) in functions.iter().enumerate() {
+            if func.name != name || func.this.is_some() != this.is_some() {
+                continue;
+            }
+            let params = func.params.iter().map(|(_, ty)| &**ty);
+            let params_match = if func.variadic {
+                args.len() >= func.params.len() && params.zip(args.iter()).all(|(p, a)| p == a)
+            } else {
+                args.len() == func.params.len() && params.zip(args.iter()).all(|(p, a)| p == a)
+            };
+            let this_match = match (this, &func.this) {
+                (Some(this), Some((_, param))) => {
+                    this == &**param
+                        || match &**param {
+                            Type::Ptr(ptr) => this == &*ptr.subtype,
+                            _ => false,
+                        }
+                }
+                _ => true,
+            };
+            if params_match && this_match {
+                return (FuncionId::new(id), func);
+            }
+        }
+        error!(
+            format!("function `{}` not found while lowering to IR", str(name)),
+            expr.pos
+        )
+    }
+}
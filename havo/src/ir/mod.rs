@@ -3,6 +3,7 @@
 #[macro_use]
 pub mod macros;
 pub mod builder;
+pub mod lower;
 pub mod opt;
 use crate::syntax::interner::Name;
 
@@ -30,9 +31,29 @@ pub fn new(id: FuncionId, name: &str) -> Function {
     }
 }
 
+/// Lowered program
+pub struct Module {
+    pub functions: Vec<Function>,
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, func) in self.functions.iter().enumerate() {
+            if i != 0 {
+                write!(f, "\n")?;
+            }
+            write!(f, "{}\n", func)?;
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Display for Function {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "function {}(", crate::str(self.name))?;
+        if self.external {
+            write!(f, "extern ")?;
+        }
+        write!(f, "function {} {}(", self.id, crate::str(self.name))?;
         for (i, param) in self.params.iter().enumerate() {
             write!(f, "{}", param)?;
             if i != self.params.len() - 1 {
@@ -40,6 +61,9 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             }
         }
 
+        if self.external {
+            return write!(f, ") {}", self.ret);
+        }
         write!(f, ") {} {{\n", self.ret)?;
         for (id, block) in self.cfg.blocks.iter() {
             write!(f, "{}:\n", id)?;
@@ -74,6 +98,12 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FuncionId(pub u32);
 
+impl fmt::Display for FuncionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fn{}", self.0)
+    }
+}
+
 impl fmt::Display for Block {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "ebb{}", self.0)
@@ -140,7 +170,7 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum CondCode {
     GreaterThan,
     GreaterThanEquals,
@@ -151,6 +181,19 @@ pub enum CondCode {
     NotEquals,
 }
 
+impl fmt::Display for CondCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CondCode::GreaterThan => write!(f, "gt"),
+            CondCode::GreaterThanEquals => write!(f, "ge"),
+            CondCode::LessThan => write!(f, "lt"),
+            CondCode::LessThanEquals => write!(f, "le"),
+            CondCode::Equals => write!(f, "eq"),
+            CondCode::NotEquals => write!(f, "ne"),
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum Opcode {
     Iconst,
@@ -167,6 +210,9 @@ pub enum Opcode {
     Imod,
     Shr,
     Shl,
+    And,
+    Or,
+    Xor,
     LoadParam,
     Icmp(CondCode),
     Fcmp(CondCode),
@@ -208,6 +254,9 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             Opcode::Imod => write!(f, "imod"),
             Opcode::Shr => write!(f, "shr"),
             Opcode::Shl => write!(f, "shl"),
+            Opcode::And => write!(f, "and"),
+            Opcode::Or => write!(f, "or"),
+            Opcode::Xor => write!(f, "xor"),
             Opcode::Fadd => write!(f, "fadd"),
             Opcode::Fsub => write!(f, "fsub"),
             Opcode::Fdiv => write!(f, "fdiv"),
@@ -218,8 +267,8 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             Opcode::Cast => write!(f, "cast"),
             Opcode::Store => write!(f, "store"),
             Opcode::Load => write!(f, "load"),
-            Opcode::Icmp(_cc) => write!(f, "icmp"),
-            Opcode::Fcmp(_cc) => write!(f, "fcmp"),
+            Opcode::Icmp(cc) => write!(f, "icmp.{}", cc),
+            Opcode::Fcmp(cc) => write!(f, "fcmp.{}", cc),
             Opcode::LoadParam => write!(f, "loadparam"),
             Opcode::Return => write!(f, "return"),
         }
@@ -265,6 +314,7 @@ pub fn without_result(&self) -> bool {
             InstructionData::Store(_, _, _) => true,
             InstructionData::Branch(_, _, _) => true,
             InstructionData::Return(_, _) => true,
+            InstructionData::VoidReturn(_) => true,
             _ => false,
         }
     }
@@ -276,14 +326,14 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             InstructionData::Alloca(_opcode, ty) => write!(f, "alloca.{}", ty),
             InstructionData::Move(_, value) => write!(f, "{}", value),
             InstructionData::Branch(opcode, to, value) => {
-                write!(f, "{} {} ", opcode, to)?;
+                write!(f, "{} {}", opcode, to)?;
                 if value.is_some() {
-                    write!(f, "{}", value.as_ref().unwrap())
+                    write!(f, " {}", value.as_ref().unwrap())
                 } else {
                     write!(f, "")
                 }
             }
-            InstructionData::Cast(opcode, val, ty) => write!(f, "{}.{} {}", opcode, val, ty),
+            InstructionData::Cast(opcode, val, ty) => write!(f, "{}.{} {}", opcode, ty, val),
             InstructionData::Return(opcode, val) => write!(f, "{} {}", opcode, val),
             InstructionData::VoidReturn(opcode) => write!(f, "{}", opcode),
             InstructionData::FloatBinary(op, x, y) => write!(f, "{} {} {}", op, x, y),
@@ -294,22 +344,30 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             InstructionData::UnaryIeee32(op, iee) => write!(f, "{} {}", op, f32::from_bits(*iee)),
             InstructionData::UnaryIeee64(op, iee) => write!(f, "{} {}", op, f64::from_bits(*iee)),
             InstructionData::UnaryImm(op, imm) => write!(f, "{} {}", op, imm),
-            InstructionData::UnaryStr(op, string) => write!(f, "{} {}", op, crate::str(*string)),
+            InstructionData::UnaryStr(op, string) => {
+                write!(f, "{} {:?}", op, crate::str(*string).as_str())
+            }
             InstructionData::Unary(op, val) => write!(f, "{} {}", op, val),
             InstructionData::LoadParam(_, ty, num) => write!(f, "loadparam.{} {}", ty, num),
             InstructionData::Call(_, func, params) => {
-                write!(f, "call function:{} ", func.0)?;
-                for param in params.iter() {
-                    write!(f, "{} ", param)?
+                write!(f, "call {}(", func)?;
+                for (i, param) in params.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?
                 }
-                write!(f, "")
+                write!(f, ")")
             }
             InstructionData::CallValue(_, func, params) => {
-                write!(f, "call {} ", func)?;
-                for param in params.iter() {
-                    write!(f, "{} ", param)?
+                write!(f, "call {}(", func)?;
+                for (i, param) in params.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?
                 }
-                write!(f, "")
+                write!(f, ")")
             }
             InstructionData::AllocaSize(opcode, size) => write!(f, "{} {}", opcode, size),
         }
@@ -361,9 +419,25 @@ pub fn switch_to_block(&mut self, block: Block) {
         self.cur_block = Some(block);
     }
 
+    /// Block instructions are currently appended to
+    pub fn current_block(&self) -> Option<Block> {
+        self.cur_block
+    }
+
+    /// Whether block ends with unconditional branch or return
+    pub fn is_terminated(&self, block: Block) -> bool {
+        self.blocks.get(&block).unwrap().terminated
+    }
+
     pub fn make_inst(&mut self, data: InstructionData) -> Value {
         let cur_block: Block = self.cur_block.unwrap();
         let cur_block = self.blocks.get_mut(&cur_block).unwrap();
+        match data {
+            InstructionData::Branch(Opcode::Br, _, _)
+            | InstructionData::Return(_, _)
+            | InstructionData::VoidReturn(_) => cur_block.terminated = true,
+            _ => (),
+        }
         let inst = Inst::new(cur_block.insts.len());
         let val = if !data.without_result() {
             let id = self.results.len();
@@ -9,14 +9,19 @@
 #[macro_use]
 pub mod macros;
 pub mod ast2cpp;
+pub mod cranelift;
+pub mod desugar;
 pub mod err;
 pub mod eval;
 pub mod gccjit;
 pub mod ir;
+pub mod mangle;
+pub mod monomorph;
 pub mod optimize;
 pub mod semantic;
 pub mod semck;
 pub mod syntax;
+pub mod test_runner;
 
 pub use syntax::{ast, position::Position};
 
@@ -64,20 +69,42 @@ pub fn next(&self) -> NodeId {
 use crate::syntax::ast::Function;
 use ast::Type;
 use std::collections::{HashMap, HashSet};
-use syntax::ast::File;
+use syntax::{ast::File, lexer::token::IntSuffix};
 
 /// Context stores ifnromation about program
 pub struct Context {
     pub file: File,
     pub types: HashMap<NodeId, Type>,
+    /// `new` expressions allocated with `GC_malloc`, filled by semantic pass when `gc` is set
     pub gced: HashSet<NodeId>,
+    /// allocate with Boehm GC instead of `calloc`/`free` (`--gc`)
+    pub gc: bool,
     pub opt: u8,
     pub jit: bool,
+    /// exit with status returned by `main` after running it with JIT (`havo run`)
+    pub run: bool,
+    /// `argv` of program run with JIT, first one is program name
+    pub args: Vec<String>,
     pub emit_asm: bool,
     pub emit_obj: bool,
     pub output: String,
     pub shared: bool,
     pub gimple: bool,
+    /// directories searched for imports not found relative to `file.root` (`-I` flags)
+    pub include_dirs: Vec<String>,
+    /// check indexes of fixed-length arrays at runtime (`--debug-bounds`)
+    pub debug_bounds: bool,
+    /// check pointers against null before dereferencing them (`--debug-null`)
+    pub debug_null: bool,
+    /// check signed integer arithmetic for overflow at runtime (`--debug-overflow`)
+    pub debug_overflow: bool,
+    /// sanitizers program is instrumented with, e.g `address` (`--sanitize`)
+    pub sanitize: Vec<String>,
+    /// function called when runtime check fails, takes no arguments and never returns
+    /// (`--panic-handler`)
+    pub panic_handler: String,
+    /// suffix of integer literals written without one, `i32` by default (`--int-type`)
+    pub int_suffix: IntSuffix,
 }
 
 impl Context {
@@ -86,27 +113,171 @@ pub fn new(file: File) -> Context {
             file,
             types: HashMap::new(),
             gced: HashSet::new(),
+            gc: false,
             opt: 2,
             emit_asm: false,
             emit_obj: false,
             jit: true,
+            run: false,
+            args: vec![],
             output: String::new(),
             shared: false,
             gimple: false,
+            include_dirs: vec![],
+            debug_bounds: false,
+            debug_null: false,
+            debug_overflow: false,
+            sanitize: vec![],
+            panic_handler: "abort".to_owned(),
+            int_suffix: IntSuffix::Int,
         }
     }
 
-    pub fn import(&mut self, path: &str) {
-        let import = if self.file.root.is_empty() {
-            path.to_owned()
-        } else {
-            format!("{}/{}", self.file.root, path)
+    /// Allocate value of `new` expression `id` with garbage collector
+    pub fn mark_gced(&mut self, id: NodeId) {
+        self.gced.insert(id);
+    }
+
+    /// Whether `new` expression `id` is allocated with garbage collector
+    pub fn is_gced(&self, id: NodeId) -> bool {
+        self.gced.contains(&id)
+    }
+
+    /// Attributes of top-level function, struct or global named `symbol` (first one of overloaded
+    /// functions), empty if it isn't declared
+    pub fn attributes_of(&self, symbol: &str) -> &[ast::Attribute] {
+        use syntax::ast::Elem;
+        for elem in self.file.elems.iter() {
+            let (name, attributes) = match elem {
+                Elem::Func(f) => (f.name, &f.attributes),
+                Elem::Struct(s) => (s.name, &s.attributes),
+                Elem::Global(g) => (g.name, &g.attributes),
+                _ => continue,
+            };
+            if str(name).as_str() == symbol {
+                return attributes;
+            }
+        }
+        &[]
+    }
+
+    /// Attribute `name` of top-level declaration `symbol`
+    pub fn attribute_of(&self, symbol: &str, name: &str) -> Option<&ast::Attribute> {
+        self.attributes_of(symbol)
+            .iter()
+            .find(|attr| str(attr.name).as_str() == name)
+    }
+
+    /// External functions and globals that program uses but doesn't define, with their declared
+    /// types (`(params) -> ret` for functions), sorted by name. Embedders have to provide these
+    /// symbols before linking or running program.
+    pub fn unresolved_externals(&self) -> Vec<(String, Type)> {
+        use syntax::{
+            ast::{visit::*, Elem, Expr, ExprKind},
+            interner::Name,
         };
+        struct Uses(HashSet<Name>);
+        impl Visitor for Uses {
+            fn visit_expr(&mut self, expr: &Expr) {
+                match &expr.kind {
+                    ExprKind::Call(path, None, _) => {
+                        self.0.insert(path.name());
+                    }
+                    ExprKind::Ident(name) | ExprKind::GetFunc(name) => {
+                        self.0.insert(*name);
+                    }
+                    _ => (),
+                }
+                walk_expr(self, expr)
+            }
+        }
+
+        let mut externals = HashMap::new();
+        let mut defined = HashSet::new();
+        let mut uses = Uses(HashSet::new());
+        for elem in self.file.elems.iter() {
+            match elem {
+                Elem::Func(f) if f.external => {
+                    let params = f.params.iter().map(|(_, ty)| ty.clone()).collect();
+                    externals.insert(f.name, Type::create_func(f.id, f.pos, params, f.ret.clone()));
+                }
+                Elem::Global(g) if g.external => {
+                    externals.insert(g.name, *g.typ.clone());
+                }
+                Elem::Func(f) => {
+                    defined.insert(f.name);
+                }
+                Elem::Global(g) => {
+                    defined.insert(g.name);
+                }
+                _ => (),
+            }
+            uses.visit_elem(elem);
+        }
+        let mut unresolved = externals
+            .into_iter()
+            .filter(|(name, _)| uses.0.contains(name) && !defined.contains(name))
+            .map(|(name, ty)| (str(name).to_string(), ty))
+            .collect::<Vec<_>>();
+        unresolved.sort_by(|(a, _), (b, _)| a.cmp(b));
+        unresolved
+    }
+
+    /// Infer type of standalone `expr` against elements of `file` without running whole
+    /// pipeline, for editor tooling like hover. Types of subexpressions are recorded in `types`.
+    /// Type errors inside `expr` are returned instead of exiting like in compiler.
+    pub fn type_check_expr(&mut self, expr: &ast::Expr) -> Result<Type, semantic::ErrorWPos> {
+        semantic::SemCheck::new(self).type_check_expr(expr)
+    }
+
+    /// Find imported file: first relative to `file.root`, then in each of `include_dirs` in
+    /// order. Exits listing all searched directories if nothing found.
+    pub fn resolve_import(&self, path: &str) -> String {
+        use colored::Colorize;
+        if let Some(import) = self.find_import(path) {
+            return import;
+        }
+
+        eprintln!("{}: import `{}` not found, searched in:", "ERROR".red(), path);
+        for root in self.import_roots() {
+            eprintln!("    {}", if root.is_empty() { "." } else { root.as_str() });
+        }
+        std::process::exit(-1);
+    }
+
+    /// Like `resolve_import`, but `None` if imported file doesn't exist
+    pub fn find_import(&self, path: &str) -> Option<String> {
+        if std::path::Path::new(path).is_absolute() {
+            return Some(path.to_owned()).filter(|path| std::path::Path::new(path).is_file());
+        }
+        self.import_roots()
+            .map(|root| {
+                if root.is_empty() {
+                    path.to_owned()
+                } else {
+                    format!("{}/{}", root, path)
+                }
+            })
+            .find(|candidate| std::path::Path::new(candidate).is_file())
+    }
+
+    fn import_roots(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.file.root).chain(self.include_dirs.iter())
+    }
+
+    pub fn import(&mut self, path: &str) {
+        let import = self.resolve_import(path);
         let mut file = File {
             elems: vec![],
             src: String::new(),
             path: String::new(),
-            root: import.clone(),
+            // nested imports of imported file are resolved relative to its own directory
+            root: std::path::Path::new(&import)
+                .parent()
+                .and_then(|dir| dir.to_str())
+                .unwrap_or("")
+                .to_owned(),
+            unsuffixed_ints: HashSet::new(),
         };
         use crate::syntax::{lexer, parser::Parser};
         use lexer::reader::Reader;
@@ -116,8 +287,10 @@ pub fn import(&mut self, path: &str) {
         parser.parse().expect("Error");
 
         let mut ctx = Context::new(file);
+        ctx.include_dirs = self.include_dirs.clone();
         ctx.imports();
 
+        self.file.unsuffixed_ints.extend(ctx.file.unsuffixed_ints);
         for elem in ctx.file.elems {
             match elem {
                 Elem::Func(f) => {
@@ -135,12 +308,70 @@ pub fn import(&mut self, path: &str) {
                         self.file.elems.push(Elem::Const(s.clone()));
                     }
                 }
+                Elem::Enum(e) => {
+                    if e.public {
+                        self.file.elems.push(Elem::Enum(e));
+                    }
+                }
                 Elem::Link(name) => self.file.elems.push(Elem::Link(name)),
                 _ => (),
             }
         }
     }
 
+    /// Add top-level elements of another source file to this context.
+    ///
+    /// Extern declarations may be repeated across files, any other symbol defined twice is an
+    /// error.
+    pub fn merge(&mut self, mut file: File) {
+        use colored::Colorize;
+        use syntax::ast::Elem;
+
+        // relative imports of merged file are found in its own directory, not in `file.root` of
+        // first one, so imports found there are made absolute
+        for elem in file.elems.iter_mut() {
+            if let Elem::Import(path) = elem {
+                let own = std::path::Path::new(&file.root).join(&*path);
+                if let Ok(own) = std::fs::canonicalize(own) {
+                    *path = own.to_string_lossy().into_owned();
+                }
+            }
+        }
+
+        let mut defined: HashMap<String, (Position, bool)> = self
+            .file
+            .elems
+            .iter()
+            .filter_map(|elem| symbol_of(elem))
+            .map(|(key, pos, external)| (key, (pos, external)))
+            .collect();
+        self.file.unsuffixed_ints.extend(file.unsuffixed_ints);
+
+        for elem in file.elems {
+            if let Some((key, pos, external)) = symbol_of(&elem) {
+                if let Some((prev, prev_external)) = defined.get(&key) {
+                    if external && *prev_external {
+                        continue;
+                    }
+                    error!(false, format!("`{}` is already defined", key), pos);
+                    note!("previous definition is here", prev);
+                    std::process::exit(-1);
+                }
+                defined.insert(key, (pos, external));
+            }
+            if let Elem::Link(name) = &elem {
+                let linked = self.file.elems.iter().any(|elem| match elem {
+                    Elem::Link(other) => other == name,
+                    _ => false,
+                });
+                if linked {
+                    continue;
+                }
+            }
+            self.file.elems.push(elem);
+        }
+    }
+
     pub fn get_func_mut(&mut self, id: NodeId) -> Option<&mut Function> {
         for elem in self.file.elems.iter_mut() {
             if let syntax::ast::Elem::Func(f) = elem {
@@ -159,3 +390,32 @@ pub fn imports(&mut self) {
         }
     }
 }
+
+/// Key identifying top-level symbol defined by `elem` (functions may be overloaded so their key
+/// includes receiver and parameter types), its position and whether it's only a declaration
+fn symbol_of(elem: &syntax::ast::Elem) -> Option<(String, Position, bool)> {
+    use syntax::ast::Elem;
+    match elem {
+        Elem::Func(f) => {
+            let mut key = String::new();
+            if let Some((_, this)) = &f.this {
+                key.push_str(&format!("({}) ", this));
+            }
+            key.push_str(&str(f.name));
+            let params = f
+                .params
+                .iter()
+                .map(|(_, ty)| ty.to_string())
+                .collect::<Vec<_>>();
+            key.push_str(&format!("({})", params.join(",")));
+            Some((key, f.pos, f.external || f.body.is_none()))
+        }
+        Elem::Struct(s) => Some((str(s.name).to_string(), s.pos, false)),
+        Elem::Const(c) => Some((str(c.name).to_string(), c.pos, false)),
+        Elem::Enum(e) => Some((str(e.name).to_string(), e.pos, false)),
+        Elem::Global(g) => Some((str(g.name).to_string(), g.pos, g.external)),
+        Elem::ConstExpr { name, pos, .. } => Some((str(*name).to_string(), *pos, false)),
+        Elem::Alias(name, ty) => Some((str(*name).to_string(), ty.pos(), false)),
+        _ => None,
+    }
+}
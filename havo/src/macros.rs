@@ -1,19 +1,49 @@
 #[macro_export]
 macro_rules! warn {
     ($fmt: expr,$pos: expr) => {{
-        eprintln!("{} {}: {}", "WARNING".yellow(), $pos, $fmt);
+        eprintln!(
+            "{} {}: {}",
+            "WARNING".yellow(),
+            $pos,
+            format!("{}", $fmt).as_str().bold()
+        );
     }};
 }
 
+#[macro_export]
+macro_rules! note {
+    ($fmt: expr,$pos: expr) => {{
+        eprintln!("{} {}: {}", "NOTE".cyan(), $pos, $fmt);
+    }};
+}
+
+/// Report error at position and exit, or unwind to `err::catch_errors` if it's running. Form
+/// with `false` first doesn't exit, caller prints notes and exits itself.
 #[macro_export]
 macro_rules! error {
     ($fmt: expr,$pos: expr) => {{
-        eprintln!("{} {}: {}", "ERROR".red(), $pos, $fmt);
+        if $crate::err::catching() {
+            $crate::err::unwind(format!("{}", $fmt), $pos);
+        }
+        eprintln!(
+            "{} {}: {}",
+            "ERROR".red(),
+            $pos,
+            format!("{}", $fmt).as_str().bold()
+        );
         std::process::exit(-1);
     }};
 
     ($EXIT: expr,$fmt: expr,$pos: expr) => {{
-        eprintln!("{} {}: {}", "ERROR".red(), $pos, $fmt);
+        if $crate::err::catching() {
+            $crate::err::unwind(format!("{}", $fmt), $pos);
+        }
+        eprintln!(
+            "{} {}: {}",
+            "ERROR".red(),
+            $pos,
+            format!("{}", $fmt).as_str().bold()
+        );
         if $EXIT {
             std::process::exit(-1);
         }
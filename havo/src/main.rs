@@ -4,16 +4,23 @@
 extern crate structopt;
 
 use havo::{
-    err::MsgWithPos,
+    err::{ColorChoice, MsgWithPos},
     gccjit::Codegen,
     optimize::const_eval,
     semantic::*,
-    syntax::{ast::*, lexer::reader::Reader, parser::*},
+    syntax::{
+        ast::*,
+        lexer::{reader::Reader, token::IntSuffix, Lexer},
+        parser::*,
+    },
     Context,
 };
 use structopt::StructOpt;
 
-use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, StructOpt)]
 pub enum Backend {
@@ -21,7 +28,7 @@ pub enum Backend {
     GccJIT,
     #[structopt(help = "C++ backend,still W.I.P")]
     CPP,
-    #[structopt(help = "Cranelift backend (UNIMPLEMENTED!)")]
+    #[structopt(help = "Cranelift backend, supports only integer programs with --jit")]
     CraneLift,
 }
 
@@ -57,15 +64,32 @@ fn from_str(s: &str) -> Result<Backend, &'static str> {
 #[derive(StructOpt, Debug)]
 #[structopt(name = "havo", about = "Havo kompilyatori")]
 pub struct Options {
-    #[structopt(parse(from_os_str))]
-    pub file: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        raw(required_unless = r#""list_targets""#),
+        help = "Source files, compiled together as one program"
+    )]
+    pub files: Vec<PathBuf>,
+    #[structopt(
+        raw(last = "true"),
+        help = "Arguments passed to program run with JIT, given after `--`"
+    )]
+    pub program_args: Vec<String>,
     #[structopt(
         short = "O",
         long = "opt-level",
         default_value = "2",
-        help = "Set optimization level"
+        parse(try_from_str = "parse_opt_level"),
+        help = "Set optimization level (0..3)"
     )]
     pub opt_level: u8,
+    #[structopt(
+        long = "int-type",
+        default_value = "i32",
+        parse(try_from_str = "parse_int_type"),
+        help = "Set type of integer literals without suffix"
+    )]
+    pub int_type: IntSuffix,
     #[structopt(long = "jit", help = "Use JIT compilation instead of AOT compilation")]
     pub jit: bool,
     #[structopt(long = "emit-obj", help = "Output object file")]
@@ -86,6 +110,11 @@ pub struct Options {
         help = "Dump GIMPLE to stdout if gccjit backend used"
     )]
     pub emit_gimple: bool,
+    #[structopt(
+        long = "emit-ir",
+        help = "Print backend independent IR to stdout (or to --output) instead of compiling"
+    )]
+    pub emit_ir: bool,
     #[structopt(
         long = "backend",
         raw(
@@ -96,6 +125,13 @@ pub struct Options {
         help = "Select backend"
     )]
     pub backend: Backend,
+    #[structopt(
+        short = "I",
+        long = "include",
+        raw(number_of_values = "1"),
+        help = "Add directory to import search path"
+    )]
+    pub include_dirs: Vec<String>,
     #[structopt(short = "l", long = "link")]
     pub libraries_link: Vec<String>,
     #[structopt(short = "f")]
@@ -105,31 +141,107 @@ pub struct Options {
         help = "Enables constant folding and const function evaluating"
     )]
     pub const_eval: bool,
+    #[structopt(
+        long = "emit-tokens",
+        help = "Print tokens of input files with their positions and exit"
+    )]
+    pub emit_tokens: bool,
     #[structopt(long = "print-ast", help = "Print program")]
     pub print_ast: bool,
+    #[structopt(
+        long = "print-types",
+        help = "Print inferred types of locals and expressions of each function and exit"
+    )]
+    pub print_types: bool,
+    #[structopt(
+        long = "dump-mono",
+        help = "Print specializations of each generic function and exit"
+    )]
+    pub dump_mono: bool,
     #[structopt(
         long = "aggressive-eval",
-        help = "try to evaluate normal (not constexpr) functions too"
+        help = "Evaluate calls of pure functions with loops or recursion too, not only simple ones"
     )]
     pub aggressive_eval: bool,
+    #[structopt(
+        long = "color",
+        raw(
+            possible_values = "&[\"auto\",\"always\",\"never\"]",
+            case_insensitive = "true",
+            default_value = "\"auto\""
+        ),
+        help = "Colorize diagnostics, `auto` colorizes only when stderr is a terminal"
+    )]
+    pub color: ColorChoice,
+    #[structopt(
+        long = "debug-bounds",
+        help = "Abort on out of bounds indexing of fixed-length arrays"
+    )]
+    pub debug_bounds: bool,
+    #[structopt(long = "debug-null", help = "Abort on dereference of null pointer")]
+    pub debug_null: bool,
+    #[structopt(
+        long = "debug-overflow",
+        help = "Abort on overflow of signed integer `+`, `-` and `*`"
+    )]
+    pub debug_overflow: bool,
+    #[structopt(
+        long = "gc",
+        help = "Allocate `new` with Boehm GC (`GC_malloc`) instead of `calloc`"
+    )]
+    pub gc: bool,
+    #[structopt(
+        long = "sanitize",
+        raw(
+            possible_values = "&[\"address\",\"undefined\"]",
+            number_of_values = "1"
+        ),
+        help = "Instrument program with sanitizer (AOT only), may be repeated"
+    )]
+    pub sanitize: Vec<String>,
+    #[structopt(
+        long = "panic-handler",
+        default_value = "abort",
+        help = "Function called instead of `abort` when runtime check fails"
+    )]
+    pub panic_handler: String,
+    #[structopt(
+        long = "test",
+        help = "Build test runner calling `@test` functions instead of `main`"
+    )]
+    pub test: bool,
+    #[structopt(
+        long = "watch",
+        help = "Compile again every time source files or their imports change"
+    )]
+    pub watch: bool,
+    #[structopt(
+        long = "list-targets",
+        help = "Print available backends and target gccjit generates code for"
+    )]
+    pub list_targets: bool,
 }
 
-fn main() -> Result<(), MsgWithPos> {
-    let opts: Options = Options::from_args();
-    let mut file = File {
-        root: opts
-            .file
+/// Empty `File` for source at `path`, its imports are resolved relative to its directory
+fn empty_file(path: &PathBuf) -> File {
+    File {
+        root: path
             .parent()
             .unwrap_or(&std::path::Path::new(""))
             .to_str()
             .unwrap()
             .to_owned(),
         src: String::new(),
-        path: opts.file.to_str().unwrap().to_owned(),
+        path: path.to_str().unwrap().to_owned(),
         elems: vec![],
-    };
+        unsuffixed_ints: HashSet::new(),
+    }
+}
 
-    let reader = Reader::from_file(opts.file.to_str().unwrap()).unwrap();
+fn parse_file(path: &PathBuf) -> File {
+    let mut file = empty_file(path);
+
+    let reader = Reader::from_file(path.to_str().unwrap()).unwrap();
 
     let mut parser = Parser::new(reader, &mut file);
 
@@ -138,25 +250,243 @@ fn main() -> Result<(), MsgWithPos> {
         println!("{}", err.clone().err().unwrap());
         std::process::exit(-1);
     }
+    file
+}
+
+/// Absolute path of `path`, files that don't exist (removed by editor while saving) keep path
+/// they were watched under
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+/// `files` and every file they import, directly or through other imports. Files that can't be
+/// read or parsed are included too, fixing them must trigger rebuild.
+fn source_files(files: &[PathBuf], include_dirs: &[String]) -> Vec<PathBuf> {
+    let mut sources = vec![];
+    let mut queue = files.to_vec();
+    while let Some(path) = queue.pop() {
+        let path = canonical(&path);
+        if sources.contains(&path) {
+            continue;
+        }
+        sources.push(path.clone());
 
-    let mut ctx = Context::new(file);
+        let reader = match Reader::from_file(path.to_str().unwrap()) {
+            Ok(reader) => reader,
+            Err(_) => continue,
+        };
+        let mut file = empty_file(&path);
+        if Parser::new(reader, &mut file).parse().is_err() {
+            continue;
+        }
+        let mut ctx = Context::new(file);
+        ctx.include_dirs = include_dirs.to_vec();
+        for elem in ctx.file.elems.iter() {
+            if let Elem::Import(import) = elem {
+                queue.extend(ctx.find_import(import).map(PathBuf::from));
+            }
+        }
+    }
+    sources
+}
+
+/// `--watch`: compile (and with `--jit` run) program in child process, then again every time
+/// one of its source files changes. Errors exit only child, so watcher keeps waiting for fix.
+fn watch(opts: &Options) -> ! {
+    use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+    use std::{process::Command, sync::mpsc::channel, time::Duration};
+
+    let exe = std::env::current_exe().unwrap();
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    // arguments after `--` belong to program
+    let flags = args.iter().position(|arg| arg == "--").unwrap_or(args.len());
+    if let Some(watch) = args[..flags].iter().position(|arg| arg == "--watch") {
+        args.remove(watch);
+    }
+    let (tx, rx) = channel();
+    let mut watcher = watcher(tx, Duration::from_millis(100)).unwrap();
+    let mut dirs = HashSet::new();
+    loop {
+        // imports may change with every edit, so graph is collected again before each build
+        let sources = source_files(&opts.files, &opts.include_dirs);
+        for dir in sources.iter().filter_map(|path| path.parent()) {
+            // editors often replace file instead of writing into it, which ends watch on file
+            // itself, so its directory is watched
+            if dirs.insert(dir.to_owned()) {
+                if let Err(err) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    eprintln!("ERROR: can't watch {}: {}", dir.display(), err);
+                }
+            }
+        }
+
+        let mut child = Command::new(&exe)
+            .args(&args)
+            .spawn()
+            .expect("failed to start compiler");
+        eprintln!("watching {} files for changes", sources.len());
+        loop {
+            let changed = match rx.recv() {
+                Ok(DebouncedEvent::Create(path))
+                | Ok(DebouncedEvent::Write(path))
+                | Ok(DebouncedEvent::Remove(path))
+                | Ok(DebouncedEvent::Rename(_, path)) => path,
+                Ok(_) => continue,
+                Err(_) => std::process::exit(-1),
+            };
+            if sources.contains(&canonical(&changed)) {
+                break;
+            }
+        }
+        // program started by previous build may still be running
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// Level passed to `-O`, only levels gccjit has are accepted
+fn parse_opt_level(s: &str) -> Result<u8, String> {
+    let level = s.parse::<u8>().map_err(|err| err.to_string())?;
+    match havo::gccjit::opt_level(level) {
+        Some(_) => Ok(level),
+        None => Err(format!(
+            "optimization level {} is out of range, expected 0..3",
+            level
+        )),
+    }
+}
+
+/// Type given to `--int-type`, `isize` and `usize` are 64 bit wide like `i64` and `u64`
+fn parse_int_type(s: &str) -> Result<IntSuffix, String> {
+    let name = match s {
+        "isize" => "i64",
+        "usize" => "u64",
+        _ => s,
+    };
+    IntSuffix::of_type(name).ok_or_else(|| {
+        format!(
+            "`{}` isn't integer type, expected i8, i32, i64, isize, u8, u32, u64 or usize",
+            s
+        )
+    })
+}
+
+/// Print every token of file at `path` as `<position> <kind>`
+fn emit_tokens(path: &PathBuf) {
+    let reader = Reader::from_file(path.to_str().unwrap()).unwrap();
+    let mut lexer = Lexer::new(reader);
+    loop {
+        match lexer.read_token() {
+            Ok(token) if token.is_eof() => break,
+            Ok(token) => println!("{} {:?}", token.position, token.kind),
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(-1);
+            }
+        }
+    }
+}
+
+/// Print backends `havo` is built with and what gcc reports about target of gccjit backend
+fn list_targets() {
+    println!("backends:");
+    let backends = [
+        (Backend::gccjit(), "JIT and AOT compilation (default)"),
+        (Backend::cpp(), "C++ source output"),
+        (Backend::cranelift(), "JIT compilation of integer programs"),
+    ];
+    for (name, about) in backends.iter() {
+        println!("  {:<10} {}", name, about);
+    }
+    match havo::gccjit::target_triple() {
+        Some(triple) => println!("gccjit target: {}", triple),
+        None => println!("gccjit target: unknown (gcc driver not found)"),
+    }
+    println!(
+        "gccjit native features: {}",
+        havo::gccjit::native_features().join(" ")
+    );
+}
+
+fn main() -> Result<(), MsgWithPos> {
+    // `havo run <files> [-- <args>]` is `havo --jit` that exits with status returned by `main`
+    let mut args: Vec<String> = std::env::args().collect();
+    let run = args.get(1).map_or(false, |arg| arg == "run");
+    if run {
+        args.remove(1);
+    }
+    let opts: Options = Options::from_iter(args);
+    opts.color.apply();
+    if opts.list_targets {
+        list_targets();
+        return Ok(());
+    }
+    if opts.watch {
+        watch(&opts);
+    }
+    if opts.emit_tokens {
+        opts.files.iter().for_each(emit_tokens);
+        return Ok(());
+    }
+    let mut ctx = Context::new(parse_file(&opts.files[0]));
+    ctx.include_dirs = opts.include_dirs.clone();
+    ctx.int_suffix = opts.int_type;
+    for path in opts.files.iter().skip(1) {
+        ctx.merge(parse_file(path));
+    }
     ctx.shared = opts.shared;
     ctx.emit_asm = opts.emit_asm;
     ctx.emit_obj = opts.emit_obj;
-    ctx.jit = opts.jit;
+    ctx.jit = opts.jit || run;
+    ctx.run = run;
+    ctx.args = std::iter::once(opts.files[0].display().to_string())
+        .chain(opts.program_args.iter().cloned())
+        .collect();
     ctx.output = opts
         .output
         .map_or(String::new(), |e: PathBuf| e.to_str().unwrap().to_owned());
     ctx.opt = opts.opt_level;
     ctx.gimple = opts.emit_gimple;
+    ctx.debug_bounds = opts.debug_bounds;
+    ctx.debug_null = opts.debug_null;
+    ctx.debug_overflow = opts.debug_overflow;
+    ctx.gc = opts.gc;
+    ctx.sanitize = opts.sanitize.clone();
+    ctx.panic_handler = opts.panic_handler.clone();
     ctx.file.elems.extend(
         opts.libraries_link
             .iter()
             .map(|name| havo::ast::Elem::Link(havo::intern(name))),
     );
+    if opts.test {
+        havo::test_runner::generate_main(&mut ctx.file);
+    }
     let mut semantic = SemCheck::new(&mut ctx);
 
     semantic.run();
+    if opts.dump_mono {
+        print!("{}", semantic.dump_mono());
+        return Ok(());
+    }
+    if opts.print_types {
+        for elem in ctx.file.elems.iter() {
+            if let Elem::Func(func) = elem {
+                if func.body.is_some() {
+                    let typed = display::TypedFunction {
+                        func,
+                        types: &ctx.types,
+                    };
+                    println!("{}", typed);
+                }
+            }
+        }
+        return Ok(());
+    }
+    havo::desugar::lower_try(&mut ctx);
+    if opts.const_eval {
+        const_eval::ConstEval::new(&mut ctx, opts.aggressive_eval).run();
+    } else if const_eval::has_comptime(&ctx.file) {
+        const_eval::ConstEval::comptime(&mut ctx).run();
+    }
     use havo::eval::EvalCtx;
     /*let mut eval = EvalCtx::new(&mut ctx);
     eval.run();*/
@@ -166,6 +496,16 @@ fn main() -> Result<(), MsgWithPos> {
         }
     }
 
+    if opts.emit_ir {
+        let module = havo::ir::lower::lower(&ctx);
+        if ctx.output.is_empty() {
+            print!("{}", module);
+        } else {
+            std::fs::write(&ctx.output, module.to_string()).unwrap();
+        }
+        return Ok(());
+    }
+
     match opts.backend {
         Backend::CPP => {
             use havo::ast2cpp::Translator;
@@ -180,7 +520,8 @@ fn main() -> Result<(), MsgWithPos> {
             cgen.compile();
         }
         Backend::CraneLift => {
-            eprintln!("Cranelift backend still unimplemented");
+            let mut cgen = havo::cranelift::Codegen::new(&mut ctx);
+            cgen.compile();
         }
     }
 
@@ -0,0 +1,181 @@
+//! Symbol names of functions.
+//!
+//! Functions may be overloaded, so symbol of function encodes its receiver and parameter types:
+//!
+//! ```text
+//! symbol = "_H" name [ "M" type ] { type } "E"
+//! name   = <length in bytes> <identifier>          e.g. `4main`, `5Point`
+//! type   = "v"                                     void
+//!        | name                                    basic or struct type
+//!        | "P" type                                pointer
+//!        | "A" <length> "_" type                   fixed-length array
+//!        | "S" type                                slice
+//!        | "V" <size> "_" type                     vector
+//!        | "F" { type } "_" type                   function, parameters then return type
+//! ```
+//!
+//! Names start with digit and every other type with its own letter, so different signatures
+//! never share symbol and `demangle` can read it back. `main`, `@export` and `@no_mangle`
+//! functions keep their names.
+
+use crate::{
+    str,
+    syntax::ast::{Function, Type},
+};
+
+/// Symbol of `func` in object file
+pub fn mangle(func: &Function) -> String {
+    let name = str(func.name).to_string();
+    if name == "main" || func.has_attribute("export") || func.has_attribute("no_mangle") {
+        return name;
+    }
+    let mut symbol = "_H".to_owned();
+    mangle_name(&mut symbol, &name);
+    if let Some((_, this)) = &func.this {
+        symbol.push('M');
+        mangle_type(&mut symbol, this);
+    }
+    for (_, param) in func.params.iter() {
+        mangle_type(&mut symbol, param);
+    }
+    symbol.push('E');
+    symbol
+}
+
+fn mangle_name(symbol: &mut String, name: &str) {
+    symbol.push_str(&name.len().to_string());
+    symbol.push_str(name);
+}
+
+fn mangle_type(symbol: &mut String, ty: &Type) {
+    match ty {
+        Type::Void(_) => symbol.push('v'),
+        Type::Basic(basic) => mangle_name(symbol, &str(basic.name)),
+        Type::Struct(struc) => mangle_name(symbol, &str(struc.name)),
+        Type::Ptr(ptr) => {
+            symbol.push('P');
+            mangle_type(symbol, &ptr.subtype);
+        }
+        Type::Array(array) => {
+            match array.len {
+                Some(len) => symbol.push_str(&format!("A{}_", len)),
+                None => symbol.push('S'),
+            }
+            mangle_type(symbol, &array.subtype);
+        }
+        Type::Vector(vector) => {
+            symbol.push_str(&format!("V{}_", vector.size));
+            mangle_type(symbol, &vector.subtype);
+        }
+        Type::Func(func) => {
+            symbol.push('F');
+            for param in func.params.iter() {
+                mangle_type(symbol, param);
+            }
+            symbol.push('_');
+            mangle_type(symbol, &func.ret);
+        }
+    }
+}
+
+/// Readable signature of function with `symbol`, e.g. `(*Point) scale(i32, f64[])` for method.
+/// Symbols that aren't produced by `mangle` are returned as is.
+pub fn demangle(symbol: &str) -> String {
+    let mut demangler = Demangler {
+        symbol: symbol.as_bytes(),
+        pos: 0,
+    };
+    demangler
+        .function()
+        .filter(|_| demangler.pos == symbol.len())
+        .unwrap_or_else(|| symbol.to_owned())
+}
+
+struct Demangler<'a> {
+    symbol: &'a [u8],
+    pos: usize,
+}
+
+impl Demangler<'_> {
+    fn function(&mut self) -> Option<String> {
+        if !self.symbol.starts_with(b"_H") {
+            return None;
+        }
+        self.pos = 2;
+        let name = self.name()?;
+        let this = if self.eat(b'M') {
+            Some(self.ty()?)
+        } else {
+            None
+        };
+        let mut params = vec![];
+        while !self.eat(b'E') {
+            params.push(self.ty()?);
+        }
+        let signature = format!("{}({})", name, params.join(", "));
+        Some(match this {
+            Some(this) => format!("({}) {}", this, signature),
+            None => signature,
+        })
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.symbol.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.symbol.get(self.pos).map_or(false, u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.symbol[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let len = self.number()?;
+        let name = self.symbol.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        String::from_utf8(name.to_vec()).ok()
+    }
+
+    fn ty(&mut self) -> Option<String> {
+        let tag = *self.symbol.get(self.pos)?;
+        if tag.is_ascii_digit() {
+            return self.name();
+        }
+        self.pos += 1;
+        Some(match tag {
+            b'v' => "void".to_owned(),
+            b'P' => format!("*{}", self.ty()?),
+            b'S' => format!("{}[]", self.ty()?),
+            b'A' | b'V' => {
+                let len = self.number()?;
+                if !self.eat(b'_') {
+                    return None;
+                }
+                let subtype = self.ty()?;
+                if tag == b'A' {
+                    format!("{}[{}]", subtype, len)
+                } else {
+                    format!("<{};{}>", subtype, len)
+                }
+            }
+            b'F' => {
+                let mut params = vec![];
+                while !self.eat(b'_') {
+                    params.push(self.ty()?);
+                }
+                format!("({}) -> {}", params.join(","), self.ty()?)
+            }
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,139 @@
+//! Specialization of generic functions.
+//!
+//! `func max<T>(a: T, b: T) T` is a template: semantic pass infers type arguments of every call
+//! from argument types and declares specialization of template with `T` replaced by them.
+//! Specialization is ordinary overload of template's name, so backends pick it by argument types
+//! like any other overload, and templates themselves never reach them.
+
+use crate::{
+    ast::{visit::*, *},
+    gen_id, str,
+    syntax::interner::Name,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Types that type parameters of template are replaced with
+pub type TypeArgs = HashMap<Name, Type>;
+
+/// Bind type parameters `params` that appear in parameter type `param` so it matches argument
+/// type `arg`, fixed-size array argument binds elements of slice or pointer parameter it decays
+/// to. Returns type parameter that is already bound to different type. Parts of `param` without
+/// type parameters aren't checked here, caller compares argument with specialized parameter
+/// afterwards.
+pub fn bind(params: &[Name], param: &Type, arg: &Type, args: &mut TypeArgs) -> Result<(), Name> {
+    match (param, arg) {
+        (Type::Basic(basic), _) if params.contains(&basic.name) => match args.get(&basic.name) {
+            Some(bound) if bound != arg => Err(basic.name),
+            Some(_) => Ok(()),
+            None => {
+                args.insert(basic.name, arg.clone());
+                Ok(())
+            }
+        },
+        (Type::Ptr(param), Type::Ptr(arg)) => bind(params, &param.subtype, &arg.subtype, args),
+        (Type::Ptr(param), Type::Array(arg)) if arg.len.is_some() => {
+            bind(params, &param.subtype, &arg.subtype, args)
+        }
+        (Type::Array(param), Type::Array(arg))
+            if param.len == arg.len || param.len.is_none() && arg.len.is_some() =>
+        {
+            bind(params, &param.subtype, &arg.subtype, args)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Report of `--dump-mono`: each generic function with number of its specializations, followed
+/// by type arguments of each of them. Sorted, so it doesn't depend on order of calls.
+pub fn dump(instances: &HashSet<(Name, Vec<Type>)>) -> String {
+    let mut funcs = BTreeMap::<String, Vec<String>>::new();
+    for (name, args) in instances.iter() {
+        let args = args.iter().map(|ty| ty.to_string()).collect::<Vec<_>>();
+        funcs
+            .entry(str(*name).to_string())
+            .or_default()
+            .push(format!("{}<{}>", str(*name), args.join(", ")));
+    }
+    let mut report = String::new();
+    for (name, mut specs) in funcs {
+        specs.sort();
+        report.push_str(&format!("{}: {} specialization(s)\n", name, specs.len()));
+        for spec in specs {
+            report.push_str(&format!("    {}\n", spec));
+        }
+    }
+    report
+}
+
+/// `ty` with type parameters replaced by their arguments
+pub fn substitute(ty: &Type, args: &TypeArgs) -> Type {
+    match ty {
+        Type::Basic(basic) => args.get(&basic.name).cloned().unwrap_or_else(|| ty.clone()),
+        Type::Ptr(ptr) => Type::Ptr(TypePtr {
+            subtype: box substitute(&ptr.subtype, args),
+            ..ptr.clone()
+        }),
+        Type::Array(array) => Type::Array(TypeArray {
+            subtype: box substitute(&array.subtype, args),
+            ..array.clone()
+        }),
+        Type::Vector(vector) => Type::Vector(TypeVector {
+            subtype: box substitute(&vector.subtype, args),
+            ..vector.clone()
+        }),
+        Type::Func(func) => Type::Func(TypeFunc {
+            params: func
+                .params
+                .iter()
+                .map(|param| box substitute(param, args))
+                .collect(),
+            ret: box substitute(&func.ret, args),
+            ..func.clone()
+        }),
+        Type::Struct(_) | Type::Void(_) => ty.clone(),
+    }
+}
+
+/// Copy of template `func` with type parameters replaced by `args`. Nodes of copy get fresh
+/// ids, otherwise types recorded for them would be shared by all specializations.
+pub fn specialize(func: &Function, args: &TypeArgs) -> Function {
+    let mut spec = func.clone();
+    spec.id = gen_id();
+    spec.type_params.clear();
+    for (_, param) in spec.params.iter_mut() {
+        let ty = substitute(param, args);
+        **param = ty;
+    }
+    spec.ret = box substitute(&spec.ret, args);
+    Specializer(args).fold_function(&mut spec);
+    spec
+}
+
+struct Specializer<'a>(&'a TypeArgs);
+
+impl Folder for Specializer<'_> {
+    fn fold_stmt(&mut self, stmt: &mut Stmt) {
+        stmt.id = gen_id();
+        if let StmtKind::Var(_, _, Some(ty), _) = &mut stmt.kind {
+            *ty = substitute(ty, self.0);
+        }
+        walk_stmt_mut(self, stmt)
+    }
+
+    fn fold_expr(&mut self, expr: &mut Expr) {
+        expr.id = gen_id();
+        match &mut expr.kind {
+            ExprKind::Array(ty, _)
+            | ExprKind::New(ty, _)
+            | ExprKind::Conv(_, ty)
+            | ExprKind::SizeOf(ty)
+            | ExprKind::AlignOf(ty)
+            | ExprKind::VaArg(ty) => {
+                let substituted = substitute(ty, self.0);
+                **ty = substituted;
+            }
+            _ => (),
+        }
+        walk_expr_mut(self, expr)
+    }
+}
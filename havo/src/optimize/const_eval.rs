@@ -1,5 +1,4 @@
 use crate::{
-    intern,
     syntax::{
         lexer::token::{FloatSuffix, IntBase, IntSuffix},
         position::Position,
@@ -10,12 +9,18 @@ pub fn rc<T>(v: T) -> Rc<RefCell<T>> {
     Rc::new(RefCell::new(v))
 }
 
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 /// Constant value that known at compile-time
 #[derive(Clone, PartialOrd, Debug)]
 pub enum Const {
     /// Immediate value or just int value
     Imm(i64, IntSuffix, IntBase),
+    /// `i128` or `u128` value and whether it's signed, unsigned value is stored as its bit pattern
+    Imm128(i128, bool),
     /// Float value
     Float(f64, FloatSuffix),
     /// Boolean value
@@ -40,10 +45,17 @@ fn is_void(&self) -> bool {
         }
     }
 
-    /// Translate Const value into Expression
-    fn to_kind(&self) -> ExprKind {
-        match self {
+    /// Translate Const value into Expression, `pos` is position of expression that computed it.
+    /// `None` if value has no literal (128-bit constant that doesn't fit into 64 bits), such
+    /// expression is left unfolded. `Const::None` has no expression, callers check that value was
+    /// evaluated first.
+    pub(crate) fn to_kind(&self, pos: Position) -> Option<ExprKind> {
+        Some(match self {
             Const::Imm(imm, suffix, base) => ExprKind::Int(*imm, base.clone(), suffix.clone()),
+            Const::Imm128(imm, _) if *imm as i64 as i128 == *imm => {
+                ExprKind::Int(*imm as i64, IntBase::Dec, IntSuffix::Long)
+            }
+            Const::Imm128(..) => return None,
             Const::Float(f, suffix) => ExprKind::Float(*f, suffix.clone()),
             Const::Bool(b) => ExprKind::Bool(*b),
             Const::Struct(name, fields) => {
@@ -51,30 +63,48 @@ fn to_kind(&self) -> ExprKind {
                 for (name, constant, id) in fields.iter() {
                     args.push(StructArg {
                         id: *id,
-                        pos: Position::new(intern(""), 0, 0),
+                        pos,
                         name: *name,
                         expr: box Expr {
                             id: NodeId(0),
-                            pos: Position::new(intern(""), 0, 0),
-                            kind: constant.borrow().to_kind(),
+                            pos,
+                            kind: constant.borrow().to_kind(pos)?,
                         },
                     })
                 }
                 ExprKind::Struct(Path::new(*name), args)
             }
-            Const::Ret(c) => c.borrow().to_kind(),
+            Const::Ret(c) => return c.borrow().to_kind(pos),
             Const::Str(s) => ExprKind::Str(s.to_owned()),
-            v => panic!("{:?}", v),
-        }
+            Const::Array(values) => {
+                let exprs = values
+                    .borrow()
+                    .iter()
+                    .map(|val| {
+                        Some(box Expr {
+                            id: NodeId(0),
+                            pos,
+                            kind: val.borrow().to_kind(pos)?,
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                ExprKind::Array(box Type::Void(pos), exprs)
+            }
+            Const::Void => error!("value of `void` expression can't be used", pos),
+            Const::None => unreachable!("value that can't be evaluated has no expression"),
+        })
     }
 }
 
-use std::cmp::PartialEq;
+use std::cmp::{Ordering, PartialEq};
 
 impl PartialEq for Const {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Const::Imm(i, _, _), Const::Imm(i2, _, _)) => i == i2,
+            (Const::Imm128(i, _), Const::Imm128(i2, _)) => i == i2,
+            (Const::Imm128(i, _), Const::Imm(i2, s, _))
+            | (Const::Imm(i2, s, _), Const::Imm128(i, _)) => *i == imm_to_i128(*i2, *s),
             (Const::Imm(i, _, _), Const::Float(f, _)) => *i as f64 == *f,
             (Const::Float(f, _), Const::Imm(i, _, _)) => *f == *i as f64,
             (Const::Float(f, _), Const::Float(f2, _)) => f == f2,
@@ -103,11 +133,128 @@ fn is_none(&self) -> bool {
         }
     }
 }
+/// 128-bit value of integer constant with suffix `suffix`, unsigned values are zero-extended
+fn imm_to_i128(imm: i64, suffix: IntSuffix) -> i128 {
+    match suffix {
+        IntSuffix::ULong => imm as u64 as i128,
+        IntSuffix::UInt => imm as u32 as i128,
+        IntSuffix::UByte => imm as u8 as i128,
+        _ => imm as i128,
+    }
+}
+
+/// Binary operation where at least one operand is `i128`/`u128`, other integer operand is
+/// widened to it. Returns `None` if neither operand is 128-bit.
+fn eval_binop128(op: &str, c1: &Const, c2: &Const) -> Option<Const> {
+    let (l, r, signed) = match (c1, c2) {
+        (Const::Imm128(l, signed), Const::Imm128(r, _)) => (*l, *r, *signed),
+        (Const::Imm128(l, signed), Const::Imm(r, s, _)) => (*l, imm_to_i128(*r, *s), *signed),
+        (Const::Imm(l, s, _), Const::Imm128(r, signed)) => (imm_to_i128(*l, *s), *r, *signed),
+        _ => return None,
+    };
+    let int = |val| Const::Imm128(val, signed);
+    let ord = if signed {
+        l.cmp(&r)
+    } else {
+        (l as u128).cmp(&(r as u128))
+    };
+    Some(match op {
+        "+" => int(l.wrapping_add(r)),
+        "-" => int(l.wrapping_sub(r)),
+        "*" => int(l.wrapping_mul(r)),
+        "/" | "%" if r == 0 => Const::None,
+        "/" if signed => int(l.wrapping_div(r)),
+        "/" => int((l as u128 / r as u128) as i128),
+        "%" if signed => int(l.wrapping_rem(r)),
+        "%" => int((l as u128 % r as u128) as i128),
+        "|" => int(l | r),
+        "&" => int(l & r),
+        "^" => int(l ^ r),
+        "<<" => int(l.wrapping_shl(r as u32)),
+        ">>" if signed => int(l.wrapping_shr(r as u32)),
+        ">>" => int((l as u128).wrapping_shr(r as u32) as i128),
+        "==" => Const::Bool(ord == Ordering::Equal),
+        "!=" => Const::Bool(ord != Ordering::Equal),
+        "<" => Const::Bool(ord == Ordering::Less),
+        ">" => Const::Bool(ord == Ordering::Greater),
+        "<=" => Const::Bool(ord != Ordering::Greater),
+        ">=" => Const::Bool(ord != Ordering::Less),
+        _ => Const::None,
+    })
+}
+
+/// Value stored to variable of type `ty`: integer constants of `i128`/`u128` variables are kept
+/// 128-bit so later arithmetic on them doesn't wrap at 64 bits
+fn widen_to(val: Rc<RefCell<Const>>, ty: Option<&Type>) -> Rc<RefCell<Const>> {
+    let ty = match ty {
+        Some(ty) if crate::semantic::ty_is_int128(ty) => ty,
+        _ => return val,
+    };
+    let signed = match ty {
+        Type::Basic(basic) => str(basic.name).as_str() == "i128",
+        _ => unreachable!(),
+    };
+    let widened = match &*val.borrow() {
+        Const::Imm(i, suffix, _) => Const::Imm128(imm_to_i128(*i, *suffix), signed),
+        Const::Imm128(i, _) => Const::Imm128(*i, signed),
+        _ => return val.clone(),
+    };
+    rc(widened)
+}
+
+/// Size of pointers. Value of function type is pointer to its code (e.g `func&f`), so function
+/// types have this size too. Shared with `Codegen::ty_size` so folded and generated `sizeof` agree.
+/// Only 64-bit targets are supported, `Codegen::compile` rejects target with other pointer size.
+pub const PTR_SIZE: usize = 8;
+
+/// Alignment of type, `None` if its size isn't known
+fn ty_align(ty: &Type) -> Option<usize> {
+    match ty {
+        Type::Ptr(_) | Type::Func(_) => Some(PTR_SIZE),
+        Type::Array(array) if array.len.is_some() => ty_align(&array.subtype),
+        Type::Array(_) => Some(PTR_SIZE),
+        Type::Struct(s) => Some(struct_layout(s)?.1),
+        Type::Void(_) => Some(1),
+        _ => ty_size(ty),
+    }
+}
+
+/// Size and alignment of struct, laid out like `Codegen::ty_size` does
+fn struct_layout(s: &TypeStruct) -> Option<(usize, usize)> {
+    let (_, size, align) = field_offsets(s)?;
+    Some((size, align))
+}
+
+/// Offsets of fields of struct in order, then its size and alignment
+fn field_offsets(s: &TypeStruct) -> Option<(Vec<usize>, usize, usize)> {
+    let mut offsets = vec![];
+    let mut size = 0;
+    let mut align = 1;
+    for field in s.fields.iter() {
+        let field_size = ty_size(&field.data_type)?;
+        let field_align = match s.packing() {
+            Some(packing) => ty_align(&field.data_type)?.min(packing),
+            None => ty_align(&field.data_type)?,
+        };
+        let field_align = field_align.max(field.align().unwrap_or(1));
+        align = align.max(field_align);
+        let offset = if s.union {
+            0
+        } else {
+            (size + field_align - 1) / field_align * field_align
+        };
+        offsets.push(offset);
+        size = size.max(offset + field_size);
+    }
+    let align = align.max(s.align().unwrap_or(1));
+    Some((offsets, (size + align - 1) / align * align, align))
+}
+
 /// return size of type
 fn ty_size(ty: &Type) -> Option<usize> {
     match ty {
         Type::Vector(_) => None, // TODO
-        Type::Ptr(_) => Some(std::mem::size_of::<*const u8>()),
+        Type::Ptr(_) => Some(PTR_SIZE),
         Type::Basic(basic) => {
             let name: &str = &str(basic.name).to_string();
             match name {
@@ -115,28 +262,19 @@ fn ty_size(ty: &Type) -> Option<usize> {
                 "u16" => Some(2),
                 "u32" => Some(4),
                 "u64" => Some(8),
+                "u128" => Some(16),
                 "usize" => Some(std::mem::size_of::<usize>()),
                 "isize" => Some(std::mem::size_of::<isize>()),
                 "i8" => Some(1),
                 "i16" => Some(2),
                 "i32" => Some(4),
                 "i64" => Some(8),
+                "i128" => Some(16),
                 "char" => Some(1),
                 _ => None,
             }
         }
-        Type::Struct(s) => {
-            let mut size = 0;
-            for field in s.fields.iter() {
-                if let Some(s) = ty_size(&field.data_type) {
-                    size += s;
-                } else {
-                    return None;
-                }
-            }
-
-            Some(size)
-        }
+        Type::Struct(s) => Some(struct_layout(s)?.0),
         Type::Array(array) => {
             if array.len.is_some() {
                 if let Some(size) = ty_size(&array.subtype) {
@@ -145,24 +283,140 @@ fn ty_size(ty: &Type) -> Option<usize> {
                     return None;
                 }
             } else {
-                return Some(std::mem::size_of::<*const u8>());
+                // slice: pointer and length
+                return Some(2 * PTR_SIZE);
             }
         }
-        Type::Func(_) => return Some(std::mem::size_of::<*const u8>()),
+        Type::Func(_) => return Some(PTR_SIZE),
         Type::Void(_) => return Some(0),
     }
 }
 
 use crate::{
-    ast::*,
+    ast::{visit::*, *},
     syntax::interner::{str, Name},
     Context,
 };
+use colored::Colorize;
 use std::intrinsics::transmute;
 
+/// `static_assert(cond, "message")`: compilation fails with `message` unless `cond` is constant
+/// `true`. Both statement and top-level forms are removed once checked.
+pub const STATIC_ASSERT: &str = "static_assert";
+
+/// Whether `expr` is call of `static_assert`
+fn is_static_assert(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Call(path, None, _) => str(path.name()).as_str() == STATIC_ASSERT,
+        _ => false,
+    }
+}
+
+/// Builtins reading compiler's environment, folded to constants at compile time
+pub const ENV_BUILTINS: [&str; 2] = ["env_int", "env_str"];
+
+/// Evaluate `env_int("NAME", default)` or `env_str("NAME", default)`, `default` used when
+/// variable `NAME` is not set. Returns `None` if `name` isn't environment builtin.
+pub fn eval_env_builtin(name: &str, args: &[Box<Expr>], pos: Position) -> Option<Const> {
+    if !ENV_BUILTINS.contains(&name) {
+        return None;
+    }
+    let (var, default) = match args {
+        [var, default] => match &var.kind {
+            ExprKind::Str(var) => (var, default),
+            _ => error!(
+                format!("{} expects environment variable name as string literal", name),
+                var.pos
+            ),
+        },
+        _ => error!(format!("{} expects 2 arguments", name), pos),
+    };
+    let value = std::env::var(var).ok();
+    let int_default = match &default.kind {
+        ExprKind::Int(int, base, suffix) => Some((*int, *base, *suffix)),
+        ExprKind::Unary(op, int) if op == "-" => match &int.kind {
+            ExprKind::Int(int, base, suffix) => Some((-*int, *base, *suffix)),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    Some(match (name, &default.kind, int_default) {
+        ("env_str", ExprKind::Str(default), _) => {
+            Const::Str(value.unwrap_or_else(|| default.clone()))
+        }
+        ("env_int", _, Some((default, base, suffix))) => match value {
+            Some(value) => match value.trim().parse::<i64>() {
+                Ok(value) => Const::Imm(value, suffix, IntBase::Dec),
+                Err(_) => error!(
+                    format!(
+                        "environment variable `{}` is not an integer: `{}`",
+                        var, value
+                    ),
+                    pos
+                ),
+            },
+            None => Const::Imm(default, suffix, base),
+        },
+        ("env_str", _, _) => error!("env_str default must be string literal", default.pos),
+        _ => error!("env_int default must be integer literal", default.pos),
+    })
+}
+
+/// Builtins embedding contents of file, folded to constants at compile time
+pub const FILE_BUILTINS: [&str; 2] = ["include_bytes", "include_str"];
+
+/// Read file of `include_bytes("path")` or `include_str("path")`, `path` is relative to directory
+/// of file containing the call
+pub fn included_file(name: &str, args: &[Box<Expr>], pos: Position) -> Vec<u8> {
+    let path = match args {
+        [path] => match &path.kind {
+            ExprKind::Str(path) => path,
+            _ => error!(
+                format!("{} expects file path as string literal", name),
+                path.pos
+            ),
+        },
+        _ => error!(format!("{} expects 1 argument", name), pos),
+    };
+    let file = str(pos.file).to_string();
+    let dir = std::path::Path::new(&file)
+        .parent()
+        .unwrap_or_else(|| std::path::Path::new(""));
+    let full = dir.join(path);
+    match std::fs::read(&full) {
+        Ok(bytes) => bytes,
+        Err(err) => error!(
+            format!("can't include `{}`: {}", full.display(), err),
+            pos
+        ),
+    }
+}
+
+/// Evaluate `include_bytes("path")` to `u8` array or `include_str("path")` to string. Returns
+/// `None` if `name` isn't file builtin.
+pub fn eval_file_builtin(name: &str, args: &[Box<Expr>], pos: Position) -> Option<Const> {
+    if !FILE_BUILTINS.contains(&name) {
+        return None;
+    }
+    let bytes = included_file(name, args, pos);
+    Some(if name == "include_str" {
+        match String::from_utf8(bytes) {
+            Ok(s) => Const::Str(s),
+            Err(_) => error!("include_str expects UTF-8 file", pos),
+        }
+    } else {
+        let bytes = bytes
+            .into_iter()
+            .map(|byte| rc(Const::Imm(byte as i64, IntSuffix::UByte, IntBase::Dec)))
+            .collect();
+        Const::Array(rc(bytes))
+    })
+}
+
 /// Constant evaluator that tries to evaluate code.
-/// If `try_eval_normal` enabled then normal (non-constexpr) function evaluated
-/// if possible too
+/// Calls of normal (non-constexpr) functions without side effects are evaluated too, if
+/// `try_eval_normal` enabled even ones that have loops or are recursive
 pub struct ConstEval<'a> {
     /// Variables defined and known in compile-time context
     known_vars: HashMap<Name, Rc<RefCell<Const>>>,
@@ -172,11 +426,21 @@ pub struct ConstEval<'a> {
     return_: Option<Const>,
     constexprs: HashMap<Name, Expr>,
     functions: HashMap<Name, Vec<Function>>,
+    /// Normal functions whose calls with known arguments are evaluated
+    foldable: HashSet<Name>,
     try_eval_normal: bool,
     builtins: HashMap<Name, *const u8>,
-    id: usize,
+    /// Variables of current function whose address is taken, their values can't be tracked
+    escaping: HashSet<Name>,
     running: bool,
+    /// Body of normal function is evaluated, statement that can't be evaluated stops evaluation
+    /// instead of being skipped: unlike `constexpr` one such function may do anything at runtime
     normal: bool,
+    /// Only `constexpr` statements are evaluated, other code is left as is
+    comptime_only: bool,
+    /// Return type of function being folded
+    cur_ret: Option<Type>,
+    enums: HashMap<Name, Enum>,
 }
 
 impl<'a> ConstEval<'a> {
@@ -189,13 +453,26 @@ pub fn new(ctx: &'a mut Context, try_eval_normal: bool) -> ConstEval<'a> {
             return_: None,
             constexprs: HashMap::new(),
             functions: HashMap::new(),
+            foldable: HashSet::new(),
             builtins: super::builtins::builtins(),
             try_eval_normal,
-            id: 0,
+            escaping: HashSet::new(),
             running: false,
             normal: false,
+            comptime_only: false,
+            cur_ret: None,
+            enums: HashMap::new(),
         }
     }
+
+    /// Evaluator that only replaces `constexpr` statements with their effects and `constexpr`
+    /// expressions with their values. Neither is lowered to runtime code, so this runs even
+    /// without `--consteval`.
+    pub fn comptime(ctx: &'a mut Context) -> ConstEval<'a> {
+        let mut eval = ConstEval::new(ctx, false);
+        eval.comptime_only = true;
+        eval
+    }
     /// try to get variable
     fn try_get_var(&mut self, name: &Name) -> Rc<RefCell<Const>> {
         if self.constexprs.contains_key(name) {
@@ -220,6 +497,9 @@ fn eval_binop(&mut self, op: &str, lhs: &Expr, rhs: &Expr) -> Rc<RefCell<Const>>
         }
         let c1: &Const = &c1.borrow();
         let c2: &Const = &c2.borrow();
+        if let Some(val) = eval_binop128(op, c1, c2) {
+            return rc(val);
+        }
         let val = match op {
             "+" => match (c1.clone(), c2.clone()) {
                 (Const::Imm(i1, suffix, base), Const::Imm(i2, _, _)) => {
@@ -322,44 +602,48 @@ fn eval_binop(&mut self, op: &str, lhs: &Expr, rhs: &Expr) -> Rc<RefCell<Const>>
 
         Rc::new(RefCell::new(val))
     }
-    /// if `to` expression is identifier
-    ///  and variable with name of identifier known
-    ///  and `from` expression known at compile time perform assign
+    /// if `to` expression is identifier or field of known variable and `from` expression
+    /// known at compile time perform assign, otherwise variable becomes unknown
     fn try_assign(&mut self, to: &Expr, from: &Expr) {
-        match &to.kind {
-            ExprKind::Ident(name) => {
-                if self.known_vars.contains_key(name) {
-                    let val = self.eval(from);
+        let name = match place_root(to) {
+            Some(name) => name,
+            None => return,
+        };
+        if !self.known_vars.contains_key(&name) {
+            return;
+        }
+        let val = self.eval(from);
+        if val.borrow().is_none() {
+            self.known_vars.remove(&name);
+            return;
+        }
 
-                    if !val.borrow().is_none() {
-                        self.known_vars.insert(*name, val);
-                    } else {
-                        self.known_vars.remove(name);
-                    }
-                }
+        match &to.kind {
+            ExprKind::Ident(_) => {
+                let val = widen_to(val, self.ctx.types.get(&to.id));
+                self.known_vars.insert(name, val);
             }
-            ExprKind::Field(expr, field) => {
-                if let ExprKind::Ident(name) = &expr.kind {
-                    if self.known_vars.contains_key(name) {
-                        let val = self.eval(from);
-                        if val.borrow().is_none() {
-                            return;
-                        }
-                        let cval = self.known_vars.get(name).unwrap();
-                        let cval: &mut Const = &mut cval.borrow_mut();
-                        if let Const::Struct(_, fields) = cval {
-                            for (name, val_, id) in fields.iter_mut() {
-                                if name == field {
-                                    *id = from.id;
-                                    *val_ = val.clone();
-                                    break;
-                                }
+            ExprKind::Field(base, field) if place_root(base) == Some(name) => {
+                if let ExprKind::Ident(_) = base.kind {
+                    // copy on write, old value may be shared with other variable
+                    let mut cval = self.known_vars.get(&name).unwrap().borrow().clone();
+                    if let Const::Struct(_, fields) = &mut cval {
+                        for (name, val_, id) in fields.iter_mut() {
+                            if name == field {
+                                *id = from.id;
+                                *val_ = val.clone();
+                                break;
                             }
                         }
                     }
+                    self.known_vars.insert(name, rc(cval));
+                } else {
+                    self.known_vars.remove(&name);
                 }
             }
-            _ => (),
+            _ => {
+                self.known_vars.remove(&name);
+            }
         }
     }
     /// Evaluate expression
@@ -369,11 +653,25 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                 let val = self.eval(expr);
 
                 if !val.borrow().is_none() {
-                    use crate::semantic::{ty_is_any_float, ty_is_any_int};
+                    use crate::semantic::{ty_is_any_float, ty_is_any_int, ty_is_int128};
                     let val: &Const = &val.borrow();
-                    if ty_is_any_int(to) {
+                    if ty_is_int128(to) {
+                        match val {
+                            Const::Imm(..) | Const::Imm128(..) => {
+                                widen_to(rc(val.clone()), Some(&**to))
+                            }
+                            Const::Bool(b) => {
+                                let val = Const::Imm(*b as i64, IntSuffix::Int, IntBase::Dec);
+                                widen_to(rc(val), Some(&**to))
+                            }
+                            _ => rc(Const::None),
+                        }
+                    } else if ty_is_any_int(to) {
                         match val {
                             Const::Imm(i, s, b) => rc(Const::Imm(*i, *s, *b)),
+                            Const::Imm128(i, _) => {
+                                rc(Const::Imm(*i as i64, IntSuffix::Long, IntBase::Dec))
+                            }
                             Const::Float(f, s) => rc(Const::Imm(
                                 *f as i64,
                                 match s {
@@ -407,6 +705,14 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                             }
                             _ => rc(Const::None),
                         }
+                    } else if crate::semantic::ty_is_bool(to) {
+                        match val {
+                            Const::Bool(b) => rc(Const::Bool(*b)),
+                            Const::Imm(i, _, _) => rc(Const::Bool(*i != 0)),
+                            Const::Imm128(i, _) => rc(Const::Bool(*i != 0)),
+                            Const::Float(f, _) => rc(Const::Bool(*f != 0.0)),
+                            _ => rc(Const::None),
+                        }
                     } else if ty_is_any_float(to) {
                         match val {
                             Const::Float(f, s) => rc(Const::Float(*f, *s)),
@@ -418,6 +724,10 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                                     _ => FloatSuffix::Float,
                                 },
                             )),
+                            Const::Imm128(i, signed) => rc(Const::Float(
+                                if *signed { *i as f64 } else { *i as u128 as f64 },
+                                FloatSuffix::Double,
+                            )),
                             _ => rc(Const::None),
                         }
                     } else {
@@ -429,10 +739,34 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
             }
 
             ExprKind::Int(i, b, s) => rc(Const::Imm(*i, *s, *b)),
+            ExprKind::Variant(name, variant) => {
+                let e = &self.enums[name];
+                let suffix = match &e.repr {
+                    Type::Basic(basic) => IntSuffix::of_type(&str(basic.name)),
+                    _ => None,
+                };
+                rc(Const::Imm(
+                    e.variant(*variant).unwrap().value,
+                    suffix.unwrap_or(IntSuffix::Int),
+                    IntBase::Dec,
+                ))
+            }
             ExprKind::Float(f, s) => rc(Const::Float(*f, *s)),
             ExprKind::Bool(b) => rc(Const::Bool(*b)),
 
             ExprKind::Binary(op, lhs, rhs) => self.eval_binop(op, lhs, rhs),
+            ExprKind::Ternary(cond, then, otherwise) => {
+                let cond = self.eval(cond);
+                let cond = match &*cond.borrow() {
+                    Const::Bool(b) => Some(*b),
+                    _ => None,
+                };
+                match cond {
+                    Some(true) => self.eval(then),
+                    Some(false) => self.eval(otherwise),
+                    None => rc(Const::None),
+                }
+            }
             ExprKind::Unary(op, expr) => {
                 let op: &str = op;
                 let val = self.eval(expr);
@@ -448,11 +782,13 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                     },
                     "-" => match val {
                         Const::Imm(i, s, b) => rc(Const::Imm(-i, *s, *b)),
+                        Const::Imm128(i, signed) => rc(Const::Imm128(i.wrapping_neg(), *signed)),
                         Const::Float(f, s) => rc(Const::Float(-f, *s)),
                         _ => rc(Const::None),
                     },
                     "!" => match val {
                         Const::Imm(i, s, b) => rc(Const::Imm(!i, *s, *b)),
+                        Const::Imm128(i, signed) => rc(Const::Imm128(!i, *signed)),
 
                         Const::Bool(b) => rc(Const::Bool(!b)),
                         _ => rc(Const::None),
@@ -486,6 +822,9 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                         }
                     }
                 }
+                if let (Const::Str(s), "len") = (val, str(*field).as_str()) {
+                    return rc(Const::Imm(s.len() as i64, IntSuffix::ULong, IntBase::Dec));
+                }
 
                 return rc(Const::None);
             }
@@ -495,6 +834,17 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                 self.try_assign(to, from);
                 return self.eval(from);
             }
+            ExprKind::Array(_, exprs) => {
+                let mut values = vec![];
+                for expr in exprs.iter() {
+                    let val = self.eval(expr);
+                    if val.borrow().is_none() {
+                        return rc(Const::None);
+                    }
+                    values.push(val);
+                }
+                rc(Const::Array(rc(values)))
+            }
             ExprKind::ArrayIdx(expr_, id) => {
                 let id = self.eval(id);
                 let id: &Const = &id.borrow();
@@ -510,15 +860,33 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                 };
                 let array: &Const = &array.borrow();
                 if let Const::Array(array) = array {
-                    return array.borrow()[idx].clone();
+                    let array = array.borrow();
+                    if idx >= array.len() {
+                        error!(
+                            format!(
+                                "index {} out of bounds for array of length {}",
+                                idx as i64,
+                                array.len()
+                            ),
+                            expr.pos
+                        );
+                    }
+                    return array[idx].clone();
                 } else {
                     return rc(Const::None);
                 }
             }
 
             ExprKind::Call(name, this, args) => {
-                if this.is_some() {
-                    return rc(Const::None); // we don't support constexpr methods yet
+                if let Some(this) = this {
+                    return self.eval_constmethod(name.name(), this, args);
+                }
+
+                if let Some(val) = eval_env_builtin(&str(name.name()), args, expr.pos) {
+                    return rc(val);
+                }
+                if let Some(val) = eval_file_builtin(&str(name.name()), args, expr.pos) {
+                    return rc(val);
                 }
 
                 if self.const_functions.contains_key(&name.name()) {
@@ -527,7 +895,10 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                     let mut func = None;
 
                     for fun in funcs.iter() {
-                        if args.len() < fun.params.len() || args.len() > fun.params.len() {
+                        if fun.this.is_some()
+                            || args.len() < fun.params.len()
+                            || args.len() > fun.params.len()
+                        {
                             continue;
                         }
                         let mut params_match = false;
@@ -553,14 +924,22 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                         for (name, _) in func.params.iter() {
                             params.push(*name);
                         }
-                        return self.eval_constfn(&params, func.body.as_ref().unwrap(), args);
+                        return self.eval_constfn(
+                            &params,
+                            func.body.as_ref().unwrap(),
+                            args,
+                            None,
+                        );
                     }
-                } else if self.functions.contains_key(&name.name()) && self.try_eval_normal {
+                } else if self.foldable.contains(&name.name()) {
                     let funcs: Vec<Function> = self.functions.get(&name.name()).unwrap().clone();
                     let mut func = None;
 
                     for fun in funcs.iter() {
-                        if args.len() < fun.params.len() || args.len() > fun.params.len() {
+                        if fun.this.is_some()
+                            || args.len() < fun.params.len()
+                            || args.len() > fun.params.len()
+                        {
                             continue;
                         }
                         let mut params_match = false;
@@ -579,14 +958,23 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                     }
 
                     if func.is_none() {
-                        panic!("function not found");
+                        // arguments need conversion, call is left to runtime
+                        return rc(Const::None);
                     } else {
                         let func: Function = func.unwrap();
                         let mut params = vec![];
                         for (name, _) in func.params.iter() {
                             params.push(*name);
                         }
-                        return self.eval_constfn(&params, func.body.as_ref().unwrap(), args);
+                        let normal = std::mem::replace(&mut self.normal, true);
+                        let val = self.eval_constfn(
+                            &params,
+                            func.body.as_ref().unwrap(),
+                            args,
+                            None,
+                        );
+                        self.normal = normal;
+                        return val;
                     }
                 } else if false {
                     let builtin = self.builtins.get(&name.name()).unwrap().clone();
@@ -607,26 +995,6 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                     } else {
                         return val;
                     }
-                } else {
-                    // try to optimize arguments
-
-                    for arg in args.iter() {
-                        let val = self.eval(arg);
-                        if val.borrow().is_none() {
-                            return rc(Const::None);
-                        } else {
-                            if let Elem::Func(f) = &mut self.ctx.file.elems[self.id] {
-                                f.replace_expr_to(
-                                    arg.id,
-                                    Expr {
-                                        id: arg.id,
-                                        pos: expr.pos,
-                                        kind: val.borrow().to_kind(),
-                                    },
-                                );
-                            }
-                        }
-                    }
                 }
 
                 rc(Const::None)
@@ -638,6 +1006,20 @@ fn eval(&mut self, expr: &Expr) -> Rc<RefCell<Const>> {
                     return rc(Const::None);
                 }
             }
+            ExprKind::AlignOf(ty) => match ty_align(ty) {
+                Some(align) => rc(Const::Imm(align as i64, IntSuffix::Int, IntBase::Dec)),
+                None => rc(Const::None),
+            },
+            ExprKind::OffsetOf(name, field) => {
+                let s = self.ctx.file.find_struct(*name).unwrap().to_type();
+                let index = s.fields.iter().position(|f| f.name == *field).unwrap();
+                match field_offsets(&s) {
+                    Some((offsets, ..)) => {
+                        rc(Const::Imm(offsets[index] as i64, IntSuffix::Int, IntBase::Dec))
+                    }
+                    None => rc(Const::None),
+                }
+            }
 
             _ => rc(Const::None),
         }
@@ -648,28 +1030,21 @@ fn eval_constfn(
         params: &[Name],
         body: &Stmt,
         args: &Vec<Box<Expr>>,
+        this: Option<(Name, Rc<RefCell<Const>>)>,
     ) -> Rc<RefCell<Const>> {
         let old_vars = self.known_vars.clone();
         //self.known_vars.clear();
         self.return_ = None;
         let mut new_vars = HashMap::new();
+        if let Some((name, val)) = this {
+            new_vars.insert(name, val);
+        }
         for (i, param) in params.iter().enumerate() {
             let val = self.eval(&args[i]);
 
             if val.borrow().is_none() {
                 return rc(Const::None); // Argument value not known at compile time, return none
             }
-
-            if let Elem::Func(f) = &mut self.ctx.file.elems[self.id] {
-                f.replace_expr_to(
-                    args[i].id,
-                    Expr {
-                        id: args[i].id,
-                        pos: args[i].pos,
-                        kind: val.borrow().to_kind(),
-                    },
-                );
-            }
             new_vars.insert(*param, val);
         }
 
@@ -689,6 +1064,49 @@ fn eval_constfn(
             return rc(Const::None);
         }
     }
+    /// Evaluate call of constexpr method, receiver is bound to method's `this` parameter
+    fn eval_constmethod(
+        &mut self,
+        name: Name,
+        this: &Expr,
+        args: &Vec<Box<Expr>>,
+    ) -> Rc<RefCell<Const>> {
+        let funcs = match self.const_functions.get(&name) {
+            Some(funcs) => funcs.clone(),
+            None => return rc(Const::None),
+        };
+        let this_ty = match self.ctx.types.get(&this.id) {
+            Some(ty) if ty.is_ptr() => ty.clone(),
+            Some(ty) => Type::create_ptr(ty.id(), ty.pos(), box ty.clone()),
+            None => return rc(Const::None),
+        };
+
+        let func = funcs.iter().find(|fun| {
+            fun.this.as_ref().map(|(_, ty)| **ty == this_ty) == Some(true)
+                && fun.params.len() == args.len()
+                && args
+                    .iter()
+                    .zip(fun.params.iter())
+                    .all(|(arg, (_, ty))| self.ctx.types.get(&arg.id) == Some(&**ty))
+        });
+        let func = match func {
+            Some(func) => func,
+            None => return rc(Const::None),
+        };
+
+        let receiver = self.eval(this);
+        if receiver.borrow().is_none() {
+            return rc(Const::None);
+        }
+        let params = func.params.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        self.eval_constfn(
+            &params,
+            func.body.as_ref().unwrap(),
+            args,
+            Some((func.this.as_ref().unwrap().0, receiver)),
+        )
+    }
+
     /// Evaluate constant
     fn eval_stmt(&mut self, stmt: &Stmt) -> Option<Rc<RefCell<Const>>> {
         match &stmt.kind {
@@ -696,6 +1114,10 @@ fn eval_stmt(&mut self, stmt: &Stmt) -> Option<Rc<RefCell<Const>>> {
                 let mut last = None;
                 for stmt in stmts.iter() {
                     let val = self.eval_stmt(stmt);
+                    let unknown = val.as_ref().map_or(true, |val| val.borrow().is_none());
+                    if unknown && self.normal {
+                        return None;
+                    }
                     last = val;
                     if last.is_some() {
                         let last: &Const = &last.as_ref().unwrap().borrow();
@@ -756,182 +1178,813 @@ fn eval_stmt(&mut self, stmt: &Stmt) -> Option<Rc<RefCell<Const>>> {
             }
 
             StmtKind::While(cond, body) => {
-                let cond = self.eval(cond);
-                let cond: &Const = &cond.borrow();
-                while let Const::Bool(true) = cond {
-                    let val = &self.eval_stmt(body);
-
-                    if val.is_none() {
-                        return None;
-                    } else if val.as_ref().unwrap().borrow().is_none() {
+                loop {
+                    match &*self.eval(cond).borrow() {
+                        Const::Bool(true) => (),
+                        Const::Bool(false) => break,
+                        _ => return None,
+                    }
+                    let val = self.eval_stmt(body)?;
+                    if val.borrow().is_none() {
                         return None;
                     }
+                    if let Const::Ret(_) = &*val.borrow() {
+                        return Some(val.clone());
+                    }
                 }
                 return Some(Rc::new(RefCell::new(Const::Void)));
             }
+            StmtKind::CompTime(stmt) => self.eval_stmt(stmt),
 
-            _ => panic!("Unsupported statement in constant function"),
+            _ => None,
         }
     }
 
-    /// Evaluates statement in non-const context
-    fn eval_normal_stmt(&mut self, s: &Stmt, fid: usize) {
-        match &s.kind {
-            StmtKind::Block(block) => {
-                for stmt in block.iter() {
-                    self.eval_normal_stmt(stmt, fid);
+    /// Literal replacing expression of type `ty` with value `val`, `None` if value can't be
+    /// written as literal of that type
+    fn literal_of(&self, val: &Const, ty: &Type) -> Option<ExprKind> {
+        let name = match ty {
+            Type::Basic(basic) => str(basic.name).to_string(),
+            Type::Ptr(ptr) => match (&*ptr.subtype, val) {
+                (Type::Basic(basic), Const::Str(s)) if str(basic.name).as_str() == "char" => {
+                    return Some(ExprKind::Str(s.clone()))
                 }
+                _ => return None,
+            },
+            _ => return None,
+        };
+        match val {
+            Const::Ret(val) => self.literal_of(&val.borrow(), ty),
+            Const::Imm(int, _, base) => {
+                Some(ExprKind::Int(*int, *base, IntSuffix::of_type(&name)?))
             }
-            StmtKind::Expr(expr) => {
-                let val = self.eval(expr);
+            Const::Float(float, _) => match name.as_str() {
+                "f32" => Some(ExprKind::Float(*float, FloatSuffix::Float)),
+                "f64" => Some(ExprKind::Float(*float, FloatSuffix::Double)),
+                _ => None,
+            },
+            Const::Bool(b) if name == "bool" => Some(ExprKind::Bool(*b)),
+            _ => None,
+        }
+    }
 
-                if !val.borrow().is_none() {
-                    if let Elem::Func(func) = &mut self.ctx.file.elems[fid] {
-                        func.replace_expr_to(
-                            expr.id,
-                            Expr {
-                                id: expr.id,
-                                pos: expr.pos,
-                                kind: val.borrow().to_kind(),
-                            },
-                        );
+    /// Fold subexpressions of place expression (assignment target, operand of `&`, method
+    /// receiver) without replacing place itself
+    fn fold_place(&mut self, place: &mut Expr) {
+        match &mut place.kind {
+            ExprKind::Ident(_) => (),
+            ExprKind::Field(base, _) => self.fold_place(base),
+            ExprKind::ArrayIdx(base, idx) => {
+                self.fold_place(base);
+                self.fold_expr(idx);
+            }
+            _ => self.fold_expr(place),
+        }
+    }
+
+    /// Fold `if`/`while` condition and warn when it always has the same value. Literal `true` or
+    /// `false` (e.g. `while true`) is written on purpose and isn't reported.
+    fn fold_condition(&mut self, cond: &mut Expr) {
+        let literal = match cond.kind {
+            ExprKind::Bool(_) => true,
+            _ => false,
+        };
+        self.fold_expr(cond);
+        if let ExprKind::Bool(value) = cond.kind {
+            if !literal && !self.comptime_only {
+                warn!(format!("condition is always {}", value), cond.pos);
+            }
+        }
+    }
+
+    /// Evaluate `constexpr` statement and replace it with its effects: `return` of computed value,
+    /// declaration of variable initialized with computed value, or assignments of computed values
+    /// to variables declared outside of it. Exits if statement can't be evaluated.
+    fn fold_comptime(&mut self, stmt: &mut Stmt) {
+        let inner = match &stmt.kind {
+            StmtKind::CompTime(inner) => inner.clone(),
+            _ => unreachable!(),
+        };
+        let mut places = Places::default();
+        places.visit_stmt(&inner);
+
+        let val = match self.eval_stmt(&inner) {
+            Some(ref val) if !val.borrow().is_none() => val.clone(),
+            _ => error!(
+                "`constexpr` statement can't be evaluated at compile time",
+                stmt.pos
+            ),
+        };
+
+        let returned = match &*val.borrow() {
+            Const::Ret(ret) => Some(ret.borrow().clone()),
+            _ => None,
+        };
+        if let Some(ret) = returned {
+            for name in places.declared.iter() {
+                self.known_vars.remove(name);
+            }
+            let ret = match ret {
+                Const::Void => None,
+                ret => {
+                    let ty = self.cur_ret.clone();
+                    match self.comptime_literal(&ret, ty.as_ref(), stmt.pos) {
+                        Some(ret) => Some(ret),
+                        None => return,
                     }
                 }
+            };
+            stmt.kind = StmtKind::Return(ret);
+            return;
+        }
+
+        if let StmtKind::Var(name, reassignable, ty, init) = &inner.kind {
+            let val = self.known_vars.get(name).unwrap().borrow().clone();
+            let var_ty = self.ctx.types.get(&inner.id).cloned();
+            let pos = init.as_ref().map_or(stmt.pos, |init| init.pos);
+            let init = match self.comptime_literal(&val, var_ty.as_ref(), pos) {
+                Some(init) => init,
+                None => return,
+            };
+            stmt.kind = StmtKind::Var(*name, *reassignable, ty.clone(), Some(init));
+            // semantic and codegen look up type of declaration by statement id
+            if let Some(var_ty) = var_ty {
+                self.ctx.types.insert(stmt.id, var_ty);
             }
-            StmtKind::If(cond, then, otherwise) => {
-                let val = self.eval(cond);
-                if !val.borrow().is_none() {
-                    if let Elem::Func(func) = &mut self.ctx.file.elems[fid] {
-                        func.replace_expr_to(
-                            cond.id,
-                            Expr {
-                                id: cond.id,
-                                pos: cond.pos,
-                                kind: val.borrow().to_kind(),
-                            },
-                        );
+            return;
+        }
+
+        let mut assigned = places
+            .assigned
+            .iter()
+            .filter(|name| !places.declared.contains(name))
+            .cloned()
+            .collect::<Vec<_>>();
+        assigned.sort_by_key(|name| str(*name).to_string());
+        let mut effects = vec![];
+        for name in assigned {
+            let (val, target) = match (self.known_vars.get(&name), places.targets.get(&name)) {
+                (Some(val), Some(target)) => (val.borrow().clone(), *target),
+                _ => error!(
+                    format!(
+                        "`constexpr` statement can't assign `{}` at compile time",
+                        str(name)
+                    ),
+                    stmt.pos
+                ),
+            };
+            let ty = self.ctx.types.get(&target).cloned();
+            let to = self.comptime_literal_expr(ExprKind::Ident(name), ty.as_ref(), stmt.pos);
+            let from = match self.comptime_literal(&val, ty.as_ref(), stmt.pos) {
+                Some(from) => from,
+                None => {
+                    for name in places.declared.iter() {
+                        self.known_vars.remove(name);
                     }
+                    return;
                 }
-                self.eval_normal_stmt(then, fid);
-                if otherwise.is_some() {
-                    self.eval_normal_stmt(otherwise.as_ref().unwrap(), fid);
+            };
+            effects.push(box Stmt {
+                id: crate::gen_id(),
+                pos: stmt.pos,
+                kind: StmtKind::Expr(self.comptime_literal_expr(
+                    ExprKind::Assign(to, from),
+                    ty.as_ref(),
+                    stmt.pos,
+                )),
+            });
+        }
+        for name in places.declared.iter() {
+            self.known_vars.remove(name);
+        }
+        stmt.kind = StmtKind::Block(effects);
+    }
+
+    /// Literal expression with value `val` computed by `constexpr` statement, typed as `ty`.
+    /// `None` if value has no literal, `constexpr` is then left to be computed at runtime.
+    fn comptime_literal(
+        &mut self,
+        val: &Const,
+        ty: Option<&Type>,
+        pos: Position,
+    ) -> Option<Box<Expr>> {
+        if val.is_none() {
+            error!("`constexpr` statement can't be evaluated at compile time", pos);
+        }
+        let kind = match ty.and_then(|ty| self.literal_of(val, ty)) {
+            Some(kind) => kind,
+            None => val.to_kind(pos)?,
+        };
+        Some(self.comptime_literal_expr(kind, ty, pos))
+    }
+
+    /// New expression of type `ty`, type is recorded so codegen can look it up
+    fn comptime_literal_expr(
+        &mut self,
+        kind: ExprKind,
+        ty: Option<&Type>,
+        pos: Position,
+    ) -> Box<Expr> {
+        let id = crate::gen_id();
+        if let Some(ty) = ty {
+            self.ctx.types.insert(id, ty.clone());
+        }
+        box Expr { id, pos, kind }
+    }
+
+    /// Fail compilation unless condition of `static_assert` call holds
+    fn check_static_assert(&mut self, call: &mut Expr) {
+        let args = match &mut call.kind {
+            ExprKind::Call(_, _, args) => args,
+            _ => unreachable!(),
+        };
+        self.fold_expr(&mut args[0]);
+        let holds = match *self.eval(&args[0]).borrow() {
+            Const::Bool(holds) => holds,
+            _ => error!("static_assert condition is not constant", args[0].pos),
+        };
+        if !holds {
+            let msg = match &args[1].kind {
+                ExprKind::Str(msg) => msg.clone(),
+                _ => unreachable!(),
+            };
+            error!(format!("static assertion failed: {}", msg), call.pos);
+        }
+    }
+
+    /// Fold statements of block and value of block expression `tail`, variables declared in block
+    /// go out of scope at its end and values of variables they shadowed are known again
+    fn fold_block(&mut self, stmts: &mut [Box<Stmt>], tail: Option<&mut Box<Expr>>) {
+        let mut shadowed = vec![];
+        for stmt in stmts.iter_mut() {
+            if let StmtKind::Var(name, ..) = &stmt.kind {
+                shadowed.push((*name, self.known_vars.get(name).cloned()));
+            }
+            self.fold_stmt(stmt);
+        }
+        if let Some(tail) = tail {
+            self.fold_expr(tail);
+        }
+        for (name, val) in shadowed.into_iter().rev() {
+            match val {
+                Some(val) => self.known_vars.insert(name, val),
+                None => self.known_vars.remove(&name),
+            };
+        }
+    }
+
+    /// Forget values of variables assigned somewhere in `stmt`
+    fn forget_assigned(&mut self, stmt: &Stmt) {
+        let mut assigned = Places::default();
+        assigned.visit_stmt(stmt);
+        for name in assigned.assigned.iter() {
+            self.known_vars.remove(name);
+        }
+    }
+
+    /// Normal functions that only compute their result from arguments and calls of other such
+    /// functions, so calling them at compile time gives the same value. Unless `try_eval_normal`
+    /// is set functions with loops or recursion are left out, evaluation of them may not end.
+    fn foldable_functions(&self) -> HashSet<Name> {
+        let mut effects: HashMap<Name, Effects> = HashMap::new();
+        for (name, funcs) in self.functions.iter() {
+            let mut found = Effects::new(&self.ctx.types);
+            for func in funcs.iter() {
+                found.locals = func.params.iter().map(|(name, _)| *name).collect();
+                found.visit_function(func);
+            }
+            effects.insert(*name, found);
+        }
+
+        let calls_only = |callees: &HashSet<Name>, pure: &HashSet<Name>| {
+            callees
+                .iter()
+                .all(|callee| pure.contains(callee) || self.const_functions.contains_key(callee))
+        };
+        let mut pure = effects
+            .iter()
+            .filter(|(_, found)| !found.impure && (self.try_eval_normal || !found.loops))
+            .map(|(name, _)| *name)
+            .collect::<HashSet<_>>();
+        // function calling impure one is impure too
+        loop {
+            let impure = pure
+                .iter()
+                .filter(|name| !calls_only(&effects[*name].calls, &pure))
+                .cloned()
+                .collect::<Vec<_>>();
+            if impure.is_empty() {
+                break;
+            }
+            for name in impure.iter() {
+                pure.remove(name);
+            }
+        }
+        if self.try_eval_normal {
+            return pure;
+        }
+
+        // recursion, direct or through other functions
+        fn reaches(
+            effects: &HashMap<Name, Effects>,
+            from: Name,
+            to: Name,
+            seen: &mut HashSet<Name>,
+        ) -> bool {
+            effects.get(&from).map_or(false, |found| {
+                found.calls.iter().any(|callee| {
+                    *callee == to || (seen.insert(*callee) && reaches(effects, *callee, to, seen))
+                })
+            })
+        }
+        pure.iter()
+            .filter(|name| !reaches(&effects, **name, **name, &mut HashSet::new()))
+            .cloned()
+            .collect()
+    }
+
+    /// Declare functions, constexprs and enums of `elems` before uses
+    fn declare(&mut self, elems: &[Elem]) {
+        for elem in elems.iter() {
+            match elem {
+                Elem::Func(func) => {
+                    let funcs = if func.constant {
+                        &mut self.const_functions
+                    } else if !func.internal && !func.external {
+                        &mut self.functions
+                    } else {
+                        continue;
+                    };
+                    funcs.entry(func.name).or_insert_with(Vec::new).push(func.clone());
                 }
+                Elem::ConstExpr { name, expr, .. } => {
+                    self.constexprs.insert(*name, *expr.clone());
+                }
+                Elem::Enum(e) => {
+                    self.enums.insert(e.name, e.clone());
+                }
+                _ => (),
             }
-            StmtKind::While(cond, body) => {
-                let val = self.eval(cond);
-                if !val.borrow().is_none() {
-                    if let Elem::Func(func) = &mut self.ctx.file.elems[fid] {
-                        func.replace_expr_to(
-                            cond.id,
-                            Expr {
-                                id: cond.id,
-                                pos: cond.pos,
-                                kind: val.borrow().to_kind(),
-                            },
-                        );
-                    }
+        }
+    }
+
+    /// Branch taken by `constexpr if` statement `stmt` when its condition only refers to
+    /// constexprs, so it can be type checked and evaluated before semantic pass
+    fn taken_branch(&mut self, stmt: &Stmt) -> Option<StmtKind> {
+        let (cond, then, otherwise) = match &stmt.kind {
+            StmtKind::CompTime(inner) => match &inner.kind {
+                StmtKind::If(cond, then, otherwise) => (cond, then, otherwise),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let mut idents = Idents::default();
+        idents.visit_expr(cond);
+        if !idents.0.iter().all(|name| self.constexprs.contains_key(name)) {
+            return None;
+        }
+        self.ctx.type_check_expr(cond).ok()?;
+        let taken = match *self.eval(cond).borrow() {
+            Const::Bool(taken) => taken,
+            _ => return None,
+        };
+        Some(match otherwise {
+            _ if taken => then.kind.clone(),
+            // `else if` is pruned as well, parser wraps it in block
+            Some(otherwise) => match &otherwise.kind {
+                StmtKind::Block(stmts) if stmts.len() == 1 && stmts[0].kind.is_if() => {
+                    StmtKind::CompTime(stmts[0].clone())
                 }
-                self.eval_normal_stmt(body, fid);
+                kind => kind.clone(),
+            },
+            None => StmtKind::Block(vec![]),
+        })
+    }
+
+    /// Replace `constexpr if` statements whose condition is known before type checking with
+    /// their taken branch. Untaken branch is never type checked, so it may use symbols that
+    /// exist only on other targets.
+    pub fn prune_branches(&mut self) {
+        let mut elems = self.ctx.file.elems.clone();
+        self.declare(&elems);
+        let mut pruning = BranchPruning(self);
+        for elem in elems.iter_mut() {
+            pruning.fold_elem(elem);
+        }
+        self.ctx.file.elems = elems;
+    }
+
+    pub fn run(&mut self) {
+        let mut elems = std::mem::replace(&mut self.ctx.file.elems, vec![]);
+        self.declare(&elems);
+
+        if !self.comptime_only {
+            self.foldable = self.foldable_functions();
+        }
+
+        for elem in elems.iter_mut() {
+            if let Elem::StaticAssert(call) = elem {
+                self.check_static_assert(call);
             }
-            StmtKind::Return(expr) => {
-                if expr.is_some() {
-                    let expr = expr.as_ref().unwrap();
-                    let val = self.eval(expr);
-                    if !val.borrow().is_none() {
-                        if let Elem::Func(func) = &mut self.ctx.file.elems[fid] {
-                            func.replace_expr_to(
-                                expr.id,
-                                Expr {
-                                    id: expr.id,
-                                    pos: expr.pos,
-                                    kind: val.borrow().to_kind(),
-                                },
-                            );
-                        }
-                    }
+        }
+        elems.retain(|elem| match elem {
+            Elem::StaticAssert(_) => false,
+            _ => true,
+        });
+        for elem in elems.iter_mut() {
+            self.fold_elem(elem);
+        }
+        self.ctx.file.elems = elems;
+    }
+}
+
+/// Whether `file` has `constexpr` statement or expression or `static_assert`
+pub fn has_comptime(file: &File) -> bool {
+    struct Finder(bool);
+    impl Visitor for Finder {
+        fn visit_stmt(&mut self, stmt: &Stmt) {
+            match stmt.kind {
+                StmtKind::CompTime(_) => self.0 = true,
+                _ => walk_stmt(self, stmt),
+            }
+        }
+
+        fn visit_expr(&mut self, expr: &Expr) {
+            match expr.kind {
+                ExprKind::CompTime(_) => self.0 = true,
+                _ if is_static_assert(expr) => self.0 = true,
+                _ => walk_expr(self, expr),
+            }
+        }
+    }
+
+    let mut finder = Finder(false);
+    for elem in file.elems.iter() {
+        finder.visit_elem(elem);
+    }
+    finder.0
+}
+
+/// Identifiers used in visited expression
+#[derive(Default)]
+struct Idents(Vec<Name>);
+
+impl Visitor for Idents {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let ExprKind::Ident(name) = &expr.kind {
+            self.0.push(*name);
+        }
+        walk_expr(self, expr)
+    }
+}
+
+struct BranchPruning<'a, 'b>(&'b mut ConstEval<'a>);
+
+impl Folder for BranchPruning<'_, '_> {
+    fn fold_stmt(&mut self, stmt: &mut Stmt) {
+        match self.0.taken_branch(stmt) {
+            Some(taken) => {
+                stmt.kind = taken;
+                self.fold_stmt(stmt);
+            }
+            None => walk_stmt_mut(self, stmt),
+        }
+    }
+}
+
+/// Root variable of place expression (`x` in `x.a[i].b`)
+fn place_root(place: &Expr) -> Option<Name> {
+    match &place.kind {
+        ExprKind::Ident(name) => Some(*name),
+        ExprKind::Field(base, _) | ExprKind::ArrayIdx(base, _) => place_root(base),
+        _ => None,
+    }
+}
+
+/// Collects variables that are assigned and variables whose address escapes (`&x`, method
+/// call on `x`, slice of `x` or array `x` decayed to pointer or slice)
+#[derive(Default)]
+struct Places<'a> {
+    assigned: HashSet<Name>,
+    escaping: HashSet<Name>,
+    /// Variables declared inside visited code
+    declared: HashSet<Name>,
+    /// Expression ids of variables assigned as a whole (`x = ...`)
+    targets: HashMap<Name, NodeId>,
+    /// Types of expressions, arrays may decay only where they are known
+    types: Option<&'a HashMap<NodeId, Type>>,
+}
+
+impl Places<'_> {
+    /// Array value `expr` may decay to pointer or slice sharing its elements, so whatever
+    /// array it's part of escapes. Copying array by value is treated same way.
+    fn may_decay(&mut self, expr: &Expr) {
+        let ty = self.types.and_then(|types| types.get(&expr.id));
+        if let Some(Type::Array(array)) = ty {
+            if array.len.is_some() {
+                self.escaping.extend(place_root(expr));
+            }
+        }
+    }
+}
+
+impl Visitor for Places<'_> {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Var(name, _, _, init) => {
+                self.declared.insert(*name);
+                if let Some(init) = init {
+                    self.may_decay(init);
                 }
             }
-            StmtKind::Var(var, _, _, val) => {
-                if val.is_some() {
-                    let expr = val.as_ref().unwrap();
-                    let val = self.eval(expr);
+            StmtKind::Return(Some(value)) => self.may_decay(value),
+            _ => (),
+        }
+        walk_stmt(self, stmt)
+    }
 
-                    if !val.borrow().is_none() {
-                        if let Elem::Func(func) = &mut self.ctx.file.elems[fid] {
-                            if !func.ret.is_void() {
-                                func.replace_expr_to(
-                                    expr.id,
-                                    Expr {
-                                        id: expr.id,
-                                        pos: expr.pos,
-                                        kind: val.borrow().to_kind(),
-                                    },
-                                );
-                            }
-                        }
-                        self.known_vars.insert(*var, val);
-                    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Assign(to, from) => {
+                self.assigned.extend(place_root(to));
+                if let ExprKind::Ident(name) = &to.kind {
+                    self.targets.insert(*name, to.id);
+                }
+                self.may_decay(from);
+            }
+            ExprKind::AddressOf(place) | ExprKind::Slice(place, ..) => {
+                self.escaping.extend(place_root(place))
+            }
+            ExprKind::Call(_, this, args) => {
+                if let Some(this) = this {
+                    self.escaping.extend(place_root(this));
+                }
+                for arg in args.iter() {
+                    self.may_decay(arg);
                 }
             }
-            StmtKind::Loop(stmt) => self.eval_normal_stmt(stmt, fid),
-            StmtKind::Continue => (),
-            StmtKind::Break => (),
+            ExprKind::Conv(value, _) => self.may_decay(value),
+            _ => (),
+        }
+        walk_expr(self, expr)
+    }
+}
+
+/// What body of normal function does besides computing its result
+struct Effects<'a> {
+    types: &'a HashMap<NodeId, Type>,
+    /// parameters and locals of function, assigning them isn't visible to caller
+    locals: HashSet<Name>,
+    /// functions called without receiver
+    calls: HashSet<Name>,
+    /// reads or writes memory through pointers, assigns globals, allocates or calls methods
+    impure: bool,
+    loops: bool,
+}
+
+impl<'a> Effects<'a> {
+    fn new(types: &'a HashMap<NodeId, Type>) -> Effects<'a> {
+        Effects {
+            types,
+            locals: HashSet::new(),
+            calls: HashSet::new(),
+            impure: false,
+            loops: false,
+        }
+    }
+
+    /// Whether `place` is part of local variable and not memory behind pointer
+    fn is_local(&self, place: &Expr) -> bool {
+        match &place.kind {
+            ExprKind::Ident(name) => self.locals.contains(name),
+            ExprKind::Field(base, _) | ExprKind::ArrayIdx(base, _) => {
+                let through_ptr = self.types.get(&base.id).map_or(true, |ty| ty.is_ptr());
+                !through_ptr && self.is_local(base)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Visitor for Effects<'_> {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Var(name, ..) => {
+                self.locals.insert(*name);
+            }
+            StmtKind::Loop(_) | StmtKind::While(..) | StmtKind::CFor(..) => self.loops = true,
+            StmtKind::Delete(_) | StmtKind::Static(_) => self.impure = true,
+            _ => (),
+        }
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Call(path, None, _) => {
+                self.calls.insert(path.name());
+            }
+            ExprKind::Assign(place, _) if !self.is_local(place) => self.impure = true,
+            ExprKind::Loop(_) => self.loops = true,
+            ExprKind::Call(_, Some(_), _)
+            | ExprKind::Deref(_)
+            | ExprKind::AddressOf(_)
+            | ExprKind::New(..)
+            | ExprKind::MacroCall(..)
+            | ExprKind::VaArg(_) => self.impure = true,
             _ => (),
         }
+        walk_expr(self, expr)
     }
+}
 
-    fn opt_func(&mut self, func: &Function, id: usize) {
-        self.eval_normal_stmt(func.body.as_ref().unwrap(), id);
+impl Folder for ConstEval<'_> {
+    fn fold_elem(&mut self, elem: &mut Elem) {
+        match elem {
+            // constexpr is folded where it's used
+            Elem::ConstExpr { .. } => (),
+            Elem::Func(func) if func.external || func.internal => (),
+            _ => walk_elem_mut(self, elem),
+        }
     }
 
-    pub fn run(&mut self) {
-        // Declare functions before uses
-        for elem in self.ctx.file.elems.clone().iter() {
-            if let Elem::Func(func) = elem {
-                if func.constant {
-                    if self.const_functions.contains_key(&func.name) {
-                        let funcs = self.const_functions.get_mut(&func.name).unwrap();
-                        funcs.push(func.clone());
-                    } else {
-                        self.const_functions.insert(func.name, vec![func.clone()]);
+    fn fold_function(&mut self, func: &mut Function) {
+        self.known_vars.clear();
+        self.cur_ret = Some(*func.ret.clone());
+        let mut places = Places {
+            types: Some(&self.ctx.types),
+            ..Places::default()
+        };
+        walk_function(&mut places, func);
+        self.escaping = places.escaping;
+        walk_function_mut(self, func);
+    }
+
+    fn fold_stmt(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            StmtKind::Block(stmts) => self.fold_block(stmts, None),
+            StmtKind::Var(name, _, _, init) => {
+                let val = init.as_mut().map(|init| {
+                    self.fold_expr(init);
+                    self.eval(init)
+                });
+                let val = val.map(|val| widen_to(val, self.ctx.types.get(&stmt.id)));
+                match val {
+                    Some(ref val) if !val.borrow().is_none() && !self.escaping.contains(name) => {
+                        self.known_vars.insert(*name, val.clone());
                     }
-                } else if !func.internal && !func.external {
-                    if self.functions.contains_key(&func.name) {
-                        let funcs = self.functions.get_mut(&func.name).unwrap();
-                        funcs.push(func.clone());
-                    } else {
-                        self.functions.insert(func.name, vec![func.clone()]);
+                    _ => {
+                        self.known_vars.remove(name);
                     }
                 }
             }
-        }
-        for (i, elem) in self.ctx.file.elems.clone().iter().enumerate() {
-            match elem {
-                Elem::Func(func) => {
-                    if func.external || func.internal {
-                        continue;
+            StmtKind::If(cond, then, otherwise) => {
+                self.fold_condition(cond);
+                let known = self.known_vars.clone();
+                self.fold_stmt(then);
+                self.known_vars = known.clone();
+                if let Some(otherwise) = otherwise {
+                    self.fold_stmt(otherwise);
+                    self.known_vars = known;
+                }
+                self.forget_assigned(stmt);
+            }
+            StmtKind::CFor(init, ..) => {
+                self.fold_stmt(init);
+                // loop condition is checked after values assigned in loop changed
+                self.forget_assigned(stmt);
+                if let StmtKind::CFor(_, cond, step, body) = &mut stmt.kind {
+                    self.fold_expr(cond);
+                    self.fold_stmt(body);
+                    self.fold_expr(step);
+                }
+                self.forget_assigned(stmt);
+            }
+            StmtKind::While(..) => {
+                self.forget_assigned(stmt);
+                if let StmtKind::While(cond, body) = &mut stmt.kind {
+                    self.fold_condition(cond);
+                    self.fold_stmt(body);
+                }
+                self.forget_assigned(stmt);
+            }
+            StmtKind::Loop(_) => {
+                self.forget_assigned(stmt);
+                walk_stmt_mut(self, stmt);
+                self.forget_assigned(stmt);
+            }
+            StmtKind::CompTime(_) => self.fold_comptime(stmt),
+            StmtKind::Expr(expr) if is_static_assert(expr) => {
+                self.check_static_assert(expr);
+                stmt.kind = StmtKind::Block(vec![]);
+            }
+            StmtKind::Static(var) => {
+                // initializer runs on first call only, later calls see whatever was stored
+                if let StmtKind::Var(name, _, _, init) = &mut var.kind {
+                    if let Some(init) = init {
+                        self.fold_expr(init);
                     }
-                    /*if func.constant
-                    {
-                        if self.const_functions.contains_key(&func.name)
-                        {
-                            let funcs = self.const_functions.get_mut(&func.name).unwrap();
-                            funcs.push(func.clone());
-                        }
-                        else
-                        {
-                            self.const_functions.insert(func.name, vec![func.clone()]);
-                        }
-                    }*/
-                    self.id = i;
+                    self.known_vars.remove(name);
+                }
+            }
+            StmtKind::Defer(_) => {
+                // deferred statement runs at block exit, values known here may be stale by then
+                let known = std::mem::replace(&mut self.known_vars, HashMap::new());
+                walk_stmt_mut(self, stmt);
+                self.known_vars = known;
+                self.forget_assigned(stmt);
+            }
+            _ => walk_stmt_mut(self, stmt),
+        }
+    }
 
-                    self.opt_func(func, i);
+    fn fold_expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Assign(to, from) => {
+                self.fold_expr(from);
+                self.fold_place(to);
+                self.try_assign(to, from);
+                return;
+            }
+            ExprKind::AddressOf(place) => return self.fold_place(place),
+            ExprKind::Ternary(cond, then, otherwise) => {
+                self.fold_condition(cond);
+                let arm = match cond.kind {
+                    ExprKind::Bool(true) => then.clone(),
+                    ExprKind::Bool(false) => otherwise.clone(),
+                    _ => {
+                        // like in `if`, only one of arms runs
+                        let mut assigned = Places::default();
+                        assigned.visit_expr(then);
+                        assigned.visit_expr(otherwise);
+                        let known = self.known_vars.clone();
+                        self.fold_expr(then);
+                        self.known_vars = known.clone();
+                        self.fold_expr(otherwise);
+                        self.known_vars = known;
+                        for name in assigned.assigned.iter() {
+                            self.known_vars.remove(name);
+                        }
+                        return;
+                    }
+                };
+                // arm that always runs replaces conditional, converted to its type if needed
+                let ty = self.ctx.types[&expr.id].clone();
+                expr.kind = if self.ctx.types.get(&arm.id) == Some(&ty) {
+                    arm.kind
+                } else {
+                    ExprKind::Conv(arm, box ty)
+                };
+                return self.fold_expr(expr);
+            }
+            ExprKind::Loop(body) => {
+                self.forget_assigned(body);
+                self.fold_stmt(body);
+                self.forget_assigned(body);
+                return;
+            }
+            ExprKind::Block(stmts, tail) => return self.fold_block(stmts, Some(tail)),
+            ExprKind::CompTime(inner) => {
+                // replaced by its value, so inside of loop it isn't computed on every iteration.
+                // Variables assigned in enclosing loops are unknown here and can't be used.
+                self.fold_expr(inner);
+                let val = self.eval(inner);
+                if val.borrow().is_none() {
+                    error!(
+                        "`constexpr` expression can't be evaluated at compile time",
+                        expr.pos
+                    );
                 }
-                Elem::ConstExpr { name, expr, .. } => {
-                    self.constexprs.insert(*name, *expr.clone());
+                let ty = self.ctx.types.get(&expr.id).cloned();
+                expr.kind = match self.comptime_literal(&val.borrow(), ty.as_ref(), expr.pos) {
+                    Some(literal) => literal.kind,
+                    // computed at runtime, codegen doesn't know `constexpr` expressions
+                    None => inner.kind.clone(),
+                };
+                return;
+            }
+            ExprKind::Call(_, Some(this), args) => {
+                self.fold_place(this);
+                for arg in args.iter_mut() {
+                    self.fold_expr(arg);
                 }
-                _ => (),
+                return;
             }
+            _ => (),
+        }
+
+        if self.comptime_only {
+            return walk_expr_mut(self, expr);
+        }
+        let val = self.eval(expr);
+        let literal = match self.ctx.types.get(&expr.id) {
+            Some(ty) if !val.borrow().is_none() => self.literal_of(&val.borrow(), ty),
+            _ => None,
+        };
+        match literal {
+            Some(literal) => expr.kind = literal,
+            None => walk_expr_mut(self, expr),
         }
     }
 }
@@ -5,9 +5,15 @@
     },
     Context, *,
 };
-use crate::ast::*;
+use crate::{
+    ast::{visit::*, *},
+    monomorph::{self, TypeArgs},
+    optimize::const_eval::{
+        eval_env_builtin, included_file, ConstEval, ENV_BUILTINS, FILE_BUILTINS, STATIC_ASSERT,
+    },
+};
 use colored::Colorize;
-use std::cell::RefCell;
+use std::{cell::RefCell, collections::HashSet};
 
 pub struct SemCheck<'a> {
     ctx: &'a mut Context,
@@ -17,13 +23,91 @@ pub struct SemCheck<'a> {
     globals: HashMap<Name, Global>,
     constants: HashMap<Name, Const>,
     vars: Vec<HashMap<Name, Type>>,
+    /// Locals declared with `let` or `const`. Shadowing is forbidden inside function, so name
+    /// identifies binding that is currently in scope.
+    immutable: HashSet<Name>,
+    /// Loops enclosing checked statement, innermost last
+    loops: Vec<LoopTarget>,
+    /// Globals whose initializers are being checked
+    initializing: HashSet<Name>,
     constexprs: HashMap<Name, Box<Expr>>,
     ret: Type,
+    /// Whether function being checked is variadic
+    variadic: bool,
     types: HashMap<NodeId, Type>,
     aliases: HashMap<Name, Type>,
+    enums: HashMap<Name, Enum>,
     imported: HashMap<Name, Elem>,
     imported_funs: HashMap<Name, Vec<Function>>,
     __internal_funs: HashMap<Name, Function>,
+    /// Generic functions, they are checked only through their specializations
+    generics: HashMap<Name, Function>,
+    /// Generic functions and type arguments they are already specialized for
+    instances: HashSet<(Name, Vec<Type>)>,
+    /// Specializations whose bodies aren't checked yet
+    pending: Vec<Function>,
+}
+
+/// Loop that `break` may leave. Only `loop` in expression position accepts value, `ty` is type
+/// of first value passed to it.
+struct LoopTarget {
+    is_expr: bool,
+    ty: Option<Type>,
+}
+
+impl LoopTarget {
+    fn stmt() -> LoopTarget {
+        LoopTarget {
+            is_expr: false,
+            ty: None,
+        }
+    }
+}
+
+/// Sets suffix of literals written without one to type they were checked with, so later passes
+/// and backends see `--int-type` or type of parameter literal is passed to in literal itself
+struct UnsuffixedInts<'a> {
+    positions: &'a HashSet<Position>,
+    types: &'a HashMap<NodeId, Type>,
+}
+
+impl Folder for UnsuffixedInts<'_> {
+    fn fold_expr(&mut self, expr: &mut Expr) {
+        if let ExprKind::Int(_, _, suffix) = &mut expr.kind {
+            if self.positions.contains(&expr.pos) {
+                if let Some(Type::Basic(basic)) = self.types.get(&expr.id) {
+                    *suffix = IntSuffix::of_type(&str(basic.name)).unwrap_or(*suffix);
+                }
+            }
+        }
+        walk_expr_mut(self, expr)
+    }
+}
+
+/// Reductions over lanes of vector: `vec_any(v)`/`vec_all(v)` check whether any/all lanes are
+/// non-zero, `vec_sum(v)` adds lanes together
+pub const VEC_BUILTINS: [&str; 3] = ["vec_any", "vec_all", "vec_sum"];
+
+/// `va_start()` begins reading variadic arguments of enclosing function with `va_arg(T)`,
+/// `va_end()` finishes it
+pub const VA_BUILTINS: [&str; 2] = ["va_start", "va_end"];
+
+/// Type of lane-wise comparison of two `vec` vectors: vector of signed integers of the same
+/// width as lanes of `vec`, with lanes set to -1 (all bits set) where comparison holds and 0
+/// otherwise
+pub fn vec_mask_type(vec: &TypeVector, id: NodeId, pos: Position) -> Type {
+    let lane = match str(vec.subtype.to_basic().unwrap().name).as_str() {
+        "i16" | "u16" => "i16",
+        "i32" | "u32" | "f32" => "i32",
+        "i64" | "u64" | "f64" | "isize" | "usize" => "i64",
+        _ => "i8",
+    };
+    Type::Vector(TypeVector {
+        id,
+        pos,
+        subtype: box Type::create_basic(id, pos, intern(lane)),
+        size: vec.size,
+    })
 }
 
 pub fn ty_is_any_int(ty: &Type) -> bool {
@@ -31,8 +115,8 @@ pub fn ty_is_any_int(ty: &Type) -> bool {
         Type::Basic(basic) => {
             let s: &str = &str(basic.name).to_string();
             match s {
-                "uchar" | "char" | "u8" | "u16" | "u32" | "u64" | "i64" | "i32" | "i16" | "i8"
-                | "isize" | "usize" => true,
+                "uchar" | "char" | "u8" | "u16" | "u32" | "u64" | "u128" | "i128" | "i64" | "i32"
+                | "i16" | "i8" | "isize" | "usize" => true,
                 _ => false,
             }
         }
@@ -41,6 +125,47 @@ pub fn ty_is_any_int(ty: &Type) -> bool {
     }
 }
 
+/// `i128` or `u128`, backends can't represent their values with `i64` constants
+pub fn ty_is_int128(ty: &Type) -> bool {
+    match ty {
+        Type::Basic(basic) => match str(basic.name).as_str() {
+            "i128" | "u128" => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Argument types accepted by parameter types, with arrays decaying to pointers
+pub fn args_match(args: &[Type], params: &[Type]) -> bool {
+    args.len() == params.len()
+        && args
+            .iter()
+            .zip(params.iter())
+            .all(|(arg, param)| arg == param || array_decays_to(arg, param))
+}
+
+/// Fixed-size array `T[N]` decays to `*T` in conversions and pointer arguments, and to slice
+/// `T[]` over all its elements
+pub fn array_decays_to(from: &Type, to: &Type) -> bool {
+    match (from, to) {
+        (Type::Array(array), Type::Ptr(ptr)) => {
+            array.len.is_some() && array.subtype == ptr.subtype
+        }
+        (Type::Array(array), Type::Array(slice)) => {
+            array.len.is_some() && slice.len.is_none() && array.subtype == slice.subtype
+        }
+        _ => false,
+    }
+}
+
+pub fn ty_is_bool(ty: &Type) -> bool {
+    match ty {
+        Type::Basic(basic) => str(basic.name).as_str() == "bool",
+        _ => false,
+    }
+}
+
 pub fn ty_is_any_float(ty: &Type) -> bool {
     match ty {
         Type::Basic(basic) => {
@@ -55,6 +180,209 @@ pub fn ty_is_any_float(ty: &Type) -> bool {
     }
 }
 
+/// Position of `return`, `break` or `continue` that jumps out of `stmt`, `loops` is number of
+/// loops inside `stmt` enclosing it
+fn escaping_jump(stmt: &Stmt, loops: usize) -> Option<Position> {
+    match &stmt.kind {
+        StmtKind::Return(_) => Some(stmt.pos),
+        StmtKind::Break(depth, _) if *depth > loops => Some(stmt.pos),
+        StmtKind::Continue(depth) if *depth > loops => Some(stmt.pos),
+        StmtKind::Block(stmts) => stmts.iter().find_map(|stmt| escaping_jump(stmt, loops)),
+        StmtKind::If(_, then, otherwise) => escaping_jump(then, loops).or_else(|| {
+            otherwise
+                .as_ref()
+                .and_then(|otherwise| escaping_jump(otherwise, loops))
+        }),
+        StmtKind::While(_, body) | StmtKind::Loop(body) | StmtKind::CFor(_, _, _, body) => {
+            escaping_jump(body, loops + 1)
+        }
+        StmtKind::CompTime(stmt) | StmtKind::Defer(stmt) => escaping_jump(stmt, loops),
+        _ => None,
+    }
+}
+
+/// Validate attributes that have special meaning for functions
+fn check_fn_attributes(func: &Function) {
+    for attr in func.attributes.iter() {
+        let name: &str = &str(attr.name);
+        match name {
+            "no_mangle" => {
+                if !attr.args.is_empty() {
+                    error!("@no_mangle doesn't take any arguments", attr.pos);
+                }
+            }
+            // libgccjit has no per-function optimization level, only `-O` of whole program.
+            // Function compiled in its own context would be separate translation unit without
+            // access to internal functions and globals, so attribute is rejected, not ignored
+            "optimize" => error!(
+                "@optimize isn't supported, optimization level is set for whole program by -O",
+                attr.pos
+            ),
+            // `--sanitize` instruments whole context and libgccjit has no attribute to exclude
+            // single function, so attribute is rejected, not ignored
+            "no_sanitize" => error!(
+                "@no_sanitize isn't supported, --sanitize instruments every function",
+                attr.pos
+            ),
+            // libgccjit sets link section only of globals (`gcc_jit_lvalue_set_link_section`),
+            // functions always go to `.text`
+            "section" => error!(
+                "@section isn't supported on functions, they are always placed in .text",
+                attr.pos
+            ),
+            "symver" => {
+                let version = match &attr.args[..] {
+                    [AttributeArg::Str(version)] => version,
+                    _ => error!(
+                        "@symver expects single string argument like \"name@VERSION\"",
+                        attr.pos
+                    ),
+                };
+                if func.external || func.body.is_none() {
+                    error!("@symver can be used only on functions with body", attr.pos);
+                }
+                if !func.public {
+                    error!("@symver requires public function", attr.pos);
+                }
+                if !is_valid_symver(version) {
+                    error!(
+                        format!(
+                            "invalid symbol version `{}`, expected `name@VERSION` or `name@@VERSION`",
+                            version
+                        ),
+                        attr.pos
+                    );
+                }
+            }
+            "noreturn" | "pure" | "const" | "inline" | "cold" | "export"
+                if !attr.args.is_empty() =>
+            {
+                error!(
+                    format!("@{} doesn't take any arguments", name),
+                    attr.pos
+                );
+            }
+            "export" if !func.public || func.external || func.body.is_none() => {
+                error!("@export can be used only on public functions with body", attr.pos);
+            }
+            "cold" if func.inline || func.has_attribute("inline") => {
+                error!("@cold function can't be inlined", attr.pos);
+            }
+            "pure" | "const" if func.ret.is_void() => {
+                error!(
+                    format!("@{} function must return value", name),
+                    attr.pos
+                );
+            }
+            "test" | "before_each" | "after_each" => {
+                if !attr.args.is_empty() {
+                    error!(
+                        format!("@{} doesn't take any arguments", name),
+                        attr.pos
+                    );
+                }
+                if func.body.is_none() || !func.params.is_empty() || func.this.is_some() {
+                    error!(
+                        format!("@{} function must have body and no parameters", name),
+                        attr.pos
+                    );
+                }
+            }
+            "deprecated" => match &attr.args[..] {
+                [] | [AttributeArg::Str(_)] => (),
+                _ => error!(
+                    "@deprecated expects optional message string",
+                    attr.pos
+                ),
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Check that backing type of enum `e` (already resolved to `repr`) is integer, that it can hold
+/// all discriminants and that variant names are unique
+fn check_enum(e: &Enum, repr: &Type) {
+    let range = match repr {
+        Type::Basic(basic) if ty_is_any_int(repr) => match str(basic.name).as_str() {
+            "i8" => (i8::MIN as i128, i8::MAX as i128),
+            "u8" | "char" | "uchar" => (0, u8::MAX as i128),
+            "i16" => (i16::MIN as i128, i16::MAX as i128),
+            "u16" => (0, u16::MAX as i128),
+            "i32" => (i32::MIN as i128, i32::MAX as i128),
+            "u32" => (0, u32::MAX as i128),
+            "u64" | "usize" | "u128" => (0, u64::MAX as i128),
+            _ => (i64::MIN as i128, i64::MAX as i128),
+        },
+        _ => error!(
+            format!("enum `{}` must be backed by integer type, not {}", e.name, repr),
+            e.repr.pos()
+        ),
+    };
+
+    let mut seen: HashMap<Name, Position> = HashMap::new();
+    for variant in e.variants.iter() {
+        if let Some(prev) = seen.insert(variant.name, variant.pos) {
+            error!(
+                false,
+                format!("variant `{}` is already defined in enum `{}`", variant.name, e.name),
+                variant.pos
+            );
+            note!("previous definition is here", prev);
+            std::process::exit(-1);
+        }
+        let value = variant.value as i128;
+        if value < range.0 || value > range.1 {
+            error!(
+                format!(
+                    "discriminant {} of `{}::{}` doesn't fit in {}",
+                    variant.value, e.name, variant.name, repr
+                ),
+                variant.pos
+            );
+        }
+    }
+}
+
+/// Validate `@section("name")` of global, `external` ones are defined elsewhere. Functions can't
+/// have `@section`, see `check_fn_attributes`
+fn check_section(attr: &Attribute, external: bool) {
+    match attr.str_arg() {
+        Some(name) if !name.is_empty() => (),
+        _ => error!("@section expects section name like \".boot\"", attr.pos),
+    }
+    if external {
+        error!("@section can't be used on external declarations", attr.pos);
+    }
+}
+
+/// Check `name@VERSION`/`name@@VERSION` format used by GNU symbol versioning
+fn is_valid_symver(version: &str) -> bool {
+    let (name, node) = match version.find('@') {
+        Some(at) => {
+            let node = &version[at + 1..];
+            let node = if node.starts_with('@') {
+                &node[1..]
+            } else {
+                node
+            };
+            (&version[..at], node)
+        }
+        None => return false,
+    };
+    let is_ident = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with(|c: char| c.is_ascii_digit())
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+
+    is_ident(name)
+        && !node.is_empty()
+        && node
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
 #[derive(Debug)]
 pub enum Error {
     ConstantExists(String),
@@ -62,6 +390,8 @@ pub enum Error {
     StructureExists(String),
     GlobalExists(String),
     VariableAlreadyDefined(String),
+    /// Any other error reported while checking expression, e.g mismatched types
+    Other(String),
 }
 
 impl Error {
@@ -74,6 +404,7 @@ pub fn message(&self) -> String {
             Error::StructureExists(s) => format!("Structure {} exists", s),
             Error::GlobalExists(s) => format!("Global {} exists", s),
             Error::VariableAlreadyDefined(s) => format!("Variable {} exists", s),
+            Error::Other(msg) => msg.clone(),
         }
     }
 }
@@ -89,7 +420,13 @@ pub struct ErrorWPos {
 
 impl fmt::Display for ErrorWPos {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "error {}: {}", self.pos, self.error.message())
+        write!(
+            f,
+            "{} {}: {}",
+            "error".red(),
+            self.pos,
+            self.error.message().as_str().bold()
+        )
     }
 }
 
@@ -118,43 +455,84 @@ pub fn new(ctx: &'a mut Context) -> SemCheck<'a> {
             globals: HashMap::new(),
             constants: HashMap::new(),
             vars: vec![],
+            immutable: HashSet::new(),
+            loops: vec![],
+            initializing: HashSet::new(),
             signatures: HashMap::new(),
             ret: Type::Void(Position::new(intern("<>"), 0, 0)),
+            variadic: false,
             types: HashMap::new(),
             constexprs: HashMap::new(),
             aliases: HashMap::new(),
+            enums: HashMap::new(),
             imported: HashMap::new(),
             imported_funs: HashMap::new(),
             __internal_funs: HashMap::new(),
+            generics: HashMap::new(),
+            instances: HashSet::new(),
+            pending: vec![],
+        }
+    }
+
+    /// Report assignment to `let`/`const` binding or to part of it. Writes through pointer
+    /// modify pointee, not binding, so they are always allowed.
+    fn check_assignable(&self, place: &Expr) {
+        match &place.kind {
+            ExprKind::Ident(name) => {
+                // local may shadow global, so it's looked up first
+                let local = self.vars.last().map_or(false, |vars| vars.contains_key(name));
+                let immutable = match self.globals.get(name) {
+                    Some(global) if !local => !global.reassignable,
+                    _ => self.immutable.contains(name),
+                };
+                if immutable {
+                    error!(
+                        format!("cannot assign to immutable variable `{}`", str(*name)),
+                        place.pos
+                    );
+                }
+            }
+            ExprKind::Field(object, _) | ExprKind::ArrayIdx(object, _) => {
+                let through_ptr = self.types.get(&object.id).map_or(false, |ty| ty.is_ptr());
+                if !through_ptr {
+                    self.check_assignable(object);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Variadic argument intrinsic `name` may appear only inside variadic function
+    fn check_variadic(&self, name: &str, pos: Position) {
+        if !self.variadic {
+            error!(format!("`{}` used outside of variadic function", name), pos);
         }
     }
 
     pub fn run(&mut self) {
         self.imports();
+        ConstEval::comptime(self.ctx).prune_branches();
         let maybe_err = self.declare();
         if maybe_err.is_ok() {
             for (_, fun) in self.functions.clone().iter() {
-                self.ret = self.infer_type(&fun.ret);
-                self.vars.clear();
-                self.vars.push(HashMap::new());
-                if !fun.external && !fun.internal {
-                    for (name, ty) in fun.params.iter() {
-                        let ty = self.infer_type(ty);
-                        self.vars.last_mut().unwrap().insert(*name, ty);
-                    }
-
-                    if fun.this.is_some() {
-                        let (name, ty) = fun.this.clone().unwrap();
-                        let ty = self.infer_type(&ty);
-
-                        self.vars.last_mut().unwrap().insert(name, ty);
-                    }
-
-                    let body = fun.body.clone();
-                    let body = body.unwrap();
-                    self.tc_stmt(&body);
+                self.check_function(fun);
+            }
+            for elem in self.ctx.file.elems.clone().iter() {
+                if let Elem::StaticAssert(call) = elem {
+                    self.vars.clear();
+                    self.vars.push(HashMap::new());
+                    self.tc_expr(call);
                 }
             }
+            // specializations may call generic functions too
+            while let Some(spec) = self.pending.pop() {
+                self.check_function(&spec);
+                self.ctx.file.elems.push(Elem::Func(spec));
+            }
+            self.ctx.file.elems.retain(|elem| match elem {
+                Elem::Func(func) => func.type_params.is_empty(),
+                _ => true,
+            });
         } else {
             eprintln!("{}", maybe_err.unwrap_err());
         }
@@ -162,6 +540,181 @@ pub fn run(&mut self) {
         for (k, v) in self.types.iter() {
             self.ctx.types.insert(k.clone(), v.clone());
         }
+        let mut literals = UnsuffixedInts {
+            positions: &self.ctx.file.unsuffixed_ints,
+            types: &self.ctx.types,
+        };
+        for elem in self.ctx.file.elems.iter_mut() {
+            literals.fold_elem(elem);
+        }
+    }
+
+    /// Whether `expr` is integer literal written without suffix
+    fn is_unsuffixed(&self, expr: &Expr) -> bool {
+        match expr.kind {
+            ExprKind::Int(_, _, IntSuffix::Int) => {
+                self.ctx.file.unsuffixed_ints.contains(&expr.pos)
+            }
+            _ => false,
+        }
+    }
+
+    /// Type check body of `fun`
+    fn check_function(&mut self, fun: &Function) {
+        if fun.is_c_abi() {
+            self.check_c_abi(fun);
+        }
+        self.ret = self.infer_type(&fun.ret);
+        self.variadic = fun.variadic;
+        self.vars.clear();
+        self.vars.push(HashMap::new());
+        self.immutable.clear();
+        if !fun.external && !fun.internal {
+            for (name, ty) in fun.params.iter() {
+                let ty = self.infer_type(ty);
+                self.vars.last_mut().unwrap().insert(*name, ty);
+            }
+
+            if fun.this.is_some() {
+                let (name, ty) = fun.this.clone().unwrap();
+                let ty = self.infer_type(&ty);
+
+                self.vars.last_mut().unwrap().insert(name, ty);
+            }
+
+            let body = fun.body.clone();
+            let body = body.unwrap();
+            self.tc_stmt(&body);
+        }
+    }
+
+    /// Reject parameter and return types of C ABI function `fun` that C code can't use
+    fn check_c_abi(&self, fun: &Function) {
+        let params = fun
+            .params
+            .iter()
+            .map(|(name, ty)| (format!("parameter `{}`", str(*name)), &**ty));
+        for (what, ty) in params.chain(std::iter::once(("return type".to_owned(), &*fun.ret))) {
+            if let Some(reason) = self.c_abi_mismatch(&self.infer_type(ty), false) {
+                error!(
+                    format!(
+                        "{} of C ABI function `{}` isn't C-compatible: {}",
+                        what,
+                        str(fun.name),
+                        reason
+                    ),
+                    ty.pos()
+                );
+            }
+        }
+    }
+
+    /// Why values of `ty` have no C equivalent, `None` if they have one. Arrays are passed by
+    /// value only as struct fields.
+    fn c_abi_mismatch(&self, ty: &Type, field: bool) -> Option<String> {
+        match ty {
+            Type::Array(array) if array.len.is_none() => Some(format!(
+                "slice `{}` has no C equivalent, pass pointer and length instead",
+                ty
+            )),
+            Type::Array(array) if field => self.c_abi_mismatch(&array.subtype, true),
+            Type::Array(_) => Some(format!(
+                "C doesn't pass array `{}` by value, pass pointer to its first element instead",
+                ty
+            )),
+            Type::Struct(struc) if struc.result_types().is_some() => {
+                Some(format!("tagged union `{}` has no C layout", ty))
+            }
+            Type::Struct(struc) => struc.fields.iter().find_map(|field| {
+                let reason = self.c_abi_mismatch(&self.infer_type(&field.data_type), true)?;
+                Some(format!("field `{}` of `{}`: {}", field.name, struc.name, reason))
+            }),
+            Type::Func(func) => func
+                .params
+                .iter()
+                .chain(std::iter::once(&func.ret))
+                .find_map(|ty| self.c_abi_mismatch(&self.infer_type(ty), false)),
+            _ => None,
+        }
+    }
+
+    /// Type of call `expr` of generic function `func` with arguments of types `args`. Type
+    /// arguments are inferred from `args`, specialization for them is declared on first use and
+    /// checked by `run` later.
+    fn instantiate(&mut self, func: &Function, args: &[Type], expr: &Expr) -> Type {
+        if args.len() != func.params.len() {
+            error!(
+                format!(
+                    "`{}` takes {} argument(s), but {} were given",
+                    func.name,
+                    func.params.len(),
+                    args.len()
+                ),
+                expr.pos
+            );
+        }
+        let mut type_args = TypeArgs::new();
+        for ((_, param), arg) in func.params.iter().zip(args.iter()) {
+            if let Err(name) = monomorph::bind(&func.type_params, param, arg, &mut type_args) {
+                error!(
+                    format!(
+                        "type parameter `{}` of `{}` is both {} and {}",
+                        name, func.name, type_args[&name], arg
+                    ),
+                    expr.pos
+                );
+            }
+        }
+        let mut key = vec![];
+        for name in func.type_params.iter() {
+            match type_args.get(name) {
+                Some(ty) => key.push(ty.clone()),
+                None => error!(
+                    format!("can't infer type parameter `{}` of `{}`", name, func.name),
+                    expr.pos
+                ),
+            }
+        }
+        let params = func
+            .params
+            .iter()
+            .map(|(_, param)| self.infer_type(&monomorph::substitute(param, &type_args)))
+            .collect::<Vec<_>>();
+        if !args_match(args, &params) {
+            let params = params.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            error!(format!("`{}` expects ({})", func.name, params.join(", ")), expr.pos);
+        }
+        if self.instances.insert((func.name, key)) {
+            let spec = monomorph::specialize(func, &type_args);
+            self.declare_function(&spec);
+            self.pending.push(spec);
+        }
+        self.infer_type(&monomorph::substitute(&func.ret, &type_args))
+    }
+
+    /// Specializations of generic functions declared by `run`, see `monomorph::dump`
+    pub fn dump_mono(&self) -> String {
+        monomorph::dump(&self.instances)
+    }
+
+    /// Declare elements of file and type check `expr` outside of any function, so only globals,
+    /// constants and functions are in scope. Imports must be merged into file already. Errors
+    /// are returned instead of exiting the process.
+    pub fn type_check_expr(&mut self, expr: &Expr) -> Result<Type, ErrorWPos> {
+        let src = self.ctx.file.src.clone();
+        let ty = crate::err::catch_errors(|| -> Result<Type, ErrorWPos> {
+            self.declare()?;
+            self.vars.clear();
+            self.vars.push(HashMap::new());
+            let ty = self.tc_expr(expr);
+            Ok(self.infer_type(&ty))
+        })
+        .map_err(|caught| ErrorWPos::new(caught.pos, Error::Other(caught.msg), src))??;
+
+        for (k, v) in self.types.iter() {
+            self.ctx.types.insert(k.clone(), v.clone());
+        }
+        Ok(ty)
     }
 
     pub fn imports(&mut self) {
@@ -169,11 +722,7 @@ pub fn imports(&mut self) {
 
         for elem in elems.iter() {
             if let Elem::Import(import) = elem {
-                let import = if self.ctx.file.root.len() == 0 {
-                    import.to_owned()
-                } else {
-                    format!("{}/{}", self.ctx.file.root, import)
-                };
+                let import = self.ctx.resolve_import(import);
 
                 let mut file = File {
                     elems: vec![],
@@ -185,6 +734,7 @@ pub fn imports(&mut self) {
                         .to_str()
                         .unwrap()
                         .to_owned(),
+                    unsuffixed_ints: HashSet::new(),
                 };
                 use crate::syntax::{lexer, parser::Parser};
                 use lexer::reader::Reader;
@@ -195,6 +745,7 @@ pub fn imports(&mut self) {
                 parser.parse().expect("Error");
 
                 let mut ctx = Context::new(file);
+                ctx.include_dirs = self.ctx.include_dirs.clone();
 
                 let mut sem = SemCheck::new(&mut ctx);
                 sem.imports();
@@ -204,6 +755,8 @@ pub fn imports(&mut self) {
                     eprintln!("{}", maybe_err.err().unwrap());
                     std::process::exit(-1);
                 }
+                let unsuffixed = ctx.file.unsuffixed_ints.iter();
+                self.ctx.file.unsuffixed_ints.extend(unsuffixed);
 
                 for elem in ctx.file.elems.iter() {
                     if let Elem::Struct(s) = elem {
@@ -291,6 +844,13 @@ pub fn imports(&mut self) {
                                 self.ctx.file.elems.push(elem);
                             }
                         }
+                        Elem::Enum(e) => {
+                            if !self.imported.contains_key(&e.name) {
+                                let elem = Elem::Enum(e.clone());
+                                self.imported.insert(e.name, elem.clone());
+                                self.ctx.file.elems.push(elem);
+                            }
+                        }
                         _ => (),
                     }
                 }
@@ -298,6 +858,71 @@ pub fn imports(&mut self) {
         }
     }
 
+    /// Declare signature of non-generic function `func`
+    fn declare_function(&mut self, func: &Function) {
+        check_fn_attributes(func);
+        // C requires at least one named parameter before `...`
+        if func.variadic && func.params.is_empty() && func.this.is_none() {
+            error!(
+                format!(
+                    "variadic function `{}` must declare at least one fixed parameter",
+                    str(func.name)
+                ),
+                func.pos
+            );
+        }
+        /*fif func.internal {
+            self.internal_funs.insert(func.name, func.clone());
+            continue;
+        }*/
+        let mut params: Vec<Type> = func.params.iter().map(|(_, ty)| *ty.clone()).collect();
+        let mut ret = func.ret.clone();
+        let this = func.this.clone();
+
+        let (mut this, this_name) = if this.is_some() {
+            (
+                Some(this.clone().unwrap().1.clone()),
+                this.clone().unwrap().0,
+            )
+        } else {
+            (None, intern(""))
+        };
+        let name = func.name;
+
+        ret = Box::new(self.infer_type(&ret));
+
+        for p in params.iter_mut() {
+            let ty = self.infer_type(p);
+            *p = ty;
+        }
+
+        if this.is_some() {
+            this = Some(Box::new(self.infer_type(&this.clone().unwrap())));
+        }
+
+        let sig = FuncSig {
+            name,
+            params,
+            ret,
+            this_name,
+            this: if this.is_some() {
+                Some(this.unwrap().clone())
+            } else {
+                None
+            },
+            variadic: func.variadic,
+        };
+
+        if !self.signatures.contains_key(&func.name) {
+            self.signatures.insert(func.name, vec![sig.clone()]);
+        } else {
+            let sigs = self.signatures.get_mut(&func.name).unwrap();
+            sigs.push(sig.clone());
+        }
+
+        self.functions.insert(sig, func.clone());
+    }
+
     pub fn declare(&mut self) -> Result<(), ErrorWPos> {
         let src = self.ctx.file.src.clone();
         for elem in self.ctx.file.elems.iter() {
@@ -308,6 +933,7 @@ pub fn declare(&mut self) -> Result<(), ErrorWPos> {
                     pos: s.pos,
                     name: s.name,
                     public: s.public,
+                    attributes: s.attributes.clone(),
                     fields: s.fields.clone(),
                 };
                 self.structures.borrow_mut().insert(s.name, s.clone());
@@ -322,6 +948,13 @@ pub fn declare(&mut self) -> Result<(), ErrorWPos> {
                     let ty = self.infer_type(ty);
                     self.aliases.insert(*name, ty);
                 }
+                Elem::Enum(e) => {
+                    // values of enum are just integers of its backing type
+                    let repr = self.infer_type(&e.repr);
+                    check_enum(e, &repr);
+                    self.aliases.insert(e.name, repr);
+                    self.enums.insert(e.name, e.clone());
+                }
                 Elem::Const(c) => {
                     if self.constants.contains_key(&c.name) {
                         return Err(ErrorWPos::new(
@@ -333,59 +966,20 @@ pub fn declare(&mut self) -> Result<(), ErrorWPos> {
                     self.constants.insert(c.name, c.clone());
                 }
 
-                Elem::Func(func) => {
-                    /*fif func.internal {
-                        self.internal_funs.insert(func.name, func.clone());
-                        continue;
-                    }*/
-                    let mut params: Vec<Type> =
-                        func.params.iter().map(|(_, ty)| *ty.clone()).collect();
-                    let mut ret = func.ret.clone();
-                    let this = func.this.clone();
-
-                    let (mut this, this_name) = if this.is_some() {
-                        (
-                            Some(this.clone().unwrap().1.clone()),
-                            this.clone().unwrap().0,
-                        )
-                    } else {
-                        (None, intern(""))
-                    };
-                    let name = func.name;
-
-                    ret = Box::new(self.infer_type(&ret));
-
-                    for p in params.iter_mut() {
-                        let ty = self.infer_type(p);
-                        *p = ty;
-                    }
-
-                    if this.is_some() {
-                        this = Some(Box::new(self.infer_type(&this.clone().unwrap())));
-                    }
-
-                    let sig = FuncSig {
-                        name,
-                        params,
-                        ret,
-                        this_name,
-                        this: if this.is_some() {
-                            Some(this.unwrap().clone())
-                        } else {
-                            None
-                        },
-                        variadic: func.variadic,
-                    };
-
-                    if !self.signatures.contains_key(&func.name) {
-                        self.signatures.insert(func.name, vec![sig.clone()]);
-                    } else {
-                        let sigs = self.signatures.get_mut(&func.name).unwrap();
-                        sigs.push(sig.clone());
+                Elem::Func(func) if !func.type_params.is_empty() => {
+                    let overloads = self.ctx.file.elems.iter().filter(|elem| match elem {
+                        Elem::Func(f) => f.name == func.name,
+                        _ => false,
+                    });
+                    if overloads.count() > 1 {
+                        error!(
+                            format!("generic function `{}` can't be overloaded", func.name),
+                            func.pos
+                        );
                     }
-
-                    self.functions.insert(sig, func.clone());
+                    self.generics.insert(func.name, func.clone());
                 }
+                Elem::Func(func) => self.declare_function(func),
                 Elem::Global(c) => {
                     if self.globals.contains_key(&c.name) {
                         return Err(ErrorWPos::new(
@@ -394,6 +988,20 @@ pub fn declare(&mut self) -> Result<(), ErrorWPos> {
                             src.clone(),
                         ));
                     }
+                    // global initializers run at start of `main`, so other threads would see zero
+                    if c.thread_local && c.expr.is_some() {
+                        error!(
+                            format!(
+                                "thread_local variable `{}` can not have initializer",
+                                str(c.name)
+                            ),
+                            c.pos
+                        );
+                    }
+
+                    if let Some(section) = c.attribute("section") {
+                        check_section(section, c.external);
+                    }
 
                     let mut c = c.clone();
                     c.typ = Box::new(self.infer_type(&*c.typ));
@@ -499,6 +1107,7 @@ pub fn infer_type(&self, ty: &Type) -> Type {
                         data_type: self.infer_type(&field.data_type),
                         id: field.id,
                         pos: field.pos,
+                        attributes: field.attributes.clone(),
                     })
                 }
 
@@ -506,6 +1115,7 @@ pub fn infer_type(&self, ty: &Type) -> Type {
                     union: struc.union,
                     id,
                     pos,
+                    attributes: struc.attributes.clone(),
                     fields,
                     name: struc.name,
                 };
@@ -576,10 +1186,64 @@ pub fn tc_stmt(&mut self, stmt: &Stmt) {
                 self.tc_stmt(var);
                 self.tc_expr(cond);
                 self.tc_expr(then);
+                self.loops.push(LoopTarget::stmt());
                 self.tc_stmt(body);
+                self.loops.pop();
                 self.vars.pop();
             }
-            StmtKind::Continue | StmtKind::Break => (),
+            StmtKind::Continue(depth) => {
+                if *depth > self.loops.len() {
+                    error!(
+                        format!(
+                            "`continue {}` used inside of {} loops",
+                            depth,
+                            self.loops.len()
+                        ),
+                        stmt.pos
+                    );
+                }
+            }
+            StmtKind::Break(depth, value) => {
+                if *depth > self.loops.len() {
+                    error!(
+                        format!(
+                            "`break {}` used inside of {} loops",
+                            depth,
+                            self.loops.len()
+                        ),
+                        stmt.pos
+                    );
+                }
+                let value_ty = value.as_ref().map(|value| {
+                    let ty = self.tc_expr(value);
+                    let ty = self.infer_type(&ty);
+                    self.types.insert(value.id, ty.clone());
+                    ty
+                });
+                let idx = self.loops.len() - depth;
+                let target = &mut self.loops[idx];
+                match value_ty {
+                    Some(_) if !target.is_expr => {
+                        error!("`break` with value from loop that isn't expression", stmt.pos)
+                    }
+                    None if target.is_expr => {
+                        error!("`break` from `loop` expression must have value", stmt.pos)
+                    }
+                    Some(ty) => match &target.ty {
+                        Some(prev)
+                            if *prev != ty && !(ty_is_any_int(prev) && ty_is_any_int(&ty)) =>
+                        {
+                            error!(
+                                format!("`break` value expected to be {}, found {}", prev, ty),
+                                stmt.pos
+                            );
+                        }
+                        Some(_) => (),
+                        None => target.ty = Some(ty),
+                    },
+                    None => (),
+                }
+            }
             StmtKind::Expr(e) => {
                 self.tc_expr(e);
             }
@@ -610,7 +1274,9 @@ pub fn tc_stmt(&mut self, stmt: &Stmt) {
                 self.tc_expr(e);
                 self.vars.push(prev);
 
+                self.loops.push(LoopTarget::stmt());
                 self.tc_stmt(s);
+                self.loops.pop();
                 self.vars.pop();
             }
             StmtKind::If(cond, then, otherwise) => {
@@ -622,10 +1288,15 @@ pub fn tc_stmt(&mut self, stmt: &Stmt) {
                     self.tc_stmt(&otherwise);
                 }
             }
-            StmtKind::Var(name, _, ty, init) => {
+            StmtKind::Var(name, reassignable, ty, init) => {
                 if self.vars.last().unwrap().contains_key(name) {
                     error!(format!("Variable {} already exists", str(*name)), stmt.pos);
                 }
+                if *reassignable {
+                    self.immutable.remove(name);
+                } else {
+                    self.immutable.insert(*name);
+                }
                 if init.is_some() && ty.is_none() {
                     let init = init.clone().unwrap();
                     let mut t = self.tc_expr(&init);
@@ -654,7 +1325,7 @@ pub fn tc_stmt(&mut self, stmt: &Stmt) {
                         self.vars.last_mut().unwrap().insert(*name, t2.clone());
                         self.types.insert(stmt.id, t2);
                     } else {
-                        if t2 != t {
+                        if t2 != t && !array_decays_to(&t, &t2) {
                             error!(format!("Expected {}, found {}", t, t2), stmt.pos);
                         }
                         self.vars.last_mut().unwrap().insert(*name, t2.clone());
@@ -675,11 +1346,51 @@ pub fn tc_stmt(&mut self, stmt: &Stmt) {
                 }
                 self.vars.pop();
             }
-            StmtKind::Loop(stmt) => self.tc_stmt(stmt),
+            StmtKind::Loop(stmt) => {
+                self.loops.push(LoopTarget::stmt());
+                self.tc_stmt(stmt);
+                self.loops.pop();
+            }
+            StmtKind::Static(var) => self.tc_stmt(var),
+            StmtKind::Defer(deferred) => {
+                if let Some(pos) = escaping_jump(deferred, 0) {
+                    error!("deferred statement can't leave enclosing block", pos);
+                }
+                self.tc_stmt(deferred);
+            }
+            StmtKind::Delete(ptr) => {
+                let ty = self.tc_expr(ptr);
+                let ty = self.infer_type(&ty);
+                self.types.insert(ptr.id, ty.clone());
+                if !ty.is_ptr() {
+                    error!(format!("`delete` expects pointer, found {}", ty), stmt.pos);
+                }
+            }
             _ => unimplemented!(),
         };
     }
 
+    /// Warn about call at `pos` resolved to function marked `@deprecated`. Attributes are copied
+    /// along with imported functions, so this fires for functions reached through re-exports too.
+    fn warn_deprecated(&self, sig: &FuncSig, pos: Position) {
+        let func = match self.functions.get(sig) {
+            Some(func) => func,
+            None => return,
+        };
+        if let Some(attr) = func.attribute("deprecated") {
+            let msg = match &attr.args[..] {
+                [AttributeArg::Str(msg)] => format!(
+                    "use of deprecated function `{}`: {}",
+                    str(func.name),
+                    msg
+                ),
+                _ => format!("use of deprecated function `{}`", str(func.name)),
+            };
+            warn!(msg, pos);
+            note!("deprecated here", attr.pos);
+        }
+    }
+
     pub fn tc_expr(&mut self, expr: &Expr) -> Type {
         match &expr.kind {
             ExprKind::CompTime(e) => {
@@ -689,14 +1400,54 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
 
                 return ty;
             }
-            ExprKind::New(ty) => {
+            ExprKind::Inline(call) => {
+                if let ExprKind::Call(path, ..) = &call.kind {
+                    let has_body = self
+                        .functions
+                        .values()
+                        .any(|f| f.name == path.name() && f.body.is_some());
+                    if !has_body {
+                        error!(
+                            format!(
+                                "can not inline `{}`: function without body",
+                                str(path.name())
+                            ),
+                            expr.pos
+                        );
+                    }
+                }
+                let ty = self.tc_expr(call);
+                self.types.insert(expr.id, ty.clone());
+
+                return ty;
+            }
+            ExprKind::New(ty, init) => {
                 let infered = self.infer_type(ty);
-                self.ctx.gced.insert(expr.id);
+                if let Some(init) = init {
+                    let init_ty = self.tc_expr(init);
+                    let init_ty = self.infer_type(&init_ty);
+                    self.types.insert(init.id, init_ty.clone());
+                    if init_ty != infered && !(ty_is_any_int(&init_ty) && ty_is_any_int(&infered))
+                    {
+                        error!(
+                            format!("Expected {} type,found {}", infered, init_ty),
+                            init.pos
+                        );
+                    }
+                }
+                if self.ctx.gc {
+                    self.ctx.mark_gced(expr.id);
+                }
 
-                self.types.insert(expr.id, infered.clone());
-                return infered;
+                let ptr = Type::create_ptr(expr.id, expr.pos, Box::new(infered));
+                self.types.insert(expr.id, ptr.clone());
+                return ptr;
             }
             ExprKind::Int(_, _, suffix) => {
+                let suffix = match suffix {
+                    IntSuffix::Int if self.is_unsuffixed(expr) => self.ctx.int_suffix,
+                    _ => *suffix,
+                };
                 let ty = match suffix {
                     IntSuffix::Byte => Type::create_basic(expr.id, expr.pos, intern("i8")),
                     IntSuffix::Int => Type::create_basic(expr.id, expr.pos, intern("i32")),
@@ -723,6 +1474,18 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                 for (_, sigs) in self.signatures.iter() {
                     for sig in sigs.iter() {
                         if sig.name == *name {
+                            // defined variadic function takes variadic arguments in array of
+                            // slots (see `gccjit::va_slots`), not as C variadic arguments
+                            let defined = self.functions.get(sig).map(|f| f.body.is_some());
+                            if sig.variadic && defined == Some(true) {
+                                error!(
+                                    format!(
+                                        "can't take address of variadic function `{}`",
+                                        str(*name)
+                                    ),
+                                    expr.pos
+                                );
+                            }
                             let ty = Type::create_func(
                                 expr.id,
                                 expr.pos,
@@ -753,6 +1516,89 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                 self.types.insert(expr.id, ty.clone());
                 Type::create_ptr(expr.id, expr.pos, Box::new(ty))
             }
+            ExprKind::Call(path, None, args)
+                if ENV_BUILTINS.contains(&str(path.name()).as_str()) =>
+            {
+                eval_env_builtin(&str(path.name()), args, expr.pos);
+                for arg in args.iter() {
+                    self.tc_expr(arg);
+                }
+                let ty = self.types.get(&args[1].id).unwrap().clone();
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
+            ExprKind::Call(path, None, args) if str(path.name()).as_str() == STATIC_ASSERT => {
+                match &args[..] {
+                    [cond, msg] => {
+                        match msg.kind {
+                            ExprKind::Str(_) => (),
+                            _ => error!("`static_assert` message must be string literal", msg.pos),
+                        }
+                        self.tc_expr(cond);
+                    }
+                    _ => error!("`static_assert` expects condition and message", expr.pos),
+                }
+                let ty = Type::Void(expr.pos);
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
+            ExprKind::Call(path, None, args)
+                if FILE_BUILTINS.contains(&str(path.name()).as_str()) =>
+            {
+                let name = str(path.name());
+                let len = included_file(&name, args, expr.pos).len();
+                let ty = if name.as_str() == "include_str" {
+                    let char_ty = Type::create_basic(expr.id, expr.pos, intern("char"));
+                    Type::create_ptr(expr.id, expr.pos, box char_ty)
+                } else {
+                    let u8_ty = Type::create_basic(expr.id, expr.pos, intern("u8"));
+                    Type::create_array(expr.id, expr.pos, box u8_ty, Some(len))
+                };
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
+            ExprKind::Call(path, None, args)
+                if VEC_BUILTINS.contains(&str(path.name()).as_str()) =>
+            {
+                let name = str(path.name());
+                let arg_ty = match &args[..] {
+                    [arg] => {
+                        let ty = self.tc_expr(arg);
+                        self.infer_type(&ty)
+                    }
+                    _ => error!(
+                        format!("`{}` expects single vector argument", name),
+                        expr.pos
+                    ),
+                };
+                let vec = match arg_ty.to_vec() {
+                    Some(vec) => vec,
+                    None => error!(
+                        format!("`{}` expects vector, found value with type {}", name, arg_ty),
+                        expr.pos
+                    ),
+                };
+                let ty = if name.as_str() == "vec_sum" {
+                    *vec.subtype.clone()
+                } else {
+                    Type::create_basic(expr.id, expr.pos, intern("bool"))
+                };
+                self.types.insert(args[0].id, arg_ty.clone());
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
+            ExprKind::Call(path, None, args)
+                if VA_BUILTINS.contains(&str(path.name()).as_str()) =>
+            {
+                let name = str(path.name());
+                self.check_variadic(&name, expr.pos);
+                if !args.is_empty() {
+                    error!(format!("`{}` takes no arguments", name), expr.pos);
+                }
+                let ty = Type::Void(expr.pos);
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
             ExprKind::Call(path, object, args) => {
                 let mut params = vec![];
                 for arg in args.iter() {
@@ -761,12 +1607,48 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                     params.push(ty.clone());
                     self.types.insert(arg.id, ty);
                 }
-                let objty = if object.is_some() {
-                    let ty = self.tc_expr(&object.clone().unwrap());
-                    Some(Box::new(self.infer_type(&ty)))
+                // unsuffixed literal passed to declared parameter takes type of that parameter
+                // (`f(5)` passes `i64` to `f(x: i64)`), `--int-type` is for literals nothing
+                // else gives type to
+                let typed = self.signatures.get(&path.name()).and_then(|sigs| {
+                    sigs.iter().find_map(|sig| {
+                        let mut typed = params.clone();
+                        for (i, (arg, param)) in args.iter().zip(sig.params.iter()).enumerate() {
+                            if let (true, Type::Basic(basic)) = (self.is_unsuffixed(arg), param) {
+                                if IntSuffix::of_type(&str(basic.name)).is_some() {
+                                    typed[i] = Type::create_basic(arg.id, arg.pos, basic.name);
+                                }
+                            }
+                        }
+                        if args_match(&typed, &sig.params) {
+                            Some(typed)
+                        } else {
+                            None
+                        }
+                    })
+                });
+                if let Some(typed) = typed {
+                    for (arg, ty) in args.iter().zip(typed.iter()) {
+                        if self.is_unsuffixed(arg) {
+                            self.types.insert(arg.id, ty.clone());
+                        }
+                    }
+                    params = typed;
+                }
+                let objty = if let Some(object) = object {
+                    let ty = self.tc_expr(object);
+                    let ty = self.infer_type(&ty);
+                    self.types.insert(object.id, ty.clone());
+                    Some(Box::new(ty))
                 } else {
                     None
                 };
+                if let (None, Some(generic)) = (object, self.generics.get(&path.name())) {
+                    let generic = generic.clone();
+                    let ty = self.instantiate(&generic, &params, expr);
+                    self.types.insert(expr.id, ty.clone());
+                    return ty;
+                }
                 let sigs = self.signatures.get(&path.name());
                 if sigs.is_some() {
                     if object.is_some() {
@@ -779,7 +1661,9 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                         };
 
                         for sig in sigs.iter() {
-                            if sig.params == params && sig.this == Some(objty.clone()) {
+                            if args_match(&params, &sig.params) && sig.this == Some(objty.clone())
+                            {
+                                self.warn_deprecated(sig, expr.pos);
                                 let ty = *sig.ret.clone();
                                 self.types.insert(expr.id, ty.clone());
 
@@ -799,6 +1683,7 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                             }*/
                             let this_sig;
                             if params.is_empty() && sig.params.is_empty() {
+                                self.warn_deprecated(sig, expr.pos);
                                 return *sig.ret.clone();
                             }
                             let mut types_good = false;
@@ -810,7 +1695,8 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                                     break;
                                 }
                                 if i < sig.params.len() {
-                                    types_good = param == &sig.params[i];
+                                    types_good = param == &sig.params[i]
+                                        || array_decays_to(param, &sig.params[i]);
                                     //if !types_good {types_good = ty_is_any_int(param) &&
                                     // ty_is_any_int(&sig.params[i]);};
                                 }
@@ -822,6 +1708,7 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                             };
 
                             if this_sig {
+                                self.warn_deprecated(sig, expr.pos);
                                 let ty = self.infer_type(&sig.ret);
                                 self.types.insert(expr.id, ty.clone());
                                 return ty;
@@ -830,24 +1717,41 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                     }
                 } else if self.vars.last().unwrap().contains_key(&path.name()) {
                     let ty: &Type = self.vars.last().unwrap().get(&path.name()).unwrap();
-                    let f = ty.to_func();
-                    if f.is_none() {
-                        error!("Function type expected", expr.pos);
-                    } else {
-                        let f = f.unwrap().clone();
-                        let mut types_good = false;
-                        for (i, p) in params.iter().enumerate() {
-                            if i < f.params.len() {
-                                types_good = p == &self.infer_type(&f.params[i]);
-                            }
-                        }
-
-                        if types_good {
-                            let ty = self.infer_type(&f.ret);
-                            self.types.insert(expr.id, ty.clone());
-                            return ty;
+                    let f = match ty.to_func() {
+                        Some(f) => f.clone(),
+                        None => error!("Function type expected", expr.pos),
+                    };
+                    if params.len() != f.params.len() {
+                        error!(
+                            format!(
+                                "`{}` takes {} argument(s), but {} were given",
+                                str(path.name()),
+                                f.params.len(),
+                                params.len()
+                            ),
+                            expr.pos
+                        );
+                    }
+                    // integer arguments are converted to parameter type by backend
+                    for (i, arg) in params.iter().enumerate() {
+                        let param = self.infer_type(&f.params[i]);
+                        let converts = ty_is_any_int(arg) && ty_is_any_int(&param);
+                        if arg != &param && !converts && !array_decays_to(arg, &param) {
+                            error!(
+                                format!(
+                                    "argument {} of `{}` has type {}, but {} is expected",
+                                    i + 1,
+                                    str(path.name()),
+                                    arg,
+                                    param
+                                ),
+                                args[i].pos
+                            );
                         }
                     }
+                    let ty = self.infer_type(&f.ret);
+                    self.types.insert(expr.id, ty.clone());
+                    return ty;
                 }
 
                 let fun_ty = Type::create_func(
@@ -917,7 +1821,8 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                             t1
                         }
                     }
-                } else if t1.is_vec() && t2.is_vec() {
+                } else if ty_is_any_float(&t1) && ty_is_any_int(&t2) {
+                    // integer operand is converted to float, like in codegen
                     match op {
                         "<" | ">" | ">=" | "<=" | "!=" | "==" => {
                             let ty = Type::create_basic(expr.id, expr.pos, intern("bool"));
@@ -929,7 +1834,25 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                             t1
                         }
                     }
-                } else if t1.is_vec() && ty_is_any_int(&t2) || ty_is_any_float(&t2) {
+                } else if t1.is_vec() && t2.is_vec() {
+                    match op {
+                        "<" | ">" | ">=" | "<=" | "!=" | "==" => {
+                            if t1 != t2 {
+                                error!(
+                                    format!("Can't compare vectors of types {} and {}", t1, t2),
+                                    expr.pos
+                                );
+                            }
+                            let ty = vec_mask_type(t1.to_vec().unwrap(), expr.id, expr.pos);
+                            self.types.insert(expr.id, ty.clone());
+                            ty
+                        }
+                        _ => {
+                            self.types.insert(expr.id, t1.clone());
+                            t1
+                        }
+                    }
+                } else if t1.is_vec() && (ty_is_any_int(&t2) || ty_is_any_float(&t2)) {
                     match op {
                         "<" | ">" | ">=" | "<=" | "!=" | "==" => {
                             let ty = Type::create_basic(expr.id, expr.pos, intern("bool"));
@@ -1016,9 +1939,10 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                 }
             }
 
-            ExprKind::Assign(to, from) => {
-                let mut to = self.tc_expr(to);
+            ExprKind::Assign(place, from) => {
+                let mut to = self.tc_expr(place);
                 to = self.infer_type(&to);
+                self.check_assignable(place);
 
                 let mut from = self.tc_expr(from);
                 from = self.infer_type(&from);
@@ -1027,7 +1951,7 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                     return Type::Void(expr.pos);
                 }
 
-                if to != from {
+                if to != from && !array_decays_to(&from, &to) {
                     error!(format!("Expected {} type,found {}", to, from), expr.pos);
                 }
 
@@ -1035,7 +1959,13 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
             }
 
             ExprKind::Conv(e, to) => {
-                self.tc_expr(e);
+                let from = self.tc_expr(e);
+                let from = self.infer_type(&from);
+                if let (Type::Array(_), Some(ptr)) = (&from, to.to_ptr()) {
+                    if !ptr.subtype.is_void() && !array_decays_to(&from, &self.infer_type(to)) {
+                        error!(format!("can't convert {} to {}", from, to), expr.pos);
+                    }
+                }
                 self.types.insert(expr.id, *to.clone());
                 *to.clone()
             }
@@ -1058,10 +1988,13 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                 } else if self.globals.contains_key(name) {
                     let expr_ = self.globals.get(name).unwrap().expr.clone();
                     self.vars.push(HashMap::new());
-                    if expr_.is_some() {
+                    // initializers reading each other are ordered, or reported as cyclic, by
+                    // codegen, checking them again here would never end
+                    if expr_.is_some() && self.initializing.insert(*name) {
                         let ty = self.tc_expr(expr_.as_ref().unwrap());
                         let ty = self.infer_type(&ty);
                         self.types.insert(expr_.as_ref().unwrap().id, ty);
+                        self.initializing.remove(name);
                     }
                     self.vars.pop();
                     let ty = *self.globals.get(name).unwrap().typ.clone();
@@ -1085,6 +2018,23 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
             ExprKind::Field(expr_, field_name) => {
                 let mut ty = self.tc_expr(expr_);
                 ty = self.infer_type(&ty);
+                if expr.str_len().is_some() {
+                    let ty = Type::create_basic(expr.id, expr.pos, intern("usize"));
+                    self.types.insert(expr.id, ty.clone());
+                    return ty;
+                }
+                if let (Some(slice), true) = (ty.to_array(), ty.is_slice()) {
+                    let field_ty = match str(*field_name).as_str() {
+                        "len" => Type::create_basic(expr.id, expr.pos, intern("usize")),
+                        "ptr" => Type::create_ptr(expr.id, expr.pos, slice.subtype.clone()),
+                        _ => error!(
+                            format!("slice has no field {}, only `len` and `ptr`", field_name),
+                            expr.pos
+                        ),
+                    };
+                    self.types.insert(expr.id, field_ty.clone());
+                    return field_ty;
+                }
                 if ty.is_struct() {
                     let struct_ = ty.to_struct().unwrap();
                     for field in struct_.fields.iter() {
@@ -1101,15 +2051,35 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                     error!(format!("Structure type expected,found {}", ty), expr.pos);
                 }
             }
-            ExprKind::Struct(construct, _) => {
+            ExprKind::Struct(construct, args) => {
                 let name = construct.name();
-                let structs = self.structures.borrow();
-                let struct_ = structs.get(&name).expect("struct not found");
+                let fields = self
+                    .structures
+                    .borrow()
+                    .get(&name)
+                    .expect("struct not found")
+                    .fields
+                    .clone();
+                for (i, arg) in args.iter().enumerate() {
+                    if fields.iter().all(|field| field.name != arg.name) {
+                        error!(format!("Field {} not found", str(arg.name)), arg.pos);
+                    }
+                    if let Some(first) = args[..i].iter().find(|prev| prev.name == arg.name) {
+                        error!(
+                            false,
+                            format!("field `{}` is initialized more than once", str(arg.name)),
+                            arg.pos
+                        );
+                        note!("first initialized here", first.pos);
+                        std::process::exit(-1);
+                    }
+                    self.tc_expr(&arg.expr);
+                }
                 let ty = self.infer_type(&Type::create_struct(
                     expr.id,
                     expr.pos,
                     name,
-                    struct_.fields.clone(),
+                    fields,
                     false,
                 ));
                 self.types.insert(expr.id, ty.clone());
@@ -1120,6 +2090,21 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                 self.types.insert(expr.id, ty.clone());
                 ty
             }
+            ExprKind::Variant(name, variant) => {
+                let e = match self.enums.get(name) {
+                    Some(e) => e,
+                    None => error!(format!("Unknown enum `{}`", name), expr.pos),
+                };
+                if e.variant(*variant).is_none() {
+                    error!(
+                        format!("enum `{}` has no variant `{}`", name, variant),
+                        expr.pos
+                    );
+                }
+                let ty = self.infer_type(&e.repr);
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
             ExprKind::Null => {
                 let ty = Type::create_ptr(
                     expr.id,
@@ -1129,9 +2114,15 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                 self.types.insert(expr.id, ty.clone());
                 ty
             }
-            ExprKind::Unary(_, expr_) => {
+            ExprKind::Unary(op, expr_) => {
                 let t = self.tc_expr(expr_);
                 let t = self.infer_type(&t);
+                // `!` negates bits of integers, anything else is tested against zero
+                let t = if op == "!" && !ty_is_any_int(&t) {
+                    Type::create_basic(expr.id, expr.pos, intern("bool"))
+                } else {
+                    t
+                };
                 self.types.insert(expr.id, t.clone());
                 t
             }
@@ -1150,12 +2141,51 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
 
                 basic
             }
-            ExprKind::SizeOf(_) => {
+            ExprKind::SizeOf(_) | ExprKind::AlignOf(_) => {
+                let basic = Type::create_basic(expr.id, expr.pos, intern("usize"));
+                self.types.insert(expr.id, basic.clone());
+
+                basic
+            }
+            ExprKind::OffsetOf(name, field) => {
+                let has_field = match self.structures.borrow().get(name) {
+                    Some(struct_) => struct_.fields.iter().any(|f| f.name == *field),
+                    None => error!(format!("unknown struct `{}`", name), expr.pos),
+                };
+                if !has_field {
+                    error!(
+                        format!("struct `{}` has no field `{}`", name, field),
+                        expr.pos
+                    );
+                }
                 let basic = Type::create_basic(expr.id, expr.pos, intern("usize"));
                 self.types.insert(expr.id, basic.clone());
 
                 basic
             }
+            ExprKind::VaArg(ty) => {
+                self.check_variadic("va_arg", expr.pos);
+                let ty = self.infer_type(ty);
+                // smaller arguments are promoted by caller, reading them back is undefined
+                let promoted = match &ty {
+                    Type::Basic(basic) => match str(basic.name).as_str() {
+                        "i32" | "u32" | "i64" | "u64" | "isize" | "usize" | "f64" => true,
+                        _ => false,
+                    },
+                    Type::Ptr(_) | Type::Func(_) => true,
+                    _ => false,
+                };
+                if !promoted {
+                    error!(false, format!("`va_arg` can't read value of type {}", ty), expr.pos);
+                    note!(
+                        "variadic arguments are integers of at least 32 bits, f64 or pointers",
+                        expr.pos
+                    );
+                    std::process::exit(-1);
+                }
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
             ExprKind::ArrayIdx(array, idx) => {
                 let array_type = self.tc_expr(array);
                 let index = self.tc_expr(idx);
@@ -1183,7 +2213,127 @@ pub fn tc_expr(&mut self, expr: &Expr) -> Type {
                 self.types.insert(expr.id, result_type.clone());
                 result_type
             }
-            ExprKind::Array(_, _) => unimplemented!(),
+            ExprKind::Array(_, exprs) => {
+                if exprs.is_empty() {
+                    error!("can not infer type of empty array literal", expr.pos);
+                }
+                let mut subtype = self.tc_expr(&exprs[0]);
+                subtype = self.infer_type(&subtype);
+                for elem in exprs.iter().skip(1) {
+                    let ty = self.tc_expr(elem);
+                    let ty = self.infer_type(&ty);
+                    if ty != subtype && !(ty_is_any_int(&ty) && ty_is_any_int(&subtype)) {
+                        error!(
+                            format!("array element expected to be {}, found {}", subtype, ty),
+                            elem.pos
+                        );
+                    }
+                }
+                let ty = Type::create_array(expr.id, expr.pos, box subtype, Some(exprs.len()));
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
+            ExprKind::Slice(array, start, end) => {
+                let array_ty = self.tc_expr(array);
+                let array_ty = self.infer_type(&array_ty);
+                let subtype = match array_ty.to_array() {
+                    Some(array) => array.subtype.clone(),
+                    None => error!(
+                        format!("only arrays and slices can be sliced, found {}", array_ty),
+                        array.pos
+                    ),
+                };
+                for bound in [start, end].iter() {
+                    let ty = self.tc_expr(bound);
+                    if !ty_is_any_int(&self.infer_type(&ty)) {
+                        error!(format!("slice bound must be integer, found {}", ty), bound.pos);
+                    }
+                }
+                let ty = Type::create_array(expr.id, expr.pos, subtype, None);
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
+            ExprKind::Try(operand) => {
+                let ty = self.tc_expr(operand);
+                let ty = self.infer_type(&ty);
+                let (value, error) = match ty.to_struct().and_then(|s| s.result_types()) {
+                    Some((value, error)) if !ty.is_ptr() => (value.clone(), error.clone()),
+                    _ => error!(format!("`?` expects Result, found {}", ty), operand.pos),
+                };
+                let ret_error = self
+                    .ret
+                    .to_struct()
+                    .and_then(|s| s.result_types())
+                    .filter(|_| !self.ret.is_ptr())
+                    .map(|(_, error)| self.infer_type(error));
+                if ret_error != Some(self.infer_type(&error)) {
+                    error!(
+                        format!(
+                            "`?` returns error {} early, but function returns {}",
+                            error, self.ret
+                        ),
+                        expr.pos
+                    );
+                }
+                self.types.insert(operand.id, ty);
+                let ty = self.infer_type(&value);
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
+            ExprKind::Loop(body) => {
+                self.loops.push(LoopTarget {
+                    is_expr: true,
+                    ty: None,
+                });
+                self.tc_stmt(body);
+                let ty = match self.loops.pop().unwrap().ty {
+                    Some(ty) => ty,
+                    None => error!("`loop` expression never breaks with value", expr.pos),
+                };
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
+            ExprKind::Block(stmts, tail) => {
+                // value is taken at end of block, jumping out of it would leave it unset
+                if let Some(pos) = stmts.iter().find_map(|stmt| escaping_jump(stmt, 0)) {
+                    error!("`return`, `break` or `continue` can't leave block expression", pos);
+                }
+                let prev = self.vars.last().cloned().unwrap_or_default();
+                self.vars.push(prev);
+                for stmt in stmts.iter() {
+                    self.tc_stmt(stmt);
+                }
+                let ty = self.tc_expr(tail);
+                let ty = self.infer_type(&ty);
+                self.vars.pop();
+                if ty.is_void() {
+                    error!("block expression must end with value", tail.pos);
+                }
+                self.types.insert(tail.id, ty.clone());
+                self.types.insert(expr.id, ty.clone());
+                ty
+            }
+            ExprKind::Ternary(cond, then, otherwise) => {
+                self.tc_expr(cond);
+                let then_ty = self.tc_expr(then);
+                let then_ty = self.infer_type(&then_ty);
+                let otherwise_ty = self.tc_expr(otherwise);
+                let otherwise_ty = self.infer_type(&otherwise_ty);
+                // like values of `break`, integers of any type convert to type of first arm
+                if then_ty != otherwise_ty
+                    && !(ty_is_any_int(&then_ty) && ty_is_any_int(&otherwise_ty))
+                {
+                    error!(
+                        format!(
+                            "arms of conditional expression have different types {} and {}",
+                            then_ty, otherwise_ty
+                        ),
+                        expr.pos
+                    );
+                }
+                self.types.insert(expr.id, then_ty.clone());
+                then_ty
+            }
             _ => unreachable!(),
         }
     }
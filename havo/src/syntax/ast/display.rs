@@ -1,7 +1,10 @@
-use super::*;
+use super::{
+    visit::{walk_expr, walk_stmt, Visitor},
+    *,
+};
 use crate::syntax::lexer::token::IntBase;
 use fmt::Display;
-use std::fmt;
+use std::{collections::HashMap, fmt};
 
 use std::intrinsics::write_bytes;
 
@@ -16,12 +19,26 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ExprKind::MacroCall(name, _) => write!(f, "{}!()", name),
             ExprKind::CompTime(e) => write!(f, "constexpr {}", e),
-            ExprKind::New(val) => write!(f, "new {}", val),
-            ExprKind::Int(i, base, _) => match base {
-                IntBase::Hex => write!(f, "{:x}", i),
-                IntBase::Bin => write!(f, "{:b}", i),
-                IntBase::Dec => write!(f, "{}", i),
-            },
+            ExprKind::Inline(e) => write!(f, "inline {}", e),
+            ExprKind::New(ty, None) => write!(f, "new {}", ty),
+            ExprKind::New(ty, Some(init)) => write!(f, "new {}({})", ty, init),
+            ExprKind::Int(i, base, suffix) => {
+                // printed so that lexer reads it back: sign and magnitude instead of two's
+                // complement, base prefix and explicit suffix
+                let magnitude = if *i < 0 && !suffix.is_unsigned() {
+                    write!(f, "-")?;
+                    (*i as u64).wrapping_neg()
+                } else {
+                    *i as u64
+                };
+                match base {
+                    IntBase::Hex => write!(f, "0x{:x}", magnitude)?,
+                    IntBase::Bin => write!(f, "0b{:b}", magnitude)?,
+                    IntBase::Oct => write!(f, "0o{:o}", magnitude)?,
+                    IntBase::Dec => write!(f, "{}", magnitude)?,
+                }
+                write!(f, "{}", suffix.width_suffix())
+            }
             ExprKind::Float(float, _) => write!(f, "{}", float),
             ExprKind::Field(expr, field) => write!(f, "{}.{}", expr, field),
             ExprKind::Conv(expr, to) => write!(f, "{} as {}", expr, to),
@@ -30,6 +47,7 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             ExprKind::Assign(to, from) => write!(f, "{} = {}", to, from),
             ExprKind::Bool(b) => write!(f, "{}", b),
             ExprKind::Null => write!(f, "null"),
+            ExprKind::Variant(name, variant) => write!(f, "{}::{}", name, variant),
             ExprKind::Ident(name) => write!(f, "{}", name),
             ExprKind::Str(s) => write!(f, "{:?}", s),
             ExprKind::Struct(path, fields) => {
@@ -42,10 +60,35 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             ExprKind::Binary(op, lhs, rhs) => write!(f, "{} {} {}", lhs, op, rhs),
             ExprKind::Unary(op, val) => write!(f, "{}{}", op, val),
             ExprKind::SizeOf(ty) => write!(f, "sizeof({})", ty),
+            ExprKind::AlignOf(ty) => write!(f, "alignof({})", ty),
+            ExprKind::OffsetOf(s, field) => write!(f, "offsetof({}, {})", s, field),
+            ExprKind::VaArg(ty) => write!(f, "va_arg({})", ty),
+            ExprKind::Loop(body) => write!(f, "loop {}", body),
+            ExprKind::Block(stmts, tail) => {
+                write!(f, "{{\n")?;
+                for stmt in stmts.iter() {
+                    write!(f, "\t{}", stmt)?
+                }
+                write!(f, "\t{}\n}}", tail)
+            }
+            ExprKind::Try(e) => write!(f, "{}?", e),
+            ExprKind::Ternary(cond, then, otherwise) => {
+                write!(f, "{} ? {} : {}", cond, then, otherwise)
+            }
             ExprKind::GetFunc(name) => write!(f, "func &{}", name),
             ExprKind::Char(c) => write!(f, "{:?}", c),
             ExprKind::ArrayIdx(array, idx) => write!(f, "{}[{}]", array, idx),
-            ExprKind::Array(_, _) => write!(f, "unimplemented"),
+            ExprKind::Slice(array, start, end) => write!(f, "{}[{}..{}]", array, start, end),
+            ExprKind::Array(_, exprs) => {
+                write!(f, "[")?;
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", expr)?;
+                }
+                write!(f, "]")
+            }
             ExprKind::Call(path, this, args) => {
                 if this.is_some() {
                     write!(f, "{}.", this.as_ref().unwrap())?;
@@ -75,8 +118,23 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             StmtKind::CFor(var, cond, then, body) => {
                 write!(f, "for {} {} {} {}", var, cond, then, body)
             }
-            StmtKind::Continue => write!(f, "continue"),
-            StmtKind::Break => write!(f, "break"),
+            StmtKind::Continue(depth) => {
+                write!(f, "continue")?;
+                if *depth != 1 {
+                    write!(f, " {}", depth)?;
+                }
+                Ok(())
+            }
+            StmtKind::Break(depth, value) => {
+                write!(f, "break")?;
+                if *depth != 1 {
+                    write!(f, " {}", depth)?;
+                }
+                if let Some(value) = value {
+                    write!(f, " {}", value)?;
+                }
+                Ok(())
+            }
             StmtKind::Block(block) => {
                 write!(f, "{{\n")?;
                 for stmt in block.iter() {
@@ -97,6 +155,9 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
                 write!(f, "\n")
             }
             StmtKind::CompTime(s) => write!(f, "constexpr {}", s),
+            StmtKind::Defer(s) => write!(f, "defer {}", s),
+            StmtKind::Static(s) => write!(f, "static {}", s),
+            StmtKind::Delete(ptr) => write!(f, "delete {}\n", ptr),
             StmtKind::While(cond, body) => write!(f, "while {} \n {{\n {} \n}}", cond, body),
             StmtKind::Loop(body) => write!(f, "{{\n{}\n}}", body),
             StmtKind::Return(ret) => {
@@ -107,8 +168,12 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
                 }
             }
 
-            StmtKind::Var(name, _, ty, expr) => {
-                write!(f, "var {}", name)?;
+            StmtKind::Var(name, reassignable, ty, expr) => {
+                if *reassignable {
+                    write!(f, "var {}", name)?;
+                } else {
+                    write!(f, "let {}", name)?;
+                }
                 if ty.is_some() {
                     write!(f, ": {}", ty.as_ref().unwrap())?;
                 }
@@ -121,8 +186,38 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
+impl Display for AttributeArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeArg::Str(s) => write!(f, "{:?}", s),
+            AttributeArg::Int(i) => write!(f, "{}", i),
+            AttributeArg::Ident(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}", self.name)?;
+        if !self.args.is_empty() {
+            write!(f, "(")?;
+            for (i, arg) in self.args.iter().enumerate() {
+                write!(f, "{}", arg)?;
+                if i != self.args.len() - 1 {
+                    write!(f, ",")?;
+                }
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
 impl Display for Function {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for attr in self.attributes.iter() {
+            write!(f, "{}\n", attr)?;
+        }
         if self.public {
             write!(f, "pub ")?;
         }
@@ -138,7 +233,12 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.static_ {
             write!(f, "static ")?;
         }
-        write!(f, "func {}(", self.name)?;
+        write!(f, "func {}", self.name)?;
+        if !self.type_params.is_empty() {
+            let params = self.type_params.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            write!(f, "<{}>", params.join(","))?;
+        }
+        write!(f, "(")?;
         for (i, param) in self.params.iter().enumerate() {
             write!(f, "{}: {}", param.0, param.1)?;
             if i != self.params.len() - 1 {
@@ -155,8 +255,14 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 
 impl Display for Struct {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for attr in self.attributes.iter() {
+            write!(f, "{}\n", attr)?;
+        }
         write!(f, "struct {} {{\n", self.name)?;
         for field in self.fields.iter() {
+            for attr in field.attributes.iter() {
+                write!(f, "\t{}\n", attr)?;
+            }
             write!(f, "\t{}: {}\n", field.name, field.data_type)?;
         }
         write!(f, "}}\n")
@@ -165,9 +271,15 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 
 impl Display for Global {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for attr in self.attributes.iter() {
+            write!(f, "{}\n", attr)?;
+        }
         if self.external {
             write!(f, "external ")?;
         }
+        if self.thread_local {
+            write!(f, "thread_local ")?;
+        }
         write!(f, "var {}: {}", self.name, self.typ)?;
         if self.expr.is_some() {
             write!(f, " = {}", self.expr.as_ref().unwrap())?;
@@ -198,6 +310,19 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
+impl Display for Enum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.public {
+            write!(f, "pub ")?;
+        }
+        write!(f, "enum {}: {} {{\n", self.name, self.repr)?;
+        for variant in self.variants.iter() {
+            write!(f, "    {} = {},\n", variant.name, variant.value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
 impl Display for Elem {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -209,7 +334,84 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             Elem::Global(g) => write!(f, "{}", g),
             Elem::Link(l) => write!(f, "link \"{}\" ", l),
             Elem::Macro(m) => write!(f, "{}", m),
+            Elem::Enum(e) => write!(f, "{}", e),
             _ => write!(f, ""),
         }
     }
 }
+
+/// Function listing for `--print-types`: signature with symbol of function, then every local
+/// and (sub)expression of the body on its own line with type inferred by semantic pass, `?` if
+/// it has none
+pub struct TypedFunction<'a> {
+    pub func: &'a Function,
+    pub types: &'a HashMap<NodeId, Type>,
+}
+
+impl Display for TypedFunction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "func ")?;
+        if let Some((name, ty)) = &self.func.this {
+            write!(f, "({}: {}) ", name, ty)?;
+        }
+        write!(f, "{}(", self.func.name)?;
+        for (i, (name, ty)) in self.func.params.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", name, ty)?;
+        }
+        writeln!(f, ") {} // {}", self.func.ret, crate::mangle::mangle(self.func))?;
+
+        let mut lines = TypeLines {
+            types: self.types,
+            depth: 1,
+            lines: vec![],
+        };
+        if let Some(body) = &self.func.body {
+            lines.visit_stmt(body);
+        }
+        for line in lines.lines.iter() {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+struct TypeLines<'a> {
+    types: &'a HashMap<NodeId, Type>,
+    depth: usize,
+    lines: Vec<String>,
+}
+
+impl TypeLines<'_> {
+    fn push(&mut self, what: String, id: NodeId) {
+        let ty = self
+            .types
+            .get(&id)
+            .map_or_else(|| "?".to_owned(), |ty| ty.to_string());
+        self.lines
+            .push(format!("{}{}: {}", "    ".repeat(self.depth), what, ty));
+    }
+}
+
+impl Visitor for TypeLines<'_> {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if let StmtKind::Var(name, reassignable, ..) = &stmt.kind {
+            let keyword = if *reassignable { "var" } else { "let" };
+            self.push(format!("{} {}", keyword, name), stmt.id);
+            self.depth += 1;
+            walk_stmt(self, stmt);
+            self.depth -= 1;
+        } else {
+            walk_stmt(self, stmt);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.push(expr.to_string(), expr.id);
+        self.depth += 1;
+        walk_expr(self, expr);
+        self.depth -= 1;
+    }
+}
@@ -1,6 +1,7 @@
-use std::{fmt, ops::Index};
+use std::{collections::HashSet, fmt, ops::Index};
 
 pub mod display;
+pub mod visit;
 
 use super::lexer::token::{FloatSuffix, IntBase, IntSuffix};
 
@@ -9,6 +10,8 @@ pub struct File {
     pub src: String,
     pub path: String,
     pub elems: Vec<Elem>,
+    /// Positions of integer literals written without suffix, `--int-type` sets their type
+    pub unsuffixed_ints: HashSet<Position>,
 }
 
 impl File {
@@ -22,6 +25,32 @@ pub fn functions(&self) -> Vec<&Function> {
 
         funs
     }
+
+    pub fn find_enum(&self, name: Name) -> Option<&Enum> {
+        self.elems.iter().find_map(|elem| match elem {
+            Elem::Enum(e) if e.name == name => Some(e),
+            _ => None,
+        })
+    }
+
+    pub fn find_struct(&self, name: Name) -> Option<&Struct> {
+        self.elems.iter().find_map(|elem| match elem {
+            Elem::Struct(s) if s.name == name => Some(s),
+            _ => None,
+        })
+    }
+
+    /// Line `n` of source, counting from 1. Empty if file has fewer lines.
+    pub fn line(&self, n: u32) -> &str {
+        source_line(&self.src, n)
+    }
+
+    /// Source text covered by `span`, parts of it outside of file are cut off
+    pub fn snippet(&self, span: Span) -> &str {
+        let start = source_offset(&self.src, span.start);
+        let end = source_offset(&self.src, span.end).max(start);
+        &self.src[start..end]
+    }
 }
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Hash, PartialOrd)]
@@ -37,7 +66,7 @@ pub enum Elem {
     Func(Function),
     Struct(Struct),
     Const(Const),
-    Enum, // todo
+    Enum(Enum),
     Macro(Macro),
     Global(Global),
     Link(Name),
@@ -49,6 +78,8 @@ pub enum Elem {
         expr: Box<Expr>,
     },
     Alias(Name, Type),
+    /// `static_assert(cond, "message")` outside of functions, holds the call
+    StaticAssert(Box<Expr>),
 }
 
 impl PartialEq for Elem {
@@ -63,6 +94,7 @@ fn eq(&self, other: &Self) -> bool {
             (Elem::Import(s), Elem::Import(s2)) => s == s2,
             (Elem::Link(l), Elem::Link(l2)) => l == l2,
             (Elem::Macro(m1), Elem::Macro(m2)) => m1.name == m2.name,
+            (Elem::Enum(e1), Elem::Enum(e2)) => e1.name == e2.name,
 
             _ => false,
         }
@@ -95,17 +127,58 @@ pub struct Global {
     pub external: bool,
     pub public: bool,
     pub reassignable: bool,
+    /// Every thread gets its own copy of the variable
+    pub thread_local: bool,
+    /// attributes written before declaration, e.g `@section(".data.hot")`
+    pub attributes: Vec<Attribute>,
 
     pub typ: Box<Type>,
     pub expr: Option<Box<Expr>>,
 }
 
+impl Global {
+    /// Find attribute by name
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        find_attribute(&self.attributes, name)
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attribute(name).is_some()
+    }
+}
+
 impl PartialEq for Global {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name
     }
 }
 
+/// Integer-backed type whose variants are named constants: `enum Color: u8 { Red, Green = 5 }`
+#[derive(Clone, Debug)]
+pub struct Enum {
+    pub id: NodeId,
+    pub pos: Position,
+    pub public: bool,
+    pub name: Name,
+    /// Integer type values of enum are stored as, `i32` unless specified
+    pub repr: Type,
+    pub variants: Vec<EnumVariant>,
+}
+
+impl Enum {
+    pub fn variant(&self, name: Name) -> Option<&EnumVariant> {
+        self.variants.iter().find(|variant| variant.name == name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EnumVariant {
+    pub pos: Position,
+    pub name: Name,
+    /// Explicit value or previous variant's value plus one
+    pub value: i64,
+}
+
 #[derive(Clone, Debug)]
 pub struct Const {
     pub id: NodeId,
@@ -129,6 +202,8 @@ pub struct Struct {
     pub pos: Position,
     pub name: Name,
     pub public: bool,
+    /// attributes written before declaration, e.g `@packed`
+    pub attributes: Vec<Attribute>,
     pub fields: Vec<StructField>,
 }
 
@@ -136,12 +211,72 @@ impl Struct {
     pub fn to_type(&self) -> TypeStruct {
         TypeStruct {
             union: self.union,
+            attributes: self.attributes.clone(),
             fields: self.fields.clone(),
             name: self.name,
             id: self.id,
             pos: self.pos,
         }
     }
+
+    /// Find attribute by name
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        find_attribute(&self.attributes, name)
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attribute(name).is_some()
+    }
+
+    /// Maximal alignment of fields set by `@packed(N)`, `@packed` alone packs to 1 byte
+    pub fn packing(&self) -> Option<usize> {
+        packing(&self.attributes)
+    }
+
+    /// Alignment set by `@align(N)`
+    pub fn align(&self) -> Option<usize> {
+        alignment(&self.attributes)
+    }
+
+    /// Built-in `Result<value, error>`: `ok` tells whether `value` or `error` is set. Each
+    /// instantiation is separate struct marked with `@result`
+    pub fn result(pos: Position, value: Type, error: Type) -> Struct {
+        let field = |name: &str, data_type: Type| StructField {
+            id: crate::gen_id(),
+            name: intern(name),
+            pos,
+            attributes: vec![],
+            data_type,
+        };
+        Struct {
+            union: false,
+            id: crate::gen_id(),
+            pos,
+            name: result_name(&value, &error),
+            public: true,
+            attributes: vec![Attribute {
+                pos,
+                name: intern("result"),
+                args: vec![],
+            }],
+            fields: vec![
+                field("ok", Type::create_basic(crate::gen_id(), pos, intern("bool"))),
+                field("value", value),
+                field("error", error),
+            ],
+        }
+    }
+}
+
+/// Name of `Result<value, error>` struct, valid identifier so backends use it as is
+pub fn result_name(value: &Type, error: &Type) -> Name {
+    let arg = |ty: &Type| {
+        ty.to_string()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect::<String>()
+    };
+    intern(&format!("Result_{}_{}", arg(value), arg(error)))
 }
 
 impl PartialEq for Struct {
@@ -155,8 +290,27 @@ pub struct StructField {
     pub id: NodeId,
     pub name: Name,
     pub pos: Position,
+    /// attributes written before field, e.g `@align(16)`
+    pub attributes: Vec<Attribute>,
     pub data_type: Type,
 }
+
+impl StructField {
+    /// Find attribute by name
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        find_attribute(&self.attributes, name)
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attribute(name).is_some()
+    }
+
+    /// Alignment of field's offset set by `@align(N)`
+    pub fn align(&self) -> Option<usize> {
+        alignment(&self.attributes)
+    }
+}
+
 impl Eq for StructField {}
 
 use std::hash::{Hash, Hasher};
@@ -183,7 +337,7 @@ pub struct StructArg {
 }
 
 use super::interner::*;
-use crate::syntax::position::Position;
+use crate::syntax::position::{source_line, source_offset, Position, Span};
 
 #[derive(Clone, Debug)]
 pub enum Type {
@@ -273,11 +427,34 @@ pub struct TypeStruct {
     pub id: NodeId,
     pub pos: Position,
     pub name: Name,
+    pub attributes: Vec<Attribute>,
     pub fields: Vec<StructField>,
     pub union: bool,
 }
 
 impl TypeStruct {
+    /// Value and error types if this is instantiation of `Result<value, error>`
+    pub fn result_types(&self) -> Option<(&Type, &Type)> {
+        find_attribute(&self.attributes, "result")?;
+        let field = |name: &str| {
+            self.fields
+                .iter()
+                .find(|field| str(field.name).as_str() == name)
+                .map(|field| &field.data_type)
+        };
+        Some((field("value")?, field("error")?))
+    }
+
+    /// Maximal alignment of fields set by `@packed(N)`, `@packed` alone packs to 1 byte
+    pub fn packing(&self) -> Option<usize> {
+        packing(&self.attributes)
+    }
+
+    /// Alignment set by `@align(N)`
+    pub fn align(&self) -> Option<usize> {
+        alignment(&self.attributes)
+    }
+
     pub fn to_struct(&self) -> Struct {
         Struct {
             union: self.union,
@@ -285,6 +462,7 @@ pub fn to_struct(&self) -> Struct {
             id: self.id,
             pos: self.pos,
             name: self.name,
+            attributes: self.attributes.clone(),
             fields: self.fields.clone(),
         }
     }
@@ -326,6 +504,7 @@ pub const fn create_struct(
             union,
             id,
             pos,
+            attributes: Vec::new(),
             fields,
             name,
         })
@@ -444,6 +623,14 @@ pub fn is_array(&self) -> bool {
         }
     }
 
+    /// Array without length, `T[]` or `[]T`: pointer to elements and their count
+    pub fn is_slice(&self) -> bool {
+        match self {
+            Type::Array(array) => array.len.is_none(),
+            _ => false,
+        }
+    }
+
     pub fn is_basic(&self) -> bool {
         match self {
             Type::Basic(_) => true,
@@ -516,6 +703,69 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
+/// Attribute
+///
+/// ```go
+/// @symver("foo@VER_1")
+/// @no_mangle
+/// pub func foo() i32 = 42;
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Attribute {
+    pub pos: Position,
+    pub name: Name,
+    pub args: Vec<AttributeArg>,
+}
+
+impl Attribute {
+    /// Single integer argument, e.g `N` of `@align(N)`
+    pub fn int_arg(&self) -> Option<i64> {
+        match &self.args[..] {
+            [AttributeArg::Int(i)] => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Single string argument, e.g `name` of `@section("name")`
+    pub fn str_arg(&self) -> Option<&str> {
+        match &self.args[..] {
+            [AttributeArg::Str(s)] => Some(s),
+            _ => None,
+        }
+    }
+}
+
+fn find_attribute<'a>(attributes: &'a [Attribute], name: &str) -> Option<&'a Attribute> {
+    attributes
+        .iter()
+        .find(|attr| str(attr.name).as_str() == name)
+}
+
+fn alignment(attributes: &[Attribute]) -> Option<usize> {
+    match find_attribute(attributes, "align")?.int_arg() {
+        Some(align) if align > 0 => Some(align as usize),
+        _ => None,
+    }
+}
+
+fn packing(attributes: &[Attribute]) -> Option<usize> {
+    let packed = find_attribute(attributes, "packed")?;
+    if packed.args.is_empty() {
+        return Some(1);
+    }
+    match packed.int_arg() {
+        Some(packing) if packing > 0 => Some(packing as usize),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AttributeArg {
+    Str(String),
+    Int(i64),
+    Ident(Name),
+}
+
 /// Function
 ///
 /// ```go
@@ -532,8 +782,8 @@ pub struct Function {
     pub pos: Position,
     pub name: Name,
 
-    /// string attributes that passed to LLVM
-    pub attributes: Vec<String>,
+    /// attributes written before declaration, e.g `@symver("foo@VER_1")`
+    pub attributes: Vec<Attribute>,
     pub variadic: bool,
     pub inline: bool,
     pub external: bool,
@@ -541,6 +791,8 @@ pub struct Function {
     pub public: bool,
     pub internal: bool,
     pub static_: bool,
+    /// `T` of `func max<T>(a: T, b: T) T`, non-empty for generic functions
+    pub type_params: Vec<Name>,
     pub params: Vec<(Name, Box<Type>)>,
     pub ret: Box<Type>,
     pub this: Option<(Name, Box<Type>)>,
@@ -549,6 +801,21 @@ pub struct Function {
 }
 
 impl Function {
+    /// Find attribute by name
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        find_attribute(&self.attributes, name)
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attribute(name).is_some()
+    }
+
+    /// Whether C code calls function or is called by it: function is external, or defined with
+    /// `@export` or `@no_mangle`
+    pub fn is_c_abi(&self) -> bool {
+        self.external || self.has_attribute("export") || self.has_attribute("no_mangle")
+    }
+
     pub fn replace_expr_to(&mut self, id: NodeId, to: Expr) {
         fn replace_stmt(s: &mut Stmt, id: NodeId, to: Expr) -> bool {
             match &mut s.kind {
@@ -570,8 +837,14 @@ fn replace_stmt(s: &mut Stmt, id: NodeId, to: Expr) -> bool {
                     }
                     false
                 }
-                StmtKind::Continue => false,
-                StmtKind::Break => false,
+                StmtKind::Continue(_) => false,
+                StmtKind::Break(_, expr) => match expr {
+                    Some(expr) if expr.id == id => {
+                        expr.kind = to.kind.clone();
+                        true
+                    }
+                    _ => false,
+                },
                 StmtKind::Return(expr) => {
                     if expr.is_some() {
                         let expr = expr.as_mut().unwrap();
@@ -591,7 +864,7 @@ fn replace_stmt(s: &mut Stmt, id: NodeId, to: Expr) -> bool {
 
                     return false;
                 }
-                StmtKind::Expr(expr) => {
+                StmtKind::Expr(expr) | StmtKind::Delete(expr) => {
                     if expr.id == id {
                         *expr = box to;
                         return true;
@@ -627,7 +900,9 @@ fn replace_stmt(s: &mut Stmt, id: NodeId, to: Expr) -> bool {
                         return false;
                     }
                 }
-                StmtKind::Loop(body) => replace_stmt(body, id, to.clone()),
+                StmtKind::Loop(body) | StmtKind::Defer(body) | StmtKind::Static(body) => {
+                    replace_stmt(body, id, to.clone())
+                }
                 StmtKind::Var(_, _, _, expr) => {
                     if expr.is_some() {
                         let expr = expr.as_mut().unwrap();
@@ -683,6 +958,17 @@ pub fn is_bool(&self, val: bool) -> bool {
             _ => false,
         }
     }
+
+    /// Length in bytes of string literal if this is `"...".len`
+    pub fn str_len(&self) -> Option<usize> {
+        match &self.kind {
+            ExprKind::Field(object, field) if str(*field).as_str() == "len" => match &object.kind {
+                ExprKind::Str(s) => Some(s.len()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 impl Expr {
@@ -714,6 +1000,8 @@ pub fn map<U>(&self, mut f: impl FnMut(&Self) -> U, or_: U) -> Vec<U> {
 pub enum ExprKind {
     MacroCall(Name, Vec<Vec<Token>>),
     CompTime(Box<Expr>),
+    /// `inline f(args)`: call that backend must inline even if `f` itself isn't `inline`
+    Inline(Box<Expr>),
     Unary(String, Box<Expr>),
     Binary(String, Box<Expr>, Box<Expr>),
     Char(char),
@@ -727,14 +1015,35 @@ pub enum ExprKind {
     Array(Box<Type>, Vec<Box<Expr>>),
     GetFunc(Name),
     Null,
-    New(Box<Type>),
+    /// `new T` or `new T(init)`: pointer to heap allocated `T`, zeroed unless initialized
+    New(Box<Type>, Option<Box<Expr>>),
     Call(Path, Option<Box<Expr>>, Vec<Box<Expr>>),
     Assign(Box<Expr>, Box<Expr>),
     Field(Box<Expr>, Name),
     Conv(Box<Expr>, Box<Type>),
     Struct(Path, Vec<StructArg>),
+    /// `Enum::Variant`
+    Variant(Name, Name),
     AddressOf(Box<Expr>),
     SizeOf(Box<Type>),
+    /// `alignof(T)`: alignment of `T` in bytes
+    AlignOf(Box<Type>),
+    /// `offsetof(S, field)`: offset of `field` in struct `S` in bytes
+    OffsetOf(Name, Name),
+    /// `loop { ... }` in expression position, value comes from `break` inside it
+    Loop(Box<Stmt>),
+    /// `array[start..end]`: slice of fixed-size array or of another slice, shares their elements
+    Slice(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `expr?`: value of `Result` or early return of its error, lowered to `loop` after semantic
+    /// pass
+    Try(Box<Expr>),
+    /// `cond ? then : otherwise`, evaluates only one of arms. `?` followed by token that can
+    /// start expression begins conditional, so `(result?) - 1` needs parentheses
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `{ stmts; tail }` in expression position, evaluates to value of `tail`
+    Block(Vec<Box<Stmt>>, Box<Expr>),
+    /// `va_arg(T)`: next variadic argument of enclosing function, read as `T`
+    VaArg(Box<Type>),
 }
 #[derive(Clone, Debug)]
 pub struct Stmt {
@@ -759,8 +1068,17 @@ pub enum StmtKind {
     Var(Name, bool, Option<Type>, Option<Box<Expr>>),
     If(Box<Expr>, Box<Stmt>, Option<Box<Stmt>>),
     CFor(Box<Stmt>, Box<Expr>, Box<Expr>, Box<Stmt>),
-    Continue,
-    Break,
+    /// `continue N`: go to next iteration of `N`th enclosing loop
+    Continue(usize),
+    /// `break N value`: leave `N` enclosing loops, passing `value` to the outermost of them
+    Break(usize, Option<Box<Expr>>),
+    /// `defer stmt`: run `stmt` when enclosing block is left, latest registered runs first
+    Defer(Box<Stmt>),
+    /// `delete ptr`: free memory allocated by `new`
+    Delete(Box<Expr>),
+    /// `static var x = init`: wrapped variable keeps its value between calls, `init` runs on
+    /// first entry only
+    Static(Box<Stmt>),
 }
 
 impl StmtKind {
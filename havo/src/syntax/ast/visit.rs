@@ -0,0 +1,326 @@
+//! AST traversal.
+//!
+//! `Visitor` walks tree by reference, `Folder` walks it mutably and may rewrite nodes in place.
+//! Every method has default implementation that calls corresponding `walk_*` function, so
+//! implementor overrides only nodes it's interested in and calls `walk_*` to continue into
+//! children.
+
+use super::*;
+
+pub trait Visitor: Sized {
+    fn visit_elem(&mut self, elem: &Elem) {
+        walk_elem(self, elem)
+    }
+
+    fn visit_function(&mut self, func: &Function) {
+        walk_function(self, func)
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+}
+
+pub fn walk_elem<V: Visitor>(v: &mut V, elem: &Elem) {
+    match elem {
+        Elem::Func(func) => v.visit_function(func),
+        Elem::Global(global) => {
+            if let Some(expr) = &global.expr {
+                v.visit_expr(expr);
+            }
+        }
+        Elem::Const(c) => v.visit_expr(&c.expr),
+        Elem::ConstExpr { expr, .. } | Elem::StaticAssert(expr) => v.visit_expr(expr),
+        _ => (),
+    }
+}
+
+pub fn walk_function<V: Visitor>(v: &mut V, func: &Function) {
+    if let Some(body) = &func.body {
+        v.visit_stmt(body);
+    }
+}
+
+pub fn walk_stmt<V: Visitor>(v: &mut V, stmt: &Stmt) {
+    match &stmt.kind {
+        StmtKind::CompTime(stmt)
+        | StmtKind::Loop(stmt)
+        | StmtKind::Defer(stmt)
+        | StmtKind::Static(stmt) => v.visit_stmt(stmt),
+        StmtKind::Return(expr) => {
+            if let Some(expr) = expr {
+                v.visit_expr(expr);
+            }
+        }
+        StmtKind::Block(stmts) => {
+            for stmt in stmts.iter() {
+                v.visit_stmt(stmt);
+            }
+        }
+        StmtKind::Expr(expr) | StmtKind::Delete(expr) => v.visit_expr(expr),
+        StmtKind::While(cond, body) => {
+            v.visit_expr(cond);
+            v.visit_stmt(body);
+        }
+        StmtKind::Var(_, _, _, init) => {
+            if let Some(init) = init {
+                v.visit_expr(init);
+            }
+        }
+        StmtKind::If(cond, then, otherwise) => {
+            v.visit_expr(cond);
+            v.visit_stmt(then);
+            if let Some(otherwise) = otherwise {
+                v.visit_stmt(otherwise);
+            }
+        }
+        StmtKind::CFor(init, cond, step, body) => {
+            v.visit_stmt(init);
+            v.visit_expr(cond);
+            v.visit_expr(step);
+            v.visit_stmt(body);
+        }
+        StmtKind::Break(_, value) => {
+            if let Some(value) = value {
+                v.visit_expr(value);
+            }
+        }
+        StmtKind::Continue(_) => (),
+    }
+}
+
+pub fn walk_expr<V: Visitor>(v: &mut V, expr: &Expr) {
+    match &expr.kind {
+        ExprKind::CompTime(expr)
+        | ExprKind::Inline(expr)
+        | ExprKind::Unary(_, expr)
+        | ExprKind::Deref(expr)
+        | ExprKind::Field(expr, _)
+        | ExprKind::Conv(expr, _)
+        | ExprKind::AddressOf(expr)
+        | ExprKind::Try(expr) => v.visit_expr(expr),
+        ExprKind::Binary(_, lhs, rhs)
+        | ExprKind::ArrayIdx(lhs, rhs)
+        | ExprKind::Assign(lhs, rhs) => {
+            v.visit_expr(lhs);
+            v.visit_expr(rhs);
+        }
+        ExprKind::Slice(array, start, end) => {
+            v.visit_expr(array);
+            v.visit_expr(start);
+            v.visit_expr(end);
+        }
+        ExprKind::Ternary(cond, then, otherwise) => {
+            v.visit_expr(cond);
+            v.visit_expr(then);
+            v.visit_expr(otherwise);
+        }
+        ExprKind::Array(_, exprs) => {
+            for expr in exprs.iter() {
+                v.visit_expr(expr);
+            }
+        }
+        ExprKind::Loop(body) => v.visit_stmt(body),
+        ExprKind::Block(stmts, tail) => {
+            for stmt in stmts.iter() {
+                v.visit_stmt(stmt);
+            }
+            v.visit_expr(tail);
+        }
+        ExprKind::Call(_, this, args) => {
+            if let Some(this) = this {
+                v.visit_expr(this);
+            }
+            for arg in args.iter() {
+                v.visit_expr(arg);
+            }
+        }
+        ExprKind::Struct(_, args) => {
+            for arg in args.iter() {
+                v.visit_expr(&arg.expr);
+            }
+        }
+        ExprKind::New(_, init) => {
+            if let Some(init) = init {
+                v.visit_expr(init);
+            }
+        }
+        ExprKind::MacroCall(..)
+        | ExprKind::Char(_)
+        | ExprKind::Int(..)
+        | ExprKind::Float(..)
+        | ExprKind::Str(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Ident(_)
+        | ExprKind::GetFunc(_)
+        | ExprKind::Null
+        | ExprKind::Variant(..)
+        | ExprKind::SizeOf(_)
+        | ExprKind::AlignOf(_)
+        | ExprKind::OffsetOf(..)
+        | ExprKind::VaArg(_) => (),
+    }
+}
+
+pub trait Folder: Sized {
+    fn fold_elem(&mut self, elem: &mut Elem) {
+        walk_elem_mut(self, elem)
+    }
+
+    fn fold_function(&mut self, func: &mut Function) {
+        walk_function_mut(self, func)
+    }
+
+    fn fold_stmt(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt)
+    }
+
+    fn fold_expr(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+}
+
+pub fn walk_elem_mut<F: Folder>(f: &mut F, elem: &mut Elem) {
+    match elem {
+        Elem::Func(func) => f.fold_function(func),
+        Elem::Global(global) => {
+            if let Some(expr) = &mut global.expr {
+                f.fold_expr(expr);
+            }
+        }
+        Elem::Const(c) => f.fold_expr(&mut c.expr),
+        Elem::ConstExpr { expr, .. } | Elem::StaticAssert(expr) => f.fold_expr(expr),
+        _ => (),
+    }
+}
+
+pub fn walk_function_mut<F: Folder>(f: &mut F, func: &mut Function) {
+    if let Some(body) = &mut func.body {
+        f.fold_stmt(body);
+    }
+}
+
+pub fn walk_stmt_mut<F: Folder>(f: &mut F, stmt: &mut Stmt) {
+    match &mut stmt.kind {
+        StmtKind::CompTime(stmt)
+        | StmtKind::Loop(stmt)
+        | StmtKind::Defer(stmt)
+        | StmtKind::Static(stmt) => f.fold_stmt(stmt),
+        StmtKind::Return(expr) => {
+            if let Some(expr) = expr {
+                f.fold_expr(expr);
+            }
+        }
+        StmtKind::Block(stmts) => {
+            for stmt in stmts.iter_mut() {
+                f.fold_stmt(stmt);
+            }
+        }
+        StmtKind::Expr(expr) | StmtKind::Delete(expr) => f.fold_expr(expr),
+        StmtKind::While(cond, body) => {
+            f.fold_expr(cond);
+            f.fold_stmt(body);
+        }
+        StmtKind::Var(_, _, _, init) => {
+            if let Some(init) = init {
+                f.fold_expr(init);
+            }
+        }
+        StmtKind::If(cond, then, otherwise) => {
+            f.fold_expr(cond);
+            f.fold_stmt(then);
+            if let Some(otherwise) = otherwise {
+                f.fold_stmt(otherwise);
+            }
+        }
+        StmtKind::CFor(init, cond, step, body) => {
+            f.fold_stmt(init);
+            f.fold_expr(cond);
+            f.fold_expr(step);
+            f.fold_stmt(body);
+        }
+        StmtKind::Break(_, value) => {
+            if let Some(value) = value {
+                f.fold_expr(value);
+            }
+        }
+        StmtKind::Continue(_) => (),
+    }
+}
+
+pub fn walk_expr_mut<F: Folder>(f: &mut F, expr: &mut Expr) {
+    match &mut expr.kind {
+        ExprKind::CompTime(expr)
+        | ExprKind::Inline(expr)
+        | ExprKind::Unary(_, expr)
+        | ExprKind::Deref(expr)
+        | ExprKind::Field(expr, _)
+        | ExprKind::Conv(expr, _)
+        | ExprKind::AddressOf(expr)
+        | ExprKind::Try(expr) => f.fold_expr(expr),
+        ExprKind::Binary(_, lhs, rhs)
+        | ExprKind::ArrayIdx(lhs, rhs)
+        | ExprKind::Assign(lhs, rhs) => {
+            f.fold_expr(lhs);
+            f.fold_expr(rhs);
+        }
+        ExprKind::Slice(array, start, end) => {
+            f.fold_expr(array);
+            f.fold_expr(start);
+            f.fold_expr(end);
+        }
+        ExprKind::Ternary(cond, then, otherwise) => {
+            f.fold_expr(cond);
+            f.fold_expr(then);
+            f.fold_expr(otherwise);
+        }
+        ExprKind::Array(_, exprs) => {
+            for expr in exprs.iter_mut() {
+                f.fold_expr(expr);
+            }
+        }
+        ExprKind::Loop(body) => f.fold_stmt(body),
+        ExprKind::Block(stmts, tail) => {
+            for stmt in stmts.iter_mut() {
+                f.fold_stmt(stmt);
+            }
+            f.fold_expr(tail);
+        }
+        ExprKind::Call(_, this, args) => {
+            if let Some(this) = this {
+                f.fold_expr(this);
+            }
+            for arg in args.iter_mut() {
+                f.fold_expr(arg);
+            }
+        }
+        ExprKind::Struct(_, args) => {
+            for arg in args.iter_mut() {
+                f.fold_expr(&mut arg.expr);
+            }
+        }
+        ExprKind::New(_, init) => {
+            if let Some(init) = init {
+                f.fold_expr(init);
+            }
+        }
+        ExprKind::MacroCall(..)
+        | ExprKind::Char(_)
+        | ExprKind::Int(..)
+        | ExprKind::Float(..)
+        | ExprKind::Str(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Ident(_)
+        | ExprKind::GetFunc(_)
+        | ExprKind::Null
+        | ExprKind::Variant(..)
+        | ExprKind::SizeOf(_)
+        | ExprKind::AlignOf(_)
+        | ExprKind::OffsetOf(..)
+        | ExprKind::VaArg(_) => (),
+    }
+}
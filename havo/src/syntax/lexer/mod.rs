@@ -133,7 +133,9 @@ fn read_identifier(&mut self) -> Result<Token, MsgWithPos> {
             ttype = tok_type;
         } else if value == "_" {
             ttype = TokenKind::Underscore;
-        } else if value == "!" {
+        } else if self.cur() == Some('!') && self.next() == Some('(') {
+            // macro invocation `name!(...)`
+            self.read_char();
             ttype = TokenKind::BangIdent(value);
         } else {
             ttype = TokenKind::Identifier(value);
@@ -164,6 +166,7 @@ fn read_char_literal(&mut self) -> Result<Token, MsgWithPos> {
     }
 
     fn read_escaped_char(&mut self, pos: Position, unclosed: Msg) -> Result<char, MsgWithPos> {
+        let escape_pos = self.reader.pos();
         if let Some(ch) = self.cur() {
             self.read_char();
 
@@ -190,17 +193,17 @@ fn read_escaped_char(&mut self, pos: Position, unclosed: Msg) -> Result<char, Ms
                     '\'' => Ok('\''),
                     '0' => Ok('\0'),
 
-                    'e' => unimplemented!(),
-                    'v' => unimplemented!(),
-                    'x' => unimplemented!(),
-                    'u' => unimplemented!(),
+                    'e' => Ok('\x1B'),
+                    'v' => Ok('\x0B'),
+                    'x' => self.read_hex_escape(escape_pos),
+                    'u' => self.read_unicode_escape(escape_pos),
 
                     _ => {
                         let msg = Msg::InvalidEscapeSequence(ch);
                         Err(MsgWithPos::new(
                             self.reader.path().to_string(),
                             self.reader.src.clone(),
-                            pos,
+                            escape_pos,
                             msg,
                         ))
                     }
@@ -218,6 +221,57 @@ fn read_escaped_char(&mut self, pos: Position, unclosed: Msg) -> Result<char, Ms
         }
     }
 
+    /// `\xNN` after `\x`: exactly two hex digits, only ASCII since strings are UTF-8
+    fn read_hex_escape(&mut self, pos: Position) -> Result<char, MsgWithPos> {
+        let mut value = 0;
+        for _ in 0..2 {
+            match self.cur().and_then(|ch| ch.to_digit(16)) {
+                Some(digit) => value = value * 16 + digit,
+                None => return Err(self.escape_error(pos, Msg::InvalidHexEscape)),
+            }
+            self.read_char();
+        }
+        if value > 0x7F {
+            return Err(self.escape_error(pos, Msg::InvalidHexEscape));
+        }
+
+        Ok(value as u8 as char)
+    }
+
+    /// `\u{N...}` after `\u`: 1 to 6 hex digits of valid code point
+    fn read_unicode_escape(&mut self, pos: Position) -> Result<char, MsgWithPos> {
+        if self.cur() != Some('{') {
+            return Err(self.escape_error(pos, Msg::InvalidUnicodeEscape));
+        }
+        self.read_char();
+
+        let mut value: u32 = 0;
+        let mut digits = 0;
+        while let Some(digit) = self.cur().and_then(|ch| ch.to_digit(16)) {
+            value = value * 16 + digit;
+            digits += 1;
+            self.read_char();
+            if digits > 6 {
+                return Err(self.escape_error(pos, Msg::InvalidUnicodeEscape));
+            }
+        }
+        if digits == 0 || self.cur() != Some('}') {
+            return Err(self.escape_error(pos, Msg::InvalidUnicodeEscape));
+        }
+        self.read_char();
+
+        std::char::from_u32(value).ok_or_else(|| self.escape_error(pos, Msg::InvalidUnicodeEscape))
+    }
+
+    fn escape_error(&self, pos: Position, msg: Msg) -> MsgWithPos {
+        MsgWithPos::new(
+            self.reader.path().to_string(),
+            self.reader.src.clone(),
+            pos,
+            msg,
+        )
+    }
+
     fn read_string(&mut self) -> Result<Token, MsgWithPos> {
         let pos = self.reader.pos();
         let mut value = String::new();
@@ -292,9 +346,11 @@ fn read_operator(&mut self) -> Result<Token, MsgWithPos> {
                 }
             }
             '$' => TokenKind::Dollar,
+            '@' => TokenKind::At,
 
             '^' => TokenKind::Caret,
             '~' => TokenKind::Tilde,
+            '?' => TokenKind::Question,
             ',' => TokenKind::Comma,
             ';' => TokenKind::Semicolon,
             ':' => {
@@ -312,8 +368,7 @@ fn read_operator(&mut self) -> Result<Token, MsgWithPos> {
                         self.read_char();
                         TokenKind::DotDotDot
                     } else {
-                        // TODO: ..=
-                        unimplemented!()
+                        TokenKind::DotDot
                     }
                 } else {
                     TokenKind::Dot
@@ -418,19 +473,26 @@ fn read_number(&mut self) -> Result<Token, MsgWithPos> {
                     IntBase::Bin
                 }
 
+                Some('o') => {
+                    self.read_char();
+                    self.read_char();
+
+                    IntBase::Oct
+                }
+
                 _ => IntBase::Dec,
             }
         } else {
             IntBase::Dec
         };
 
-        self.read_digits(&mut value, base);
+        self.read_digits(&mut value, base)?;
 
         if base == IntBase::Dec && self.cur() == Some('.') && is_digit(self.next()) {
             self.read_char();
             value.push('.');
 
-            self.read_digits(&mut value, IntBase::Dec);
+            self.read_digits(&mut value, IntBase::Dec)?;
 
             if self.cur() == Some('e') || self.cur() == Some('E') {
                 value.push(self.cur().unwrap());
@@ -441,7 +503,7 @@ fn read_number(&mut self) -> Result<Token, MsgWithPos> {
                     self.read_char();
                 }
 
-                self.read_digits(&mut value, IntBase::Dec);
+                self.read_digits(&mut value, IntBase::Dec)?;
             }
 
             let suffix = match self.cur() {
@@ -498,19 +560,69 @@ fn read_number(&mut self) -> Result<Token, MsgWithPos> {
                 suffix
             }
 
-            _ => IntSuffix::Int,
+            Some('i') | Some('u') if is_digit(self.next()) => self.read_width_suffix()?,
+
+            _ => return Ok(Token::new(TokenKind::LitInt(value, base, None), pos)),
         };
 
-        let ttype = TokenKind::LitInt(value, base, suffix);
+        let ttype = TokenKind::LitInt(value, base, Some(suffix));
         Ok(Token::new(ttype, pos))
     }
 
-    fn read_digits(&mut self, buffer: &mut String, base: IntBase) {
+    /// Reads explicit width suffix such as `u32` or `i64`, this form is accepted after any base
+    fn read_width_suffix(&mut self) -> Result<IntSuffix, MsgWithPos> {
+        let pos = self.reader.pos();
+        let mut suffix = String::new();
+
+        while is_identifier(self.cur()) {
+            suffix.push(self.cur().unwrap());
+            self.read_char();
+        }
+
+        match &suffix[..] {
+            "i8" => Ok(IntSuffix::Byte),
+            "i32" => Ok(IntSuffix::Int),
+            "i64" => Ok(IntSuffix::Long),
+            "u8" => Ok(IntSuffix::UByte),
+            "u32" => Ok(IntSuffix::UInt),
+            "u64" => Ok(IntSuffix::ULong),
+            _ => Err(MsgWithPos::new(
+                self.reader.path().to_string(),
+                self.reader.src.clone(),
+                pos,
+                Msg::InvalidNumberSuffix(suffix),
+            )),
+        }
+    }
+
+    /// Reads digits into `buffer` dropping `_` separators, separator is only valid between digits
+    fn read_digits(&mut self, buffer: &mut String, base: IntBase) -> Result<(), MsgWithPos> {
+        let mut prev = None;
         while is_digit_or_underscore(self.cur(), base) {
             let ch = self.cur().unwrap();
+            if ch == '_' && (prev.is_none() || prev == Some('_')) {
+                return Err(self.invalid_separator());
+            }
+            prev = Some(ch);
             self.read_char();
-            buffer.push(ch);
+            if ch != '_' {
+                buffer.push(ch);
+            }
         }
+        if prev == Some('_') {
+            return Err(self.invalid_separator());
+        }
+
+        Ok(())
+    }
+
+    fn invalid_separator(&self) -> MsgWithPos {
+        MsgWithPos::new(
+            self.reader.path().to_string(),
+            self.reader.src.clone(),
+            self.reader.pos(),
+            Msg::InvalidNumberSeparator,
+        )
     }
 
     fn read_char(&mut self) {
@@ -568,7 +680,7 @@ fn is_char_quote(ch: Option<char>) -> bool {
 }
 
 fn is_operator(ch: Option<char>) -> bool {
-    ch.map(|ch| "^+-*/%&|,=!~;:.()[]{}<>".contains(ch))
+    ch.map(|ch| "^+-*/%&|,=!~?;:.()[]{}<>@".contains(ch))
         .unwrap_or(false)
 }
 
@@ -605,6 +717,7 @@ fn keywords_in_map() -> HashMap<&'static str, TokenKind> {
     keywords.insert("link", TokenKind::Link);
     keywords.insert("import", TokenKind::Import);
     keywords.insert("loop", TokenKind::Loop);
+    keywords.insert("switch", TokenKind::Switch);
     keywords.insert("break", TokenKind::Break);
     keywords.insert("continue", TokenKind::Continue);
     keywords.insert("nextloop", TokenKind::NextLoop);
@@ -617,7 +730,10 @@ fn keywords_in_map() -> HashMap<&'static str, TokenKind> {
     keywords.insert("alias", TokenKind::Alias);
     keywords.insert("struct", TokenKind::Struct);
     keywords.insert("sizeof", TokenKind::SizeOf);
+    keywords.insert("alignof", TokenKind::AlignOf);
+    keywords.insert("offsetof", TokenKind::OffsetOf);
     keywords.insert("defer", TokenKind::Defer);
+    keywords.insert("delete", TokenKind::Delete);
     keywords.insert("lambda", TokenKind::Lambda);
     keywords.insert("as", TokenKind::As);
     keywords.insert("internal", TokenKind::Internal);
@@ -625,6 +741,7 @@ fn keywords_in_map() -> HashMap<&'static str, TokenKind> {
 
     keywords.insert("pub", TokenKind::Pub);
     keywords.insert("static", TokenKind::Static);
+    keywords.insert("thread_local", TokenKind::ThreadLocal);
 
     keywords.insert("const", TokenKind::Const);
 
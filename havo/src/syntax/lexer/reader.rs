@@ -101,6 +101,9 @@ pub fn next(&self) -> Option<char> {
     }
 }
 
+/// Columns tab advances to multiple of, unless changed with `set_tabwidth`
+pub const TAB_WIDTH: usize = 4;
+
 fn common_init(name: String, src: String) -> Reader {
     let mut reader = Reader {
         filename: name,
@@ -111,7 +114,7 @@ fn common_init(name: String, src: String) -> Reader {
         cur: Some('\n'),
         line: 0,
         col: 0,
-        tabwidth: 4,
+        tabwidth: TAB_WIDTH,
     };
 
     reader.advance();
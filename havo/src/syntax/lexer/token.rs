@@ -5,7 +5,8 @@ pub enum TokenKind {
     At,
     String(String),
     LitChar(char),
-    LitInt(String, IntBase, IntSuffix),
+    /// Suffix is `None` for literal written without one
+    LitInt(String, IntBase, Option<IntSuffix>),
     LitFloat(String, FloatSuffix),
     Identifier(String),
     BangIdent(String),
@@ -23,6 +24,7 @@ pub enum TokenKind {
     If,
     Else,
     Loop,
+    Switch,
     Break,
     Continue,
     NextLoop,
@@ -33,6 +35,7 @@ pub enum TokenKind {
     Null,
     Pub,
     Static,
+    ThreadLocal,
     Inline,
     Import,
     Extern,
@@ -44,8 +47,11 @@ pub enum TokenKind {
     Const,
     ConstExpr,
     SizeOf,
+    AlignOf,
+    OffsetOf,
     Underscore,
     Defer,
+    Delete,
     Lambda,
     New,
     // Operators
@@ -69,6 +75,7 @@ pub enum TokenKind {
     Sep, // ::
     Arrow,
     Tilde,
+    Question,
     BitOr,
     BitAnd,
     Caret,
@@ -88,6 +95,7 @@ pub enum TokenKind {
     NeEqEq,
     Is,
     As,
+    DotDot,
     DotDotDot,
     GtGt,
     GtGtGt,
@@ -98,7 +106,7 @@ impl TokenKind {
     pub fn name(&self) -> &str {
         match *self {
             TokenKind::String(_) => "string",
-            TokenKind::LitInt(_, _, suffix) => match suffix {
+            TokenKind::LitInt(_, _, suffix) => match suffix.unwrap_or(IntSuffix::Int) {
                 IntSuffix::Byte => "byte number",
                 IntSuffix::Int => "int number",
                 IntSuffix::Long => "long number",
@@ -134,6 +142,7 @@ pub fn name(&self) -> &str {
             TokenKind::If => "if",
             TokenKind::Else => "else",
             TokenKind::Loop => "loop",
+            TokenKind::Switch => "switch",
 
             TokenKind::Break => "break",
             TokenKind::Continue => "continue",
@@ -143,6 +152,7 @@ pub fn name(&self) -> &str {
             TokenKind::Null => "null",
             TokenKind::Pub => "pub",
             TokenKind::Static => "static",
+            TokenKind::ThreadLocal => "thread_local",
             TokenKind::Inline => "inline",
             TokenKind::Extern => "extern",
             TokenKind::Link => "link",
@@ -152,9 +162,12 @@ pub fn name(&self) -> &str {
             TokenKind::Struct => "struct",
             TokenKind::Const => "const",
             TokenKind::SizeOf => "sizeof",
+            TokenKind::AlignOf => "alignof",
+            TokenKind::OffsetOf => "offsetof",
             TokenKind::ConstExpr => "constexpr",
             TokenKind::Underscore => "_",
             TokenKind::Defer => "defer",
+            TokenKind::Delete => "delete",
 
             // Operators
             TokenKind::At => "@",
@@ -177,6 +190,8 @@ pub fn name(&self) -> &str {
             TokenKind::Sep => "::",
             TokenKind::Arrow => "=>",
             TokenKind::Tilde => "~",
+            TokenKind::Question => "?",
+            TokenKind::DotDot => "..",
             TokenKind::BitOr => "|",
             TokenKind::BitAnd => "&",
             TokenKind::Caret => "^",
@@ -231,7 +246,7 @@ pub fn is(&self, kind: TokenKind) -> bool {
     pub fn name(&self) -> String {
         match self.kind {
             TokenKind::LitInt(ref val, _, suffix) => {
-                let suffix = match suffix {
+                let suffix = match suffix.unwrap_or(IntSuffix::Int) {
                     IntSuffix::Byte => "B",
                     IntSuffix::Int => "",
                     IntSuffix::Long => "L",
@@ -260,6 +275,7 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd)]
 pub enum IntBase {
     Bin,
+    Oct,
     Dec,
     Hex,
 }
@@ -268,6 +284,7 @@ impl IntBase {
     pub fn num(self) -> u32 {
         match self {
             IntBase::Bin => 2,
+            IntBase::Oct => 8,
             IntBase::Dec => 10,
             IntBase::Hex => 16,
         }
@@ -284,6 +301,40 @@ pub enum IntSuffix {
     UByte,
 }
 
+impl IntSuffix {
+    /// Explicit width suffix the lexer accepts after literal of any base, empty for default `Int`
+    pub fn width_suffix(self) -> &'static str {
+        match self {
+            IntSuffix::Int => "",
+            IntSuffix::Long => "i64",
+            IntSuffix::Byte => "i8",
+            IntSuffix::ULong => "u64",
+            IntSuffix::UInt => "u32",
+            IntSuffix::UByte => "u8",
+        }
+    }
+
+    /// Suffix of literals of integer type named `name`
+    pub fn of_type(name: &str) -> Option<IntSuffix> {
+        Some(match name {
+            "i8" => IntSuffix::Byte,
+            "i32" => IntSuffix::Int,
+            "i64" => IntSuffix::Long,
+            "u8" => IntSuffix::UByte,
+            "u32" => IntSuffix::UInt,
+            "u64" => IntSuffix::ULong,
+            _ => return None,
+        })
+    }
+
+    pub fn is_unsigned(self) -> bool {
+        match self {
+            IntSuffix::ULong | IntSuffix::UInt | IntSuffix::UByte => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug, PartialOrd)]
 pub enum FloatSuffix {
     Float,
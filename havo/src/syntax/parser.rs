@@ -8,15 +8,29 @@
 };
 use crate::{
     err::{Msg, MsgWithPos},
+    optimize::const_eval::STATIC_ASSERT,
     *,
 };
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 pub struct Parser<'a> {
     lexer: Lexer,
     token: Token,
     pub ast: &'a mut File,
+    /// macros defined so far, invocations of them are expanded while reading tokens
+    macros: HashMap<Name, Macro>,
+    /// tokens produced by macro expansion, read before the rest of source
+    expanded: VecDeque<Token>,
+    /// set while reading macro definition, its body is kept unexpanded
+    in_macro_def: bool,
+    /// number of expansions so far, used to rename variables declared in macro bodies
+    expansions: usize,
+    /// labels of loops enclosing statement being parsed and whether loop is `loop` expression
+    /// (only those take `break` value), innermost last
+    labels: Vec<(Option<Name>, bool)>,
+    /// label written before loop that is parsed next
+    label: Option<Name>,
 }
 
 type ExprResult = Result<Box<Expr>, MsgWithPos>;
@@ -29,7 +43,17 @@ pub fn new(reader: Reader, ast: &'a mut File) -> Parser<'a> {
             Position::new(intern(&reader.filename), 1, 1),
         );
         let lexer = Lexer::new(reader);
-        Parser { lexer, token, ast }
+        Parser {
+            lexer,
+            token,
+            ast,
+            macros: HashMap::new(),
+            expanded: VecDeque::new(),
+            in_macro_def: false,
+            expansions: 0,
+            labels: vec![],
+            label: None,
+        }
     }
 
     fn generate_id(&self) -> NodeId {
@@ -50,15 +74,57 @@ pub fn parse_statement(&mut self) -> StmtResult {
                     kind: StmtKind::CompTime(stmt),
                 })
             }
-            TokenKind::Let | TokenKind::Var => self.parse_var(),
+            TokenKind::Let | TokenKind::Var | TokenKind::Const => self.parse_var(),
+            TokenKind::Static => {
+                let pos = self.advance_token()?.position;
+                if !self.token.is(TokenKind::Var) && !self.token.is(TokenKind::Let) {
+                    return Err(MsgWithPos::new(
+                        self.lexer.path().to_string(),
+                        self.src(),
+                        self.token.position,
+                        Msg::ExpectedToken(TokenKind::Var.name().into(), self.token.name()),
+                    ));
+                }
+                let var = self.parse_var()?;
+                Ok(box Stmt {
+                    id: self.generate_id(),
+                    pos,
+                    kind: StmtKind::Static(var),
+                })
+            }
+            TokenKind::Identifier(_) if self.peek_token()?.is(TokenKind::Colon) => {
+                self.parse_labeled_loop()
+            }
             TokenKind::LBrace => self.parse_block(),
             TokenKind::If => self.parse_if(),
             TokenKind::While => self.parse_while(),
             TokenKind::For => self.parse_for(),
-            TokenKind::Loop => self.parse_loop(),
+            TokenKind::Loop => self.parse_loop(false),
+            TokenKind::Switch => self.parse_switch(),
             TokenKind::Return => self.parse_return(),
             TokenKind::Break => self.parse_break(),
             TokenKind::Continue => self.parse_continue(),
+            TokenKind::Defer => {
+                let pos = self.advance_token()?.position;
+                let stmt = self.parse_statement()?;
+                Ok(box Stmt {
+                    id: self.generate_id(),
+                    pos,
+                    kind: StmtKind::Defer(stmt),
+                })
+            }
+            TokenKind::Delete => {
+                let pos = self.advance_token()?.position;
+                let ptr = self.parse_expression()?;
+                if self.token.is(TokenKind::Semicolon) {
+                    self.expect_semicolon()?;
+                }
+                Ok(box Stmt {
+                    id: self.generate_id(),
+                    pos,
+                    kind: StmtKind::Delete(ptr),
+                })
+            }
             TokenKind::Else => Err(MsgWithPos::new(
                 self.lexer.path().to_string(),
                 self.src(),
@@ -73,6 +139,7 @@ pub fn parse_statement(&mut self) -> StmtResult {
     fn parse_global(
         &mut self,
         modifiers: &HashSet<String>,
+        attributes: Vec<Attribute>,
         elements: &mut Vec<Elem>,
     ) -> Result<(), MsgWithPos> {
         let pos = self.token.position;
@@ -105,6 +172,8 @@ fn parse_global(
             expr,
             external: modifiers.contains("extern"),
             public: modifiers.contains("pub"),
+            thread_local: modifiers.contains("thread_local"),
+            attributes,
         };
 
         elements.push(Elem::Global(global));
@@ -121,6 +190,7 @@ fn parse_modifiers(&mut self) -> Result<HashSet<String>, MsgWithPos> {
                 TokenKind::Internal => "internal",
                 TokenKind::Pub => "pub",
                 TokenKind::Static => "static",
+                TokenKind::ThreadLocal => "thread_local",
                 //TokenKind::ConstExpr => "constant",
                 _ => {
                     break;
@@ -143,6 +213,46 @@ fn parse_modifiers(&mut self) -> Result<HashSet<String>, MsgWithPos> {
 
         Ok(modifiers)
     }
+    /// Parse attributes list: `@name` or `@name(arg,...)` where every argument is string, integer or identifier
+    fn parse_attributes(&mut self) -> Result<Vec<Attribute>, MsgWithPos> {
+        let mut attributes = vec![];
+        while self.token.is(TokenKind::At) {
+            let pos = self.advance_token()?.position;
+            // `@const` and `@inline` are keywords used as attribute names
+            let name = match self.token.kind {
+                TokenKind::Const | TokenKind::Inline => intern(self.advance_token()?.kind.name()),
+                _ => self.expect_identifier()?,
+            };
+            let mut args = vec![];
+            if self.token.is(TokenKind::LParen) {
+                self.advance_token()?;
+                while !self.token.is(TokenKind::RParen) && !self.token.is_eof() {
+                    let arg = match self.token.kind {
+                        TokenKind::String(_) => match self.parse_string()?.kind {
+                            ExprKind::Str(s) => AttributeArg::Str(s),
+                            _ => unreachable!(),
+                        },
+                        TokenKind::LitInt(..) => match self.parse_lit_int()?.kind {
+                            ExprKind::Int(i, _, _) => AttributeArg::Int(i),
+                            _ => unreachable!(),
+                        },
+                        _ => AttributeArg::Ident(self.expect_identifier()?),
+                    };
+                    args.push(arg);
+
+                    if !self.token.is(TokenKind::RParen) {
+                        self.expect_token(TokenKind::Comma)?;
+                    }
+                }
+                self.expect_token(TokenKind::RParen)?;
+            }
+
+            attributes.push(Attribute { pos, name, args });
+        }
+
+        Ok(attributes)
+    }
+
     fn init(&mut self) -> Result<(), MsgWithPos> {
         self.advance_token()?;
 
@@ -163,9 +273,34 @@ pub fn parse(&mut self) -> Result<(), MsgWithPos> {
         Ok(())
     }
 
+    /// Parse source that consists of single expression, e.g for tooling that type checks
+    /// expression typed by user
+    pub fn parse_standalone_expr(&mut self) -> ExprResult {
+        self.init()?;
+        let expr = self.parse_expression()?;
+        self.expect_token(TokenKind::End)?;
+
+        Ok(expr)
+    }
+
     pub fn parse_top_level_element(&mut self, elements: &mut Vec<Elem>) -> Result<(), MsgWithPos> {
+        let attributes = self.parse_attributes()?;
         let mut modifiers = self.parse_modifiers()?;
 
+        let attributes_allowed = match self.token.kind {
+            TokenKind::Fun | TokenKind::Struct | TokenKind::Union => true,
+            TokenKind::Let | TokenKind::Var => true,
+            _ => false,
+        };
+        if !attributes.is_empty() && !attributes_allowed {
+            return Err(MsgWithPos::new(
+                self.lexer.path().to_string(),
+                self.src(),
+                attributes[0].pos,
+                Msg::MisplacedAttribute(str(attributes[0].name).to_string()),
+            ));
+        }
+
         match &self.token.kind {
             TokenKind::Alias => {
                 self.advance_token()?;
@@ -189,21 +324,29 @@ pub fn parse_top_level_element(&mut self, elements: &mut Vec<Elem>) -> Result<()
                 elements.push(Elem::Link(intern(&string)));
             }
             TokenKind::Fun => {
-                let fun = self.parse_function(modifiers)?;
+                let mut fun = self.parse_function(modifiers)?;
+                fun.attributes = attributes;
                 elements.push(Elem::Func(fun));
             }
             TokenKind::Union => {
                 let mut struc = self.parse_struct(true)?;
                 struc.public = modifiers.contains("pub");
+                struc.attributes = attributes;
                 elements.push(Elem::Struct(struc))
             }
+            TokenKind::Enum => {
+                let mut enum_ = self.parse_enum()?;
+                enum_.public = modifiers.contains("pub");
+                elements.push(Elem::Enum(enum_))
+            }
             TokenKind::Struct => {
                 let mut struc = self.parse_struct(false)?;
                 struc.public = modifiers.contains("pub");
+                struc.attributes = attributes;
                 elements.push(Elem::Struct(struc))
             }
             TokenKind::Let | TokenKind::Var => {
-                self.parse_global(&modifiers, elements)?;
+                self.parse_global(&modifiers, attributes, elements)?;
             }
             TokenKind::ConstExpr => {
                 let pos = self.advance_token()?.position;
@@ -223,8 +366,16 @@ pub fn parse_top_level_element(&mut self, elements: &mut Vec<Elem>) -> Result<()
                     })
                 }
             }
+            TokenKind::Identifier(name) if name == STATIC_ASSERT => {
+                let call = self.parse_expression()?;
+                if self.token.is(TokenKind::Semicolon) {
+                    self.expect_semicolon()?;
+                }
+                elements.push(Elem::StaticAssert(call));
+            }
             TokenKind::Macro => {
-                elements.push(Elem::Macro(self.parse_macro()?));
+                let mac = self.parse_macro()?;
+                elements.push(Elem::Macro(mac));
             }
             TokenKind::Const => {
                 self.advance_token()?;
@@ -282,11 +433,13 @@ fn parse_struct(&mut self, union: bool) -> Result<Struct, MsgWithPos> {
             name: ident,
             public: false,
             pos,
+            attributes: vec![],
             fields,
         })
     }
 
     fn parse_struct_field(&mut self) -> Result<StructField, MsgWithPos> {
+        let attributes = self.parse_attributes()?;
         let pos = self.token.position;
         let ident = self.expect_identifier()?;
 
@@ -297,6 +450,7 @@ fn parse_struct_field(&mut self) -> Result<StructField, MsgWithPos> {
             id: self.generate_id(),
             name: ident,
             pos,
+            attributes,
             data_type: ty,
         })
     }
@@ -374,12 +528,12 @@ fn parse_block(&mut self) -> StmtResult {
     }
 
     fn parse_var(&mut self) -> StmtResult {
-        let reassignable = if self.token.is(TokenKind::Let) {
+        let reassignable = if self.token.is(TokenKind::Let) || self.token.is(TokenKind::Const) {
             false
         } else if self.token.is(TokenKind::Var) {
             true
         } else {
-            panic!("let or var expected")
+            panic!("let, const or var expected")
         };
 
         let pos = self.advance_token()?.position;
@@ -418,10 +572,204 @@ fn parse_var_type(&mut self) -> Result<Option<Type>, MsgWithPos> {
     }
 
     fn advance_token(&mut self) -> Result<Token, MsgWithPos> {
-        let tok = self.lexer.read_token()?;
+        let mut tok = self.read_raw_token()?;
+        while let TokenKind::BangIdent(name) = &tok.kind {
+            if self.in_macro_def {
+                break;
+            }
+            let name = intern(name);
+            self.expand_macro(name, tok.position)?;
+            tok = self.read_raw_token()?;
+        }
 
         Ok(mem::replace(&mut self.token, tok))
     }
+
+    /// Token after current one, it's still read next by `advance_token`
+    fn peek_token(&mut self) -> Result<Token, MsgWithPos> {
+        let tok = self.read_raw_token()?;
+        self.expanded.push_front(tok.clone());
+        Ok(tok)
+    }
+
+    fn read_raw_token(&mut self) -> Result<Token, MsgWithPos> {
+        match self.expanded.pop_front() {
+            Some(tok) => Ok(tok),
+            None => self.lexer.read_token(),
+        }
+    }
+
+    /// Expand invocation of macro `name` whose `name!` token is at `pos`, resulting tokens are
+    /// read next. `stringify!` and `concat_idents!` are builtin.
+    fn expand_macro(&mut self, name: Name, pos: Position) -> Result<(), MsgWithPos> {
+        let args = self.read_macro_args()?;
+        let tokens = match str(name).as_str() {
+            "stringify" => {
+                let text = args
+                    .iter()
+                    .map(|arg| stringify_tokens(arg))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                vec![Token::new(TokenKind::String(text), pos)]
+            }
+            "concat_idents" => {
+                let mut ident = String::new();
+                for arg in args.iter() {
+                    match &arg[..] {
+                        [Token {
+                            kind: TokenKind::Identifier(part),
+                            ..
+                        }] => ident.push_str(part),
+                        _ => {
+                            return Err(MsgWithPos::new(
+                                self.lexer.path().to_string(),
+                                self.src(),
+                                pos,
+                                Msg::ConcatIdentsExpectsIdents,
+                            ))
+                        }
+                    }
+                }
+                vec![Token::new(TokenKind::Identifier(ident), pos)]
+            }
+            _ => self.substitute_macro(name, pos, args)?,
+        };
+
+        for tok in tokens.into_iter().rev() {
+            self.expanded.push_front(tok);
+        }
+        Ok(())
+    }
+
+    /// Read `(arg, ...)` of macro invocation, arguments are unexpanded token lists split by
+    /// commas that aren't nested in brackets
+    fn read_macro_args(&mut self) -> Result<Vec<Vec<Token>>, MsgWithPos> {
+        let tok = self.read_raw_token()?;
+        if tok.kind != TokenKind::LParen {
+            return Err(MsgWithPos::new(
+                self.lexer.path().to_string(),
+                self.src(),
+                tok.position,
+                Msg::ExpectedToken(TokenKind::LParen.name().into(), tok.name()),
+            ));
+        }
+
+        let mut args = vec![];
+        let mut arg = vec![];
+        let mut depth = 0;
+        loop {
+            let tok = self.read_raw_token()?;
+            match &tok.kind {
+                TokenKind::LParen | TokenKind::LBrace | TokenKind::LBracket => depth += 1,
+                TokenKind::RParen | TokenKind::RBrace | TokenKind::RBracket if depth > 0 => {
+                    depth -= 1
+                }
+                TokenKind::RParen => {
+                    if !arg.is_empty() || !args.is_empty() {
+                        args.push(arg);
+                    }
+                    return Ok(args);
+                }
+                TokenKind::Comma if depth == 0 => {
+                    args.push(mem::replace(&mut arg, vec![]));
+                    continue;
+                }
+                TokenKind::End => {
+                    return Err(MsgWithPos::new(
+                        self.lexer.path().to_string(),
+                        self.src(),
+                        tok.position,
+                        Msg::ExpectedToken(TokenKind::RParen.name().into(), tok.name()),
+                    ))
+                }
+                _ => (),
+            }
+            arg.push(tok);
+        }
+    }
+
+    /// Substitute `args` into body of user defined macro `name`.
+    ///
+    /// Variables declared by macro body itself are renamed for each expansion, so they never
+    /// clash with identifiers passed in arguments or used around invocation.
+    fn substitute_macro(
+        &mut self,
+        name: Name,
+        pos: Position,
+        args: Vec<Vec<Token>>,
+    ) -> Result<Vec<Token>, MsgWithPos> {
+        let mac = match self.macros.get(&name) {
+            Some(mac) => mac.clone(),
+            None => {
+                return Err(MsgWithPos::new(
+                    self.lexer.path().to_string(),
+                    self.src(),
+                    pos,
+                    Msg::UnknownMacro(str(name).to_string()),
+                ))
+            }
+        };
+        let variadic = mac.body.iter().any(|tok| match tok {
+            MacroToken::VarArgs => true,
+            _ => false,
+        });
+        if args.len() < mac.args.len() || (args.len() > mac.args.len() && !variadic) {
+            return Err(MsgWithPos::new(
+                self.lexer.path().to_string(),
+                self.src(),
+                pos,
+                Msg::MacroArgsCount(str(name).to_string(), mac.args.len(), args.len()),
+            ));
+        }
+        self.expansions += 1;
+
+        // second element is true for tokens that come from macro body
+        let mut tokens: Vec<(Token, bool)> = vec![];
+        for tok in mac.body.iter() {
+            match tok {
+                MacroToken::Token(tok) => tokens.push((tok.clone(), true)),
+                MacroToken::Var(var) => {
+                    let idx = mac.args.iter().position(|arg| arg == var).unwrap();
+                    tokens.extend(args[idx].iter().map(|tok| (tok.clone(), false)));
+                }
+                MacroToken::VarArgs => {
+                    for (i, arg) in args[mac.args.len()..].iter().enumerate() {
+                        if i != 0 {
+                            tokens.push((Token::new(TokenKind::Comma, pos), false));
+                        }
+                        tokens.extend(arg.iter().map(|tok| (tok.clone(), false)));
+                    }
+                }
+            }
+        }
+
+        let mut declared = HashSet::new();
+        for pair in tokens.windows(2) {
+            if let [(decl, true), (ident, true)] = pair {
+                let is_decl = match decl.kind {
+                    TokenKind::Var | TokenKind::Let | TokenKind::Const => true,
+                    _ => false,
+                };
+                if let TokenKind::Identifier(name) = &ident.kind {
+                    if is_decl {
+                        declared.insert(name.clone());
+                    }
+                }
+            }
+        }
+        let expansion = self.expansions;
+        Ok(tokens
+            .into_iter()
+            .map(|(mut tok, from_body)| {
+                if let TokenKind::Identifier(name) = &mut tok.kind {
+                    if from_body && declared.contains(name) {
+                        *name = format!("{}__{}", name, expansion);
+                    }
+                }
+                tok
+            })
+            .collect())
+    }
     fn expect_semicolon(&mut self) -> Result<Token, MsgWithPos> {
         self.expect_token(TokenKind::Semicolon)
     }
@@ -458,6 +806,37 @@ fn expect_identifier(&mut self) -> Result<Name, MsgWithPos> {
         }
     }
 
+    /// Parse array literal `[a, b, c]`, its type is inferred by semantic check
+    fn parse_array_literal(&mut self) -> ExprResult {
+        let pos = self.expect_token(TokenKind::LBracket)?.position;
+        let exprs = self.parse_comma_list(TokenKind::RBracket, |p| p.parse_expression())?;
+
+        Ok(Box::new(Expr {
+            id: self.generate_id(),
+            pos,
+            kind: ExprKind::Array(Box::new(Type::Void(pos)), exprs),
+        }))
+    }
+
+    fn parse_new(&mut self) -> ExprResult {
+        let pos = self.expect_token(TokenKind::New)?.position;
+        let ty = self.parse_type()?;
+        let init = if self.token.is(TokenKind::LParen) {
+            self.advance_token()?;
+            let init = self.parse_expression()?;
+            self.expect_token(TokenKind::RParen)?;
+            Some(init)
+        } else {
+            None
+        };
+
+        Ok(Box::new(Expr {
+            id: self.generate_id(),
+            pos,
+            kind: ExprKind::New(Box::new(ty), init),
+        }))
+    }
+
     fn parse_null(&mut self) -> ExprResult {
         let tok = self.advance_token()?;
         Ok(Box::new(Expr {
@@ -527,11 +906,72 @@ fn parse_lit_char(&mut self) -> ExprResult {
         }
     }
 
+    fn parse_enum(&mut self) -> Result<Enum, MsgWithPos> {
+        let pos = self.expect_token(TokenKind::Enum)?.position;
+        let name = self.expect_identifier()?;
+        let repr = if self.token.is(TokenKind::Colon) {
+            self.advance_token()?;
+            self.parse_type()?
+        } else {
+            Type::create_basic(self.generate_id(), pos, intern("i32"))
+        };
+
+        self.expect_token(TokenKind::LBrace)?;
+        let mut next = 0i64;
+        let variants = self.parse_comma_list(TokenKind::RBrace, |p| {
+            let pos = p.token.position;
+            let name = p.expect_identifier()?;
+            let value = if p.token.is(TokenKind::Eq) {
+                p.advance_token()?;
+                p.parse_discriminant()?
+            } else {
+                next
+            };
+            next = value.wrapping_add(1);
+
+            Ok(EnumVariant { pos, name, value })
+        })?;
+
+        Ok(Enum {
+            id: self.generate_id(),
+            pos,
+            public: false,
+            name,
+            repr,
+            variants,
+        })
+    }
+
+    /// Explicit value of enum variant: integer literal, optionally negated
+    fn parse_discriminant(&mut self) -> Result<i64, MsgWithPos> {
+        let negative = self.token.is(TokenKind::Sub);
+        if negative {
+            self.advance_token()?;
+        }
+        match &self.token.kind {
+            TokenKind::LitInt(..) => match self.parse_lit_int()?.kind {
+                ExprKind::Int(i, _, _) if negative => Ok(i.wrapping_neg()),
+                ExprKind::Int(i, _, _) => Ok(i),
+                _ => unreachable!(),
+            },
+            _ => Err(MsgWithPos::new(
+                self.lexer.path().to_string(),
+                self.src(),
+                self.token.position,
+                Msg::InvalidEnumDiscriminant(self.token.name()),
+            )),
+        }
+    }
+
     fn parse_lit_int(&mut self) -> ExprResult {
         let tok = self.advance_token()?;
         let pos = tok.position;
 
         if let TokenKind::LitInt(value, base, suffix) = tok.kind {
+            if suffix.is_none() {
+                self.ast.unsuffixed_ints.insert(pos);
+            }
+            let suffix = suffix.unwrap_or(IntSuffix::Int);
             let filtered = value.chars().filter(|&ch| ch != '_').collect::<String>();
             let parsed = u64::from_str_radix(&filtered, base.num());
 
@@ -584,11 +1024,29 @@ fn parse_expression_statement(&mut self) -> StmtResult {
 
     fn parse_expression(&mut self) -> ExprResult {
         let opts = ExprParsingOpts::new();
-        self.parse_binary(0, &opts)
+        self.parse_ternary(&opts)
     }
 
     fn parse_expression_with_opts(&mut self, opts: &ExprParsingOpts) -> ExprResult {
-        self.parse_binary(0, opts)
+        self.parse_ternary(opts)
+    }
+
+    /// `cond ? then : otherwise`, binds looser than any binary operator except assignment
+    fn parse_ternary(&mut self, opts: &ExprParsingOpts) -> ExprResult {
+        let cond = self.parse_binary(0, opts)?;
+        if !self.token.is(TokenKind::Question) {
+            return Ok(cond);
+        }
+        let tok = self.advance_token()?;
+        let then = self.parse_ternary(opts)?;
+        self.expect_token(TokenKind::Colon)?;
+        let otherwise = self.parse_ternary(opts)?;
+
+        Ok(Box::new(Expr {
+            pos: tok.position,
+            id: self.generate_id(),
+            kind: ExprKind::Ternary(cond, then, otherwise),
+        }))
     }
 
     fn parse_call(&mut self, pos: Position, object: Option<Box<Expr>>, path: Path) -> ExprResult {
@@ -613,12 +1071,45 @@ fn parse_identifier_or_call(&mut self, opts: &ExprParsingOpts) -> ExprResult {
             path.push(ident);
         }
 
+        // `Result<T, E> { ... }`
+        if path.len() == 1
+            && &str(path[0]).to_string() == "Result"
+            && self.token.is(TokenKind::Lt)
+            && opts.parse_struct_lit
+        {
+            let name = self.parse_result_args(pos)?;
+            return self.parse_lit_struct(pos, Path::new(name));
+        }
+
+        // `va_arg(T)` takes type instead of value
+        if path.len() == 1
+            && &str(path[0]).to_string() == "va_arg"
+            && self.token.is(TokenKind::LParen)
+        {
+            self.advance_token()?;
+            let ty = self.parse_type()?;
+            self.expect_token(TokenKind::RParen)?;
+            return Ok(Box::new(Expr {
+                id: self.generate_id(),
+                pos,
+                kind: ExprKind::VaArg(Box::new(ty)),
+            }));
+        }
+
         // is this a function call?
         if self.token.is(TokenKind::LParen) {
             self.parse_call(pos, None, Path { path })
         } else if self.token.is(TokenKind::LBrace) && opts.parse_struct_lit {
             self.parse_lit_struct(pos, Path { path })
 
+        // `Enum::Variant`
+        } else if path.len() == 2 {
+            Ok(Box::new(Expr {
+                id: self.generate_id(),
+                pos,
+                kind: ExprKind::Variant(path[0], path[1]),
+            }))
+
         // if not we have a simple identifier
         } else {
             assert_eq!(1, path.len());
@@ -631,6 +1122,26 @@ fn parse_identifier_or_call(&mut self, opts: &ExprParsingOpts) -> ExprResult {
         }
     }
 
+    /// Type arguments of `Result<T, E>`, struct of this instantiation is declared on first use
+    fn parse_result_args(&mut self, pos: Position) -> Result<Name, MsgWithPos> {
+        self.expect_token(TokenKind::Lt)?;
+        let value = self.parse_type()?;
+        self.expect_token(TokenKind::Comma)?;
+        let error = self.parse_type()?;
+        self.expect_token(TokenKind::Gt)?;
+
+        let result = Struct::result(pos, value, error);
+        let name = result.name;
+        let declared = self.ast.elems.iter().any(|elem| match elem {
+            Elem::Struct(s) => s.name == name,
+            _ => false,
+        });
+        if !declared {
+            self.ast.elems.push(Elem::Struct(result));
+        }
+        Ok(name)
+    }
+
     fn parse_lit_struct(&mut self, pos: Position, path: Path) -> ExprResult {
         self.expect_token(TokenKind::LBrace)?;
         let args = self.parse_comma_list(TokenKind::RBrace, |p| p.parse_lit_struct_arg())?;
@@ -701,6 +1212,11 @@ fn parse_binary(&mut self, precedence: u32, opts: &ExprParsingOpts) -> ExprResul
                     Box::new(expr)
                 }
 
+                // value assigned is whole expression on the right, `x = c ? a : b` included
+                TokenKind::Eq => {
+                    let right = self.parse_ternary(opts)?;
+                    self.create_binary(tok, left, right)
+                }
                 _ => {
                     let right = self.parse_binary(right_precedence, opts)?;
                     self.create_binary(tok, left, right)
@@ -709,8 +1225,35 @@ fn parse_binary(&mut self, precedence: u32, opts: &ExprParsingOpts) -> ExprResul
         }
     }
 
+    /// `{ stmts; tail }`, semicolons are optional so last statement is taken as value
+    fn parse_block_expr(&mut self) -> ExprResult {
+        let block = self.parse_block()?;
+        let mut stmts = match block.kind {
+            StmtKind::Block(stmts) => stmts,
+            _ => unreachable!(),
+        };
+        let tail = match stmts.pop().map(|stmt| stmt.kind) {
+            Some(StmtKind::Expr(tail)) => tail,
+            _ => {
+                return Err(MsgWithPos::new(
+                    self.lexer.path().to_string(),
+                    self.src(),
+                    block.pos,
+                    Msg::BlockValueExpected,
+                ))
+            }
+        };
+
+        Ok(Box::new(Expr {
+            pos: block.pos,
+            id: self.generate_id(),
+            kind: ExprKind::Block(stmts, tail),
+        }))
+    }
+
+    /// `sizeof(T)` or `alignof(T)`
     fn parse_sizeof(&mut self) -> ExprResult {
-        let tok = self.expect_token(TokenKind::SizeOf)?;
+        let tok = self.advance_token()?;
         let expect_rparen = if self.token.is(TokenKind::LParen) {
             self.advance_token()?;
             true
@@ -726,7 +1269,27 @@ fn parse_sizeof(&mut self) -> ExprResult {
         Ok(Box::new(Expr {
             pos: tok.position,
             id: self.generate_id(),
-            kind: ExprKind::SizeOf(Box::new(ty)),
+            kind: if tok.is(TokenKind::AlignOf) {
+                ExprKind::AlignOf(Box::new(ty))
+            } else {
+                ExprKind::SizeOf(Box::new(ty))
+            },
+        }))
+    }
+
+    /// `offsetof(S, field)`
+    fn parse_offsetof(&mut self) -> ExprResult {
+        let pos = self.expect_token(TokenKind::OffsetOf)?.position;
+        self.expect_token(TokenKind::LParen)?;
+        let structure = self.expect_identifier()?;
+        self.expect_token(TokenKind::Comma)?;
+        let field = self.expect_identifier()?;
+        self.expect_token(TokenKind::RParen)?;
+
+        Ok(Box::new(Expr {
+            pos,
+            id: self.generate_id(),
+            kind: ExprKind::OffsetOf(structure, field),
         }))
     }
 
@@ -750,12 +1313,29 @@ fn parse_primary(&mut self, opts: &ExprParsingOpts) -> ExprResult {
                 TokenKind::LBracket => {
                     let tok = self.advance_token()?;
                     let index = self.parse_expression()?;
+                    let kind = if self.token.is(TokenKind::DotDot) {
+                        self.advance_token()?;
+                        let end = self.parse_expression()?;
+                        ExprKind::Slice(left, index, end)
+                    } else {
+                        ExprKind::ArrayIdx(left, index)
+                    };
                     self.expect_token(TokenKind::RBracket)?;
 
                     Box::new(Expr {
                         pos: tok.position,
                         id: self.generate_id(),
-                        kind: ExprKind::ArrayIdx(left, index),
+                        kind,
+                    })
+                }
+                // `?` before operand is left to conditional expression
+                TokenKind::Question if !starts_expr(&self.peek_token()?.kind) => {
+                    let tok = self.advance_token()?;
+
+                    Box::new(Expr {
+                        pos: tok.position,
+                        id: self.generate_id(),
+                        kind: ExprKind::Try(left),
                     })
                 }
 
@@ -813,6 +1393,7 @@ macro_rules! gen_t_tree {
     }
 
     fn parse_macro(&mut self) -> Result<Macro, MsgWithPos> {
+        self.in_macro_def = true;
         let pos = self.expect_token(TokenKind::Macro)?.position;
 
         let name = match &self.advance_token()?.kind {
@@ -834,21 +1415,22 @@ fn parse_macro(&mut self) -> Result<Macro, MsgWithPos> {
         self.expect_token(TokenKind::LBrace)?;
 
         let mut body: Vec<MacroToken> = vec![];
-        loop {
-            if self.token.is(TokenKind::RBrace) {
-                self.advance_token()?;
-                break;
-            }
+        while !self.token.is(TokenKind::RBrace) {
             body.extend(self.eat_macro_tree(&args_map)?);
         }
 
-        Ok(Macro {
+        let mac = Macro {
             name,
             id: self.generate_id(),
             pos,
             args,
             body,
-        })
+        };
+        // token after `}` may already invoke the macro, so it's registered before reading it
+        self.macros.insert(name, mac.clone());
+        self.in_macro_def = false;
+        self.advance_token()?;
+        Ok(mac)
     }
 
     fn parse_function(&mut self, modifiers: HashSet<String>) -> Result<Function, MsgWithPos> {
@@ -866,6 +1448,12 @@ fn parse_function(&mut self, modifiers: HashSet<String>) -> Result<Function, Msg
             None
         };
         let ident = self.expect_identifier()?;
+        let type_params = if self.token.is(TokenKind::Lt) {
+            self.advance_token()?;
+            self.parse_comma_list(TokenKind::Gt, |p| p.expect_identifier())?
+        } else {
+            vec![]
+        };
         self.expect_token(TokenKind::LParen)?;
 
         /*let params = self.parse_comma_list(TokenKind::RParen, |p| {
@@ -938,6 +1526,7 @@ fn parse_function(&mut self, modifiers: HashSet<String>) -> Result<Function, Msg
             attributes: Vec::new(),
             this: this_,
             ret: Box::new(ty),
+            type_params,
             params,
             variadic,
             body,
@@ -982,8 +1571,107 @@ fn parse_if(&mut self) -> StmtResult {
         // else_block)))
     }
 
+    /// `switch value { case A, B { .. } case C { .. } else { .. } }` runs block of first case
+    /// one of whose values equals `value`, or `else` block if none does. There's no fallthrough.
+    /// Lowered to block that stores `value` to temporary and `if` chain comparing it.
+    fn parse_switch(&mut self) -> StmtResult {
+        let pos = self.expect_token(TokenKind::Switch)?.position;
+        let mut opts = ExprParsingOpts::new();
+        opts.parse_struct_lit(false);
+        let value = self.parse_expression_with_opts(&opts)?;
+        let tmp = intern(&format!("_switch_{}_", self.generate_id().0));
+
+        self.expect_token(TokenKind::LBrace)?;
+        let mut cases = vec![];
+        let mut default = None;
+        while !self.token.is(TokenKind::RBrace) && !self.token.is_eof() {
+            if default.is_none() && self.token.is(TokenKind::Else) {
+                self.advance_token()?;
+                default = Some(self.parse_block()?);
+                continue;
+            }
+            match &self.token.kind {
+                TokenKind::Identifier(case) if default.is_none() && case == "case" => (),
+                _ => {
+                    return Err(MsgWithPos::new(
+                        self.lexer.path().to_string(),
+                        self.src(),
+                        self.token.position,
+                        Msg::ExpectedToken("case".into(), self.token.name()),
+                    ))
+                }
+            }
+            self.advance_token()?;
+            let mut cond: Option<Box<Expr>> = None;
+            loop {
+                let case = self.parse_expression_with_opts(&opts)?;
+                let eq = box Expr {
+                    id: self.generate_id(),
+                    pos: case.pos,
+                    kind: ExprKind::Binary(
+                        "==".into(),
+                        box Expr {
+                            id: self.generate_id(),
+                            pos: case.pos,
+                            kind: ExprKind::Ident(tmp),
+                        },
+                        case,
+                    ),
+                };
+                cond = Some(match cond {
+                    Some(prev) => box Expr {
+                        id: self.generate_id(),
+                        pos: eq.pos,
+                        kind: ExprKind::Binary("||".into(), prev, eq),
+                    },
+                    None => eq,
+                });
+                if !self.token.is(TokenKind::Comma) {
+                    break;
+                }
+                self.advance_token()?;
+            }
+            cases.push((cond.unwrap(), self.parse_block()?));
+        }
+        self.expect_token(TokenKind::RBrace)?;
+
+        let mut chain = default;
+        for (cond, block) in cases.into_iter().rev() {
+            let pos = cond.pos;
+            // `else if` is parsed as `else` block with single `if` in it
+            let otherwise = chain.map(|stmt| {
+                if stmt.kind.is_if() {
+                    box Stmt {
+                        id: self.generate_id(),
+                        pos: stmt.pos,
+                        kind: StmtKind::Block(vec![stmt]),
+                    }
+                } else {
+                    stmt
+                }
+            });
+            chain = Some(box Stmt {
+                id: self.generate_id(),
+                pos,
+                kind: StmtKind::If(cond, block, otherwise),
+            });
+        }
+        let mut stmts = vec![box Stmt {
+            id: self.generate_id(),
+            pos,
+            kind: StmtKind::Var(tmp, false, None, Some(value)),
+        }];
+        stmts.extend(chain);
+        Ok(box Stmt {
+            id: self.generate_id(),
+            pos,
+            kind: StmtKind::Block(stmts),
+        })
+    }
+
     fn parse_for(&mut self) -> StmtResult {
         let pos = self.expect_token(TokenKind::For)?.position;
+        let label = self.label.take();
 
         let mut opts = ExprParsingOpts::new();
         opts.parse_struct_lit(false);
@@ -993,7 +1681,9 @@ fn parse_for(&mut self) -> StmtResult {
         self.expect_token(TokenKind::Comma)?;
         let then = self.parse_expression()?;
 
+        self.labels.push((label, false));
         let body = self.parse_statement()?;
+        self.labels.pop();
 
         Ok(Box::new(Stmt {
             id: self.generate_id(),
@@ -1004,12 +1694,15 @@ fn parse_for(&mut self) -> StmtResult {
 
     fn parse_while(&mut self) -> StmtResult {
         let pos = self.expect_token(TokenKind::While)?.position;
+        let label = self.label.take();
 
         let mut opts = ExprParsingOpts::new();
         opts.parse_struct_lit(false);
         let expr = self.parse_expression_with_opts(&opts)?;
 
+        self.labels.push((label, false));
         let block = self.parse_block()?;
+        self.labels.pop();
 
         Ok(Box::new(Stmt {
             id: self.generate_id(),
@@ -1018,9 +1711,12 @@ fn parse_while(&mut self) -> StmtResult {
         }))
     }
 
-    fn parse_loop(&mut self) -> StmtResult {
+    fn parse_loop(&mut self, is_expr: bool) -> StmtResult {
         let pos = self.expect_token(TokenKind::Loop)?.position;
+        let label = self.label.take();
+        self.labels.push((label, is_expr));
         let block = self.parse_block()?;
+        self.labels.pop();
 
         Ok(Box::new(Stmt {
             id: self.generate_id(),
@@ -1029,8 +1725,72 @@ fn parse_loop(&mut self) -> StmtResult {
         }))
     }
 
+    /// `label: while ...`, `label: for ...` or `label: loop ...`
+    fn parse_labeled_loop(&mut self) -> StmtResult {
+        let label = self.expect_identifier()?;
+        self.expect_token(TokenKind::Colon)?;
+        match self.token.kind {
+            TokenKind::While | TokenKind::For | TokenKind::Loop => (),
+            _ => {
+                return Err(MsgWithPos::new(
+                    self.lexer.path().to_string(),
+                    self.src(),
+                    self.token.position,
+                    Msg::ExpectedToken("loop".into(), self.token.name()),
+                ))
+            }
+        }
+        self.label = Some(label);
+        self.parse_statement()
+    }
+
+    /// Number of loops `break` or `continue` leaves, written as positive integer or label of
+    /// enclosing loop. 1 if neither is given. Identifier that isn't label is value of `break`
+    /// only when innermost loop is `loop` expression, otherwise it's reported as unknown label.
+    fn parse_loop_depth(&mut self) -> Result<usize, MsgWithPos> {
+        let depth = match &self.token.kind {
+            TokenKind::LitInt(lit, _, _) => match lit.parse::<usize>() {
+                Ok(depth) if depth != 0 => depth,
+                _ => {
+                    return Err(MsgWithPos::new(
+                        self.lexer.path().to_string(),
+                        self.src(),
+                        self.token.position,
+                        Msg::InvalidBreakDepth,
+                    ))
+                }
+            },
+            TokenKind::Identifier(ident) => {
+                let name = intern(ident);
+                match self.labels.iter().rposition(|(label, _)| *label == Some(name)) {
+                    Some(idx) => self.labels.len() - idx,
+                    None if self.labels.last().map_or(false, |(_, is_expr)| *is_expr) => {
+                        return Ok(1)
+                    }
+                    None => {
+                        return Err(MsgWithPos::new(
+                            self.lexer.path().to_string(),
+                            self.src(),
+                            self.token.position,
+                            Msg::UnknownLabel(ident.clone()),
+                        ))
+                    }
+                }
+            }
+            _ => return Ok(1),
+        };
+        self.advance_token()?;
+        Ok(depth)
+    }
+
     fn parse_break(&mut self) -> StmtResult {
         let pos = self.expect_token(TokenKind::Break)?.position;
+        let depth = self.parse_loop_depth()?;
+        let value = if self.token.is(TokenKind::Semicolon) || self.token.is(TokenKind::RBrace) {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
         if self.token.is(TokenKind::Semicolon) {
             self.expect_semicolon()?;
         }
@@ -1038,12 +1798,21 @@ fn parse_break(&mut self) -> StmtResult {
         Ok(Box::new(Stmt {
             id: self.generate_id(),
             pos,
-            kind: StmtKind::Break,
+            kind: StmtKind::Break(depth, value),
         }))
     }
 
     fn parse_continue(&mut self) -> StmtResult {
         let pos = self.expect_token(TokenKind::Continue)?.position;
+        let depth = self.parse_loop_depth()?;
+        if let TokenKind::Identifier(name) = &self.token.kind {
+            return Err(MsgWithPos::new(
+                self.lexer.path().to_string(),
+                self.src(),
+                self.token.position,
+                Msg::UnknownLabel(name.clone()),
+            ));
+        }
         if self.token.is(TokenKind::Semicolon) {
             self.expect_semicolon()?;
         }
@@ -1051,7 +1820,7 @@ fn parse_continue(&mut self) -> StmtResult {
         Ok(Box::new(Stmt {
             id: self.generate_id(),
             pos,
-            kind: StmtKind::Continue,
+            kind: StmtKind::Continue(depth),
         }))
     }
 
@@ -1117,6 +1886,12 @@ fn parse_type(&mut self) -> Result<Type, MsgWithPos> {
                 if &str(name).to_string() == "void" {
                     return Ok(Type::Void(pos));
                 }
+                let name = if &str(name).to_string() == "Result" && self.token.is(TokenKind::Lt)
+                {
+                    self.parse_result_args(pos)?
+                } else {
+                    name
+                };
 
                 Type::create_basic(self.generate_id(), pos, name)
             }
@@ -1128,6 +1903,14 @@ fn parse_type(&mut self) -> Result<Type, MsgWithPos> {
                 Type::create_ptr(self.generate_id(), pos, Box::new(subty))
             }
 
+            // `[]T`, same as `T[]`
+            TokenKind::LBracket => {
+                let pos = self.advance_token()?.position;
+                self.expect_token(TokenKind::RBracket)?;
+                let subty = self.parse_type()?;
+                return Ok(Type::create_array(self.generate_id(), pos, box subty, None));
+            }
+
             TokenKind::LParen => {
                 let token = self.advance_token()?;
                 let subtypes = self.parse_comma_list(TokenKind::RParen, |p| {
@@ -1213,6 +1996,25 @@ fn parse_unary(&mut self, opts: &ExprParsingOpts) -> ExprResult {
                     kind: ExprKind::Deref(expr),
                 }))
             }
+            TokenKind::Inline => {
+                let pos = self.advance_token()?.position;
+                let expr = self.parse_primary(opts)?;
+                if let ExprKind::Call(..) = expr.kind {
+                } else {
+                    return Err(MsgWithPos::new(
+                        self.lexer.path().to_string(),
+                        self.src(),
+                        expr.pos,
+                        Msg::InlineExpectsCall,
+                    ));
+                }
+
+                Ok(Box::new(Expr {
+                    pos,
+                    id: self.generate_id(),
+                    kind: ExprKind::Inline(expr),
+                }))
+            }
             _ => self.parse_primary(opts),
         }
     }
@@ -1323,7 +2125,25 @@ fn parse_factor(&mut self, opts: &ExprParsingOpts) -> ExprResult {
             TokenKind::String(_) => self.parse_string(),
             TokenKind::True | TokenKind::False => self.parse_bool_literal(),
             TokenKind::Null => self.parse_null(),
-            TokenKind::SizeOf => self.parse_sizeof(),
+            TokenKind::LBracket => self.parse_array_literal(),
+            TokenKind::LBrace => self.parse_block_expr(),
+            TokenKind::SizeOf | TokenKind::AlignOf => self.parse_sizeof(),
+            TokenKind::OffsetOf => self.parse_offsetof(),
+            TokenKind::New => self.parse_new(),
+            TokenKind::Loop => {
+                let stmt = self.parse_loop(true)?;
+                let stmt = *stmt;
+                let body = match stmt.kind {
+                    StmtKind::Loop(body) => body,
+                    _ => unreachable!(),
+                };
+
+                Ok(box Expr {
+                    id: self.generate_id(),
+                    pos: stmt.pos,
+                    kind: ExprKind::Loop(body),
+                })
+            }
             TokenKind::Identifier(_) => self.parse_identifier_or_call(opts),
             _ => Err(MsgWithPos::new(
                 self.lexer.path().to_string(),
@@ -1335,6 +2155,37 @@ fn parse_factor(&mut self, opts: &ExprParsingOpts) -> ExprResult {
     }
 }
 
+/// Whether token of `kind` can begin expression
+fn starts_expr(kind: &TokenKind) -> bool {
+    match kind {
+        TokenKind::Identifier(_)
+        | TokenKind::BangIdent(_)
+        | TokenKind::LitChar(_)
+        | TokenKind::LitInt(..)
+        | TokenKind::LitFloat(..)
+        | TokenKind::String(_)
+        | TokenKind::True
+        | TokenKind::False
+        | TokenKind::Null
+        | TokenKind::LParen
+        | TokenKind::LBracket
+        | TokenKind::Add
+        | TokenKind::Sub
+        | TokenKind::Not
+        | TokenKind::Mul
+        | TokenKind::BitAnd
+        | TokenKind::Fun
+        | TokenKind::SizeOf
+        | TokenKind::AlignOf
+        | TokenKind::OffsetOf
+        | TokenKind::New
+        | TokenKind::Loop
+        | TokenKind::Inline
+        | TokenKind::ConstExpr => true,
+        _ => false,
+    }
+}
+
 struct ExprParsingOpts {
     parse_struct_lit: bool,
 }
@@ -1351,3 +2202,28 @@ pub fn parse_struct_lit(&mut self, val: bool) -> &mut ExprParsingOpts {
         self
     }
 }
+
+/// Source-like text of `tokens`: tokens adjacent in source stay adjacent, others are separated
+/// by single space
+fn stringify_tokens(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    let mut prev_end: Option<Position> = None;
+    for tok in tokens.iter() {
+        let tok_text = match &tok.kind {
+            TokenKind::BangIdent(name) => format!("{}!", name),
+            _ => tok.name(),
+        };
+        if let Some(end) = prev_end {
+            if end != tok.position {
+                text.push(' ');
+            }
+        }
+        text.push_str(&tok_text);
+        prev_end = Some(Position::new(
+            tok.position.file,
+            tok.position.line,
+            tok.position.column + tok_text.chars().count() as u32,
+        ));
+    }
+    text
+}
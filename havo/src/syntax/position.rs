@@ -1,4 +1,7 @@
-use super::interner::{str, Name};
+use super::{
+    interner::{str, Name},
+    lexer::reader::TAB_WIDTH,
+};
 use std::{
     fmt::{Display, Error, Formatter},
     result::Result,
@@ -24,6 +27,50 @@ pub const fn new(name: Name, l: u32, c: u32) -> Position {
     }
 }
 
+/// Source text from `start` up to `end`, not including character at `end`
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub const fn new(start: Position, end: Position) -> Span {
+        Span { start, end }
+    }
+}
+
+/// Line `n` (counting from 1) of `src` without line terminator, empty if there's no such line
+pub fn source_line(src: &str, n: u32) -> &str {
+    match n {
+        0 => "",
+        n => src.lines().nth(n as usize - 1).unwrap_or(""),
+    }
+}
+
+/// Byte offset of `pos` in `src`. Columns are counted like lexer does, with tabs advancing to
+/// next multiple of `TAB_WIDTH`. Positions past end of line or file are clamped to it.
+pub fn source_offset(src: &str, pos: Position) -> usize {
+    let start = match pos.line {
+        0 | 1 => 0,
+        line => match src.match_indices('\n').nth(line as usize - 2) {
+            Some((newline, _)) => newline + 1,
+            None => return src.len(),
+        },
+    };
+    let mut column = 1;
+    for (i, ch) in src[start..].char_indices() {
+        if ch == '\n' || column >= pos.column as usize {
+            return start + i;
+        }
+        column = match ch {
+            '\t' => 1 + TAB_WIDTH * ((column - 1) / TAB_WIDTH + 1),
+            _ => column + 1,
+        };
+    }
+    src.len()
+}
+
 impl Display for Position {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         write!(f, "{}.{}:{}", str(self.file), self.line, self.column)
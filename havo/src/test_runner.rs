@@ -0,0 +1,132 @@
+//! `--test` mode: replaces `main` of program with runner that calls every `@test` function.
+//!
+//! Functions marked `@before_each` run before each test and `@after_each` after it, both in
+//! declaration order. Test fails by aborting, runner prints `test <name> ... ok` for every test
+//! that returned.
+
+use crate::{
+    ast::*,
+    gen_id, intern,
+    syntax::{
+        interner::Name,
+        lexer::token::{IntBase, IntSuffix},
+    },
+    Position,
+};
+
+/// Names of functions marked with attribute `name`, in declaration order
+fn marked(file: &File, name: &str) -> Vec<Name> {
+    file.elems
+        .iter()
+        .filter_map(|elem| match elem {
+            Elem::Func(func) if func.has_attribute(name) => Some(func.name),
+            _ => None,
+        })
+        .collect()
+}
+
+fn expr(pos: Position, kind: ExprKind) -> Box<Expr> {
+    box Expr {
+        id: gen_id(),
+        pos,
+        kind,
+    }
+}
+
+fn stmt(pos: Position, kind: StmtKind) -> Box<Stmt> {
+    box Stmt {
+        id: gen_id(),
+        pos,
+        kind,
+    }
+}
+
+fn call(pos: Position, name: Name, args: Vec<Box<Expr>>) -> Box<Stmt> {
+    stmt(
+        pos,
+        StmtKind::Expr(expr(pos, ExprKind::Call(Path::new(name), None, args))),
+    )
+}
+
+/// Replace `main` in `file` with test runner
+pub fn generate_main(file: &mut File) {
+    let tests = marked(file, "test");
+    let before = marked(file, "before_each");
+    let after = marked(file, "after_each");
+    let main = intern("main");
+    let puts = intern("puts");
+    let pos = Position::new(intern(&file.path), 1, 1);
+
+    file.elems.retain(|elem| match elem {
+        Elem::Func(func) => func.name != main,
+        _ => true,
+    });
+    let has_puts = file.elems.iter().any(|elem| match elem {
+        Elem::Func(func) => func.name == puts && func.external,
+        _ => false,
+    });
+    if !has_puts {
+        file.elems.push(Elem::Func(Function {
+            id: gen_id(),
+            pos,
+            name: puts,
+            attributes: vec![],
+            variadic: false,
+            inline: false,
+            external: true,
+            constant: false,
+            public: false,
+            internal: false,
+            static_: false,
+            type_params: vec![],
+            params: vec![(
+                intern("s"),
+                box Type::create_ptr(
+                    gen_id(),
+                    pos,
+                    box Type::create_basic(gen_id(), pos, intern("char")),
+                ),
+            )],
+            ret: box Type::create_basic(gen_id(), pos, intern("i32")),
+            this: None,
+            body: None,
+            ir_temp_id: 0,
+        }));
+    }
+
+    let mut body = vec![];
+    for test in tests.iter() {
+        body.extend(before.iter().map(|name| call(pos, *name, vec![])));
+        body.push(call(pos, *test, vec![]));
+        body.extend(after.iter().map(|name| call(pos, *name, vec![])));
+        let report = format!("test {} ... ok", crate::str(*test));
+        body.push(call(pos, puts, vec![expr(pos, ExprKind::Str(report))]));
+    }
+    body.push(stmt(
+        pos,
+        StmtKind::Return(Some(expr(
+            pos,
+            ExprKind::Int(0, IntBase::Dec, IntSuffix::Int),
+        ))),
+    ));
+
+    file.elems.push(Elem::Func(Function {
+        id: gen_id(),
+        pos,
+        name: main,
+        attributes: vec![],
+        variadic: false,
+        inline: false,
+        external: false,
+        constant: false,
+        public: true,
+        internal: false,
+        static_: false,
+        type_params: vec![],
+        params: vec![],
+        ret: box Type::create_basic(gen_id(), pos, intern("i32")),
+        this: None,
+        body: Some(stmt(pos, StmtKind::Block(body))),
+        ir_temp_id: 0,
+    }));
+}
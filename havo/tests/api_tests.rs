@@ -0,0 +1,334 @@
+//! Tests of library API used by tooling, they don't go through `havo` binary.
+
+use havo::{
+    ast::{AttributeArg, Expr, ExprKind, File, StmtKind},
+    mangle::{demangle, mangle},
+    syntax::{
+        lexer::{
+            reader::Reader,
+            token::{IntBase, IntSuffix},
+        },
+        parser::Parser,
+        position::{Position, Span},
+    },
+    Context,
+};
+
+fn empty_file(path: &str) -> File {
+    File {
+        root: String::new(),
+        src: String::new(),
+        path: path.to_owned(),
+        elems: vec![],
+        unsuffixed_ints: std::collections::HashSet::new(),
+    }
+}
+
+#[test]
+fn type_check_standalone_expr() {
+    let mut file = empty_file("decls.osmx");
+    Parser::new(Reader::from_string("var a: i64;\nvar b: i64;\n"), &mut file)
+        .parse()
+        .unwrap();
+    let mut ctx = Context::new(file);
+
+    let mut scratch = empty_file("hover.osmx");
+    let expr = Parser::new(Reader::from_string("a + b"), &mut scratch)
+        .parse_standalone_expr()
+        .unwrap();
+
+    let ty = ctx.type_check_expr(&expr).unwrap();
+    assert_eq!(ty.to_string(), "i64");
+    assert_eq!(
+        ctx.types.get(&expr.id).map(|ty| ty.to_string()),
+        Some("i64".to_owned())
+    );
+}
+
+#[test]
+fn type_check_returns_error_instead_of_exiting() {
+    let mut file = empty_file("decls.osmx");
+    Parser::new(Reader::from_string("var a: i64;\n"), &mut file)
+        .parse()
+        .unwrap();
+    let mut ctx = Context::new(file);
+
+    let mut scratch = empty_file("hover.osmx");
+    let expr = Parser::new(Reader::from_string("a + missing"), &mut scratch)
+        .parse_standalone_expr()
+        .unwrap();
+
+    let err = ctx.type_check_expr(&expr).unwrap_err();
+    assert_eq!(err.error.message(), "Variable missing not found");
+    assert_eq!((err.pos.line, err.pos.column), (1, 5));
+    // still usable after error
+    let expr = Parser::new(Reader::from_string("a"), &mut scratch)
+        .parse_standalone_expr()
+        .unwrap();
+    assert_eq!(ctx.type_check_expr(&expr).unwrap().to_string(), "i64");
+}
+
+#[test]
+fn type_check_records_conversion_type() {
+    let mut file = empty_file("decls.osmx");
+    Parser::new(Reader::from_string("var a: i32;\n"), &mut file)
+        .parse()
+        .unwrap();
+    let mut ctx = Context::new(file);
+
+    let mut scratch = empty_file("hover.osmx");
+    let expr = Parser::new(Reader::from_string("(a as i64) + 1i64"), &mut scratch)
+        .parse_standalone_expr()
+        .unwrap();
+
+    assert_eq!(ctx.type_check_expr(&expr).unwrap().to_string(), "i64");
+    let cast = match &expr.kind {
+        ExprKind::Binary(_, lhs, _) => lhs,
+        kind => panic!("expected binary expression, found {}", kind),
+    };
+    match &cast.kind {
+        ExprKind::Conv(..) => {}
+        kind => panic!("expected conversion, found {}", kind),
+    }
+    assert_eq!(
+        ctx.types.get(&cast.id).map(|ty| ty.to_string()),
+        Some("i64".to_owned())
+    );
+}
+
+#[test]
+fn extract_source_lines_and_spans() {
+    let mut file = empty_file("snippet.osmx");
+    Parser::new(
+        Reader::from_string("func main() i32 {\n\treturn 42;\n}\n"),
+        &mut file,
+    )
+    .parse()
+    .unwrap();
+    let at = |line, column| Position::new(havo::intern("snippet.osmx"), line, column);
+
+    assert_eq!(file.line(2), "\treturn 42;");
+    assert_eq!(file.line(0), "");
+    assert_eq!(file.line(10), "");
+
+    // tab advances `return` to column 5, where parser puts statement
+    let ret = match &file.functions()[0].body.as_ref().unwrap().kind {
+        StmtKind::Block(stmts) => stmts[0].pos,
+        kind => panic!("expected block, found {:?}", kind),
+    };
+    assert_eq!((ret.line, ret.column), (2, 5));
+    assert_eq!(file.snippet(Span::new(at(2, 5), at(2, 11))), "return");
+    assert_eq!(file.snippet(Span::new(at(1, 17), at(2, 5))), "{\n\t");
+    assert_eq!(file.snippet(Span::new(at(2, 12), at(2, 40))), "42;");
+    assert_eq!(file.snippet(Span::new(at(7, 1), at(9, 1))), "");
+    assert_eq!(file.snippet(Span::new(at(2, 11), at(2, 5))), "");
+}
+
+#[test]
+fn mangled_names_round_trip() {
+    let mut file = empty_file("mangle.osmx");
+    let src = "struct Point { x: i32 }
+        func f(p: *i32) void { return; }
+        func f(p: i32[4]) void { return; }
+        func f(p: i32[]) void { return; }
+        func f(p: <f32;4>) void { return; }
+        func f(x: i32) void { return; }
+        func fi32() void { return; }
+        func f(cb: (i32,*u8) -> void) void { return; }
+        func (p: *Point) scale(k: i32, ks: f64[]) void { return; }
+        func main() i32 { return 0; }
+        @no_mangle
+        func exported(x: i32) i32 { return x; }";
+    Parser::new(Reader::from_string(src), &mut file).parse().unwrap();
+
+    let symbols = file.functions().into_iter().map(mangle).collect::<Vec<_>>();
+    let demangled = symbols.iter().map(|s| demangle(s)).collect::<Vec<_>>();
+    assert_eq!(
+        demangled,
+        [
+            "f(*i32)",
+            "f(i32[4])",
+            "f(i32[])",
+            "f(<f32;4>)",
+            "f(i32)",
+            "fi32()",
+            "f((i32,*u8) -> void)",
+            "(*Point) scale(i32, f64[])",
+            "main",
+            "exported",
+        ]
+    );
+    // pointer and array, `f(i32)` and `fi32()` used to share symbol
+    let unique = symbols.iter().collect::<std::collections::HashSet<_>>();
+    assert_eq!(unique.len(), symbols.len(), "{:?}", symbols);
+    assert_eq!(symbols[7], "_H5scaleMP5Point3i32S3f64E");
+
+    assert_eq!(demangle("printf"), "printf");
+    assert_eq!(demangle("_H9f"), "_H9f");
+    assert_eq!(demangle("_H1f3i32Ex"), "_H1f3i32Ex");
+}
+
+fn parse_expr(src: &str) -> Box<Expr> {
+    let mut scratch = empty_file("expr.osmx");
+    Parser::new(Reader::from_string(src), &mut scratch)
+        .parse_standalone_expr()
+        .unwrap()
+}
+
+/// Value and suffix of integer literal, possibly negated by unary minus
+fn int_literal(expr: &Expr) -> (i64, IntSuffix) {
+    match &expr.kind {
+        ExprKind::Int(i, _, suffix) => (*i, *suffix),
+        ExprKind::Unary(op, expr) if op == "-" => {
+            let (i, suffix) = int_literal(expr);
+            (i.wrapping_neg(), suffix)
+        }
+        kind => panic!("expected integer literal, found {}", kind),
+    }
+}
+
+#[test]
+fn int_literal_display_round_trip() {
+    let parsed = vec![parse_expr("0xFF"), parse_expr("0b1011u8"), parse_expr("0o17i64")];
+    let built = [
+        (-42, IntBase::Dec, IntSuffix::Int),
+        (-255, IntBase::Hex, IntSuffix::Long),
+        (-5, IntBase::Bin, IntSuffix::Byte),
+        (-1, IntBase::Hex, IntSuffix::ULong),
+    ]
+    .iter()
+    .map(|&(i, base, suffix)| {
+        let mut expr = parse_expr("0");
+        expr.kind = ExprKind::Int(i, base, suffix);
+        expr
+    });
+
+    for expr in parsed.into_iter().chain(built) {
+        let printed = expr.to_string();
+        let reparsed = parse_expr(&printed);
+        assert_eq!(int_literal(&reparsed), int_literal(&expr), "{}", printed);
+    }
+    assert_eq!(parse_expr("0xFF").to_string(), "0xff");
+    let negative = ExprKind::Int(-255, IntBase::Hex, IntSuffix::Long);
+    assert_eq!(negative.to_string(), "-0xffi64");
+}
+
+#[test]
+fn query_declaration_attributes() {
+    let src = "@cold\n@section(\".text.unlikely\")\n@optimize(3)\nfunc slow() void {}\n\
+               @section(\".data.hot\")\nvar counter: i32;\n\
+               @packed\nstruct Header { tag: u8, len: u32 }\n";
+    let mut file = empty_file("attrs.osmx");
+    Parser::new(Reader::from_string(src), &mut file)
+        .parse()
+        .unwrap();
+    let ctx = Context::new(file);
+
+    let names = ctx
+        .attributes_of("slow")
+        .iter()
+        .map(|attr| attr.name.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["cold", "section", "optimize"]);
+    let section = ctx.attribute_of("slow", "section").unwrap();
+    assert_eq!(section.str_arg(), Some(".text.unlikely"));
+    assert_eq!(ctx.attribute_of("slow", "optimize").unwrap().int_arg(), Some(3));
+    assert!(ctx.attribute_of("slow", "inline").is_none());
+
+    let data = ctx.attribute_of("counter", "section").unwrap();
+    assert_eq!(data.args, [AttributeArg::Str(".data.hot".to_owned())]);
+    assert!(ctx.attribute_of("Header", "packed").is_some());
+    assert!(ctx.attributes_of("missing").is_empty());
+}
+
+#[test]
+fn section_attribute_round_trips() {
+    let src = "@section(\".boot\")\nfunc reset() void {}\n\
+               @section(\".boot.data\")\nvar flag: i32;\n";
+    let mut file = empty_file("section.osmx");
+    Parser::new(Reader::from_string(src), &mut file)
+        .parse()
+        .unwrap();
+    let printed = file
+        .elems
+        .iter()
+        .map(|elem| elem.to_string())
+        .collect::<String>();
+
+    let mut reparsed = empty_file("section.osmx");
+    Parser::new(Reader::from_string(&printed), &mut reparsed)
+        .parse()
+        .unwrap();
+    let ctx = Context::new(reparsed);
+    let section = |name| ctx.attribute_of(name, "section").unwrap().str_arg().unwrap();
+    assert_eq!(section("reset"), ".boot", "{}", printed);
+    assert_eq!(section("flag"), ".boot.data", "{}", printed);
+}
+
+#[test]
+fn list_unresolved_externals() {
+    let src = "extern func write_log(level: i32, msg: *char) void;\n\
+               extern func unused(x: i32) i32;\n\
+               extern var log_level: i32;\n\
+               func warn(msg: *char) void {\n\
+                   if log_level > 1 { write_log(2, msg); }\n\
+               }\n";
+    let mut file = empty_file("externs.osmx");
+    Parser::new(Reader::from_string(src), &mut file)
+        .parse()
+        .unwrap();
+    let ctx = Context::new(file);
+
+    let externals = ctx
+        .unresolved_externals()
+        .into_iter()
+        .map(|(name, ty)| format!("{}: {}", name, ty))
+        .collect::<Vec<_>>();
+    assert_eq!(externals, ["log_level: i32", "write_log: (i32,*char) -> void"]);
+}
+
+#[test]
+fn opt_levels_map_to_gccjit_levels() {
+    use gccjit_rs::ctx::OptimizationLevel;
+    use havo::gccjit::opt_level;
+
+    let level = |level| match opt_level(level) {
+        Some(OptimizationLevel::None) => 0,
+        Some(OptimizationLevel::Limited) => 1,
+        Some(OptimizationLevel::Standard) => 2,
+        Some(OptimizationLevel::Aggressive) => 3,
+        None => -1,
+    };
+    assert_eq!((0..=3).map(level).collect::<Vec<_>>(), [0, 1, 2, 3]);
+    assert_eq!(level(4), -1);
+    assert_eq!(level(9), -1);
+}
+
+#[test]
+fn gccjit_target_has_64_bit_pointers() {
+    // layouts assume 8-byte pointers, `compile` rejects other targets
+    if let Some(size) = havo::gccjit::target_ptr_size() {
+        assert_eq!(size, 8);
+    }
+}
+
+#[test]
+fn codegen_reports_position_of_untyped_expr() {
+    use havo::{err::catch_errors, gccjit::Codegen};
+
+    // semantic pass isn't run, so no expression has recorded type
+    let mut file = empty_file("untyped.osmx");
+    Parser::new(
+        Reader::from_string("pub func main() i32 {\n    return 7;\n}\n"),
+        &mut file,
+    )
+    .parse()
+    .unwrap();
+    let mut ctx = Context::new(file);
+    let mut elems = ctx.file.elems.clone();
+
+    let err = catch_errors(|| Codegen::new(&mut ctx, "untyped").gen_toplevel(&mut elems))
+        .unwrap_err();
+    assert_eq!(err.msg, "type of expression `7` is unknown");
+    assert_eq!((err.pos.line, err.pos.column), (2, 12));
+}
@@ -0,0 +1,1047 @@
+//! Tests that drive `havo` in modes `run_tests` can't express (shared libraries, object files,
+//! assembly) and inspect produced artifacts.
+
+use std::process::{Command, Output};
+
+use tempdir::TempDir;
+
+fn havo(args: &[&str]) -> Output {
+    Command::new("havo")
+        .args(args)
+        .output()
+        .expect("failed to run havo")
+}
+
+fn havo_with_env(args: &[&str], vars: &[(&str, &str)]) -> Output {
+    Command::new("havo")
+        .args(args)
+        .env_remove("FOO")
+        .env_remove("HAVO_GREETING")
+        .env_remove("HAVO_INDEX")
+        .envs(vars.iter().cloned())
+        .output()
+        .expect("failed to run havo")
+}
+
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "havo failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn symver_creates_version_node() {
+    let dir = TempDir::new("havo-symver").unwrap();
+    let lib = dir.path().join("libsymver.so");
+    let lib = lib.to_str().unwrap();
+
+    let child = Command::new("havo")
+        .args(&["--shared", "-o", lib, "tests/driver/symver.osmx"])
+        .spawn()
+        .expect("failed to run havo");
+    let script = std::env::temp_dir().join(format!("havo-{}.map", child.id()));
+    assert_success(&child.wait_with_output().unwrap());
+    assert!(!script.exists(), "version script {} left behind", script.display());
+
+    let readelf = Command::new("readelf")
+        .args(&["--dyn-syms", "-W", lib])
+        .output()
+        .unwrap();
+    let symbols = String::from_utf8_lossy(&readelf.stdout);
+    assert!(symbols.contains("answer@@HAVO_1.0"), "{}", symbols);
+}
+
+#[test]
+fn cranelift_matches_gccjit() {
+    for entry in std::fs::read_dir("tests/driver/cranelift").unwrap() {
+        let path = entry.unwrap().path();
+        let path = path.to_str().unwrap();
+
+        let gccjit = havo(&["--jit", path]);
+        let cranelift = havo(&["--jit", "--backend", "cranelift", path]);
+        assert_success(&gccjit);
+        assert_success(&cranelift);
+        assert_eq!(
+            String::from_utf8_lossy(&gccjit.stdout),
+            String::from_utf8_lossy(&cranelift.stdout),
+            "backends disagree on {}",
+            path
+        );
+    }
+}
+
+#[test]
+fn cpp_backend_output_compiles() {
+    let dir = TempDir::new("havo-cpp").unwrap();
+    let source = dir.path().join("points.cc");
+    let exe = dir.path().join("points");
+
+    assert_success(&havo(&[
+        "--backend",
+        "cpp",
+        "-o",
+        source.to_str().unwrap(),
+        "tests/driver/cpp/points.osmx",
+    ]));
+
+    let cxx = Command::new("c++")
+        .args(&["-o", exe.to_str().unwrap(), source.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(
+        cxx.status.success(),
+        "emitted C++ doesn't compile:\n{}",
+        String::from_utf8_lossy(&cxx.stderr)
+    );
+
+    let run = Command::new(&exe).output().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "4 -8 12\n5050\n4\t6\t10\t12\t\n\"quoted\" 1\n"
+    );
+}
+
+#[test]
+fn cpp_backend_reads_variadic_arguments() {
+    let dir = TempDir::new("havo-cpp").unwrap();
+    let source = dir.path().join("variadic.cc");
+    let exe = dir.path().join("variadic");
+
+    assert_success(&havo(&[
+        "--backend",
+        "cpp",
+        "-o",
+        source.to_str().unwrap(),
+        "tests/driver/cpp/variadic.osmx",
+    ]));
+
+    let cxx = Command::new("c++")
+        .args(&["-o", exe.to_str().unwrap(), source.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(
+        cxx.status.success(),
+        "emitted C++ doesn't compile:\n{}",
+        String::from_utf8_lossy(&cxx.stderr)
+    );
+
+    let run = Command::new(&exe).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "0 7 10\nabcd\n");
+}
+
+#[test]
+fn cpp_backend_keeps_nul_followed_by_digit() {
+    let dir = TempDir::new("havo-cpp").unwrap();
+    let source = dir.path().join("nul.cc");
+    let exe = dir.path().join("nul");
+
+    assert_success(&havo(&[
+        "--backend",
+        "cpp",
+        "-o",
+        source.to_str().unwrap(),
+        "tests/driver/cpp/nul.osmx",
+    ]));
+
+    let cxx = Command::new("c++")
+        .args(&["-o", exe.to_str().unwrap(), source.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(
+        cxx.status.success(),
+        "emitted C++ doesn't compile:\n{}",
+        String::from_utf8_lossy(&cxx.stderr)
+    );
+
+    let run = Command::new(&exe).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "0 49 0\n");
+}
+
+#[test]
+fn gccjit_reads_variadic_arguments() {
+    let run = havo(&["--jit", "tests/driver/cpp/variadic.osmx"]);
+    assert_success(&run);
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "0 7 10\nabcd\n");
+}
+
+/// Return assembly emitted for `symbol` (from its label up to `.size`)
+fn asm_of<'a>(asm: &'a str, symbol: &str) -> &'a str {
+    let start = asm
+        .find(&format!("\n{}:", symbol))
+        .unwrap_or_else(|| panic!("no `{}` in assembly:\n{}", symbol, asm));
+    let end = asm[start..]
+        .find(".size")
+        .map_or(asm.len(), |end| start + end);
+    &asm[start..end]
+}
+
+#[test]
+fn object_output_is_reproducible() {
+    let dir = TempDir::new("havo-reproducible").unwrap();
+    let mut objects = vec![];
+    for i in 0..2 {
+        let out = dir.path().join(format!("reproducible{}.o", i));
+        assert_success(&havo(&[
+            "--emit-obj",
+            "-o",
+            out.to_str().unwrap(),
+            "tests/driver/reproducible.osmx",
+        ]));
+        objects.push(std::fs::read(&out).unwrap());
+    }
+    assert!(objects[0] == objects[1], "object files differ between runs");
+}
+
+#[test]
+fn multiple_input_files() {
+    let run = havo(&[
+        "--jit",
+        "tests/driver/multi/main.osmx",
+        "tests/driver/multi/helper.osmx",
+    ]);
+    assert_success(&run);
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "49\n");
+
+    let duplicate = havo(&[
+        "--jit",
+        "tests/driver/multi/main.osmx",
+        "tests/driver/multi/helper.osmx",
+        "tests/driver/multi/duplicate.osmx",
+    ]);
+    assert!(!duplicate.status.success());
+    assert!(
+        String::from_utf8_lossy(&duplicate.stderr).contains("`square(i32)` is already defined"),
+        "{}",
+        String::from_utf8_lossy(&duplicate.stderr)
+    );
+}
+
+#[test]
+fn merged_file_imports_relative_to_its_directory() {
+    let run = havo(&[
+        "--jit",
+        "tests/driver/multi/cube_main.osmx",
+        "tests/driver/multi/sub/twice.osmx",
+    ]);
+    assert_success(&run);
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "54\n");
+}
+
+#[test]
+fn import_from_include_dir() {
+    let run = havo(&[
+        "--jit",
+        "-I",
+        "tests/driver/include/lib",
+        "tests/driver/include/main.osmx",
+    ]);
+    assert_success(&run);
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "42\n");
+
+    let missing = havo(&["--jit", "-I", "tests/driver", "tests/driver/include/main.osmx"]);
+    assert!(!missing.status.success());
+    let stderr = String::from_utf8_lossy(&missing.stderr);
+    assert!(stderr.contains("import `shapes.osmx` not found"), "{}", stderr);
+    assert!(stderr.contains("tests/driver/include\n"), "{}", stderr);
+    assert!(stderr.contains("tests/driver\n"), "{}", stderr);
+}
+
+#[test]
+fn deprecated_function_warns_through_reexport() {
+    let run = havo(&["--jit", "tests/driver/deprecated/main.osmx"]);
+    assert_success(&run);
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "42\n");
+
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert!(
+        stderr.contains("use of deprecated function `rect_area`: use `area` instead"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("main.osmx.6:"), "{}", stderr);
+    assert!(stderr.contains("legacy.osmx.1:"), "{}", stderr);
+}
+
+#[test]
+fn env_builtins_fold_at_compile_time() {
+    let args = ["--jit", "--consteval", "tests/driver/env.osmx"];
+
+    let set = havo_with_env(&args, &[("FOO", "42"), ("HAVO_GREETING", "hi")]);
+    assert_success(&set);
+    assert_eq!(String::from_utf8_lossy(&set.stdout), "42\nhi\n");
+
+    let unset = havo_with_env(&args, &[]);
+    assert_success(&unset);
+    assert_eq!(String::from_utf8_lossy(&unset.stdout), "0\nhello\n");
+}
+
+#[test]
+fn include_builtins_embed_files() {
+    // with and without `--consteval`, paths are relative to including file
+    for args in &[&["--jit"][..], &["--jit", "--consteval"][..]] {
+        let mut args = args.to_vec();
+        args.push("tests/driver/include/embed.osmx");
+        let run = havo(&args);
+        assert_success(&run);
+        assert_eq!(
+            String::from_utf8_lossy(&run.stdout),
+            "202 254 42\nhello from file\n"
+        );
+    }
+
+    let dir = TempDir::new("havo-include").unwrap();
+    let path = dir.path().join("missing.osmx");
+    std::fs::write(
+        &path,
+        "pub func main() i32 {\n    var data = include_bytes(\"nope.bin\");\n    return 0;\n}\n",
+    )
+    .unwrap();
+    let run = havo(&["--jit", path.to_str().unwrap()]);
+    assert!(!run.status.success());
+    assert!(
+        String::from_utf8_lossy(&run.stderr).contains("can't include"),
+        "{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
+
+#[test]
+fn consteval_folds_array_index() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--print-ast",
+        "tests/driver/fold_index.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(stdout.contains("ptr[0]"), "{}", stdout);
+    assert!(stdout.ends_with("42\n"), "{}", stdout);
+}
+
+#[test]
+fn emit_ir_golden() {
+    let run = havo(&["--emit-ir", "tests/driver/ir/sum.osmx"]);
+    assert_success(&run);
+    let expected = std::fs::read_to_string("tests/driver/ir/sum.ir").unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), expected);
+}
+
+#[test]
+fn print_types_golden() {
+    let run = havo(&["--print-types", "tests/driver/types/widths.osmx"]);
+    assert_success(&run);
+    let expected = std::fs::read_to_string("tests/driver/types/widths.types").unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), expected);
+}
+
+#[test]
+fn dump_mono_lists_specializations() {
+    let run = havo(&["--dump-mono", "tests/driver/generic_max.osmx"]);
+    assert_success(&run);
+    // `first` is never called, so it has no specializations
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "max: 2 specialization(s)\n    max<f64>\n    max<i32>\n"
+    );
+}
+
+#[test]
+fn color_option_controls_escape_codes() {
+    let files = [
+        "tests/driver/multi/main.osmx",
+        "tests/driver/multi/helper.osmx",
+        "tests/driver/multi/duplicate.osmx",
+    ];
+    let diagnostics = |color: &str| {
+        let mut args = vec!["--jit", "--color", color];
+        args.extend(files.iter());
+        let output = havo(&args);
+        assert!(!output.status.success());
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    let plain = diagnostics("never");
+    assert!(!plain.contains('\x1b'), "{:?}", plain);
+    assert!(plain.contains("NOTE"), "{:?}", plain);
+
+    let colored = diagnostics("always");
+    assert!(colored.contains("\x1b["), "{:?}", colored);
+}
+
+#[test]
+fn debug_bounds_aborts_on_out_of_range_index() {
+    let args = ["--jit", "--debug-bounds", "tests/driver/bounds.osmx"];
+
+    let in_range = havo_with_env(&args, &[("HAVO_INDEX", "3")]);
+    assert_success(&in_range);
+    assert_eq!(String::from_utf8_lossy(&in_range.stdout), "30\n");
+
+    for (index, message) in &[
+        ("4", "index 4 out of bounds for array of length 4"),
+        ("-1", "index -1 out of bounds for array of length 4"),
+    ] {
+        let out_of_range = havo_with_env(&args, &[("HAVO_INDEX", index)]);
+        assert!(!out_of_range.status.success());
+        let stderr = String::from_utf8_lossy(&out_of_range.stderr);
+        assert!(stderr.contains("bounds.osmx.9:"), "{}", stderr);
+        assert!(stderr.contains(message), "{}", stderr);
+    }
+}
+
+#[test]
+fn debug_bounds_checks_slices() {
+    let args = ["--jit", "--debug-bounds", "tests/driver/slice_bounds.osmx"];
+
+    let in_range = havo_with_env(&args, &[("HAVO_INDEX", "2")]);
+    assert_success(&in_range);
+    assert_eq!(String::from_utf8_lossy(&in_range.stdout), "2\n30\n");
+
+    for (index, line, message) in &[
+        ("3", "slice_bounds.osmx.4:", "index 3 out of bounds for slice of length 3"),
+        ("5", "slice_bounds.osmx.13:", "slice 5..4 out of bounds for length 4"),
+    ] {
+        let out_of_range = havo_with_env(&args, &[("HAVO_INDEX", index)]);
+        assert!(!out_of_range.status.success());
+        let stderr = String::from_utf8_lossy(&out_of_range.stderr);
+        assert!(stderr.contains(line), "{}", stderr);
+        assert!(stderr.contains(message), "{}", stderr);
+    }
+}
+
+#[test]
+fn debug_null_aborts_on_null_dereference() {
+    let args = ["--jit", "--debug-null", "tests/driver/null_deref.osmx"];
+
+    let valid = havo_with_env(&args, &[("HAVO_NULL", "0")]);
+    assert_success(&valid);
+    assert_eq!(String::from_utf8_lossy(&valid.stdout), "7\n5\n3\n");
+
+    for (kind, line) in &[("1", "null_deref.osmx.21:"), ("2", "null_deref.osmx.22:")] {
+        let null = havo_with_env(&args, &[("HAVO_NULL", kind)]);
+        assert!(!null.status.success());
+        let stderr = String::from_utf8_lossy(&null.stderr);
+        assert!(stderr.contains(line), "{}", stderr);
+        assert!(stderr.contains("null pointer dereference"), "{}", stderr);
+    }
+}
+
+#[test]
+fn test_mode_runs_fixtures_around_each_test() {
+    let run = havo(&["--jit", "--test", "tests/driver/fixtures.osmx"]);
+    assert_success(&run);
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "first 10\nafter 11\ntest starts_from_setup ... ok\n\
+         second 10\nafter 15\ntest setup_runs_again ... ok\n"
+    );
+
+    let run = havo(&["--jit", "tests/driver/fixtures.osmx"]);
+    assert_success(&run);
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "not a test\n");
+}
+
+#[test]
+fn debug_overflow_aborts_on_signed_overflow() {
+    let wrapping = havo(&["--jit", "tests/driver/overflow.osmx"]);
+    assert_success(&wrapping);
+    assert_eq!(String::from_utf8_lossy(&wrapping.stdout), "-1\n-2147483648\n");
+
+    let checked = havo(&["--jit", "--debug-overflow", "tests/driver/overflow.osmx"]);
+    assert!(!checked.status.success());
+    let stderr = String::from_utf8_lossy(&checked.stderr);
+    assert!(stderr.contains("overflow.osmx.8:"), "{}", stderr);
+    assert!(stderr.contains("signed integer overflow in `+`"), "{}", stderr);
+}
+
+#[test]
+fn emit_tokens_lists_kinds_in_order() {
+    let run = havo(&["--emit-tokens", "tests/driver/tokens.osmx"]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    // `<position> <kind>`, payload of kind is left out
+    let kinds = lines
+        .iter()
+        .map(|line| {
+            let kind = line.splitn(2, ' ').nth(1).unwrap();
+            kind.split('(').next().unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            "Var", "Identifier", "Colon", "Identifier", "Eq", "LitInt", "Add", "LitChar",
+            "Semicolon", "Identifier", "Eq", "Identifier", "LtLt", "LitInt", "Semicolon",
+        ],
+        "{}",
+        stdout
+    );
+    assert!(lines[0].starts_with("tests/driver/tokens.osmx.1:"), "{}", stdout);
+    assert!(lines[9].starts_with("tests/driver/tokens.osmx.2:"), "{}", stdout);
+    assert!(lines[5].ends_with("Hex, None)"), "{}", stdout);
+    assert!(lines[7].ends_with("LitChar('a')"), "{}", stdout);
+}
+
+#[test]
+fn gc_collects_unreachable_allocations() {
+    // needs libgc (Boehm GC) installed
+    let run = havo(&["--jit", "--gc", "tests/driver/gc_finalizer.osmx"]);
+    assert_success(&run);
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "7 1 1\n");
+}
+
+#[test]
+fn sanitize_address_reports_heap_overflow() {
+    let dir = TempDir::new("havo-asan").unwrap();
+    let probe = dir.path().join("probe.c");
+    std::fs::write(&probe, "int main(void) { return 0; }\n").unwrap();
+    let probe_exe = dir.path().join("probe");
+    let asan = Command::new("cc")
+        .args(&["-fsanitize=address", "-o", probe_exe.to_str().unwrap()])
+        .arg(&probe)
+        .output();
+    if !asan.map_or(false, |out| out.status.success()) {
+        eprintln!("skipping: AddressSanitizer runtime is not available");
+        return;
+    }
+
+    let exe = dir.path().join("heap_overflow");
+    assert_success(&havo(&[
+        "--sanitize",
+        "address",
+        "-o",
+        exe.to_str().unwrap(),
+        "tests/driver/heap_overflow.osmx",
+    ]));
+    let run = Command::new(&exe).output().unwrap();
+    assert!(!run.status.success());
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert!(
+        stderr.contains("AddressSanitizer: heap-buffer-overflow"),
+        "{}",
+        stderr
+    );
+
+    let jit = havo(&["--jit", "--sanitize", "address", "tests/driver/heap_overflow.osmx"]);
+    assert!(!jit.status.success());
+    let stderr = String::from_utf8_lossy(&jit.stderr);
+    assert!(stderr.contains("`--sanitize` needs AOT compilation"), "{}", stderr);
+}
+
+#[test]
+fn consteval_indexes_constexpr_array() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--print-ast",
+        "tests/driver/const_table.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(stdout.contains(r#"printf("%i %i\n",11,5)"#), "{}", stdout);
+    assert!(stdout.ends_with("11 5\n"), "{}", stdout);
+}
+
+#[test]
+fn consteval_folds_constexpr_methods() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--print-ast",
+        "tests/driver/const_method.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(stdout.contains(r#"printf("%i %i\n",12,15)"#), "{}", stdout);
+    assert!(stdout.ends_with("12 15\n"), "{}", stdout);
+}
+
+#[test]
+fn consteval_prunes_ternary_with_known_condition() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--print-ast",
+        "tests/driver/const_ternary.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(stdout.contains(r#"printf("%i %i\n",1,10)"#), "{}", stdout);
+    assert!(!stdout.contains(" ? "), "{}", stdout);
+    assert!(stdout.ends_with("1 10\n"), "{}", stdout);
+}
+
+#[test]
+fn consteval_folds_string_literal_len() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--print-ast",
+        "tests/driver/str_len.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(stdout.contains(r#"printf("%i\n",9)"#), "{}", stdout);
+    assert!(stdout.ends_with("9\n"), "{}", stdout);
+}
+
+#[test]
+fn consteval_calls_void_constexpr_functions() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--print-ast",
+        "tests/driver/const_void.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(stdout.contains(r#"printf("%i\n",42)"#), "{}", stdout);
+    assert!(stdout.ends_with("42\n"), "{}", stdout);
+}
+
+#[test]
+fn consteval_folds_calls_of_pure_functions() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--print-ast",
+        "tests/driver/pure_fold.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    // loop may not end, it's evaluated only with `--aggressive-eval`
+    assert!(
+        stdout.contains(r#"printf("%i %i %i\n",49,sum_to(4),noisy(3))"#),
+        "{}",
+        stdout
+    );
+    assert!(stdout.ends_with("noisy\n49 10 3\n"), "{}", stdout);
+
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--aggressive-eval",
+        "--print-ast",
+        "tests/driver/pure_fold.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(
+        stdout.contains(r#"printf("%i %i %i\n",49,10,noisy(3))"#),
+        "{}",
+        stdout
+    );
+    assert!(stdout.ends_with("noisy\n49 10 3\n"), "{}", stdout);
+}
+
+#[test]
+fn list_targets_shows_backends_and_host() {
+    let run = havo(&["--list-targets"]);
+    assert_success(&run);
+    let out = String::from_utf8_lossy(&run.stdout);
+    assert!(out.contains("  gccjit "), "{}", out);
+    let target = out
+        .lines()
+        .find(|line| line.starts_with("gccjit target: "))
+        .unwrap();
+    assert!(target.contains(std::env::consts::ARCH), "{}", out);
+    assert!(target.contains(std::env::consts::OS), "{}", out);
+}
+
+#[test]
+fn opt_level_is_validated() {
+    for level in &["0", "1", "2", "3"] {
+        let run = havo(&["--jit", "-O", level, "tests/driver/str_len.osmx"]);
+        assert_success(&run);
+        assert_eq!(String::from_utf8_lossy(&run.stdout), "9\n");
+    }
+
+    let run = havo(&["--jit", "-O", "9", "tests/driver/str_len.osmx"]);
+    assert!(!run.status.success());
+    assert!(
+        String::from_utf8_lossy(&run.stderr).contains("optimization level 9 is out of range"),
+        "{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
+
+#[test]
+fn failed_checks_call_panic_handler() {
+    let dir = TempDir::new("havo-panic-handler").unwrap();
+    let out = dir.path().join("panic_handler.s");
+    assert_success(&havo(&[
+        "-O",
+        "0",
+        "--debug-bounds",
+        "--panic-handler",
+        "halt",
+        "--emit-asm",
+        "-o",
+        out.to_str().unwrap(),
+        "tests/driver/panic_handler.osmx",
+    ]));
+    let asm = std::fs::read_to_string(&out).unwrap();
+    let main = asm_of(&asm, "main");
+    assert!(main.contains("call\thalt"), "{}", asm);
+    assert!(!main.contains("abort"), "{}", asm);
+
+    let args = [
+        "--jit",
+        "--debug-bounds",
+        "--panic-handler",
+        "halt",
+        "tests/driver/panic_handler.osmx",
+    ];
+    let run = havo_with_env(&args, &[("HAVO_INDEX", "4")]);
+    assert_eq!(run.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "halted\n");
+
+    let run = havo(&["--jit", "--panic-handler", "missing", "tests/driver/str_len.osmx"]);
+    assert!(!run.status.success());
+    assert!(
+        String::from_utf8_lossy(&run.stderr).contains("panic handler `missing` is not declared"),
+        "{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
+
+#[test]
+fn run_forwards_program_arguments() {
+    let run = havo(&["run", "tests/driver/argc.osmx", "--", "first", "second"]);
+    assert_eq!(run.status.code(), Some(3), "{}", String::from_utf8_lossy(&run.stderr));
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "3\nfirst\nsecond\n"
+    );
+
+    // compiler flags aren't part of program's argv
+    let run = havo(&["run", "-O", "0", "tests/driver/argc.osmx"]);
+    assert_eq!(run.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "1\n");
+}
+
+#[test]
+fn watch_rebuilds_when_import_changes() {
+    use std::{
+        io::{BufRead, BufReader},
+        process::Stdio,
+        sync::mpsc::channel,
+        time::Duration,
+    };
+
+    let dir = TempDir::new("havo-watch").unwrap();
+    let main = dir.path().join("main.osmx");
+    let lib = dir.path().join("lib.osmx");
+    let lib_src = |value: i32| format!("pub func answer() i32 {{\n    return {};\n}}\n", value);
+    std::fs::write(
+        &main,
+        "import \"lib.osmx\"\n\
+         extern func printf(fmt: *char,...) i32;\n\
+         pub func main() i32 {\n    printf(\"%i\\n\", answer());\n    return 0;\n}\n",
+    )
+    .unwrap();
+    std::fs::write(&lib, lib_src(1)).unwrap();
+
+    let mut watcher = Command::new("havo")
+        .args(&["--watch", "--jit", main.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let stdout = watcher.stdout.take().unwrap();
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if tx.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let timeout = Duration::from_secs(30);
+    let first = rx.recv_timeout(timeout);
+    std::fs::write(&lib, lib_src(2)).unwrap();
+    let second = rx.recv_timeout(timeout);
+    watcher.kill().unwrap();
+    assert_eq!(first, Ok("1".to_owned()));
+    assert_eq!(second, Ok("2".to_owned()));
+}
+
+#[test]
+fn call_site_inline_reaches_backend() {
+    let dir = TempDir::new("havo-inline").unwrap();
+    let out = dir.path().join("inline_call.s");
+
+    // -O0 inlines nothing on its own, only always-inline functions
+    assert_success(&havo(&[
+        "-O",
+        "0",
+        "--emit-asm",
+        "-o",
+        out.to_str().unwrap(),
+        "tests/driver/inline_call.osmx",
+    ]));
+
+    let asm = std::fs::read_to_string(&out).unwrap();
+    assert!(!asm_of(&asm, "hot").contains("call"), "{}", asm);
+    assert!(asm_of(&asm, "cold").contains("square"), "{}", asm);
+}
+
+#[test]
+fn inline_functions_inline_regardless_of_visibility() {
+    let dir = TempDir::new("havo-inline-linkage").unwrap();
+    let out = dir.path().join("inline_linkage.s");
+
+    assert_success(&havo(&[
+        "-O",
+        "0",
+        "--emit-asm",
+        "-o",
+        out.to_str().unwrap(),
+        "tests/driver/inline_linkage.osmx",
+    ]));
+
+    let asm = std::fs::read_to_string(&out).unwrap();
+    assert!(!asm_of(&asm, "both").contains("call"), "{}", asm);
+    // `pub inline` is still exported, `static inline` isn't
+    assert!(asm.contains(".globl\tpub_square"), "{}", asm);
+    assert!(!asm.contains(".globl\tstatic_cube"), "{}", asm);
+}
+
+#[test]
+fn trivial_accessors_inline_at_o0() {
+    let dir = TempDir::new("havo-accessor").unwrap();
+    let out = dir.path().join("accessor_inline.s");
+
+    assert_success(&havo(&[
+        "-O",
+        "0",
+        "--emit-asm",
+        "-o",
+        out.to_str().unwrap(),
+        "tests/driver/accessor_inline.osmx",
+    ]));
+
+    let asm = std::fs::read_to_string(&out).unwrap();
+    let accessors = asm_of(&asm, "accessors");
+    // `get` and `set` only touch field, `bump` does more and stays a call
+    assert_eq!(accessors.matches("call").count(), 1, "{}", asm);
+    assert!(accessors.contains("bump"), "{}", asm);
+}
+
+#[test]
+fn comptime_statements_are_not_emitted() {
+    let dir = TempDir::new("havo-comptime").unwrap();
+    let out = dir.path().join("comptime.s");
+
+    // no `--consteval`, `constexpr` statements are evaluated regardless
+    assert_success(&havo(&[
+        "-O",
+        "0",
+        "--emit-asm",
+        "-o",
+        out.to_str().unwrap(),
+        "tests/driver/comptime.osmx",
+    ]));
+
+    let asm = std::fs::read_to_string(&out).unwrap();
+    let answer = asm_of(&asm, "answer");
+    assert!(!answer.contains("call"), "{}", asm);
+    assert!(answer.contains("$6765"), "{}", asm);
+    let scaled = asm_of(&asm, "scaled");
+    assert!(!scaled.contains("call"), "{}", asm);
+    assert!(scaled.contains("$165"), "{}", asm);
+    // evaluated once while compiling instead of on every iteration
+    let ticks = asm_of(&asm, "ticks");
+    assert!(!ticks.contains("call"), "{}", asm);
+    assert!(ticks.contains("$144"), "{}", asm);
+    assert!(ticks.contains("$610"), "{}", asm);
+}
+
+#[test]
+fn malformed_number_separators_are_rejected() {
+    let dir = TempDir::new("havo-separators").unwrap();
+    for literal in &["1__000", "1000_", "0x_FF", "0b1010_", "0o_7", "1_.5"] {
+        let path = dir.path().join("literal.osmx");
+        let source = format!("pub func main() i32 {{\n\treturn {} as i32;\n}}\n", literal);
+        std::fs::write(&path, source).unwrap();
+
+        let output = havo(&["--jit", path.to_str().unwrap()]);
+        assert!(!output.status.success(), "`{}` accepted", literal);
+        // lexer errors are printed to stdout
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("`_` is only allowed between digits of number."),
+            "{}: {}",
+            literal,
+            stdout
+        );
+    }
+}
+
+#[test]
+fn consteval_warns_on_constant_condition() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--color",
+        "never",
+        "tests/driver/constant_condition.osmx",
+    ]);
+    assert_success(&run);
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "always\n3\n");
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert!(stderr.contains("constant_condition.osmx.5:"), "{}", stderr);
+    assert!(stderr.contains("condition is always true"), "{}", stderr);
+    // `while true` is intentional infinite loop
+    assert_eq!(stderr.matches("condition is always").count(), 1, "{}", stderr);
+}
+
+#[test]
+fn malformed_escapes_are_rejected() {
+    let dir = TempDir::new("havo-escapes").unwrap();
+    for literal in &[r#""\x4""#, r#""\xFF""#, r#""\u263A""#, r#""\u{}""#, r#""\u{D800}""#] {
+        let path = dir.path().join("escape.osmx");
+        let source = format!("pub func main() i32 {{\n\tvar s = {};\n\treturn 0;\n}}\n", literal);
+        std::fs::write(&path, source).unwrap();
+
+        let output = havo(&["--jit", path.to_str().unwrap()]);
+        assert!(!output.status.success(), "{} accepted", literal);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("escape.osmx.2:"), "{}: {}", literal, stdout);
+    }
+}
+
+#[test]
+fn cold_function_is_not_inlined() {
+    let dir = TempDir::new("havo-cold").unwrap();
+    let out = dir.path().join("cold.s");
+
+    assert_success(&havo(&[
+        "-O",
+        "2",
+        "--emit-asm",
+        "-o",
+        out.to_str().unwrap(),
+        "tests/driver/cold.osmx",
+    ]));
+
+    let asm = std::fs::read_to_string(&out).unwrap();
+    // cold functions go to their own section, away from hot code
+    assert!(asm.contains(".text.unlikely"), "{}", asm);
+    assert!(asm_of(&asm, "handle").contains("report"), "{}", asm);
+}
+
+#[test]
+fn int_type_sets_type_of_unsuffixed_literals() {
+    let run = havo(&["--jit", "--int-type", "i64", "tests/driver/int_type.osmx"]);
+    assert_success(&run);
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "5000000001\n42\n6000000001\n");
+
+    let output = havo(&["--jit", "--int-type", "f64", "tests/driver/int_type.osmx"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`f64` isn't integer type"), "{}", stderr);
+}
+
+#[test]
+fn self_tail_calls_reuse_frame() {
+    // `-O1` doesn't turn calls into jumps by itself, million frames would overflow stack
+    let run = havo(&["--jit", "-O", "1", "tests/driver/tail_call.osmx"]);
+    assert_success(&run);
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "641102369\n");
+}
+
+#[test]
+fn section_attribute_places_symbols() {
+    let dir = TempDir::new("havo-section").unwrap();
+    let obj = dir.path().join("section.o");
+
+    assert_success(&havo(&[
+        "--emit-obj",
+        "-o",
+        obj.to_str().unwrap(),
+        "tests/driver/section.osmx",
+    ]));
+
+    let objdump = Command::new("objdump")
+        .args(&["-t", obj.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let symbols = String::from_utf8_lossy(&objdump.stdout);
+    let section_of = |symbol: &str| {
+        symbols
+            .lines()
+            .find(|line| line.ends_with(&format!(" {}", symbol)))
+            .unwrap_or_else(|| panic!("no `{}` in symbols:\n{}", symbol, symbols))
+            .to_owned()
+    };
+    assert!(section_of("boot_flag").contains(" .boot.data\t"), "{}", symbols);
+    assert!(section_of("reset_handler").contains(" .text\t"), "{}", symbols);
+
+    let path = dir.path().join("function.osmx");
+    std::fs::write(&path, "@section(\".boot\")\npub func reset() void {}\n").unwrap();
+    let output = havo(&["--emit-obj", "-o", obj.to_str().unwrap(), path.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("@section isn't supported on functions"), "{}", stderr);
+}
+
+#[test]
+fn consteval_restores_variables_shadowed_in_block_expression() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--print-ast",
+        "tests/driver/const_block.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    // global `count` isn't known, local one declared in block mustn't be used for it
+    assert!(stdout.contains(r#"printf("%i %i\n",count,y)"#), "{}", stdout);
+    assert!(stdout.ends_with("1 11\n"), "{}", stdout);
+}
+
+#[test]
+fn consteval_leaves_wide_int128_constants_unfolded() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--print-ast",
+        "tests/driver/const_int128.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(stdout.ends_with("4 4\n"), "{}", stdout);
+}
+
+#[test]
+fn consteval_zero_extends_unsigned_to_u128() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--print-ast",
+        "tests/driver/const_u128.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(stdout.ends_with("4294967295 0\n"), "{}", stdout);
+}
+
+#[test]
+fn consteval_forgets_arrays_decayed_or_sliced() {
+    let run = havo(&[
+        "--jit",
+        "--consteval",
+        "--print-ast",
+        "tests/driver/const_decay.osmx",
+    ]);
+    assert_success(&run);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    // elements are changed through slices sharing them
+    assert!(!stdout.contains(r#"printf("%i %i\n",7,6)"#), "{}", stdout);
+    assert!(stdout.ends_with("0 9\n"), "{}", stdout);
+}